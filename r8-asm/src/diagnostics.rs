@@ -0,0 +1,144 @@
+use std::path::Path;
+
+use r8_assembly::{error::Error, Warning};
+use serde::Serialize;
+
+/// How bad a diagnostic is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+  Error,
+  Warning,
+}
+
+/// A problem found in a source, printed for people or as JSON for editors and CI.
+///
+/// # Fields
+///
+/// * `severity` - Whether the problem stops the assembly.
+/// * `file` - The file of the problem, `None` when it is not in a file.
+/// * `line` - The line in `file`, `None` when the problem is with the whole file.
+/// * `message` - What is wrong.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Diagnostic {
+  pub severity: Severity,
+  pub file: Option<String>,
+  pub line: Option<usize>,
+  pub message: String,
+}
+
+impl Diagnostic {
+  /// Creates the diagnostic of an error of the assembler.
+  ///
+  /// The location is the innermost one: the file and line in the included file or
+  /// the macro definition, the message says where it was included or called.
+  pub fn error(err: &Error) -> Self {
+    Diagnostic {
+      severity: Severity::Error,
+      file: err.file().map(str::to_string),
+      line: err.line(),
+      message: err.to_string(),
+    }
+  }
+
+  /// Creates the diagnostic of a warning of the assembler.
+  pub fn warning(warning: &Warning) -> Self {
+    Diagnostic {
+      severity: Severity::Warning,
+      file: Some(warning.file.clone()).filter(|file| !file.is_empty()),
+      line: Some(warning.line),
+      message: warning.message.clone(),
+    }
+  }
+
+  /// Creates an error about a whole file, like one that cannot be read.
+  pub fn file(path: &Path, message: impl std::fmt::Display) -> Self {
+    let file = path.display().to_string();
+    Diagnostic {
+      severity: Severity::Error,
+      message: format!("{}: {}", file, message),
+      file: Some(file),
+      line: None,
+    }
+  }
+
+  /// Prints the diagnostic on stderr, as a JSON object on a line or as a message,
+  /// so it never mixes with a ROM or source written to stdout.
+  pub fn report(&self, json: bool) {
+    if json {
+      eprintln!("{}", serde_json::to_string(self).unwrap_or_default());
+    } else {
+      eprintln!("{}", self);
+    }
+  }
+}
+
+impl std::fmt::Display for Diagnostic {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self.severity {
+      // The message of an error already says its file and line.
+      Severity::Error => write!(f, "error: {}", self.message),
+      Severity::Warning => match (&self.file, self.line) {
+        (Some(file), Some(line)) => write!(f, "warning: {}:{}: {}", file, line, self.message),
+        (None, Some(line)) => write!(f, "warning: line {}: {}", line, self.message),
+        (Some(file), None) => write!(f, "warning: {}: {}", file, self.message),
+        (None, None) => write!(f, "warning: {}", self.message),
+      },
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use r8_assembly::FileResolver;
+  use std::{io, path::PathBuf};
+
+  /// Serves `main.8s` including `lib.8s`, which has an error on its second line.
+  struct Files;
+
+  impl FileResolver for Files {
+    fn resolve(&self, name: &str, _from: &Path) -> io::Result<PathBuf> {
+      Ok(PathBuf::from(name))
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+      match path.to_str() {
+        Some("main.8s") => Ok(b"CLS\nINCLUDE \"lib.8s\"".to_vec()),
+        _ => Ok(b"RET\nJP nowhere".to_vec()),
+      }
+    }
+  }
+
+  #[test]
+  fn errors_point_at_the_innermost_line() {
+    let err =
+      r8_assembly::assemble_file(Path::new("main.8s"), &Files, &mut Vec::new()).unwrap_err();
+    let diagnostic = Diagnostic::error(&err);
+    assert_eq!(diagnostic.file.as_deref(), Some("lib.8s"));
+    assert_eq!(diagnostic.line, Some(2));
+    let json = serde_json::to_value(&diagnostic).unwrap();
+    assert_eq!(json["severity"], "error");
+    assert_eq!(json["file"], "lib.8s");
+    assert_eq!(json["line"], 2);
+  }
+
+  #[test]
+  fn warnings_and_file_errors() {
+    let warning = Warning {
+      file: "a.8s".to_string(),
+      line: 3,
+      message: "odd".to_string(),
+    };
+    assert_eq!(
+      Diagnostic::warning(&warning).to_string(),
+      "warning: a.8s:3: odd"
+    );
+    let missing = Diagnostic::file(Path::new("b.8s"), "not found");
+    assert_eq!(missing.to_string(), "error: b.8s: not found");
+    assert_eq!(
+      serde_json::to_string(&missing).unwrap(),
+      r#"{"severity":"error","file":"b.8s","line":null,"message":"b.8s: not found"}"#
+    );
+  }
+}
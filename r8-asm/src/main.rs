@@ -0,0 +1,226 @@
+use std::{
+  fs::File,
+  io::{self, Read, Write},
+  path::{Path, PathBuf},
+  process::ExitCode,
+};
+
+use clap::{error::ErrorKind, CommandFactory, Parser, Subcommand};
+mod diagnostics;
+use crate::diagnostics::Diagnostic;
+use r8_assembly::{Assembly, FileSystem};
+
+/// The source has errors, or is not formatted for `fmt --check`.
+const SOURCE_ERRORS: u8 = 1;
+
+/// A file could not be read or written.
+const IO_ERRORS: u8 = 2;
+
+/// CLI for the assembler tools
+#[derive(Parser)]
+/// r8-asm - CHIP-8 assembler, disassembler and formatter
+///
+/// Exits with 0 on success, 1 when a source has errors (or is not formatted for
+/// `fmt --check`) and 2 when a file cannot be read or written.
+pub struct R8Asm {
+  /// Print the errors and warnings as JSON objects, one per line on stderr
+  #[clap(long, global = true)]
+  json: bool,
+  #[clap(subcommand)]
+  command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+  /// Assemble a source into a ROM
+  Build {
+    /// Path to the source
+    input: PathBuf,
+    /// Path to the ROM (default: the source with a .ch8 extension, `-` for stdout)
+    #[clap(short, long)]
+    output: Option<PathBuf>,
+    /// Write the listing: the address, bytes and source of every line
+    #[clap(short, long)]
+    listing: Option<PathBuf>,
+    /// Write the labels and constants, as JSON when the file ends with .json
+    #[clap(short, long)]
+    symbols: Option<PathBuf>,
+    /// Directory searched for INCLUDE and INCBIN files (repeatable)
+    #[clap(short = 'I', long = "include")]
+    include_paths: Vec<PathBuf>,
+  },
+  /// Disassemble a ROM into source
+  Disasm {
+    /// Path to the ROM
+    rom: PathBuf,
+    /// Path to the source (`-` for stdout)
+    #[clap(short, long, default_value = "-")]
+    output: PathBuf,
+  },
+  /// Assemble a source and only report its errors and warnings
+  Check {
+    /// Path to the source
+    input: PathBuf,
+    /// Directory searched for INCLUDE and INCBIN files (repeatable)
+    #[clap(short = 'I', long = "include")]
+    include_paths: Vec<PathBuf>,
+  },
+  /// Format sources in place (`-` formats stdin to stdout)
+  Fmt {
+    /// Paths to the sources
+    #[clap(required = true)]
+    files: Vec<PathBuf>,
+    /// Only report the sources that are not formatted, without changing them
+    #[clap(long)]
+    check: bool,
+  },
+}
+
+fn main() -> ExitCode {
+  let args = R8Asm::parse();
+  let json = args.json;
+  let status = match args.command {
+    Command::Build {
+      input,
+      output,
+      listing,
+      symbols,
+      include_paths,
+    } => {
+      let output = output.unwrap_or_else(|| input.with_extension("ch8"));
+      let stdout = [Some(&output), listing.as_ref(), symbols.as_ref()]
+        .iter()
+        .filter(|path| path.is_some_and(|path| path == Path::new("-")))
+        .count();
+      if stdout > 1 {
+        R8Asm::command()
+          .error(
+            ErrorKind::ArgumentConflict,
+            "only one of --output, --listing and --symbols can be `-`",
+          )
+          .exit();
+      }
+      build(&input, include_paths, json, |rom, assembly| {
+        let mut outputs = vec![(output, rom.to_vec())];
+        if let Some(listing) = listing {
+          outputs.push((listing, assembly.listing().into_bytes()));
+        }
+        if let Some(symbols) = symbols {
+          let table = match symbols.extension().is_some_and(|ext| ext == "json") {
+            true => assembly.symbols_json(),
+            false => assembly.symbol_table(),
+          };
+          outputs.push((symbols, table.into_bytes()));
+        }
+        outputs
+      })
+    }
+    Command::Disasm { rom, output } => match std::fs::read(&rom) {
+      Ok(data) => {
+        let source = r8_assembly::disassemble(&data);
+        write_file(&output, source.as_bytes(), json)
+      }
+      Err(err) => report_io(&rom, err, json),
+    },
+    Command::Check {
+      input,
+      include_paths,
+    } => build(&input, include_paths, json, |_, _| Vec::new()),
+    Command::Fmt { files, check } => files
+      .iter()
+      .map(|path| format(path, check, json))
+      .max()
+      .unwrap_or(0),
+  };
+  ExitCode::from(status)
+}
+
+/// Assembles `input`, reporting its warnings and errors, and writes the files
+/// `outputs` makes from the ROM and the assembly.
+///
+/// # Returns
+///
+/// * `u8` - The exit code.
+fn build(
+  input: &Path,
+  include_paths: Vec<PathBuf>,
+  json: bool,
+  outputs: impl FnOnce(&[u8], &Assembly) -> Vec<(PathBuf, Vec<u8>)>,
+) -> u8 {
+  let mut rom = Vec::new();
+  let files = FileSystem::new(include_paths);
+  match r8_assembly::assemble_file(input, &files, &mut rom) {
+    Ok(assembly) => {
+      for warning in &assembly.warnings {
+        Diagnostic::warning(warning).report(json);
+      }
+      outputs(&rom, &assembly)
+        .iter()
+        .map(|(path, data)| write_file(path, data, json))
+        .max()
+        .unwrap_or(0)
+    }
+    Err(err) => {
+      Diagnostic::error(&err).report(json);
+      // Only the errors reading the source itself have no line.
+      match err.line() {
+        Some(_) => SOURCE_ERRORS,
+        None => IO_ERRORS,
+      }
+    }
+  }
+}
+
+/// Formats the source at `path`, or only checks it is formatted.
+///
+/// # Returns
+///
+/// * `u8` - The exit code.
+fn format(path: &Path, check: bool, json: bool) -> u8 {
+  let stdin = path == Path::new("-");
+  let source = match stdin {
+    true => {
+      let mut source = String::new();
+      io::stdin().read_to_string(&mut source).map(|_| source)
+    }
+    false => std::fs::read_to_string(path),
+  };
+  let source = match source {
+    Ok(source) => source,
+    Err(err) => return report_io(path, err, json),
+  };
+  let formatted = r8_assembly::format_source(&source);
+  match (check, formatted == source) {
+    (true, true) => 0,
+    (true, false) => {
+      Diagnostic::file(path, "not formatted").report(json);
+      SOURCE_ERRORS
+    }
+    (false, true) if !stdin => 0,
+    (false, _) => write_file(path, formatted.as_bytes(), json),
+  }
+}
+
+/// Writes `data` to the file at `path`, or to stdout when `path` is `-`.
+///
+/// # Returns
+///
+/// * `u8` - The exit code.
+fn write_file(path: &Path, data: &[u8], json: bool) -> u8 {
+  let result = if path == Path::new("-") {
+    let mut stdout = io::stdout().lock();
+    stdout.write_all(data).and_then(|_| stdout.flush())
+  } else {
+    File::create(path).and_then(|mut file| file.write_all(data))
+  };
+  match result {
+    Ok(()) => 0,
+    Err(err) => report_io(path, err, json),
+  }
+}
+
+/// Reports a file that could not be read or written.
+fn report_io(path: &Path, err: io::Error, json: bool) -> u8 {
+  Diagnostic::file(path, err).report(json);
+  IO_ERRORS
+}
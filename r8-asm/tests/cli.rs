@@ -0,0 +1,103 @@
+//! Runs the `r8-asm` binary over sources in a temporary directory and checks its
+//! outputs and exit codes.
+
+use std::{
+  fs,
+  path::{Path, PathBuf},
+  process::{Command, Output},
+};
+
+/// Creates an empty directory for a test.
+fn directory(name: &str) -> PathBuf {
+  let dir = std::env::temp_dir().join(format!("r8-asm-{}-{}", std::process::id(), name));
+  let _ = fs::remove_dir_all(&dir);
+  fs::create_dir_all(&dir).unwrap();
+  dir
+}
+
+/// Runs `r8-asm` in `dir` with `args`.
+fn r8_asm(dir: &Path, args: &[&str]) -> Output {
+  Command::new(env!("CARGO_BIN_EXE_r8-asm"))
+    .current_dir(dir)
+    .args(args)
+    .output()
+    .unwrap()
+}
+
+#[test]
+fn build_writes_the_rom_listing_and_symbols() {
+  let dir = directory("build");
+  fs::write(dir.join("game.8s"), "start: CLS\nJP start\n").unwrap();
+  let output = r8_asm(
+    &dir,
+    &["build", "game.8s", "-l", "game.lst", "-s", "game.json"],
+  );
+  assert_eq!(output.status.code(), Some(0));
+  assert_eq!(
+    fs::read(dir.join("game.ch8")).unwrap(),
+    [0x00, 0xE0, 0x12, 0x00]
+  );
+  let listing = fs::read_to_string(dir.join("game.lst")).unwrap();
+  assert!(listing.contains("200  00 E0"));
+  let symbols = fs::read_to_string(dir.join("game.json")).unwrap();
+  assert!(symbols.contains("\"start\""));
+
+  // The ROM on stdout is not mixed with the JSON warnings.
+  fs::write(dir.join("odd.8s"), "DB 1\nCLS\n").unwrap();
+  let output = r8_asm(&dir, &["build", "odd.8s", "-o", "-", "--json"]);
+  assert_eq!(output.status.code(), Some(0));
+  assert_eq!(output.stdout, [0x01, 0x00, 0xE0]);
+  let warning = String::from_utf8(output.stderr).unwrap();
+  assert!(warning.starts_with("{\"severity\":\"warning\""));
+}
+
+#[test]
+fn only_one_output_goes_to_stdout() {
+  let dir = directory("stdout");
+  fs::write(dir.join("game.8s"), "CLS\n").unwrap();
+  let output = r8_asm(&dir, &["build", "game.8s", "-o", "-", "-l", "-"]);
+  assert_eq!(output.status.code(), Some(2));
+  assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn check_reports_errors() {
+  let dir = directory("check");
+  fs::write(dir.join("bad.8s"), "CLS\nJP nowhere\n").unwrap();
+  let output = r8_asm(&dir, &["check", "bad.8s", "--json"]);
+  assert_eq!(output.status.code(), Some(1));
+  assert!(output.stdout.is_empty());
+  let error: serde_json::Value = serde_json::from_slice(&output.stderr).unwrap();
+  assert_eq!(error["severity"], "error");
+  assert_eq!(error["line"], 2);
+
+  let output = r8_asm(&dir, &["check", "missing.8s"]);
+  assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn disasm_assembles_back() {
+  let dir = directory("disasm");
+  fs::write(dir.join("game.ch8"), [0x60, 0x05, 0x12, 0x00]).unwrap();
+  let output = r8_asm(&dir, &["disasm", "game.ch8", "-o", "game.8s"]);
+  assert_eq!(output.status.code(), Some(0));
+  let output = r8_asm(&dir, &["build", "game.8s", "-o", "-"]);
+  assert_eq!(output.status.code(), Some(0));
+  assert_eq!(output.stdout, [0x60, 0x05, 0x12, 0x00]);
+}
+
+#[test]
+fn fmt_checks_and_formats() {
+  let dir = directory("fmt");
+  fs::write(dir.join("game.8s"), "start:CLS\n  JP   start\n").unwrap();
+  let output = r8_asm(&dir, &["fmt", "--check", "game.8s"]);
+  assert_eq!(output.status.code(), Some(1));
+  let output = r8_asm(&dir, &["fmt", "game.8s"]);
+  assert_eq!(output.status.code(), Some(0));
+  assert_eq!(
+    fs::read_to_string(dir.join("game.8s")).unwrap(),
+    "start: CLS\n    JP start\n"
+  );
+  let output = r8_asm(&dir, &["fmt", "--check", "game.8s"]);
+  assert_eq!(output.status.code(), Some(0));
+}
@@ -0,0 +1,293 @@
+use std::{collections::BTreeMap, fmt::Write};
+
+use r8_core::Opcode;
+
+/// Address the ROM is loaded at, the tracing starts there.
+const ENTRY_POINT: u16 = 0x200;
+
+/// How many bytes of a ROM fit in memory, from `ENTRY_POINT` to `0xFFF`.
+const LOADABLE: usize = crate::MEMORY_END - ENTRY_POINT as usize;
+
+/// What a recovered label marks, a label used in several ways takes the name of
+/// the strongest one.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+enum LabelKind {
+  /// Target of `LD I`.
+  Data,
+  /// Target of `JP` or `JP V0`.
+  Code,
+  /// Target of `CALL`.
+  Subroutine,
+}
+
+impl LabelKind {
+  /// Returns the name of the label at `address`.
+  fn name(self, address: u16) -> String {
+    let prefix = match self {
+      LabelKind::Data => "data",
+      LabelKind::Code => "code",
+      LabelKind::Subroutine => "sub",
+    };
+    format!("{}_{:03X}", prefix, address)
+  }
+}
+
+/// The result of tracing a ROM image.
+///
+/// # Fields
+///
+/// * `rom` - The image.
+/// * `code` - For every offset, whether an instruction starts there.
+/// * `covered` - For every offset, whether it belongs to an instruction.
+struct Trace<'rom> {
+  rom: &'rom [u8],
+  code: Vec<bool>,
+  covered: Vec<bool>,
+}
+
+impl<'rom> Trace<'rom> {
+  /// Follows the control flow from the entry point through jumps, calls and both
+  /// outcomes of skips.
+  ///
+  /// The walk stops at `RET`, `SYS` (machine code), `JP V0` (its target depends on
+  /// V0) and invalid instructions. An instruction overlapping one traced before
+  /// is left out, so every byte belongs to at most one instruction. Bytes past the
+  /// end of memory are never traced.
+  fn new(rom: &'rom [u8]) -> Self {
+    let mut trace = Self {
+      rom,
+      code: vec![false; rom.len()],
+      covered: vec![false; rom.len()],
+    };
+    let mut pending = vec![0];
+    while let Some(offset) = pending.pop() {
+      if offset + 1 >= rom.len().min(LOADABLE)
+        || trace.code[offset]
+        || trace.covered[offset]
+        || trace.covered[offset + 1]
+      {
+        continue;
+      }
+      trace.code[offset] = true;
+      trace.covered[offset] = true;
+      trace.covered[offset + 1] = true;
+      let next = offset + 2;
+      let target = |address: u16| address.checked_sub(ENTRY_POINT).map(usize::from);
+      match trace.opcode(offset) {
+        Opcode::Ret | Opcode::Sys { .. } | Opcode::JpV0 { .. } | Opcode::Invalid(_) => {}
+        Opcode::Jp { address } => pending.extend(target(address.inner())),
+        Opcode::Call { address } => {
+          pending.push(next);
+          pending.extend(target(address.inner()));
+        }
+        Opcode::SeByte { .. }
+        | Opcode::SneByte { .. }
+        | Opcode::SeRegister { .. }
+        | Opcode::SneRegister { .. }
+        | Opcode::Skp { .. }
+        | Opcode::Sknp { .. } => pending.extend([next + 2, next]),
+        _ => pending.push(next),
+      }
+    }
+    trace
+  }
+
+  /// Returns the word at `offset`.
+  fn word(&self, offset: usize) -> u16 {
+    u16::from_be_bytes([self.rom[offset], self.rom[offset + 1]])
+  }
+
+  /// Decodes the instruction at `offset`.
+  fn opcode(&self, offset: usize) -> Opcode {
+    let word = self.word(offset);
+    Opcode::try_from(word).unwrap_or(Opcode::Invalid(word))
+  }
+
+  /// Returns `true` if a label can be put at `address`: on an instruction, on a
+  /// data byte or right after the image, not inside an instruction.
+  fn is_line_start(&self, address: u16) -> bool {
+    let Some(offset) = address.checked_sub(ENTRY_POINT).map(usize::from) else {
+      return false;
+    };
+    offset == self.rom.len()
+      || (offset < self.rom.len() && (self.code[offset] || !self.covered[offset]))
+  }
+
+  /// Returns the labels of the traced instructions, keyed by address.
+  fn labels(&self) -> BTreeMap<u16, String> {
+    let mut kinds = BTreeMap::<u16, LabelKind>::new();
+    for offset in (0..self.rom.len()).filter(|&offset| self.code[offset]) {
+      let (address, kind) = match self.opcode(offset) {
+        Opcode::Jp { address } | Opcode::JpV0 { address } => (address, LabelKind::Code),
+        Opcode::Call { address } => (address, LabelKind::Subroutine),
+        Opcode::LdI { address } => (address, LabelKind::Data),
+        _ => continue,
+      };
+      let address = address.inner();
+      if self.is_line_start(address) {
+        let strongest = kinds.get(&address).map_or(kind, |&other| other.max(kind));
+        kinds.insert(address, strongest);
+      }
+    }
+    kinds
+      .into_iter()
+      .map(|(address, kind)| (address, kind.name(address)))
+      .collect()
+  }
+}
+
+/// Formats the instruction at `offset` of a trace, with label names for the
+/// addresses that have one.
+///
+/// Words the assembler would not encode back to the same bytes (invalid
+/// instructions, `SHR`/`SHL` with an unusual Y) are written as `DW`.
+fn instruction(trace: &Trace, offset: usize, labels: &BTreeMap<u16, String>) -> String {
+  let word = trace.word(offset);
+  let opcode = trace.opcode(offset);
+  let target = |mnemonic: &str, address: u16| match labels.get(&address) {
+    Some(label) => format!("{} {}", mnemonic, label),
+    None => opcode.to_string(),
+  };
+  match opcode {
+    Opcode::Sys { address } => target("SYS", address.inner()),
+    Opcode::Jp { address } => target("JP", address.inner()),
+    Opcode::Call { address } => target("CALL", address.inner()),
+    Opcode::LdI { address } => target("LD I,", address.inner()),
+    Opcode::JpV0 { address } => target("JP V0,", address.inner()),
+    // The assembler writes Y = 1 for SHR and Y = 0 for SHL.
+    Opcode::Shr { .. } if word & 0x00F0 != 0x0010 => format!("DW #{:04X}", word),
+    Opcode::Shl { .. } if word & 0x00F0 != 0x0000 => format!("DW #{:04X}", word),
+    Opcode::Invalid(_) => format!("DW #{:04X}", word),
+    _ => opcode.to_string(),
+  }
+}
+
+/// Disassembles a CHIP-8 program into source `assemble` accepts.
+///
+/// Code is told apart from data by tracing the control flow from `0x200`, see
+/// `Trace::new`. Jump, call and `LD I` targets get labels (`code_XXX`, `sub_XXX`
+/// and `data_XXX`), bytes the trace never reaches are written as `DB` data.
+/// Bytes past the end of memory at `0xFFF` follow as `DB` data too, after a
+/// comment, the assembler refuses them.
+///
+/// # Arguments
+///
+/// * `rom` - The program, as loaded at `0x200`.
+///
+/// # Returns
+///
+/// * `String` - The source. Assembling it gives back `rom` byte for byte, if it
+///   fits in memory.
+///
+/// # Examples
+///
+/// ```
+/// let source = r8_assembly::disassemble(&[0x12, 0x00]);
+/// assert!(source.contains("code_200:\n    JP code_200"));
+/// ```
+pub fn disassemble(rom: &[u8]) -> String {
+  let trace = Trace::new(rom);
+  let labels = trace.labels();
+  let mut source = String::new();
+  let mut offset = 0;
+  while offset < rom.len().min(LOADABLE) {
+    let address = ENTRY_POINT + offset as u16;
+    if let Some(label) = labels.get(&address) {
+      let _ = writeln!(source, "{}:", label);
+    }
+    if trace.code[offset] {
+      let line = instruction(&trace, offset, &labels);
+      let _ = writeln!(
+        source,
+        "    {:<20} ; {:03X}: {:04X}",
+        line,
+        address,
+        trace.word(offset)
+      );
+      offset += 2;
+    } else {
+      let _ = writeln!(source, "    DB #{:02X}", rom[offset]);
+      offset += 1;
+    }
+  }
+  if let Some(label) = labels.get(&(ENTRY_POINT + offset as u16)) {
+    let _ = writeln!(source, "{}:", label);
+  }
+  if let Some(past_end) = rom.get(LOADABLE..).filter(|bytes| !bytes.is_empty()) {
+    let _ = writeln!(source, "; {} bytes past the end of memory", past_end.len());
+    for byte in past_end {
+      let _ = writeln!(source, "    DB #{:02X}", byte);
+    }
+  }
+  source
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Assembles `source` into bytes.
+  fn assemble(source: &str) -> Vec<u8> {
+    let mut rom = Vec::new();
+    crate::assemble(&mut source.as_bytes(), &mut rom).unwrap();
+    rom
+  }
+
+  #[test]
+  fn labels_and_data() {
+    let rom = [
+      0x22, 0x08, // CALL sub_208
+      0xA2, 0x0C, // LD I, data_20C
+      0x3A, 0x00, // SE VA, #0
+      0x12, 0x00, // JP code_200
+      0xD0, 0x11, // DRW V0, V1, #1
+      0x00, 0xEE, // RET
+      0xF0, 0x80, // sprite
+    ];
+    let source = disassemble(&rom);
+    assert!(source.contains("code_200:\n    CALL sub_208"));
+    assert!(source.contains("LD I, data_20C"));
+    assert!(source.contains("sub_208:\n    DRW V0, V1, #1"));
+    assert!(source.contains("data_20C:\n    DB #F0\n    DB #80\n"));
+    assert_eq!(assemble(&source), rom);
+  }
+
+  #[test]
+  fn words_that_do_not_assemble_back() {
+    // SHR V0 with Y = 2, SE V0, #0, 5XY1, then a jump into the middle of it.
+    let rom = [0x80, 0x26, 0x30, 0x00, 0x50, 0x01, 0x12, 0x05];
+    let source = disassemble(&rom);
+    assert!(source.contains("DW #8026"));
+    assert!(source.contains("DW #5001"));
+    assert!(source.contains("JP #205"));
+    assert_eq!(assemble(&source), rom);
+  }
+
+  #[test]
+  fn bytes_past_the_end_of_memory() {
+    // A jump to the last word of memory, which jumps back.
+    let mut rom = vec![0x1F, 0xFE];
+    rom.resize(LOADABLE - 2, 0xAA);
+    rom.extend([0x12, 0x00, 0x01, 0x02, 0x03]);
+    let source = disassemble(&rom);
+    assert!(source.contains("code_FFE:\n    JP code_200"));
+    let (loadable, past_end) = source
+      .split_once("; 3 bytes past the end of memory\n")
+      .unwrap();
+    assert_eq!(past_end, "    DB #01\n    DB #02\n    DB #03\n");
+    assert_eq!(assemble(loadable), rom[..LOADABLE]);
+
+    // Offsets that do not fit in an address do not overflow.
+    let source = disassemble(&vec![0; 0x10000]);
+    assert_eq!(source.matches("past the end of memory").count(), 1);
+  }
+
+  #[test]
+  fn bundled_roms_roundtrip() {
+    let roms = concat!(env!("CARGO_MANIFEST_DIR"), "/../roms");
+    for entry in std::fs::read_dir(roms).unwrap() {
+      let rom = std::fs::read(entry.unwrap().path()).unwrap();
+      assert_eq!(assemble(&disassemble(&rom)), rom);
+    }
+  }
+}
@@ -1,6 +1,8 @@
 use std::{
   fmt::{Debug, Display},
   num::ParseIntError,
+  path::Path,
+  rc::Rc,
 };
 
 use super::tokenizer::OwnedLine;
@@ -9,19 +11,119 @@ pub enum Error {
   IOErr(std::io::Error),
   DuplicateLabel(String, usize),
   UndefinedLabel(String, usize),
-  InvalidAddress(u16, usize),
+  InvalidAddress(i64, usize),
   InvalidNumber(ParseIntError, String, usize),
   InvalidRegister(u16, usize),
-  InvalidByte(u16, usize),
-  InvalidNibble(u16, usize),
+  InvalidByte(i64, usize),
+  InvalidWord(i64, usize),
+  InvalidNibble(i64, usize),
   InvalidToken(String, usize),
   InvalidLine(OwnedLine),
+  InvalidExpression(String, usize),
+  DivisionByZero(usize),
+  RecursiveConstant(String, usize),
+  DuplicateMacro(String, usize),
+  UnterminatedMacro(String, usize),
+  MacroArguments(String, usize, usize, usize),
+  MacroDepth(String, usize),
+  /// A `MACRO` directive in the body of a macro: the outer macro and the line.
+  NestedMacro(String, usize),
+  /// An error in the expansion of a macro: the macro, the line of the call and the
+  /// error, whose line is in the definition of the macro.
+  InMacro(String, usize, Box<Error>),
+  Include(String, std::io::Error, usize),
+  IncludeCycle(String, usize),
+  IncbinRange(String, usize),
+  /// An error in a named file: the file and the error, whose line is in that file.
+  InFile(String, Box<Error>),
+  InvalidOrigin(i64, usize),
+  InvalidCount(i64, usize),
+  OutOfMemory(u16, usize),
+  Overlap(u16, usize),
+  InvalidSprite(String, usize),
+  SpriteHeight(i64, usize),
+  InvalidCharset(String, usize),
+  MissingCharacter(char, usize),
 }
 
 impl Error {
   pub fn warp<T>(self) -> Result<T, Self> {
     Err(self)
   }
+
+  /// Wraps the error of a line with where the line comes from.
+  ///
+  /// # Arguments
+  ///
+  /// * `err` - The error, with its line in `file`.
+  /// * `file` - The file of the line, empty for a source without a path.
+  /// * `calls` - The macro calls the line was expanded from, with their file and
+  ///   line, outermost first.
+  pub(crate) fn in_context(err: Self, file: &Path, calls: &[(String, Rc<Path>, usize)]) -> Self {
+    let in_file = |err: Self, file: &Path| match file.as_os_str().is_empty() {
+      true => err,
+      false => Error::InFile(file.display().to_string(), Box::new(err)),
+    };
+    let mut err = err;
+    let mut file = file;
+    for (name, call_file, line) in calls.iter().rev() {
+      if file != call_file.as_ref() {
+        err = in_file(err, file);
+      }
+      err = Error::InMacro(name.clone(), *line, Box::new(err));
+      file = call_file;
+    }
+    in_file(err, file)
+  }
+}
+
+impl Error {
+  /// Returns the line of the error in the innermost file or macro, `None` for
+  /// errors reading the input.
+  pub fn line(&self) -> Option<usize> {
+    match self {
+      Error::IOErr(_) => None,
+      Error::InvalidLine(line) => Some(line.line),
+      Error::InMacro(_, _, err) | Error::InFile(_, err) => err.line(),
+      Error::DivisionByZero(line)
+      | Error::InvalidNumber(_, _, line)
+      | Error::MacroArguments(_, _, _, line)
+      | Error::Include(_, _, line)
+      | Error::DuplicateLabel(_, line)
+      | Error::UndefinedLabel(_, line)
+      | Error::InvalidAddress(_, line)
+      | Error::InvalidRegister(_, line)
+      | Error::InvalidByte(_, line)
+      | Error::InvalidWord(_, line)
+      | Error::InvalidNibble(_, line)
+      | Error::InvalidToken(_, line)
+      | Error::InvalidExpression(_, line)
+      | Error::RecursiveConstant(_, line)
+      | Error::DuplicateMacro(_, line)
+      | Error::UnterminatedMacro(_, line)
+      | Error::MacroDepth(_, line)
+      | Error::NestedMacro(_, line)
+      | Error::IncludeCycle(_, line)
+      | Error::IncbinRange(_, line)
+      | Error::InvalidOrigin(_, line)
+      | Error::InvalidCount(_, line)
+      | Error::OutOfMemory(_, line)
+      | Error::Overlap(_, line)
+      | Error::InvalidSprite(_, line)
+      | Error::SpriteHeight(_, line)
+      | Error::InvalidCharset(_, line)
+      | Error::MissingCharacter(_, line) => Some(*line),
+    }
+  }
+
+  /// Returns the file `line` is in, `None` for a source without a path.
+  pub fn file(&self) -> Option<&str> {
+    match self {
+      Error::InFile(file, err) => err.file().or(Some(file)),
+      Error::InMacro(_, _, err) => err.file(),
+      _ => None,
+    }
+  }
 }
 
 impl From<std::io::Error> for Error {
@@ -34,8 +136,8 @@ impl Display for Error {
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
     match self {
       Error::IOErr(err) => write!(f, "IO Error: {}", err),
-      Error::DuplicateLabel(name, line) => write!(f, "Duplicate label {} at line {}", name, line),
-      Error::UndefinedLabel(name, line) => write!(f, "Undefined label {} at line {}", name, line),
+      Error::DuplicateLabel(name, line) => write!(f, "Duplicate symbol {} at line {}", name, line),
+      Error::UndefinedLabel(name, line) => write!(f, "Undefined symbol {} at line {}", name, line),
       Error::InvalidAddress(addr, line) => write!(f, "Invalid address {} at line {}", addr, line),
       Error::InvalidNumber(msg, num, line) => write!(
         f,
@@ -46,16 +148,113 @@ impl Display for Error {
       Error::InvalidToken(token, line) => write!(f, "Invalid token {} at line {}", token, line),
       Error::InvalidByte(byte, line) => write!(
         f,
-        "Invalid byte at line {}: {} is bigger than 0xFF",
+        "Invalid byte at line {}: {} does not fit in 8 bits",
         line, byte
       ),
+      Error::InvalidWord(word, line) => write!(
+        f,
+        "Invalid word at line {}: {} does not fit in 16 bits",
+        line, word
+      ),
       Error::InvalidNibble(nibble, line) => write!(
         f,
-        "Invalid nibble at line {}: {} is bigger than 0xF",
+        "Invalid nibble at line {}: {} does not fit in 4 bits",
         line, nibble
       ),
       Error::InvalidLine(line) => write!(f, "Invalid line: {:?}", line),
+      Error::InvalidExpression(msg, line) => {
+        write!(f, "Invalid expression at line {}: {}", line, msg)
+      }
+      Error::DivisionByZero(line) => write!(f, "Division by zero at line {}", line),
+      Error::RecursiveConstant(name, line) => write!(
+        f,
+        "Constant {} refers to itself, used at line {}",
+        name, line
+      ),
+      Error::DuplicateMacro(name, line) => write!(f, "Duplicate macro {} at line {}", name, line),
+      Error::UnterminatedMacro(name, line) => {
+        write!(f, "Macro {} at line {} has no ENDM", name, line)
+      }
+      Error::MacroArguments(name, expected, found, line) => write!(
+        f,
+        "Macro {} takes {} arguments but {} were given at line {}",
+        name, expected, found, line
+      ),
+      Error::MacroDepth(name, line) => write!(
+        f,
+        "Macro {} called at line {} nests too deep, does it call itself?",
+        name, line
+      ),
+      Error::NestedMacro(name, line) => write!(
+        f,
+        "MACRO at line {} is inside the definition of macro {}, macros cannot be nested",
+        line, name
+      ),
+      Error::InMacro(name, line, err) => {
+        write!(f, "{}, in macro {} called at line {}", err, name, line)
+      }
+      Error::Include(name, err, line) => {
+        write!(f, "Cannot include {} at line {}: {}", name, line, err)
+      }
+      Error::IncludeCycle(name, line) => {
+        write!(f, "{} includes itself at line {}", name, line)
+      }
+      Error::IncbinRange(name, line) => {
+        write!(f, "INCBIN at line {} reads outside of {}", line, name)
+      }
+      Error::InFile(file, err) => write!(f, "{}: {}", file, err),
+      Error::InvalidOrigin(address, line) => write!(
+        f,
+        "Invalid origin at line {}: {} is outside of #200 to #1000",
+        line, address
+      ),
+      Error::InvalidCount(count, line) => write!(f, "Invalid count {} at line {}", count, line),
+      Error::OutOfMemory(address, line) => write!(
+        f,
+        "Line {} at #{:03X} goes past the end of memory at #FFF",
+        line, address
+      ),
+      Error::Overlap(address, line) => write!(
+        f,
+        "Line {} at #{:03X} overlaps bytes assembled before",
+        line, address
+      ),
+      Error::InvalidSprite(msg, line) => write!(f, "Invalid sprite at line {}: {}", line, msg),
+      Error::SpriteHeight(height, line) => write!(
+        f,
+        "Invalid sprite at line {}: {} rows, DRW draws 1 to 15",
+        line, height
+      ),
+      Error::InvalidCharset(msg, line) => write!(f, "Invalid charset at line {}: {}", line, msg),
+      Error::MissingCharacter(char, line) => write!(
+        f,
+        "Character '{}' at line {} is not in the charset",
+        char, line
+      ),
+    }
+  }
+}
+
+/// A problem in the source that does not stop the assembly.
+///
+/// # Fields
+///
+/// * `file` - The file of the line, empty for a source without a path.
+/// * `line` - The line.
+/// * `message` - What is suspicious about it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Warning {
+  pub file: String,
+  pub line: usize,
+  pub message: String,
+}
+
+impl Display for Warning {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    if !self.file.is_empty() {
+      write!(f, "{}: ", self.file)?;
     }
+    write!(f, "Warning at line {}: {}", self.line, self.message)
   }
 }
 
@@ -0,0 +1,330 @@
+use std::collections::HashMap;
+
+use super::error::Error;
+use super::tokenizer::Token;
+
+/// How deep constants may refer to other constants before the evaluation gives up.
+const MAX_DEPTH: usize = 64;
+
+/// An operator applied to a single value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+  /// `-x`
+  Negate,
+  /// `~x`
+  Not,
+  /// `HI(x)`, bits 8 to 15 of the value.
+  High,
+  /// `LO(x)`, bits 0 to 7 of the value.
+  Low,
+}
+
+/// An operator combining two values, listed from the tightest to the loosest binding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+  Mul,
+  Div,
+  Rem,
+  Add,
+  Sub,
+  Shl,
+  Shr,
+  And,
+  Xor,
+  Or,
+}
+
+impl BinaryOp {
+  /// Returns the operator spelled `op` and how tightly it binds, higher first.
+  fn parse(op: &str) -> Option<(Self, u8)> {
+    Some(match op {
+      "*" => (BinaryOp::Mul, 5),
+      "/" => (BinaryOp::Div, 5),
+      "%" => (BinaryOp::Rem, 5),
+      "+" => (BinaryOp::Add, 4),
+      "-" => (BinaryOp::Sub, 4),
+      "<<" => (BinaryOp::Shl, 3),
+      ">>" => (BinaryOp::Shr, 3),
+      "&" => (BinaryOp::And, 2),
+      "^" => (BinaryOp::Xor, 1),
+      "|" => (BinaryOp::Or, 0),
+      _ => return None,
+    })
+  }
+}
+
+/// A numeric expression, kept unevaluated until every symbol is known.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+  Number(i64),
+  Symbol(String),
+  Unary(UnaryOp, Box<Expr>),
+  Binary(BinaryOp, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+  /// Parses the tokens of an operand.
+  ///
+  /// # Arguments
+  ///
+  /// * `tokens` - The tokens, without the commas around the operand.
+  /// * `line` - The line the operand is on.
+  ///
+  /// # Returns
+  ///
+  /// * `Ok(Expr)` - The expression.
+  /// * `Err(Error)` - If the tokens are not a well formed expression.
+  pub fn parse(tokens: &[Token<'_>], line: usize) -> Result<Self, Error> {
+    let mut parser = Parser {
+      tokens,
+      position: 0,
+      line,
+    };
+    let expr = parser.binary(0)?;
+    match parser.tokens.get(parser.position) {
+      None => Ok(expr),
+      Some(token) => parser.unexpected(Some(token)),
+    }
+  }
+
+  /// Computes the value of the expression.
+  ///
+  /// # Arguments
+  ///
+  /// * `symbols` - The labels and constants the expression can refer to.
+  /// * `line` - The line the expression is on, for errors.
+  ///
+  /// # Returns
+  ///
+  /// * `Ok(i64)` - The value.
+  /// * `Err(Error)` - If a symbol is undefined, a constant refers to itself or the
+  ///   expression divides by zero.
+  pub fn eval(&self, symbols: &Symbols, line: usize) -> Result<i64, Error> {
+    self.eval_at(symbols, line, 0)
+  }
+
+  fn eval_at(&self, symbols: &Symbols, line: usize, depth: usize) -> Result<i64, Error> {
+    match self {
+      Expr::Number(value) => Ok(*value),
+      Expr::Symbol(name) => symbols.value(name, line, depth),
+      Expr::Unary(op, value) => {
+        let value = value.eval_at(symbols, line, depth)?;
+        Ok(match op {
+          UnaryOp::Negate => value.wrapping_neg(),
+          UnaryOp::Not => !value,
+          UnaryOp::High => (value >> 8) & 0xFF,
+          UnaryOp::Low => value & 0xFF,
+        })
+      }
+      Expr::Binary(op, lhs, rhs) => {
+        let lhs = lhs.eval_at(symbols, line, depth)?;
+        let rhs = rhs.eval_at(symbols, line, depth)?;
+        match op {
+          BinaryOp::Div | BinaryOp::Rem if rhs == 0 => Err(Error::DivisionByZero(line)),
+          BinaryOp::Mul => Ok(lhs.wrapping_mul(rhs)),
+          BinaryOp::Div => Ok(lhs.wrapping_div(rhs)),
+          BinaryOp::Rem => Ok(lhs.wrapping_rem(rhs)),
+          BinaryOp::Add => Ok(lhs.wrapping_add(rhs)),
+          BinaryOp::Sub => Ok(lhs.wrapping_sub(rhs)),
+          BinaryOp::Shl => Ok(lhs.checked_shl(rhs as u32).unwrap_or(0)),
+          BinaryOp::Shr => Ok(lhs.checked_shr(rhs as u32).unwrap_or(lhs >> 63)),
+          BinaryOp::And => Ok(lhs & rhs),
+          BinaryOp::Xor => Ok(lhs ^ rhs),
+          BinaryOp::Or => Ok(lhs | rhs),
+        }
+      }
+    }
+  }
+}
+
+/// Precedence climbing parser over the tokens of one operand.
+struct Parser<'tokens, 'src> {
+  tokens: &'tokens [Token<'src>],
+  position: usize,
+  line: usize,
+}
+
+impl<'src> Parser<'_, 'src> {
+  /// Parses operators binding at least as tightly as `min`.
+  fn binary(&mut self, min: u8) -> Result<Expr, Error> {
+    let mut lhs = self.unary()?;
+    while let Some(&Token::Operator(op)) = self.tokens.get(self.position) {
+      let Some((op, precedence)) = BinaryOp::parse(op).filter(|&(_, p)| p >= min) else {
+        break;
+      };
+      self.position += 1;
+      let rhs = self.binary(precedence + 1)?;
+      lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+    }
+    Ok(lhs)
+  }
+
+  /// Parses a value with its prefix operators.
+  fn unary(&mut self) -> Result<Expr, Error> {
+    let token = self.tokens.get(self.position);
+    self.position += 1;
+    match token {
+      Some(Token::Number(value)) => Ok(Expr::Number(*value as i64)),
+      Some(Token::Operator("-")) => Ok(Expr::Unary(UnaryOp::Negate, Box::new(self.unary()?))),
+      Some(Token::Operator("~")) => Ok(Expr::Unary(UnaryOp::Not, Box::new(self.unary()?))),
+      Some(Token::Operator("+")) => self.unary(),
+      Some(Token::Operator("(")) => self.parenthesized(),
+      Some(Token::Identifier(function @ ("HI" | "LO")))
+        if matches!(self.tokens.get(self.position), Some(Token::Operator("("))) =>
+      {
+        self.position += 1;
+        let op = if *function == "HI" {
+          UnaryOp::High
+        } else {
+          UnaryOp::Low
+        };
+        Ok(Expr::Unary(op, Box::new(self.parenthesized()?)))
+      }
+      Some(Token::Identifier(name)) => Ok(Expr::Symbol(name.to_string())),
+      token => self.unexpected(token),
+    }
+  }
+
+  /// Parses the rest of a parenthesized expression, the `(` already consumed.
+  fn parenthesized(&mut self) -> Result<Expr, Error> {
+    let expr = self.binary(0)?;
+    match self.tokens.get(self.position) {
+      Some(Token::Operator(")")) => {
+        self.position += 1;
+        Ok(expr)
+      }
+      token => self.unexpected(token),
+    }
+  }
+
+  fn unexpected<T>(&self, token: Option<&Token<'src>>) -> Result<T, Error> {
+    let found = match token {
+      Some(token) => format!("unexpected {:?}", token),
+      None => "unexpected end of expression".to_string(),
+    };
+    Error::InvalidExpression(found, self.line).warp()
+  }
+}
+
+/// What a symbol stands for.
+#[derive(Debug, Clone)]
+pub enum Symbol {
+  /// A label, the address it was defined at.
+  Label(u16),
+  /// A constant from `EQU` or `DEFINE`, evaluated when used so it can refer to
+  /// symbols defined after it.
+  Constant(Expr),
+}
+
+/// The labels and constants of a program.
+#[derive(Debug, Default)]
+pub struct Symbols {
+  table: HashMap<String, Symbol>,
+}
+
+impl Symbols {
+  /// Defines a symbol.
+  ///
+  /// # Arguments
+  ///
+  /// * `name` - The name of the symbol.
+  /// * `symbol` - What it stands for.
+  /// * `line` - The line it is defined on.
+  ///
+  /// # Returns
+  ///
+  /// * `Ok(())` - If the symbol was defined.
+  /// * `Err(Error)` - If a symbol with the same name was already defined.
+  pub fn define(&mut self, name: &str, symbol: Symbol, line: usize) -> Result<(), Error> {
+    if self.table.contains_key(name) {
+      return Error::DuplicateLabel(name.to_string(), line).warp();
+    }
+    self.table.insert(name.to_string(), symbol);
+    Ok(())
+  }
+
+  /// Returns every symbol, in no particular order.
+  pub fn iter(&self) -> impl Iterator<Item = (&str, &Symbol)> {
+    self
+      .table
+      .iter()
+      .map(|(name, symbol)| (name.as_str(), symbol))
+  }
+
+  /// Returns the value of the symbol `name`, used on `line`.
+  fn value(&self, name: &str, line: usize, depth: usize) -> Result<i64, Error> {
+    match self.table.get(name) {
+      None => Error::UndefinedLabel(name.to_string(), line).warp(),
+      Some(Symbol::Label(address)) => Ok(*address as i64),
+      Some(Symbol::Constant(_)) if depth == MAX_DEPTH => {
+        Error::RecursiveConstant(name.to_string(), line).warp()
+      }
+      Some(Symbol::Constant(expr)) => expr.eval_at(self, line, depth + 1),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::tokenizer::Tokenizer;
+
+  /// Parses and evaluates `src` against `symbols`.
+  fn eval(src: &str, symbols: &Symbols) -> Result<i64, Error> {
+    let line = Tokenizer::new(src).next().unwrap()?;
+    Expr::parse(&line.tokens, 1)?.eval(symbols, 1)
+  }
+
+  #[test]
+  fn precedence_and_parentheses() {
+    let symbols = Symbols::default();
+    assert_eq!(eval("1 + 2 * 3", &symbols).unwrap(), 7);
+    assert_eq!(eval("(1 + 2) * 3", &symbols).unwrap(), 9);
+    assert_eq!(eval("1 << 4 | 1", &symbols).unwrap(), 17);
+    assert_eq!(eval("#F0 & ~#30 ^ 1", &symbols).unwrap(), 0xC1);
+    assert_eq!(eval("10 - 3 - 2", &symbols).unwrap(), 5);
+    assert_eq!(eval("-7 % 4 + 17 / 5", &symbols).unwrap(), 0);
+    assert_eq!(eval("HI(#1234) + LO(#1234)", &symbols).unwrap(), 0x46);
+  }
+
+  #[test]
+  fn symbols_and_errors() {
+    let mut symbols = Symbols::default();
+    symbols.define("start", Symbol::Label(0x200), 1).unwrap();
+    let width = Expr::Binary(
+      BinaryOp::Add,
+      Box::new(Expr::Symbol("start".to_string())),
+      Box::new(Expr::Number(8)),
+    );
+    symbols.define("WIDTH", Symbol::Constant(width), 2).unwrap();
+    symbols
+      .define(
+        "LOOP",
+        Symbol::Constant(Expr::Symbol("LOOP".to_string())),
+        3,
+      )
+      .unwrap();
+    assert_eq!(eval("WIDTH-1", &symbols).unwrap(), 0x207);
+    assert!(symbols.define("start", Symbol::Label(0), 4).is_err());
+    assert!(matches!(
+      eval("nope", &symbols),
+      Err(Error::UndefinedLabel(..))
+    ));
+    assert!(matches!(
+      eval("LOOP", &symbols),
+      Err(Error::RecursiveConstant(..))
+    ));
+    assert!(matches!(
+      eval("1 / (2 - 2)", &symbols),
+      Err(Error::DivisionByZero(1))
+    ));
+    assert!(matches!(
+      eval("(1 + 2", &symbols),
+      Err(Error::InvalidExpression(..))
+    ));
+    assert!(matches!(
+      eval("1 2", &symbols),
+      Err(Error::InvalidExpression(..))
+    ));
+  }
+}
@@ -0,0 +1,116 @@
+use std::{
+  io,
+  path::{Component, Path, PathBuf},
+};
+
+/// Finds and reads the files of `INCLUDE` and `INCBIN`.
+///
+/// `FileSystem` reads them from disk, other implementations can serve them from
+/// memory.
+pub trait FileResolver {
+  /// Finds a file named in an `INCLUDE` or `INCBIN`.
+  ///
+  /// # Arguments
+  ///
+  /// * `name` - The name, as written in the directive.
+  /// * `from` - The file the directive is in, empty for a source without a path.
+  ///
+  /// # Returns
+  ///
+  /// * `Ok(PathBuf)` - The path of the file, the same file must always get the same
+  ///   path as it is used to detect include cycles.
+  /// * `Err(io::Error)` - If there is no such file.
+  fn resolve(&self, name: &str, from: &Path) -> io::Result<PathBuf>;
+
+  /// Reads a file found by `resolve`.
+  ///
+  /// # Arguments
+  ///
+  /// * `path` - The path of the file.
+  ///
+  /// # Returns
+  ///
+  /// * `Ok(Vec<u8>)` - The contents of the file.
+  /// * `Err(io::Error)` - If the file could not be read.
+  fn read(&self, path: &Path) -> io::Result<Vec<u8>>;
+}
+
+/// Reads included files from disk.
+///
+/// A name is looked up next to the file that includes it, then in every include
+/// path in order.
+///
+/// # Fields
+///
+/// * `include_paths` - The directories searched after the one of the including file.
+#[derive(Debug, Clone, Default)]
+pub struct FileSystem {
+  pub include_paths: Vec<PathBuf>,
+}
+
+impl FileSystem {
+  /// Creates a resolver searching the given include paths.
+  ///
+  /// # Arguments
+  ///
+  /// * `include_paths` - The directories searched after the one of the including file.
+  pub fn new(include_paths: Vec<PathBuf>) -> Self {
+    FileSystem { include_paths }
+  }
+}
+
+impl FileResolver for FileSystem {
+  fn resolve(&self, name: &str, from: &Path) -> io::Result<PathBuf> {
+    let here = from.parent().unwrap_or(Path::new(""));
+    std::iter::once(here)
+      .chain(self.include_paths.iter().map(PathBuf::as_path))
+      .map(|dir| normalize(&dir.join(name)))
+      .find(|path| path.is_file())
+      .ok_or_else(|| {
+        io::Error::new(
+          io::ErrorKind::NotFound,
+          format!("{} not found in the include paths", name),
+        )
+      })
+  }
+
+  fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+    std::fs::read(path)
+  }
+}
+
+/// Removes the `.` and `..` components of a path without touching the disk, so a
+/// file reached through different relative paths gets a single name.
+fn normalize(path: &Path) -> PathBuf {
+  let mut normalized = PathBuf::new();
+  for component in path.components() {
+    match component {
+      Component::CurDir => {}
+      Component::ParentDir
+        if matches!(
+          normalized.components().next_back(),
+          Some(Component::Normal(_))
+        ) =>
+      {
+        normalized.pop();
+      }
+      component => normalized.push(component),
+    }
+  }
+  normalized
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn normalize_relative_paths() {
+    assert_eq!(normalize(Path::new("a/./b/../c.8s")), Path::new("a/c.8s"));
+    assert_eq!(
+      normalize(Path::new("../lib/./x.8s")),
+      Path::new("../lib/x.8s")
+    );
+    assert_eq!(normalize(Path::new("/a/b/../../c")), Path::new("/c"));
+  }
+}
@@ -0,0 +1,219 @@
+use super::tokenizer::LIST_DIRECTIVES;
+
+/// Column instructions are indented to.
+const INDENT: usize = 4;
+
+/// Column trailing comments are aligned to, when the code is shorter.
+const COMMENT_COLUMN: usize = 24;
+
+/// Directives that start at the first column, like labels.
+const TOP_LEVEL: [&str; 3] = ["MACRO", "ENDM", "DEFINE"];
+
+/// Formats a source the way `disassemble` writes it.
+///
+/// Labels, constants and macro definitions start at the first column, the other
+/// statements are indented by 4 spaces. Spaces are collapsed, operands are
+/// separated by `, ` and trailing comments are aligned. The rows of a `SPRITE`,
+/// `DB`, `DW`, `TEXT` or `TEXTW` going on after a comma are aligned with its first
+/// operand. Runs of blank lines are collapsed into one. Only spacing changes, the
+/// source assembles to the same bytes.
+///
+/// # Arguments
+///
+/// * `src` - The source.
+///
+/// # Returns
+///
+/// * `String` - The formatted source, formatting it again changes nothing.
+///
+/// # Examples
+///
+/// ```
+/// let source = r8_assembly::format_source("start:  LD V0,1   ; one\n");
+/// assert_eq!(source, "start: LD V0, 1          ; one\n");
+/// ```
+pub fn format_source(src: &str) -> String {
+  let mut formatted = String::new();
+  // The column of the operands of a statement going on in the next line.
+  let mut continuation: Option<usize> = None;
+  let mut blank = false;
+  for line in src.lines() {
+    let (code, comment) = split_comment(line);
+    let code = normalize(code);
+    if code.is_empty() && comment.is_empty() {
+      blank = !formatted.is_empty();
+      continue;
+    }
+    if blank {
+      formatted.push('\n');
+      blank = false;
+    }
+    let indent = match (continuation, code.as_str()) {
+      (Some(column), _) => column,
+      (None, "") if !line.starts_with([' ', '\t']) => 0,
+      (None, "") => INDENT,
+      (None, code) if is_top_level(code) => 0,
+      (None, _) => INDENT,
+    };
+    let mut text = format!("{:indent$}{}", "", code, indent = indent);
+    if !comment.is_empty() {
+      if !code.is_empty() {
+        let width = text.len().max(COMMENT_COLUMN);
+        text = format!("{:<width$} ", text, width = width);
+      }
+      text.push_str(comment);
+    }
+    formatted.push_str(&text);
+    formatted.push('\n');
+    if code.ends_with(',') && (continuation.is_some() || is_list(&code)) {
+      continuation = continuation.or_else(|| Some(indent + operand_column(&code)));
+    } else if !code.is_empty() {
+      continuation = None;
+    }
+  }
+  formatted
+}
+
+/// Splits a line into its code and its comment, the comment with its `;`.
+fn split_comment(line: &str) -> (&str, &str) {
+  let (mut in_string, mut escaped) = (false, false);
+  for (index, char) in line.char_indices() {
+    match char {
+      _ if escaped => escaped = false,
+      '\\' if in_string => escaped = true,
+      '"' => in_string = !in_string,
+      ';' if !in_string => return (&line[..index], line[index..].trim_end()),
+      _ => {}
+    }
+  }
+  (line, "")
+}
+
+/// Collapses the spaces of the code of a line and puts a single space after commas.
+fn normalize(code: &str) -> String {
+  let mut normalized = String::new();
+  let (mut in_string, mut escaped) = (false, false);
+  let code = code.trim();
+  // A label is followed by a space, like the other words.
+  let (label, code) = code.split_at(label_len(code).unwrap_or(0));
+  normalized.push_str(label);
+  let mut space = !label.is_empty();
+  for char in code.chars() {
+    if in_string {
+      normalized.push(char);
+      in_string = char != '"' || escaped;
+      escaped = char == '\\' && !escaped;
+      continue;
+    }
+    match char {
+      char if char.is_whitespace() => space = true,
+      ',' => {
+        normalized.push(',');
+        space = true;
+      }
+      char => {
+        if space && !normalized.is_empty() {
+          normalized.push(' ');
+        }
+        space = false;
+        normalized.push(char);
+        in_string = char == '"';
+      }
+    }
+  }
+  normalized
+}
+
+/// Returns `true` if the statement starts at the first column: a label, a constant
+/// or a macro definition.
+fn is_top_level(code: &str) -> bool {
+  let mut words = code.split(' ');
+  let first = words.next().unwrap_or_default();
+  label_len(code).is_some() || TOP_LEVEL.contains(&first) || words.next() == Some("EQU")
+}
+
+/// Returns `true` if the statement is a directive whose operand list can go on
+/// with the next line.
+fn is_list(code: &str) -> bool {
+  let label = label_len(code).map_or(0, |len| len + 1);
+  let statement = code.get(label..).unwrap_or_default();
+  LIST_DIRECTIVES.contains(&statement.split(' ').next().unwrap_or_default())
+}
+
+/// Returns the length of the label a statement starts with, with its `:`.
+fn label_len(code: &str) -> Option<usize> {
+  let end = code
+    .find(|char: char| !(char.is_ascii_alphanumeric() || char == '_' || char == '.'))
+    .unwrap_or(code.len());
+  let starts = code.starts_with(|char: char| char.is_ascii_alphabetic() || char == '_');
+  (starts && code[end..].starts_with(':')).then_some(end + 1)
+}
+
+/// Returns the column of the first operand of a statement, after its label and
+/// its mnemonic.
+fn operand_column(code: &str) -> usize {
+  let label = label_len(code).map_or(0, |len| len + 1);
+  let statement = code.get(label..).unwrap_or_default();
+  match statement.split_once(' ') {
+    Some((mnemonic, _)) => label + mnemonic.len() + 1,
+    None => label,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn formats_and_keeps_the_bytes() {
+    let source = "\
+; Header
+WIDTH   EQU 8
+
+
+start:LD V0 ,WIDTH;comment
+  DB  \"a  ;b\" , 1
+  TEXT \"say \\\"hi;  \\\"\"  ,#FF ; quoted
+ship:  SPRITE \"XX\",
+   \"..\",
+  ; between rows
+        #FF
+loop: ; forever
+JP loop
+  LD V0,
+  5
+";
+    let formatted = format_source(source);
+    assert_eq!(
+      formatted,
+      "\
+; Header
+WIDTH EQU 8
+
+start: LD V0, WIDTH      ;comment
+    DB \"a  ;b\", 1
+    TEXT \"say \\\"hi;  \\\"\", #FF ; quoted
+ship: SPRITE \"XX\",
+             \"..\",
+             ; between rows
+             #FF
+loop:                    ; forever
+    JP loop
+    LD V0,
+    5
+"
+    );
+    assert_eq!(format_source(&formatted), formatted);
+  }
+
+  #[test]
+  fn pong_keeps_its_bytes() {
+    let source = include_str!("../../assembly_roms/pong.8s");
+    let formatted = format_source(source);
+    assert_eq!(format_source(&formatted), formatted);
+    let (mut before, mut after) = (Vec::new(), Vec::new());
+    crate::assemble(&mut source.as_bytes(), &mut before).unwrap();
+    crate::assemble(&mut formatted.as_bytes(), &mut after).unwrap();
+    assert_eq!(before, after);
+  }
+}
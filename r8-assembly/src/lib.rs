@@ -1,16 +1,70 @@
-use std::io::{Read, Write};
+use std::{
+  collections::HashMap,
+  io::{Read, Write},
+  path::{Path, PathBuf},
+  rc::Rc,
+};
 
-use std::collections::HashMap;
+use self::expression::{Expr, Symbol, Symbols};
+use self::macros::Macro;
+use self::memory_slices::{Field, MemorySlices};
+use self::text::Charset;
+use self::tokenizer::{Line, OwnedLine, OwnedToken, Token};
 
-use self::memory_slices::MemorySlices;
-use self::tokenizer::Line;
+pub use self::error::Warning;
 
+mod disassembler;
 pub mod error;
+mod expression;
+mod files;
+mod format;
+mod listing;
+mod macros;
 mod memory_slices;
+mod sprites;
+mod text;
 mod tokenizer;
 
+pub use disassembler::disassemble;
+pub use files::{FileResolver, FileSystem};
+pub use format::format_source;
+pub use listing::{Assembly, ListingLine, SymbolEntry, SymbolKind};
+
 /// Assembles a CHIP-8 program.
 ///
+/// Anywhere an instruction takes a number it also takes an expression: numbers
+/// (`#` hex, `0b` binary or decimal), labels, constants, `+ - * / % & | ^ << >>`, unary `-` and
+/// `~`, parentheses, and `HI(x)`/`LO(x)` for the high and low byte of a value.
+/// Constants are defined with `NAME EQU expr` or `DEFINE NAME expr`. Labels and
+/// constants can be used before their definition, every operand is evaluated once
+/// the whole program was read. `DB` and `DW` take a comma separated list.
+///
+/// Macros are defined with `MACRO name param, ...` up to `ENDM` and called like
+/// instructions. Labels and constants defined in a macro are local to each of its
+/// calls, and macros cannot be defined inside macros.
+///
+/// `INCLUDE "file.8s"` assembles another source in place and `INCBIN "file.bin"`,
+/// optionally followed by an offset and a length, copies the bytes of a file. The
+/// input has no path, so the files are looked up from the current directory, see
+/// `assemble_file` to look them up next to the source.
+///
+/// The program starts at `0x200`. `ORG addr` moves to another address, `ALIGN n`
+/// pads with zeros up to a multiple of `n`, `DS n` reserves `n` bytes and
+/// `FILL n, byte` repeats a byte. Gaps between the parts of the program are
+/// filled with zeros, parts may not overlap nor go past `0xFFF`.
+///
+/// `SPRITE` takes rows of ASCII art (`"XX..XX.."`) or numbers, and
+/// `INCSPRITE "file.png", width, height` converts a monochrome image into sprites.
+/// The operands of `SPRITE`, `DB`, `DW`, `TEXT` and `TEXTW` go on with the next
+/// line when a line ends with a comma.
+///
+/// `CHARSET "chars"` sets the glyph table of the `TEXT` directives after it, each
+/// character is written as its index in `chars`. `CHARSET "chars", first, step`
+/// writes `first + index * step` instead, like the offset of glyphs `step` bytes
+/// long. `TEXT "HELLO", #FF` writes strings through the charset and numbers as
+/// they are, one byte each. `TEXTW` writes words instead, like the addresses of
+/// glyphs. `\"` and `\\` write a quote and a backslash in their strings.
+///
 /// # Arguments
 ///
 /// * `input` - The input stream to read from.
@@ -18,7 +72,9 @@ mod tokenizer;
 ///
 /// # Returns
 ///
-/// * `Ok(())` - If the program was successfully assembled.
+/// * `Ok(Assembly)` - If the program was successfully assembled: the suspicious
+///   lines found, like instructions at odd addresses, the listing and the symbols.
+///   Before 0.3.0 it was `Ok(())`.
 /// * `Err(Error)` - If the program was not successfully assembled.
 ///
 /// # Examples
@@ -34,245 +90,1277 @@ mod tokenizer;
 ///    Err(err) => println!("{}", err),
 /// }
 /// ```
-pub fn assemble<R: Read, W: Write>(input: &mut R, output: &mut W) -> Result<(), error::Error> {
+pub fn assemble<R: Read, W: Write>(
+  input: &mut R,
+  output: &mut W,
+) -> Result<Assembly, error::Error> {
   let mut src = String::new();
   input.read_to_string(&mut src)?;
-  let tokenizer = tokenizer::Tokenizer::new(&src);
-  let mut address = 0x200;
-  let mut labels: HashMap<&str, u16> = HashMap::new();
-  let slices = tokenizer
-    .map(|src_line| match src_line {
-      Ok(src_line) => cast_line(src_line, &mut address, &mut labels),
-      Err(err) => Err(err),
-    })
-    .collect::<Vec<Result<MemorySlices, error::Error>>>();
-  for result in slices {
-    let slice = result?;
-    slice.write(&labels, output)?;
+  assemble_source(&src, Path::new(""), &FileSystem::default(), output)
+}
+
+/// Assembles a CHIP-8 program from a file, see `assemble` for the syntax.
+///
+/// # Arguments
+///
+/// * `path` - The path of the source, given to `files` to read it.
+/// * `files` - Reads the source and the files it includes.
+/// * `output` - The output stream to write to.
+///
+/// # Returns
+///
+/// * `Ok(Assembly)` - If the program was successfully assembled.
+/// * `Err(Error)` - If the program was not successfully assembled, the errors in
+///   a file are wrapped in `Error::InFile`.
+///
+/// # Examples
+///
+/// ```ignore
+/// let mut rom = Vec::new();
+/// let files = r8_assembly::FileSystem::new(vec!["lib".into()]);
+/// r8_assembly::assemble_file("game.8s".as_ref(), &files, &mut rom)?;
+/// ```
+pub fn assemble_file<F: FileResolver, W: Write>(
+  path: &Path,
+  files: &F,
+  output: &mut W,
+) -> Result<Assembly, error::Error> {
+  let src = files
+    .read(path)
+    .and_then(|src| String::from_utf8(src).map_err(std::io::Error::other))
+    .map_err(|err| error::Error::InFile(path.display().to_string(), Box::new(err.into())))?;
+  assemble_source(&src, path, files, output)
+}
+
+/// Assembles the source of the file at `path`.
+fn assemble_source<W: Write>(
+  src: &str,
+  path: &Path,
+  files: &dyn FileResolver,
+  output: &mut W,
+) -> Result<Assembly, error::Error> {
+  // First pass: give every label its address and keep the operands unevaluated.
+  let mut assembler = Assembler::new(files);
+  let file: Rc<Path> = path.into();
+  assembler.including.push(file.clone());
+  assembler.add_source(&file, src);
+  let lines = tokenizer::Tokenizer::new(src).map(|line| line.map(|line| (&line).into()));
+  assembler.lines(lines, &file, &[])?;
+  // Second pass: every symbol is known, evaluate the operands.
+  let image = assembler.image()?;
+  output.write_all(&image)?;
+
+  Ok(assembler.assembly(&image))
+}
+
+/// The address programs are loaded at, the image starts there.
+const PROGRAM_START: usize = 0x200;
+
+/// The first address past the 4 KiB of memory.
+const MEMORY_END: usize = 0x1000;
+
+/// A macro call a line was expanded from: the name of the macro, and the file and
+/// line of the call.
+type Call = (String, Rc<Path>, usize);
+
+/// Where a line comes from.
+///
+/// # Fields
+///
+/// * `file` - The file the line is in.
+/// * `line` - The line number in the file.
+/// * `end` - The last line, after the rows of an operand list going on.
+/// * `calls` - The macro calls the line was expanded from, outermost first.
+struct Origin {
+  file: Rc<Path>,
+  line: usize,
+  end: usize,
+  calls: Vec<Call>,
+}
+
+/// Lines a statement makes the assembler read before the next one.
+enum Nested {
+  /// The expansion of a macro call and where the call is.
+  Macro(Vec<OwnedLine>, Rc<Path>, Call),
+  /// The path and source of an included file.
+  Include(Rc<Path>, String),
+}
+
+/// The state of the first pass.
+///
+/// # Fields
+///
+/// * `files` - Reads the included files.
+/// * `including` - The files being assembled, the innermost last.
+/// * `address` - The address the next line is assembled at.
+/// * `symbols` - The labels and constants defined so far.
+/// * `constants` - The file and line every constant is defined at.
+/// * `macros` - The macros defined so far.
+/// * `expansions` - How many macro calls were expanded, numbers their local labels.
+/// * `charset` - The glyph table of `TEXT`, from the last `CHARSET`.
+/// * `sources` - The lines of every file read, for the listing.
+/// * `slices` - What every line assembles to, at which address, and where the
+///   line comes from.
+/// * `warnings` - The suspicious lines found so far.
+struct Assembler<'files> {
+  files: &'files dyn FileResolver,
+  including: Vec<Rc<Path>>,
+  address: u16,
+  symbols: Symbols,
+  constants: HashMap<String, (Rc<Path>, usize)>,
+  macros: HashMap<String, Macro>,
+  expansions: usize,
+  charset: Option<Charset>,
+  sources: HashMap<Rc<Path>, Vec<String>>,
+  slices: Vec<(u16, MemorySlices, Origin)>,
+  warnings: Vec<Warning>,
+}
+
+impl<'files> Assembler<'files> {
+  fn new(files: &'files dyn FileResolver) -> Self {
+    Assembler {
+      files,
+      including: Vec::new(),
+      address: 0x200,
+      symbols: Symbols::default(),
+      constants: HashMap::new(),
+      macros: HashMap::new(),
+      expansions: 0,
+      charset: None,
+      sources: HashMap::new(),
+      slices: Vec::new(),
+      warnings: Vec::new(),
+    }
+  }
+
+  /// Assembles a sequence of lines: a source file or the expansion of a macro call.
+  ///
+  /// # Arguments
+  ///
+  /// * `lines` - The lines.
+  /// * `file` - The file the lines are in.
+  /// * `calls` - The macro calls the lines were expanded from, outermost first.
+  ///
+  /// # Returns
+  ///
+  /// * `Ok(())` - If every line was assembled.
+  /// * `Err(Error)` - The first error, wrapped with where its line comes from.
+  fn lines<I>(&mut self, lines: I, file: &Rc<Path>, calls: &[Call]) -> Result<(), error::Error>
+  where
+    I: IntoIterator<Item = Result<OwnedLine, error::Error>>,
+  {
+    let context = |err| error::Error::in_context(err, file, calls);
+    // The macro being defined and its name.
+    let mut defining: Option<(String, Macro)> = None;
+    for line in lines {
+      let line = line.map_err(context)?;
+      if let Some((name, definition)) = &mut defining {
+        match line.tokens.first() {
+          // A macro defined by a macro would be defined again by every call.
+          Some(OwnedToken::Identifier(id)) if id == "MACRO" => {
+            return Err(context(error::Error::NestedMacro(name.clone(), line.line)));
+          }
+          Some(OwnedToken::Identifier(id)) if id == "ENDM" => {
+            let (name, definition) = defining.take().unwrap();
+            self.macros.insert(name, definition);
+            continue;
+          }
+          _ => {}
+        }
+        definition.push(line);
+        continue;
+      }
+      match line.to_line().tokens.as_slice() {
+        [Token::Identifier("MACRO"), Token::Identifier(name), params @ ..] => {
+          if self.macros.contains_key(*name) {
+            return Err(context(error::Error::DuplicateMacro(
+              name.to_string(),
+              line.line,
+            )));
+          }
+          let params = split_operands(params)
+            .into_iter()
+            .map(|param| match param {
+              [Token::Identifier(param)] => Ok(param.to_string()),
+              _ => Err(error::Error::InvalidLine(line.clone())),
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(context)?;
+          let definition = Macro::new(params, file.clone(), line.line);
+          defining = Some((name.to_string(), definition));
+        }
+        _ => self.line(&line, file, calls)?,
+      }
+    }
+    match defining {
+      Some((name, definition)) => Err(context(error::Error::UnterminatedMacro(
+        name,
+        definition.line(),
+      ))),
+      None => Ok(()),
+    }
+  }
+
+  /// Assembles one line, and the lines of the macro call or the file it includes.
+  fn line(
+    &mut self,
+    line: &OwnedLine,
+    file: &Rc<Path>,
+    calls: &[Call],
+  ) -> Result<(), error::Error> {
+    let nested = self
+      .statement(line, file, calls)
+      .map_err(|err| error::Error::in_context(err, file, calls))?;
+    match nested {
+      None => Ok(()),
+      Some(Nested::Macro(lines, definition_file, call)) => {
+        let mut inner = calls.to_vec();
+        inner.push(call);
+        self.lines(lines.into_iter().map(Ok), &definition_file, &inner)
+      }
+      Some(Nested::Include(path, src)) => {
+        self.including.push(path.clone());
+        self.add_source(&path, &src);
+        let lines = tokenizer::Tokenizer::new(&src).map(|line| line.map(|line| (&line).into()));
+        let result = self.lines(lines, &path, calls);
+        self.including.pop();
+        result
+      }
+    }
+  }
+
+  /// Assembles a statement: defines its label or constant, and records the
+  /// instruction or data it assembles to.
+  ///
+  /// # Returns
+  ///
+  /// * `Ok(Some(Nested))` - If the statement calls a macro or includes a file.
+  /// * `Ok(None)` - If the statement was assembled.
+  /// * `Err(Error)` - If the statement is invalid, with its line in `file`.
+  fn statement(
+    &mut self,
+    line: &OwnedLine,
+    file: &Rc<Path>,
+    calls: &[Call],
+  ) -> Result<Option<Nested>, error::Error> {
+    let view = line.to_line();
+    let mut tokens = view.tokens.as_slice();
+    if tokens.is_empty() {
+      return Ok(None);
+    }
+    match tokens {
+      [Token::Label(name), Token::Identifier("EQU"), value @ ..]
+      | [Token::Identifier(name), Token::Identifier("EQU"), value @ ..]
+      | [Token::Identifier("DEFINE"), Token::Identifier(name), value @ ..] => {
+        let value = Expr::parse(value, line.line)?;
+        self
+          .symbols
+          .define(name, Symbol::Constant(value), line.line)?;
+        self
+          .constants
+          .insert(name.to_string(), (file.clone(), line.line));
+        return Ok(None);
+      }
+      [Token::Label(name), rest @ ..] => {
+        self
+          .symbols
+          .define(name, Symbol::Label(self.address), line.line)?;
+        tokens = rest;
+      }
+      _ => {}
+    }
+    let slice = match tokens {
+      [Token::Identifier(name), args @ ..] if self.macros.contains_key(*name) => {
+        if calls.len() == macros::MAX_DEPTH {
+          return Err(error::Error::MacroDepth(name.to_string(), line.line));
+        }
+        let args = split_operands(args)
+          .into_iter()
+          .map(|arg| arg.iter().map(OwnedToken::from).collect())
+          .collect::<Vec<_>>();
+        self.expansions += 1;
+        let definition = &self.macros[*name];
+        let lines = definition.expand(name, &args, self.expansions, line.line)?;
+        let call = (name.to_string(), file.clone(), line.line);
+        return Ok(Some(Nested::Macro(lines, definition.file().clone(), call)));
+      }
+      [Token::Identifier("INCLUDE"), Token::Str(name)] => {
+        let (path, src) = self.read(name, file, line.line)?;
+        if self.including.contains(&path) {
+          return Err(error::Error::IncludeCycle(name.to_string(), line.line));
+        }
+        let src = String::from_utf8(src).map_err(|err| {
+          error::Error::Include(name.to_string(), std::io::Error::other(err), line.line)
+        })?;
+        return Ok(Some(Nested::Include(path, src)));
+      }
+      [Token::Identifier("INCBIN"), Token::Str(name), range @ ..] => {
+        let (_, data) = self.read(name, file, line.line)?;
+        MemorySlices::Raw(self.incbin(name, data, range, &view)?)
+      }
+      [Token::Identifier("SPRITE"), rows @ ..] => {
+        let rows = split_operands(rows);
+        sprites::check_height(rows.len() as i64, line.line)?;
+        let rows = rows
+          .into_iter()
+          .map(|row| match row {
+            [Token::Str(row)] => Ok(Expr::Number(sprites::ascii_row(row, line.line)? as i64)),
+            row => Expr::parse(row, line.line),
+          })
+          .collect::<Result<Vec<_>, _>>()?;
+        MemorySlices::Bytes(rows)
+      }
+      [Token::Identifier("INCSPRITE"), Token::Str(name), Token::Comma, size @ ..] => {
+        let [width, height] = split_operands(size)[..] else {
+          return Err(error::Error::InvalidLine(line.clone()));
+        };
+        let (width, height) = (
+          self.eval_now(width, line.line)?,
+          self.eval_now(height, line.line)?,
+        );
+        let (_, png) = self.read(name, file, line.line)?;
+        MemorySlices::Raw(sprites::import_png(name, &png, width, height, line.line)?)
+      }
+      [Token::Identifier("CHARSET"), Token::Str(chars), values @ ..] => {
+        let (first, step) = match values {
+          [] => (Expr::Number(0), Expr::Number(1)),
+          [Token::Comma, values @ ..] => match split_operands(values).as_slice() {
+            [first] => (Expr::parse(first, line.line)?, Expr::Number(1)),
+            [first, step] => (
+              Expr::parse(first, line.line)?,
+              Expr::parse(step, line.line)?,
+            ),
+            _ => return Err(error::Error::InvalidLine(line.clone())),
+          },
+          _ => return Err(error::Error::InvalidLine(line.clone())),
+        };
+        self.charset = Some(Charset::new(
+          &text::unescape(chars),
+          first,
+          step,
+          line.line,
+        )?);
+        return Ok(None);
+      }
+      [Token::Identifier(directive @ ("TEXT" | "TEXTW")), operands @ ..]
+        if !operands.is_empty() =>
+      {
+        let mut values = Vec::new();
+        for operand in split_operands(operands) {
+          match (operand, &self.charset) {
+            ([Token::Str(string)], Some(charset)) => {
+              values.extend(charset.encode(&text::unescape(string), line.line)?)
+            }
+            ([Token::Str(_)], None) => {
+              let msg = format!("{} needs a CHARSET before it", directive);
+              return Err(error::Error::InvalidCharset(msg, line.line));
+            }
+            (value, _) => values.push(Expr::parse(value, line.line)?),
+          }
+        }
+        match *directive {
+          "TEXTW" => MemorySlices::Words(values),
+          _ => MemorySlices::Bytes(values),
+        }
+      }
+      [Token::Identifier("ORG"), address @ ..] => {
+        let address = self.eval_now(address, line.line)?;
+        if !(PROGRAM_START as i64..=MEMORY_END as i64).contains(&address) {
+          return Err(error::Error::InvalidOrigin(address, line.line));
+        }
+        self.address = address as u16;
+        return Ok(None);
+      }
+      [Token::Identifier("ALIGN"), alignment @ ..] => match self.eval_now(alignment, line.line)? {
+        alignment @ 1..=0x1000 => {
+          let padding = (alignment - self.address as i64).rem_euclid(alignment);
+          MemorySlices::Fill(padding as usize, Expr::Number(0))
+        }
+        alignment => return Err(error::Error::InvalidCount(alignment, line.line)),
+      },
+      [Token::Identifier("DS"), count @ ..] => MemorySlices::Reserve(self.count(count, line.line)?),
+      [Token::Identifier("FILL"), operands @ ..] => match split_operands(operands).as_slice() {
+        [count, value] => {
+          let count = self.count(count, line.line)?;
+          MemorySlices::Fill(count, Expr::parse(value, line.line)?)
+        }
+        _ => return Err(error::Error::InvalidLine(line.clone())),
+      },
+      tokens => cast_line(tokens, &view)?,
+    };
+    let address = self.address;
+    if address as usize + slice.len() > MEMORY_END {
+      return Err(error::Error::OutOfMemory(address, line.line));
+    }
+    if matches!(slice, MemorySlices::Opcode(..)) && address % 2 == 1 {
+      self.warnings.push(Warning {
+        file: file.display().to_string(),
+        line: line.line,
+        message: format!("instruction at the odd address #{:03X}", address),
+      });
+    }
+    self.address += slice.len() as u16;
+    let origin = Origin {
+      file: file.clone(),
+      line: line.line,
+      end: line.end,
+      calls: calls.to_vec(),
+    };
+    self.slices.push((address, slice, origin));
+    Ok(None)
+  }
+
+  /// Evaluates an operand during the first pass, it can only use the symbols
+  /// defined before it.
+  fn eval_now(&self, tokens: &[Token<'_>], line: usize) -> Result<i64, error::Error> {
+    Expr::parse(tokens, line)?.eval(&self.symbols, line)
+  }
+
+  /// Evaluates the number of bytes of a `DS` or `FILL`.
+  fn count(&self, tokens: &[Token<'_>], line: usize) -> Result<usize, error::Error> {
+    match self.eval_now(tokens, line)? {
+      count @ 0..=0x1000 => Ok(count as usize),
+      count => Err(error::Error::InvalidCount(count, line)),
+    }
+  }
+
+  /// Evaluates the operands and lays the slices out in an image of the memory
+  /// from `PROGRAM_START`, up to the last byte assembled.
+  ///
+  /// # Returns
+  ///
+  /// * `Ok(Vec<u8>)` - The image, with zeros in the gaps.
+  /// * `Err(Error)` - If two slices overlap or an operand is invalid.
+  fn image(&self) -> Result<Vec<u8>, error::Error> {
+    let context = |err, origin: &Origin| error::Error::in_context(err, &origin.file, &origin.calls);
+    let mut placed = (0..self.slices.len())
+      .filter(|&index| self.slices[index].1.len() > 0)
+      .collect::<Vec<_>>();
+    placed.sort_by_key(|&index| self.slices[index].0);
+    for pair in placed.windows(2) {
+      let (first, second) = (&self.slices[pair[0]], &self.slices[pair[1]]);
+      if first.0 as usize + first.1.len() > second.0 as usize {
+        // Blame the slice that comes last in the source.
+        let (address, origin) = match pair[0] < pair[1] {
+          true => (second.0, &second.2),
+          false => (first.0, &first.2),
+        };
+        return Err(context(error::Error::Overlap(address, origin.line), origin));
+      }
+    }
+    let end = self
+      .slices
+      .iter()
+      .filter(|(_, slice, _)| !matches!(slice, MemorySlices::Reserve(_)))
+      .map(|(address, slice, _)| *address as usize + slice.len())
+      .max()
+      .unwrap_or(PROGRAM_START);
+    let mut image = vec![0; end.saturating_sub(PROGRAM_START)];
+    let mut bytes = Vec::new();
+    for (address, slice, origin) in &self.slices {
+      if matches!(slice, MemorySlices::Reserve(_)) || slice.len() == 0 {
+        continue;
+      }
+      bytes.clear();
+      slice
+        .write(&self.symbols, origin.line, &mut bytes)
+        .map_err(|err| context(err, origin))?;
+      let start = *address as usize - PROGRAM_START;
+      image[start..start + bytes.len()].copy_from_slice(&bytes);
+    }
+    Ok(image)
+  }
+
+  /// Keeps the lines of a file for the listing.
+  fn add_source(&mut self, file: &Rc<Path>, src: &str) {
+    let lines = src.lines().map(str::to_string).collect();
+    self.sources.insert(file.clone(), lines);
+  }
+
+  /// Gathers the listing and the symbols once the image is assembled.
+  ///
+  /// # Arguments
+  ///
+  /// * `image` - The image from `image`, the bytes of the listing come from it.
+  fn assembly(mut self, image: &[u8]) -> Assembly {
+    let lines = self
+      .slices
+      .iter()
+      .map(|(address, slice, origin)| {
+        let start = *address as usize - PROGRAM_START;
+        let bytes = match slice {
+          MemorySlices::Reserve(_) => &[][..],
+          slice => image.get(start..start + slice.len()).unwrap_or_default(),
+        };
+        let source = self
+          .sources
+          .get(&origin.file)
+          .and_then(|lines| lines.get(origin.line - 1..origin.end))
+          .unwrap_or_default()
+          .iter()
+          .map(|line| line.trim_end())
+          .collect::<Vec<_>>()
+          .join("\n");
+        ListingLine {
+          address: *address,
+          bytes: bytes.to_vec(),
+          file: origin.file.display().to_string(),
+          line: origin.line,
+          source: source.to_string(),
+        }
+      })
+      .collect();
+    let mut symbols = Vec::new();
+    let mut unused = Vec::new();
+    for (name, symbol) in self.symbols.iter() {
+      let (value, kind) = match symbol {
+        Symbol::Label(address) => (*address as i64, SymbolKind::Label),
+        Symbol::Constant(expr) => {
+          let (file, line) = &self.constants[name];
+          match expr.eval(&self.symbols, *line) {
+            Ok(value) => (value, SymbolKind::Constant),
+            // A constant no line uses may not evaluate, it has no value to show.
+            Err(err) => {
+              unused.push(Warning {
+                file: file.display().to_string(),
+                line: *line,
+                message: format!("constant {} has no value: {}", name, err),
+              });
+              continue;
+            }
+          }
+        }
+      };
+      symbols.push(SymbolEntry {
+        name: name.to_string(),
+        value,
+        kind,
+      });
+    }
+    unused.sort_by(|a, b| (&a.file, a.line).cmp(&(&b.file, b.line)));
+    self.warnings.extend(unused);
+    symbols.sort_by(|a, b| (a.value, &a.name).cmp(&(b.value, &b.name)));
+    Assembly {
+      warnings: self.warnings,
+      lines,
+      symbols,
+    }
+  }
+
+  /// Finds and reads the file `name` of an `INCLUDE` or `INCBIN` in `file`.
+  fn read(
+    &self,
+    name: &str,
+    file: &Path,
+    line: usize,
+  ) -> Result<(Rc<Path>, Vec<u8>), error::Error> {
+    let err = |err| error::Error::Include(name.to_string(), err, line);
+    let path: PathBuf = self.files.resolve(name, file).map_err(err)?;
+    let data = self.files.read(&path).map_err(err)?;
+    Ok((path.into(), data))
+  }
+
+  /// Returns the bytes of an `INCBIN`.
+  ///
+  /// # Arguments
+  ///
+  /// * `name` - The file, for errors.
+  /// * `data` - Its contents.
+  /// * `range` - The tokens after the file name: nothing, `, offset` or
+  ///   `, offset, length`. They can only use the symbols defined before.
+  /// * `line` - The line of the directive.
+  fn incbin(
+    &self,
+    name: &str,
+    mut data: Vec<u8>,
+    range: &[Token<'_>],
+    line: &Line<'_>,
+  ) -> Result<Vec<u8>, error::Error> {
+    let range = match range {
+      [] => Vec::new(),
+      [Token::Comma, range @ ..] => split_operands(range),
+      _ => return Err(error::Error::InvalidLine(line.into())),
+    };
+    let mut values = Vec::with_capacity(range.len());
+    for tokens in &range {
+      values.push(Expr::parse(tokens, line.line)?.eval(&self.symbols, line.line)?);
+    }
+    let size = data.len() as i64;
+    let (offset, length) = match values.as_slice() {
+      [] => (0, size),
+      &[offset] => (offset, size.saturating_sub(offset)),
+      &[offset, length] => (offset, length),
+      _ => return Err(error::Error::InvalidLine(line.into())),
+    };
+    let end = match offset.checked_add(length) {
+      Some(end) if offset >= 0 && length >= 0 && end <= size => end,
+      _ => return Err(error::Error::IncbinRange(name.to_string(), line.line)),
+    };
+    data.truncate(end as usize);
+    data.drain(..offset as usize);
+    Ok(data)
+  }
+}
+
+/// Splits the operands of an instruction or directive at the commas.
+fn split_operands<'line, 'src>(tokens: &'line [Token<'src>]) -> Vec<&'line [Token<'src>]> {
+  if tokens.is_empty() {
+    return Vec::new();
   }
+  tokens
+    .split(|token| matches!(token, Token::Comma))
+    .collect()
+}
 
-  Ok(())
+/// An operand of an instruction.
+enum Operand<'src> {
+  /// `V0` to `VF`.
+  Register(u8),
+  /// One of `KEYWORDS`.
+  Keyword(&'src str),
+  /// Anything else, an expression.
+  Value(Expr),
+}
+
+/// The operand names that are not expressions.
+const KEYWORDS: [&str; 7] = ["I", "DT", "ST", "K", "F", "B", "[I]"];
+
+impl<'src> Operand<'src> {
+  /// Parses the tokens between two commas of an instruction.
+  fn parse(tokens: &[Token<'src>], line: usize) -> Result<Self, error::Error> {
+    match tokens {
+      &[Token::Register(reg)] => Ok(Operand::Register(reg)),
+      &[Token::Identifier(keyword)] if KEYWORDS.contains(&keyword) => Ok(Operand::Keyword(keyword)),
+      tokens => Ok(Operand::Value(Expr::parse(tokens, line)?)),
+    }
+  }
 }
 
 /// Converts a line of Tokens into a Raw opcode
 ///
 /// # Arguments
 ///
-/// * `line` - The line to convert
-/// * `address` - The current address of the line
-/// * `labels` - The labels defined in the program
+/// * `tokens` - The instruction or data directive, without the label before it
+/// * `line` - The whole line, for errors
 ///
 /// # Returns
 ///
 /// * `Ok(MemorySlices)` - The memory slices that make up the line
 /// * `Err(Error)` - If there was an error converting the line
 #[allow(clippy::match_ref_pats)]
-fn cast_line<'src>(
-  line: Line<'src>,
-  address: &mut u16,
-  labels: &mut HashMap<&'src str, u16>,
-) -> Result<MemorySlices<'src>, error::Error> {
+fn cast_line(tokens: &[Token<'_>], line: &Line<'_>) -> Result<MemorySlices, error::Error> {
   // Match macro helpers
   macro_rules! id {
-    ($id:ident) => {
-      tokenizer::Token::Identifier($id)
-    };
-    ($id:expr) => {
-      tokenizer::Token::Identifier($id)
-    };
-  }
-  macro_rules! register {
-    ($reg:ident) => {
-      tokenizer::Token::Register($reg)
-    };
-    (0) => {
-      tokenizer::Token::Register(0)
+    ($id:pat) => {
+      Token::Identifier($id)
     };
   }
-  macro_rules! num {
-    ($num:ident) => {
-      tokenizer::Token::Number($num)
+  macro_rules! reg {
+    ($reg:pat) => {
+      Operand::Register($reg)
     };
   }
-  macro_rules! label {
-    ($label:ident) => {
-      tokenizer::Token::Label($label)
+  macro_rules! kw {
+    ($kw:pat) => {
+      Operand::Keyword($kw)
     };
   }
-  macro_rules! comma {
-    () => {
-      tokenizer::Token::Comma
+  macro_rules! value {
+    ($value:ident) => {
+      Operand::Value(ref $value)
     };
   }
 
-  // Num checkers
-  macro_rules! addr {
-    ($num:expr) => {{
-      if $num > 0x0FFF {
-        return Err(error::Error::InvalidAddress($num, line.line));
-      }
-      $num
-    }};
-  }
-  macro_rules! byte {
-    ($num:expr) => {{
-      if $num > 0xFF {
-        return Err(error::Error::InvalidByte($num as u16, line.line));
-      }
-      $num as u8
-    }};
+  // Opcode helpers
+  macro_rules! op {
+    ($opcode:expr) => {
+      MemorySlices::Opcode($opcode, None)
+    };
   }
-  macro_rules! nibble {
-    ($num:expr) => {{
-      if $num > 0xF {
-        return Err(error::Error::InvalidNibble($num as u16, line.line));
-      }
-      $num as u8
-    }};
+  macro_rules! op_with {
+    ($opcode:expr, $field:ident, $value:expr) => {
+      MemorySlices::Opcode($opcode, Some((Field::$field, $value.clone())))
+    };
   }
+  let vx = |x: u8| (x as u16) << 8;
+  let vy = |y: u8| (y as u16) << 4;
 
-  // Opcode helpers
-  macro_rules! op_sxyn {
-    ($s:expr, $x:expr, $y:expr, $n:expr) => {{
-      *address += 2;
-      let (s, x, y, n) = (nibble!($s), nibble!($x), nibble!($y), nibble!($n));
-      Ok(MemorySlices::Opcode(
-        ((s as u16) << 12) | ((x as u16) << 8) | ((y as u16) << 4) | n as u16,
-      ))
-    }};
-  }
-
-  macro_rules! op_snnn {
-    ($s:expr, $nnn:expr) => {{
-      *address += 2;
-      let (s, nnn) = (nibble!($s), addr!($nnn));
-      Ok(MemorySlices::Opcode(((s as u16) << 12) | nnn))
-    }};
-  }
-
-  macro_rules! op_sxkk {
-    ($s:expr, $x:expr, $kk:expr) => {{
-      *address += 2;
-      let (s, x, kk) = ($s, $x, byte!($kk));
-      Ok(MemorySlices::Opcode(
-        ((s as u16) << 12) | ((x as u16) << 8) | kk as u16,
-      ))
-    }};
-  }
-
-  macro_rules! op_slabel {
-    ($s:expr, $label:ident) => {{
-      *address += 2;
-      if labels.contains_key($label) {
-        let addr = labels[$label];
-        Ok(MemorySlices::Opcode((($s as u16) << 12) | addr))
-      } else {
-        Ok(MemorySlices::Pending($s, $label, line.line))
-      }
-    }};
-  }
+  let (mnemonic, operands) = match tokens {
+    &[] => return Ok(MemorySlices::Empty),
+    &[id!(mnemonic), ref operands @ ..] => (
+      mnemonic,
+      split_operands(operands)
+        .into_iter()
+        .map(|tokens| Operand::parse(tokens, line.line))
+        .collect::<Result<Vec<_>, _>>()?,
+    ),
+    _ => return Err(error::Error::InvalidLine(line.into())),
+  };
 
-  match line.tokens.as_slice() {
-    &[] => Ok(MemorySlices::Empty),
-    &[label!(label)] => {
-      if labels.contains_key(label) {
-        return Err(error::Error::DuplicateLabel(label.to_string(), line.line));
-      }
-      labels.insert(label, *address);
-      Ok(MemorySlices::Empty)
-    }
+  Ok(match (mnemonic, operands.as_slice()) {
     // 00E0 - CLS
-    &[id!("CLS")] => op_sxyn!(0x0, 0x0, 0xE, 0x0),
+    ("CLS", &[]) => op!(0x00E0),
     // 00EE - RET
-    &[id!("RET")] => op_sxyn!(0x0, 0x0, 0xE, 0xE),
+    ("RET", &[]) => op!(0x00EE),
     // 0NNN - SYS NNN
-    &[id!("SYS"), num!(addr)] => op_snnn!(0x00, addr),
-    // 0NNN - SYS :label
-    &[id!("SYS"), id!(lb)] => op_slabel!(0x0, lb),
+    ("SYS", &[value!(nnn)]) => op_with!(0x0000, Address, nnn),
     // 1NNN - JP NNN
-    &[id!("JP"), num!(addr)] => op_snnn!(0x1, addr),
-    // 1NNN - JP :label
-    &[id!("JP"), id!(lb)] => op_slabel!(0x1, lb),
+    ("JP", &[value!(nnn)]) => op_with!(0x1000, Address, nnn),
     // 2NNN - CALL NNN
-    &[id!("CALL"), num!(addr)] => op_snnn!(0x2, addr),
-    // 2NNN - CALL :label
-    &[id!("CALL"), id!(lb)] => op_slabel!(0x2, lb),
+    ("CALL", &[value!(nnn)]) => op_with!(0x2000, Address, nnn),
     // 3XKK - SE VX, KK
-    &[id!("SE"), register!(x), comma!(), num!(kk)] => op_sxkk!(0x3, x, kk),
+    ("SE", &[reg!(x), value!(kk)]) => op_with!(0x3000 | vx(x), Byte, kk),
     // 4XKK - SNE VX, KK
-    &[id!("SNE"), register!(x), comma!(), num!(kk)] => op_sxkk!(0x4, x, kk),
+    ("SNE", &[reg!(x), value!(kk)]) => op_with!(0x4000 | vx(x), Byte, kk),
     // 5XY0 - SE VX, VY
-    &[id!("SE"), register!(x), comma!(), register!(y)] => op_sxyn!(0x5, x, y, 0x0),
+    ("SE", &[reg!(x), reg!(y)]) => op!(0x5000 | vx(x) | vy(y)),
     // 6XKK - LD VX, KK
-    &[id!("LD"), register!(x), comma!(), num!(kk)] => op_sxkk!(0x6, x, kk),
+    ("LD", &[reg!(x), value!(kk)]) => op_with!(0x6000 | vx(x), Byte, kk),
     // 7XKK - ADD VX, KK
-    &[id!("ADD"), register!(x), comma!(), num!(kk)] => op_sxkk!(0x7, x, kk),
+    ("ADD", &[reg!(x), value!(kk)]) => op_with!(0x7000 | vx(x), Byte, kk),
     // 8XY0 - LD VX, VY
-    &[id!("LD"), register!(x), comma!(), register!(y)] => op_sxyn!(0x8, x, y, 0x00),
+    ("LD", &[reg!(x), reg!(y)]) => op!(0x8000 | vx(x) | vy(y)),
     // 8XY1 - OR VX, VY
-    &[id!("OR"), register!(x), comma!(), register!(y)] => op_sxyn!(0x8, x, y, 0x01),
+    ("OR", &[reg!(x), reg!(y)]) => op!(0x8001 | vx(x) | vy(y)),
     // 8XY2 - AND VX, VY
-    &[id!("AND"), register!(x), comma!(), register!(y)] => op_sxyn!(0x8, x, y, 0x02),
+    ("AND", &[reg!(x), reg!(y)]) => op!(0x8002 | vx(x) | vy(y)),
     // 8XY3 - XOR VX, VY
-    &[id!("XOR"), register!(x), comma!(), register!(y)] => op_sxyn!(0x8, x, y, 0x03),
+    ("XOR", &[reg!(x), reg!(y)]) => op!(0x8003 | vx(x) | vy(y)),
     // 8XY4 - ADD VX, VY
-    &[id!("ADD"), register!(x), comma!(), register!(y)] => op_sxyn!(0x8, x, y, 0x04),
+    ("ADD", &[reg!(x), reg!(y)]) => op!(0x8004 | vx(x) | vy(y)),
     // 8XY5 - SUB VX, VY
-    &[id!("SUB"), register!(x), comma!(), register!(y)] => op_sxyn!(0x8, x, y, 0x05),
+    ("SUB", &[reg!(x), reg!(y)]) => op!(0x8005 | vx(x) | vy(y)),
     // 8XY6 - SHR VX, VY
-    &[id!("SHR"), register!(x)] => op_sxyn!(0x8, x, 0x1, 0x06),
+    ("SHR", &[reg!(x)]) => op!(0x8006 | vx(x) | vy(0x1)),
     // 8XY7 - SUBN VX, VY
-    &[id!("SUBN"), register!(x), comma!(), register!(y)] => {
-      op_sxyn!(0x8, x, y, 0x07)
-    }
+    ("SUBN", &[reg!(x), reg!(y)]) => op!(0x8007 | vx(x) | vy(y)),
     // 8XYE - SHL VX, VY
-    &[id!("SHL"), register!(x)] => op_sxyn!(0x8, x, 0x0, 0x0E),
+    ("SHL", &[reg!(x)]) => op!(0x800E | vx(x)),
     // 9XY0 - SNE VX, VY
-    &[id!("SNE"), register!(x), comma!(), register!(y)] => op_sxyn!(0x9, x, y, 0x00),
+    ("SNE", &[reg!(x), reg!(y)]) => op!(0x9000 | vx(x) | vy(y)),
     // ANNN - LD I, NNN
-    &[id!("LD"), id!("I"), comma!(), num!(addr)] => op_snnn!(0xA, addr),
-    // ANNN - LD I, :label
-    &[id!("LD"), id!("I"), comma!(), id!(lb)] => op_slabel!(0xA, lb),
+    ("LD", &[kw!("I"), value!(nnn)]) => op_with!(0xA000, Address, nnn),
     // BNNN - JP V0, NNN
-    &[id!("JP"), register!(0), comma!(), num!(addr)] => op_snnn!(0xB, addr),
-    // BNNN - JP V0, :label
-    &[id!("JP"), register!(0), comma!(), id!(lb)] => op_slabel!(0xB, lb),
+    ("JP", &[reg!(0), value!(nnn)]) => op_with!(0xB000, Address, nnn),
     // CXKK - RND VX, KK
-    &[id!("RND"), register!(x), comma!(), num!(kk)] => op_sxkk!(0xC, x, kk),
+    ("RND", &[reg!(x), value!(kk)]) => op_with!(0xC000 | vx(x), Byte, kk),
     // DXYN - DRW VX, VY, N
-    &[id!("DRW"), register!(x), comma!(), register!(y), comma!(), num!(n)] => {
-      op_sxyn!(0xD, x, y, n)
-    }
+    ("DRW", &[reg!(x), reg!(y), value!(n)]) => op_with!(0xD000 | vx(x) | vy(y), Nibble, n),
     // EX9E - SKP VX
-    &[id!("SKP"), register!(x)] => op_sxyn!(0xE, x, 0x9, 0xE),
+    ("SKP", &[reg!(x)]) => op!(0xE09E | vx(x)),
     // EXA1 - SKNP VX
-    &[id!("SKNP"), register!(x)] => op_sxyn!(0xE, x, 0xA, 0x1),
+    ("SKNP", &[reg!(x)]) => op!(0xE0A1 | vx(x)),
     // FX07 - LD VX, DT
-    &[id!("LD"), register!(x), comma!(), id!("DT")] => op_sxyn!(0xF, x, 0x0, 0x7),
+    ("LD", &[reg!(x), kw!("DT")]) => op!(0xF007 | vx(x)),
     // FX0A - LD VX, K
-    &[id!("LD"), register!(x), comma!(), id!("K")] => op_sxyn!(0xF, x, 0x0, 0xA),
+    ("LD", &[reg!(x), kw!("K")]) => op!(0xF00A | vx(x)),
     // FX15 - LD DT, VX
-    &[id!("LD"), id!("DT"), comma!(), register!(x)] => op_sxyn!(0xF, x, 0x1, 0x5),
+    ("LD", &[kw!("DT"), reg!(x)]) => op!(0xF015 | vx(x)),
     // FX18 - LD ST, VX
-    &[id!("LD"), id!("ST"), comma!(), register!(x)] => op_sxyn!(0xF, x, 0x1, 0x8),
+    ("LD", &[kw!("ST"), reg!(x)]) => op!(0xF018 | vx(x)),
     // FX1E - ADD I, VX
-    &[id!("ADD"), id!("I"), comma!(), register!(x)] => op_sxyn!(0xF, x, 0x1, 0xE),
+    ("ADD", &[kw!("I"), reg!(x)]) => op!(0xF01E | vx(x)),
     // FX29 - LD F, VX
-    &[id!("LD"), id!("F"), comma!(), register!(x)] => op_sxyn!(0xF, x, 0x2, 0x9),
+    ("LD", &[kw!("F"), reg!(x)]) => op!(0xF029 | vx(x)),
     // FX33 - LD B, VX
-    &[id!("LD"), id!("B"), comma!(), register!(x)] => op_sxyn!(0xF, x, 0x3, 0x3),
+    ("LD", &[kw!("B"), reg!(x)]) => op!(0xF033 | vx(x)),
     // FX55 - LD [I], VX
-    &[id!("LD"), id!("[I]"), comma!(), register!(x)] => op_sxyn!(0xF, x, 0x5, 0x5),
+    ("LD", &[kw!("[I]"), reg!(x)]) => op!(0xF055 | vx(x)),
     // FX65 - LD VX, [I]
-    &[id!("LD"), register!(x), comma!(), id!("[I]")] => op_sxyn!(0xF, x, 0x6, 0x5),
-    &[id!("DB"), num!(n)] => {
-      *address += 1;
-      Ok(MemorySlices::Byte(byte!(n)))
+    ("LD", &[reg!(x), kw!("[I]")]) => op!(0xF065 | vx(x)),
+    ("DB", values) if !values.is_empty() => MemorySlices::Bytes(expressions(values, line)?),
+    ("DW", values) if !values.is_empty() => MemorySlices::Words(expressions(values, line)?),
+    _ => return Err(error::Error::InvalidLine(line.into())),
+  })
+}
+
+/// Returns the expressions of a data directive.
+///
+/// # Arguments
+///
+/// * `operands` - The operands of the directive.
+/// * `line` - The line of the directive, for errors.
+///
+/// # Returns
+///
+/// * `Ok(Vec<Expr>)` - The expressions.
+/// * `Err(Error)` - If an operand is a register or a keyword.
+fn expressions(operands: &[Operand<'_>], line: &Line<'_>) -> Result<Vec<Expr>, error::Error> {
+  operands
+    .iter()
+    .map(|operand| match operand {
+      Operand::Value(value) => Ok(value.clone()),
+      _ => Err(error::Error::InvalidLine(line.into())),
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Files served from memory, looked up by name relative to the root.
+  struct Memory(HashMap<&'static str, Vec<u8>>);
+
+  impl FileResolver for Memory {
+    fn resolve(&self, name: &str, _from: &Path) -> std::io::Result<PathBuf> {
+      match self.0.contains_key(name) {
+        true => Ok(name.into()),
+        false => Err(std::io::ErrorKind::NotFound.into()),
+      }
     }
-    &[id!("DW"), num!(n)] => {
-      *address += 2;
-      Ok(MemorySlices::Word(n))
+
+    fn read(&self, path: &Path) -> std::io::Result<Vec<u8>> {
+      Ok(self.0[path.to_str().unwrap()].clone())
     }
-    _ => Err(error::Error::InvalidLine((&line).into())),
+  }
+
+  /// Assembles `main.8s` from `files` into bytes.
+  fn assemble_files(files: &[(&'static str, &'static str)]) -> Result<Vec<u8>, error::Error> {
+    let files = Memory(
+      files
+        .iter()
+        .map(|&(name, src)| (name, src.as_bytes().to_vec()))
+        .collect(),
+    );
+    let mut rom = Vec::new();
+    assemble_file(Path::new("main.8s"), &files, &mut rom)?;
+    Ok(rom)
+  }
+
+  /// Assembles `source` into bytes.
+  fn assemble_str(source: &str) -> Result<Vec<u8>, error::Error> {
+    let mut rom = Vec::new();
+    assemble(&mut source.as_bytes(), &mut rom)?;
+    Ok(rom)
+  }
+
+  #[test]
+  fn expressions_and_constants() {
+    let source = "
+      WIDTH EQU 8
+      DEFINE HEIGHT WIDTH / 2
+      start: LD I, sprites + HEIGHT
+      LD V0, WIDTH-1
+      ADD V1, -1
+      DRW V0, V1, HEIGHT
+      LD V2, HI(table) << 4
+      LD V3, LO(table)
+      JP (start)
+      sprites: DB #F0, #90 | 1, ~#0F & #FF, 1 + 2 * 3
+      table EQU sprites
+    ";
+    let rom = assemble_str(source).unwrap();
+    assert_eq!(
+      rom,
+      [
+        0xA2, 0x12, 0x60, 0x07, 0x71, 0xFF, 0xD0, 0x14, 0x62, 0x20, 0x63, 0x0E, 0x12, 0x00, 0xF0,
+        0x91, 0xF0, 0x07,
+      ]
+    );
+  }
+
+  #[test]
+  fn operand_errors() {
+    let error = |source: &str| assemble_str(source).unwrap_err();
+    assert!(matches!(
+      error("JP nowhere"),
+      error::Error::UndefinedLabel(..)
+    ));
+    assert!(matches!(
+      error("LD V0, 256"),
+      error::Error::InvalidByte(256, 1)
+    ));
+    assert!(matches!(
+      error("DRW V0, V1, 8 * 2"),
+      error::Error::InvalidNibble(16, 1)
+    ));
+    assert!(matches!(
+      error("JP #1000 - 1 + 1"),
+      error::Error::InvalidAddress(..)
+    ));
+    assert!(matches!(error("DW 1 << 16"), error::Error::InvalidWord(..)));
+    assert!(matches!(
+      error("\nA EQU B\nB EQU A\nDB A"),
+      error::Error::RecursiveConstant(_, 4)
+    ));
+    assert!(matches!(
+      error("a:\na EQU 1"),
+      error::Error::DuplicateLabel(_, 2)
+    ));
+    assert!(matches!(
+      error("LD V0, (1"),
+      error::Error::InvalidExpression(..)
+    ));
+    assert!(matches!(error("DB V0"), error::Error::InvalidLine(..)));
+  }
+
+  #[test]
+  fn pong_assembles() {
+    let source = include_str!("../../assembly_roms/pong.8s");
+    let rom = assemble_str(source).unwrap();
+    assert_eq!(rom.len(), 293);
+    assert_eq!(&rom[..4], [0x22, 0xFB, 0x6B, 0x0C]);
+  }
+
+  #[test]
+  fn macros() {
+    let source = "
+      MACRO WAIT ticks
+        LD V0, ticks
+        LD DT, V0
+      loop:
+        LD V0, DT
+        SE V0, 0
+        JP loop
+      ENDM
+      MACRO BEEP length, pause
+        LD V1, length
+        LD ST, V1
+        WAIT pause
+      ENDM
+      WAIT 2
+      BEEP 3, 4 * 2
+    ";
+    let rom = assemble_str(source).unwrap();
+    assert_eq!(
+      rom,
+      [
+        0x60, 0x02, 0xF0, 0x15, 0xF0, 0x07, 0x30, 0x00, 0x12, 0x04, // WAIT 2
+        0x61, 0x03, 0xF1, 0x18, // BEEP 3, 8
+        0x60, 0x08, 0xF0, 0x15, 0xF0, 0x07, 0x30, 0x00, 0x12, 0x12, // WAIT 8
+      ]
+    );
+    // Expression arguments keep their precedence.
+    let source = "MACRO DOUBLE x\nLD V0, x * 2\nENDM\nDOUBLE 1 + 1\nDOUBLE -1 + 3";
+    assert_eq!(assemble_str(source).unwrap(), [0x60, 0x04, 0x60, 0x04]);
+    // Constants are local to every call, like labels.
+    let source = "
+      MACRO STEP value
+        half EQU value / 2
+        DEFINE twice value * 2
+        LD V0, half
+        LD V1, twice
+      ENDM
+      STEP 4
+      STEP 6
+    ";
+    assert_eq!(
+      assemble_str(source).unwrap(),
+      [0x60, 0x02, 0x61, 0x08, 0x60, 0x03, 0x61, 0x0C]
+    );
+  }
+
+  #[test]
+  fn macro_errors() {
+    let error = |source: &str| assemble_str(source).unwrap_err();
+    let err = error("MACRO SET reg, value\nLD reg, value\nENDM\nSET V0, 256");
+    assert!(matches!(
+      &err,
+      error::Error::InMacro(name, 4, inner) if name == "SET" && matches!(**inner, error::Error::InvalidByte(256, 2))
+    ));
+    assert_eq!(
+      err.to_string(),
+      "Invalid byte at line 2: 256 does not fit in 8 bits, in macro SET called at line 4"
+    );
+    assert!(matches!(
+      error("MACRO SET reg, value\nLD reg, value\nENDM\nSET V0"),
+      error::Error::MacroArguments(_, 2, 1, 4)
+    ));
+    assert!(matches!(
+      error("MACRO LOOP\nLOOP\nENDM\nLOOP"),
+      error::Error::InMacro(_, 4, _)
+    ));
+    let mut err = error("MACRO LOOP\nLOOP\nENDM\nLOOP");
+    while let error::Error::InMacro(_, _, inner) = err {
+      err = *inner;
+    }
+    assert!(matches!(err, error::Error::MacroDepth(_, 2)));
+    assert!(matches!(
+      error("\nMACRO OPEN\nCLS"),
+      error::Error::UnterminatedMacro(_, 2)
+    ));
+    assert!(matches!(
+      error("MACRO A\nENDM\nMACRO A\nENDM"),
+      error::Error::DuplicateMacro(_, 3)
+    ));
+    let err = error("MACRO OUTER\nMACRO INNER\nCLS\nENDM\nENDM\nOUTER\nOUTER");
+    assert!(matches!(&err, error::Error::NestedMacro(name, 2) if name == "OUTER"));
+    assert_eq!(
+      err.to_string(),
+      "MACRO at line 2 is inside the definition of macro OUTER, macros cannot be nested"
+    );
+  }
+
+  #[test]
+  fn include_and_incbin() {
+    let rom = assemble_files(&[
+      (
+        "main.8s",
+        "INCLUDE \"lib.8s\"\nCALL draw\nINCBIN \"font.bin\", 1, 2\nINCBIN \"font.bin\"",
+      ),
+      ("lib.8s", "SIZE EQU 3\ndraw: DRW V0, V1, SIZE\nRET"),
+      ("font.bin", "\u{1}\u{2}\u{3}\u{4}"),
+    ])
+    .unwrap();
+    assert_eq!(rom, [0xD0, 0x13, 0x00, 0xEE, 0x22, 0x00, 2, 3, 1, 2, 3, 4]);
+  }
+
+  #[test]
+  fn include_errors() {
+    let err = assemble_files(&[
+      ("main.8s", "CLS\nINCLUDE \"a.8s\""),
+      ("a.8s", "\nLD V0, 300"),
+    ]);
+    assert_eq!(
+      err.unwrap_err().to_string(),
+      "a.8s: Invalid byte at line 2: 300 does not fit in 8 bits"
+    );
+    let err = assemble_files(&[
+      ("main.8s", "INCLUDE \"a.8s\""),
+      ("a.8s", "INCLUDE \"main.8s\""),
+    ]);
+    assert!(matches!(
+      err.unwrap_err(),
+      error::Error::InFile(file, inner) if file == "a.8s" && matches!(*inner, error::Error::IncludeCycle(_, 1))
+    ));
+    let err = assemble_files(&[("main.8s", "\nINCLUDE \"missing.8s\"")]);
+    assert_eq!(
+      err.unwrap_err().to_string(),
+      "main.8s: Cannot include missing.8s at line 2: entity not found"
+    );
+    let err = assemble_files(&[("main.8s", "INCBIN \"b\", 1, 4"), ("b", "1234")]);
+    assert!(err
+      .unwrap_err()
+      .to_string()
+      .contains("INCBIN at line 1 reads outside of b"));
+    // An offset and a length overflowing when added are outside of the file too.
+    for source in [
+      "INCBIN \"b\", 1 << 62, 1 << 62",
+      "INCBIN \"b\", -1",
+      "INCBIN \"b\", 2, -1",
+    ] {
+      let err = assemble_files(&[("main.8s", source), ("b", "1234")]);
+      assert!(matches!(
+        err.unwrap_err(),
+        error::Error::InFile(_, inner) if matches!(*inner, error::Error::IncbinRange(_, 1))
+      ));
+    }
+    let err = assemble_files(&[
+      ("main.8s", "INCLUDE \"m.8s\"\nSET 256"),
+      ("m.8s", "MACRO SET value\nLD V0, value\nENDM"),
+    ]);
+    assert_eq!(
+      err.unwrap_err().to_string(),
+      "main.8s: m.8s: Invalid byte at line 2: 256 does not fit in 8 bits, in macro SET called at line 2"
+    );
+  }
+
+  #[test]
+  fn layout_directives() {
+    let source = "
+      JP main
+      FILL 3, #AA
+      ALIGN 4
+      sprite: DB #F0
+      ORG #210
+      main: LD I, sprite
+      DS 2
+      buffer: DS 16
+    ";
+    let rom = assemble_str(source).unwrap();
+    // The reserved bytes at the end are not part of the image.
+    assert_eq!(
+      rom,
+      [
+        0x12, 0x10, 0xAA, 0xAA, 0xAA, 0x00, 0x00, 0x00, 0xF0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0xA2, 0x08,
+      ]
+    );
+    let rom = assemble_str("JP end\nDS 2\nend: DB 1").unwrap();
+    assert_eq!(rom, [0x12, 0x04, 0x00, 0x00, 0x01]);
+  }
+
+  #[test]
+  fn layout_errors() {
+    let error = |source: &str| assemble_str(source).unwrap_err();
+    assert!(matches!(
+      error("CLS\nCLS\nORG #202\nDB 1"),
+      error::Error::Overlap(0x202, 4)
+    ));
+    assert!(matches!(
+      error("ORG #204\nCLS\nORG #200\nFILL 6, 0"),
+      error::Error::Overlap(0x200, 4)
+    ));
+    assert!(matches!(
+      error("ORG #FFE\nCLS\nCLS"),
+      error::Error::OutOfMemory(0x1000, 3)
+    ));
+    assert!(matches!(
+      error("ORG #100"),
+      error::Error::InvalidOrigin(0x100, 1)
+    ));
+    assert!(matches!(
+      error("ORG later\nlater:"),
+      error::Error::UndefinedLabel(..)
+    ));
+    assert!(matches!(error("ALIGN 0"), error::Error::InvalidCount(0, 1)));
+    assert!(matches!(error("DS -1"), error::Error::InvalidCount(-1, 1)));
+  }
+
+  #[test]
+  fn odd_address_warning() {
+    let mut rom = Vec::new();
+    let warnings = assemble(&mut "DB 1\nCLS\nALIGN 2\nCLS".as_bytes(), &mut rom)
+      .unwrap()
+      .warnings;
+    assert_eq!(
+      warnings,
+      [Warning {
+        file: String::new(),
+        line: 2,
+        message: "instruction at the odd address #201".to_string()
+      }]
+    );
+    assert_eq!(
+      warnings[0].to_string(),
+      "Warning at line 2: instruction at the odd address #201"
+    );
+  }
+
+  #[test]
+  fn sprites() {
+    let source = "
+      ship: SPRITE \"..XX....\",
+                   \".XXXX...\",
+                   0b11111100, #FF
+    ";
+    assert_eq!(assemble_str(source).unwrap(), [0x30, 0x78, 0xFC, 0xFF]);
+    // Only operand lists go on with the next line, a stray comma is an error.
+    let err = assemble_str("LD V0,\n5").unwrap_err();
+    assert_eq!(err.line(), Some(1));
+    let rows = vec!["\"X\""; 16].join(", ");
+    let err = assemble_str(&format!("SPRITE {}", rows)).unwrap_err();
+    assert!(matches!(err, error::Error::SpriteHeight(16, 1)));
+    assert!(matches!(
+      assemble_str("SPRITE \"X.?\"").unwrap_err(),
+      error::Error::InvalidSprite(..)
+    ));
+
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, 8, 2);
+    encoder.set_color(png::ColorType::Grayscale);
+    let mut writer = encoder.write_header().unwrap();
+    writer
+      .write_image_data(&[0xFF, 0, 0, 0, 0, 0, 0, 0xFF, 0, 0xFF, 0, 0, 0, 0, 0xFF, 0])
+      .unwrap();
+    writer.finish().unwrap();
+    let files = Memory(HashMap::from([
+      ("main.8s", b"INCSPRITE \"ship.png\", 8, 2".to_vec()),
+      ("ship.png", png),
+    ]));
+    let mut rom = Vec::new();
+    assemble_file(Path::new("main.8s"), &files, &mut rom).unwrap();
+    assert_eq!(rom, [0x81, 0x42]);
+  }
+
+  #[test]
+  fn text() {
+    let source = "
+      CHARSET \"ABC \"
+      TEXT \"CAB A\", #FF
+      CHARSET \"0123456789\", digits - glyphs, 5
+      TEXT \"20\"
+    glyphs:
+      DS 5
+    digits:
+    ";
+    assert_eq!(
+      assemble_str(source).unwrap()[..8],
+      [2, 0, 1, 3, 0, 0xFF, 15, 5]
+    );
+    let source = "
+      CHARSET \"\\\"\\\\;\", glyphs, 5
+      TEXTW \"\\\\;\\\"\", #ABC ; backslash, semicolon, quote
+      ORG #300
+    glyphs:
+    ";
+    assert_eq!(
+      assemble_str(source).unwrap()[..8],
+      [0x03, 0x05, 0x03, 0x0A, 0x03, 0x00, 0x0A, 0xBC]
+    );
+    assert!(matches!(
+      assemble_str("CHARSET \"AB\"\nTEXT \"ABC\"").unwrap_err(),
+      error::Error::MissingCharacter('C', 2)
+    ));
+    assert!(matches!(
+      assemble_str("CHARSET \"ABA\"").unwrap_err(),
+      error::Error::InvalidCharset(_, 1)
+    ));
+    assert!(matches!(
+      assemble_str("TEXT \"A\"").unwrap_err(),
+      error::Error::InvalidCharset(_, 1)
+    ));
+  }
+
+  #[test]
+  fn listing_of_continued_lines() {
+    let source = "ship: SPRITE \"XX\", #01,\n  ; rows\n  #02, #03,\n  #04\nBAD EQU NOPE + 1\n";
+    let mut rom = Vec::new();
+    let assembly = assemble(&mut source.as_bytes(), &mut rom).unwrap();
+    assert_eq!(
+      assembly.listing(),
+      concat!(
+        "200  C0 01 02 03     1  ship: SPRITE \"XX\", #01,\n",
+        "204  04\n",
+        "                     2    ; rows\n",
+        "                     3    #02, #03,\n",
+        "                     4    #04\n",
+      )
+    );
+    // BAD is never used, it is not an error but it is not in the symbols either.
+    assert_eq!(assembly.symbol_table(), "ship    #200  label\n");
+    assert_eq!(
+      assembly.warnings,
+      [Warning {
+        file: String::new(),
+        line: 5,
+        message: "constant BAD has no value: Undefined symbol NOPE at line 5".to_string()
+      }]
+    );
+  }
+
+  #[test]
+  fn listing_and_symbols() {
+    let source =
+      "; Draws a row\nWIDTH EQU 8\nstart: CLS\n  LD I, row\nrow: DB 1, 2, 3, 4, 5\n  DS 2\n";
+    let mut rom = Vec::new();
+    let assembly = assemble(&mut source.as_bytes(), &mut rom).unwrap();
+    assert_eq!(
+      assembly.listing(),
+      concat!(
+        "200  00 E0           3  start: CLS\n",
+        "202  A2 04           4    LD I, row\n",
+        "204  01 02 03 04     5  row: DB 1, 2, 3, 4, 5\n",
+        "208  05\n",
+        "209                  6    DS 2\n",
+      )
+    );
+    assert_eq!(
+      assembly.symbol_table(),
+      "WIDTH    #008  constant\nstart    #200  label\nrow      #204  label\n"
+    );
+    let map = assembly.source_map();
+    assert_eq!(map.get(&0x203), Some(&(String::new(), 4)));
+    assert_eq!(map.get(&0x208), Some(&(String::new(), 5)));
+    assert_eq!(map.get(&0x209), None);
+    assert_eq!(assembly.label_for(0x200).as_deref(), Some("start"));
+    assert_eq!(assembly.label_for(0x206).as_deref(), Some("row+2"));
+    assert_eq!(assembly.label_for(0x1FF), None);
+    let json: serde_json::Value = serde_json::from_str(&assembly.symbols_json()).unwrap();
+    assert_eq!(
+      json[2],
+      serde_json::json!({"name": "row", "value": 0x204, "kind": "label"})
+    );
   }
 }
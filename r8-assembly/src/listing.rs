@@ -0,0 +1,195 @@
+use std::{collections::BTreeMap, fmt::Write};
+
+use serde::Serialize;
+
+use super::error::Warning;
+
+/// How many bytes a row of the listing shows, longer lines go on in rows without
+/// source.
+const BYTES_PER_ROW: usize = 4;
+
+/// What a line of source assembled to.
+///
+/// # Fields
+///
+/// * `address` - The address of its first byte.
+/// * `bytes` - The bytes, empty for labels and `DS`.
+/// * `file` - The file of the line, empty for a source without a path.
+/// * `line` - The line number in the file.
+/// * `source` - The text of the line, followed by the lines its operand list goes
+///   on to, separated by `\n`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ListingLine {
+  pub address: u16,
+  pub bytes: Vec<u8>,
+  pub file: String,
+  pub line: usize,
+  pub source: String,
+}
+
+/// What a symbol stands for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SymbolKind {
+  /// A label, its value is an address.
+  Label,
+  /// A constant from `EQU` or `DEFINE`.
+  Constant,
+}
+
+/// A label or constant of the program.
+///
+/// # Fields
+///
+/// * `name` - The name, labels of macro calls end with `@` and the call number.
+/// * `value` - The address of a label or the value of a constant.
+/// * `kind` - Whether it is a label or a constant.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SymbolEntry {
+  pub name: String,
+  pub value: i64,
+  pub kind: SymbolKind,
+}
+
+/// What an assembly produced besides the ROM.
+///
+/// # Fields
+///
+/// * `warnings` - The suspicious lines found, like instructions at odd addresses.
+/// * `lines` - The lines that assembled to something, in source order.
+/// * `symbols` - The labels and constants, sorted by value then by name.
+#[derive(Debug, Clone, Default)]
+pub struct Assembly {
+  pub warnings: Vec<Warning>,
+  pub lines: Vec<ListingLine>,
+  pub symbols: Vec<SymbolEntry>,
+}
+
+impl Assembly {
+  /// Formats the listing: the address, the bytes and the source of every line.
+  ///
+  /// A line with the file name comes before the lines of every file, except for a
+  /// source without a path. The bytes of a statement continued over several lines
+  /// start on its first line and fill as many rows as they need, its other lines
+  /// follow without bytes.
+  ///
+  /// # Examples
+  ///
+  /// ```text
+  /// ; game.8s
+  /// 200  00 E0           1  start: CLS
+  /// 202  12 00           2    JP start
+  /// ```
+  pub fn listing(&self) -> String {
+    let mut listing = String::new();
+    let mut file = "";
+    for line in &self.lines {
+      if line.file != file {
+        file = &line.file;
+        let _ = writeln!(listing, "; {}", file);
+      }
+      let mut write_row = |address: String, bytes: &[u8], source: Option<(usize, &str)>| {
+        let row = match source {
+          Some((offset, source)) => format!(
+            "{:<3}  {:<12} {:>4}  {}",
+            address,
+            hex(bytes),
+            line.line + offset,
+            source
+          ),
+          None => format!("{}  {}", address, hex(bytes)),
+        };
+        let _ = writeln!(listing, "{}", row.trim_end());
+      };
+      // Only the first source line shares its row with bytes, a line without bytes
+      // still shows its address.
+      let mut sources = line.source.split('\n').enumerate();
+      let mut chunks = line.bytes.chunks(BYTES_PER_ROW);
+      for row in 0..line.bytes.len().div_ceil(BYTES_PER_ROW).max(1) {
+        let address = format!("{:03X}", line.address as usize + row * BYTES_PER_ROW);
+        let source = if row == 0 { sources.next() } else { None };
+        write_row(address, chunks.next().unwrap_or_default(), source);
+      }
+      for source in sources {
+        write_row(String::new(), &[], Some(source));
+      }
+    }
+    listing
+  }
+
+  /// Maps every assembled byte to the line it comes from, for debuggers.
+  ///
+  /// # Returns
+  ///
+  /// * `BTreeMap<u16, (String, usize)>` - The file and line of every address, the
+  ///   lines of a macro are in the file of its definition.
+  pub fn source_map(&self) -> BTreeMap<u16, (String, usize)> {
+    let mut map = BTreeMap::new();
+    for line in &self.lines {
+      for offset in 0..line.bytes.len() {
+        map.insert(line.address + offset as u16, (line.file.clone(), line.line));
+      }
+    }
+    map
+  }
+
+  /// Returns the label at or right before `address`, with the distance from it,
+  /// like `loop+4`.
+  pub fn label_for(&self, address: u16) -> Option<String> {
+    let label = self
+      .symbols
+      .iter()
+      .filter(|symbol| symbol.kind == SymbolKind::Label && symbol.value <= address as i64)
+      .max_by_key(|symbol| symbol.value)?;
+    Some(match address as i64 - label.value {
+      0 => label.name.clone(),
+      offset => format!("{}+{}", label.name, offset),
+    })
+  }
+
+  /// Formats the symbol table, a symbol per line: its name, its value in hex and
+  /// whether it is a label or a constant.
+  pub fn symbol_table(&self) -> String {
+    let width = self
+      .symbols
+      .iter()
+      .map(|symbol| symbol.name.len())
+      .max()
+      .unwrap_or(0);
+    let mut table = String::new();
+    for symbol in &self.symbols {
+      let kind = match symbol.kind {
+        SymbolKind::Label => "label",
+        SymbolKind::Constant => "constant",
+      };
+      let value = match symbol.value {
+        value if value < 0 => format!("-#{:X}", value.unsigned_abs()),
+        value => format!("#{:03X}", value),
+      };
+      let _ = writeln!(
+        table,
+        "{:<width$}  {:>6}  {}",
+        symbol.name,
+        value,
+        kind,
+        width = width
+      );
+    }
+    table
+  }
+
+  /// Formats the symbol table as a JSON array of `{"name", "value", "kind"}`
+  /// objects, `kind` being `"label"` or `"constant"`.
+  pub fn symbols_json(&self) -> String {
+    serde_json::to_string_pretty(&self.symbols).unwrap_or_default()
+  }
+}
+
+/// Formats bytes in hex, separated by spaces.
+fn hex(bytes: &[u8]) -> String {
+  bytes
+    .iter()
+    .map(|byte| format!("{:02X}", byte))
+    .collect::<Vec<_>>()
+    .join(" ")
+}
@@ -0,0 +1,154 @@
+use std::{collections::HashSet, path::Path, rc::Rc};
+
+use super::error::Error;
+use super::tokenizer::{OwnedLine, OwnedToken};
+
+/// How many macro calls may be nested before the expansion gives up, it stops a
+/// macro calling itself forever.
+pub const MAX_DEPTH: usize = 16;
+
+/// A macro defined with `MACRO name params ... ENDM`.
+///
+/// # Fields
+///
+/// * `params` - The names of the parameters.
+/// * `body` - The lines between `MACRO` and `ENDM`.
+/// * `file` - The file the macro is defined in.
+/// * `line` - The line of the `MACRO` directive.
+#[derive(Debug, Clone)]
+pub struct Macro {
+  params: Vec<String>,
+  body: Vec<OwnedLine>,
+  file: Rc<Path>,
+  line: usize,
+}
+
+impl Macro {
+  /// Creates a macro without a body yet.
+  ///
+  /// # Arguments
+  ///
+  /// * `params` - The names of the parameters.
+  /// * `file` - The file the macro is defined in.
+  /// * `line` - The line of the `MACRO` directive.
+  pub fn new(params: Vec<String>, file: Rc<Path>, line: usize) -> Self {
+    Macro {
+      params,
+      body: Vec::new(),
+      file,
+      line,
+    }
+  }
+
+  /// Returns the file the macro is defined in.
+  pub fn file(&self) -> &Rc<Path> {
+    &self.file
+  }
+
+  /// Appends a line to the body.
+  pub fn push(&mut self, line: OwnedLine) {
+    self.body.push(line);
+  }
+
+  /// Returns the line of the `MACRO` directive.
+  pub fn line(&self) -> usize {
+    self.line
+  }
+
+  /// Expands a call of the macro.
+  ///
+  /// Every parameter is replaced by the tokens of its argument, in parentheses when
+  /// the argument is an expression so `x * 2` with `1 + 1` is `(1 + 1) * 2`. Labels
+  /// and constants defined in the body are local to the expansion: they are renamed
+  /// to `name@expansion`, so calling the macro twice does not define them twice.
+  ///
+  /// # Arguments
+  ///
+  /// * `name` - The name of the macro, for errors.
+  /// * `args` - The tokens of every argument.
+  /// * `expansion` - A number unique to this call.
+  /// * `line` - The line of the call, for errors.
+  ///
+  /// # Returns
+  ///
+  /// * `Ok(Vec<OwnedLine>)` - The lines to assemble, numbered as in the definition.
+  /// * `Err(Error)` - If the number of arguments does not match the parameters.
+  pub fn expand(
+    &self,
+    name: &str,
+    args: &[Vec<OwnedToken>],
+    expansion: usize,
+    line: usize,
+  ) -> Result<Vec<OwnedLine>, Error> {
+    if args.len() != self.params.len() {
+      return Error::MacroArguments(name.to_string(), self.params.len(), args.len(), line).warp();
+    }
+    let locals = self
+      .body
+      .iter()
+      .filter_map(|line| local_name(&line.tokens))
+      .collect::<HashSet<_>>();
+    let local = |name: &str| format!("{}@{}", name, expansion);
+    let lines = self
+      .body
+      .iter()
+      .map(|body_line| {
+        let mut tokens = Vec::with_capacity(body_line.tokens.len());
+        for token in &body_line.tokens {
+          match token {
+            OwnedToken::Identifier(id) => match self.params.iter().position(|param| param == id) {
+              Some(param) => tokens.extend(substitute(&args[param])),
+              None if locals.contains(id.as_str()) => {
+                tokens.push(OwnedToken::Identifier(local(id)))
+              }
+              None => tokens.push(token.clone()),
+            },
+            OwnedToken::Label(label) if locals.contains(label.as_str()) => {
+              tokens.push(OwnedToken::Label(local(label)))
+            }
+            token => tokens.push(token.clone()),
+          }
+        }
+        OwnedLine {
+          tokens,
+          line: body_line.line,
+          end: body_line.end,
+        }
+      })
+      .collect();
+    Ok(lines)
+  }
+}
+
+/// Returns the name of the label or constant a line of a macro body defines.
+fn local_name(tokens: &[OwnedToken]) -> Option<&str> {
+  match tokens {
+    [OwnedToken::Label(name), ..] => Some(name),
+    [OwnedToken::Identifier(name), OwnedToken::Identifier(equ), ..] if equ == "EQU" => Some(name),
+    [OwnedToken::Identifier(define), OwnedToken::Identifier(name), ..] if define == "DEFINE" => {
+      Some(name)
+    }
+    _ => None,
+  }
+}
+
+/// Returns the tokens an argument is replaced with: itself, in parentheses when it
+/// is an expression of several tokens.
+fn substitute(arg: &[OwnedToken]) -> Vec<OwnedToken> {
+  let expression = arg.len() > 1
+    && arg.iter().all(|token| {
+      matches!(
+        token,
+        OwnedToken::Number(_) | OwnedToken::Identifier(_) | OwnedToken::Operator(_)
+      )
+    });
+  match expression {
+    true => {
+      let mut tokens = vec![OwnedToken::Operator("(".to_string())];
+      tokens.extend(arg.iter().cloned());
+      tokens.push(OwnedToken::Operator(")".to_string()));
+      tokens
+    }
+    false => arg.to_vec(),
+  }
+}
@@ -1,53 +1,121 @@
-use std::{collections::HashMap, io::Write};
+use std::io::Write;
 
 use super::error;
+use super::expression::{Expr, Symbols};
 
+/// The part of an opcode an operand is encoded into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+  /// `NNN`, the lower 12 bits.
+  Address,
+  /// `KK`, the lower 8 bits. Negative values are stored in two's complement.
+  Byte,
+  /// `N`, the lower 4 bits.
+  Nibble,
+}
+
+impl Field {
+  /// Checks that `value` fits in the field.
+  ///
+  /// # Arguments
+  ///
+  /// * `value` - The value of the operand.
+  /// * `line` - The line of the operand, for errors.
+  ///
+  /// # Returns
+  ///
+  /// * `Ok(u16)` - The bits to combine with the opcode.
+  /// * `Err(Error)` - If the value is out of range.
+  pub fn encode(self, value: i64, line: usize) -> Result<u16, error::Error> {
+    match self {
+      Field::Address if (0..=0x0FFF).contains(&value) => Ok(value as u16),
+      Field::Address => Err(error::Error::InvalidAddress(value, line)),
+      Field::Byte if (-0x80..=0xFF).contains(&value) => Ok(value as u16 & 0xFF),
+      Field::Byte => Err(error::Error::InvalidByte(value, line)),
+      Field::Nibble if (0..=0xF).contains(&value) => Ok(value as u16),
+      Field::Nibble => Err(error::Error::InvalidNibble(value, line)),
+    }
+  }
+}
+
+/// What a line of source assembles to, with the operands still to be evaluated so
+/// they can refer to symbols defined later.
 #[derive(Debug)]
-pub enum MemorySlices<'src> {
-  Opcode(u16),
-  Pending(u8, &'src str, usize),
-  Byte(u8),
-  Word(u16),
+pub enum MemorySlices {
+  /// An instruction and, if it takes one, the operand to combine with it.
+  Opcode(u16, Option<(Field, Expr)>),
+  /// `DB` data.
+  Bytes(Vec<Expr>),
+  /// `DW` data, stored big endian.
+  Words(Vec<Expr>),
+  /// Bytes copied as they are, from `INCBIN`.
+  Raw(Vec<u8>),
+  /// A byte repeated, from `FILL` and `ALIGN`.
+  Fill(usize, Expr),
+  /// Space kept free by `DS`, nothing is written there.
+  Reserve(usize),
   Empty,
 }
 
-impl MemorySlices<'_> {
+impl MemorySlices {
+  /// Returns the number of bytes the slice takes.
+  pub fn len(&self) -> usize {
+    match self {
+      MemorySlices::Opcode(..) => 2,
+      MemorySlices::Bytes(bytes) => bytes.len(),
+      MemorySlices::Words(words) => 2 * words.len(),
+      MemorySlices::Raw(data) => data.len(),
+      MemorySlices::Fill(count, _) | MemorySlices::Reserve(count) => *count,
+      MemorySlices::Empty => 0,
+    }
+  }
+
   /// Write the memory slice to the writer.
   ///
   /// # Arguments
   ///
-  /// * `labels` - The labels to use for the pending memory slices.
+  /// * `symbols` - The symbols the operands can refer to.
+  /// * `line` - The line the slice comes from, for errors.
   /// * `writer` - The writer to write to.
   ///
   /// # Returns
   ///
   /// * `Ok(())` - If the write was successful.
-  /// * `Err(Error)` - If there was an error writing to the writer or if a label was undefined.
+  /// * `Err(Error)` - If there was an error writing to the writer or evaluating an operand.
   pub fn write<W: Write>(
-    self,
-    labels: &HashMap<&str, u16>,
+    &self,
+    symbols: &Symbols,
+    line: usize,
     writer: &mut W,
   ) -> Result<(), error::Error> {
     match self {
-      MemorySlices::Opcode(data) | MemorySlices::Word(data) => {
-        writer.write_all(&data.to_be_bytes())?;
-        Ok(())
+      MemorySlices::Opcode(opcode, None) => writer.write_all(&opcode.to_be_bytes())?,
+      MemorySlices::Opcode(opcode, Some((field, operand))) => {
+        let operand = field.encode(operand.eval(symbols, line)?, line)?;
+        writer.write_all(&(opcode | operand).to_be_bytes())?;
+      }
+      MemorySlices::Bytes(bytes) => {
+        for byte in bytes {
+          let byte = Field::Byte.encode(byte.eval(symbols, line)?, line)?;
+          writer.write_all(&[byte as u8])?;
+        }
       }
-      MemorySlices::Pending(first_nibble, label, line) => {
-        if let Some(&addr) = labels.get(label) {
-          let addr = addr & 0x0FFF; // Mask out upper bits
-          let opcode = (first_nibble as u16) << 12 | addr; // Combine first nibble and address
-          writer.write_all(&opcode.to_be_bytes())?;
-          Ok(())
-        } else {
-          Err(error::Error::UndefinedLabel(label.to_string(), line))
+      MemorySlices::Words(words) => {
+        for word in words {
+          let word = match word.eval(symbols, line)? {
+            word @ -0x8000..=0xFFFF => word as u16,
+            word => return Err(error::Error::InvalidWord(word, line)),
+          };
+          writer.write_all(&word.to_be_bytes())?;
         }
       }
-      MemorySlices::Byte(data) => {
-        writer.write_all(&[data])?;
-        Ok(())
+      MemorySlices::Raw(data) => writer.write_all(data)?,
+      MemorySlices::Fill(count, byte) => {
+        let byte = Field::Byte.encode(byte.eval(symbols, line)?, line)?;
+        writer.write_all(&vec![byte as u8; *count])?;
       }
-      MemorySlices::Empty => Ok(()),
+      MemorySlices::Reserve(_) | MemorySlices::Empty => {}
     }
+    Ok(())
   }
 }
@@ -0,0 +1,195 @@
+use super::error::Error;
+
+/// The most rows `DRW` draws, its height is a nibble and 0 is not a CHIP-8 sprite.
+pub const MAX_HEIGHT: i64 = 15;
+
+/// Converts an ASCII-art row of a `SPRITE` into a byte.
+///
+/// `X`, `x`, `#`, `*` and `1` are lit pixels, `.`, `_`, `0` and spaces are dark
+/// ones. Rows shorter than 8 pixels are padded with dark pixels on the right.
+///
+/// # Arguments
+///
+/// * `row` - The row, from left to right.
+/// * `line` - The line of the directive, for errors.
+///
+/// # Returns
+///
+/// * `Ok(u8)` - The row, the leftmost pixel in the highest bit.
+/// * `Err(Error)` - If the row is wider than 8 pixels or has another character.
+pub fn ascii_row(row: &str, line: usize) -> Result<u8, Error> {
+  if row.chars().count() > 8 {
+    return Error::InvalidSprite(format!("row \"{}\" is wider than 8 pixels", row), line).warp();
+  }
+  let mut byte = 0;
+  for (column, pixel) in row.chars().enumerate() {
+    match pixel {
+      'X' | 'x' | '#' | '*' | '1' => byte |= 0x80 >> column,
+      '.' | '_' | '0' | ' ' => {}
+      other => {
+        let msg = format!("'{}' in row \"{}\" is not a pixel", other, row);
+        return Error::InvalidSprite(msg, line).warp();
+      }
+    }
+  }
+  Ok(byte)
+}
+
+/// Checks that a sprite of `height` rows can be drawn with a single `DRW`.
+pub fn check_height(height: i64, line: usize) -> Result<(), Error> {
+  match height {
+    1..=MAX_HEIGHT => Ok(()),
+    height => Error::SpriteHeight(height, line).warp(),
+  }
+}
+
+/// Converts a monochrome PNG into sprites for `INCSPRITE`.
+///
+/// The image is cut into tiles of `width` by `height` pixels, read from left to
+/// right and top to bottom. A tile wider than 8 pixels is stored as columns of 8
+/// pixels, from left to right, each `height` bytes long. A pixel is lit when it is
+/// light and opaque.
+///
+/// # Arguments
+///
+/// * `name` - The file, for errors.
+/// * `png` - The contents of the file.
+/// * `width` - The width of a sprite, a multiple of 8.
+/// * `height` - The height of a sprite, at most `MAX_HEIGHT`.
+/// * `line` - The line of the directive, for errors.
+///
+/// # Returns
+///
+/// * `Ok(Vec<u8>)` - The sprite bytes.
+/// * `Err(Error)` - If the file is not a PNG or its size is not a multiple of the
+///   sprite size.
+pub fn import_png(
+  name: &str,
+  png: &[u8],
+  width: i64,
+  height: i64,
+  line: usize,
+) -> Result<Vec<u8>, Error> {
+  check_height(height, line)?;
+  let invalid = |msg: String| Error::InvalidSprite(format!("{}: {}", name, msg), line);
+  if width <= 0 || width % 8 != 0 {
+    return Err(invalid(format!(
+      "sprite width {} is not a multiple of 8",
+      width
+    )));
+  }
+  let (pixels, image_width, image_height) = decode(png).map_err(|err| invalid(err.to_string()))?;
+  let (width, height) = (width as usize, height as usize);
+  if image_width % width != 0 || image_height % height != 0 {
+    let msg = format!(
+      "the image is {}x{}, not a multiple of {}x{}",
+      image_width, image_height, width, height
+    );
+    return Err(invalid(msg));
+  }
+  let mut bytes = Vec::with_capacity(image_width * image_height / 8);
+  for top in (0..image_height).step_by(height) {
+    for left in (0..image_width).step_by(width) {
+      for column in (left..left + width).step_by(8) {
+        for y in top..top + height {
+          let row = &pixels[y * image_width + column..][..8];
+          let byte = row.iter().enumerate().fold(
+            0,
+            |byte, (x, &lit)| if lit { byte | 0x80 >> x } else { byte },
+          );
+          bytes.push(byte);
+        }
+      }
+    }
+  }
+  Ok(bytes)
+}
+
+/// Decodes a PNG into lit pixels, row by row, and its width and height.
+fn decode(png: &[u8]) -> Result<(Vec<bool>, usize, usize), png::DecodingError> {
+  let mut decoder = png::Decoder::new(std::io::Cursor::new(png));
+  decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+  let mut reader = decoder.read_info()?;
+  let mut buffer = vec![0; reader.output_buffer_size().unwrap_or(0)];
+  let info = reader.next_frame(&mut buffer)?;
+  let (width, height) = (info.width as usize, info.height as usize);
+  let channels = info.color_type.samples();
+  let pixels = buffer[..info.buffer_size()]
+    .chunks(info.line_size)
+    .flat_map(|row| row[..width * channels].chunks(channels))
+    .map(|pixel| {
+      let (color, alpha) = match pixel {
+        [gray] => (*gray as u32, 0xFF),
+        [gray, alpha] => (*gray as u32, *alpha),
+        [r, g, b] => ((*r as u32 + *g as u32 + *b as u32) / 3, 0xFF),
+        [r, g, b, alpha, ..] => ((*r as u32 + *g as u32 + *b as u32) / 3, *alpha),
+        [] => (0, 0),
+      };
+      color >= 0x80 && alpha >= 0x80
+    })
+    .collect();
+  Ok((pixels, width, height))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Encodes a grayscale PNG.
+  fn png(width: u32, height: u32, pixels: &[u8]) -> Vec<u8> {
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, width, height);
+    encoder.set_color(png::ColorType::Grayscale);
+    let mut writer = encoder.write_header().unwrap();
+    writer.write_image_data(pixels).unwrap();
+    writer.finish().unwrap();
+    png
+  }
+
+  #[test]
+  fn ascii_rows() {
+    assert_eq!(ascii_row("XX..XX..", 1).unwrap(), 0xCC);
+    assert_eq!(ascii_row("#", 1).unwrap(), 0x80);
+    assert_eq!(ascii_row(" *_1", 1).unwrap(), 0x50);
+    assert!(matches!(
+      ascii_row("XXXXXXXXX", 1),
+      Err(Error::InvalidSprite(..))
+    ));
+    assert!(matches!(ascii_row("X?", 1), Err(Error::InvalidSprite(..))));
+  }
+
+  #[test]
+  fn png_tiles() {
+    // Two 8x2 sprites side by side: a diagonal and a full row.
+    let mut pixels = vec![0; 16 * 2];
+    pixels[0] = 0xFF;
+    pixels[16 + 1] = 0xFF;
+    pixels[16 + 8..16 + 16].fill(0xFF);
+    let png = png(16, 2, &pixels);
+    assert_eq!(
+      import_png("a.png", &png, 8, 2, 1).unwrap(),
+      [0x80, 0x40, 0x00, 0xFF]
+    );
+    // The same image as one 16x2 sprite, stored column by column.
+    assert_eq!(
+      import_png("a.png", &png, 16, 2, 1).unwrap(),
+      [0x80, 0x40, 0x00, 0xFF]
+    );
+    assert!(matches!(
+      import_png("a.png", &png, 8, 16, 1),
+      Err(Error::SpriteHeight(16, 1))
+    ));
+    assert!(matches!(
+      import_png("a.png", &png, 4, 2, 1),
+      Err(Error::InvalidSprite(..))
+    ));
+    assert!(matches!(
+      import_png("a.png", &png, 8, 3, 1),
+      Err(Error::InvalidSprite(..))
+    ));
+    assert!(matches!(
+      import_png("a.png", b"GIF89a", 8, 2, 1),
+      Err(Error::InvalidSprite(..))
+    ));
+  }
+}
@@ -0,0 +1,110 @@
+use std::borrow::Cow;
+
+use super::error::Error;
+use super::expression::{BinaryOp, Expr};
+
+/// The glyph table `TEXT` strings are encoded with, set by `CHARSET`.
+///
+/// The character at index `i` of the charset is written as `first + i * step`:
+/// its index in the glyph table by default, or the offset of its glyph from the
+/// start of the table when glyphs are `step` bytes long.
+///
+/// # Fields
+///
+/// * `chars` - The characters, in the order of their glyphs.
+/// * `first` - The value of the first character.
+/// * `step` - How much the value grows from a character to the next.
+#[derive(Debug, Clone)]
+pub struct Charset {
+  chars: Vec<char>,
+  first: Expr,
+  step: Expr,
+}
+
+impl Charset {
+  /// Creates a charset.
+  ///
+  /// # Arguments
+  ///
+  /// * `chars` - The characters, in the order of their glyphs.
+  /// * `first` - The value of the first character.
+  /// * `step` - How much the value grows from a character to the next.
+  /// * `line` - The line of the `CHARSET`, for errors.
+  ///
+  /// # Returns
+  ///
+  /// * `Ok(Charset)` - The charset.
+  /// * `Err(Error)` - If `chars` is empty or has a character twice.
+  pub fn new(chars: &str, first: Expr, step: Expr, line: usize) -> Result<Self, Error> {
+    let chars = chars.chars().collect::<Vec<_>>();
+    if chars.is_empty() {
+      return Error::InvalidCharset("it has no characters".to_string(), line).warp();
+    }
+    for (index, char) in chars.iter().enumerate() {
+      if chars[..index].contains(char) {
+        return Error::InvalidCharset(format!("'{}' is in it twice", char), line).warp();
+      }
+    }
+    Ok(Charset { chars, first, step })
+  }
+
+  /// Encodes the string of a `TEXT`.
+  ///
+  /// # Arguments
+  ///
+  /// * `text` - The string.
+  /// * `line` - The line of the `TEXT`, for errors.
+  ///
+  /// # Returns
+  ///
+  /// * `Ok(Vec<Expr>)` - The value of every character, evaluated with the other
+  ///   operands so `first` and `step` can use labels defined later.
+  /// * `Err(Error)` - If a character is not in the charset.
+  pub fn encode(&self, text: &str, line: usize) -> Result<Vec<Expr>, Error> {
+    text
+      .chars()
+      .map(
+        |char| match self.chars.iter().position(|&glyph| glyph == char) {
+          Some(index) => Ok(Expr::Binary(
+            BinaryOp::Add,
+            Box::new(self.first.clone()),
+            Box::new(Expr::Binary(
+              BinaryOp::Mul,
+              Box::new(Expr::Number(index as i64)),
+              Box::new(self.step.clone()),
+            )),
+          )),
+          None => Error::MissingCharacter(char, line).warp(),
+        },
+      )
+      .collect()
+  }
+}
+
+/// Replaces the escapes of a string, `\"` and `\\`, by the characters they stand for.
+/// Other backslashes are kept as they are.
+///
+/// # Arguments
+///
+/// * `text` - The string, as written between its quotes.
+///
+/// # Returns
+///
+/// * `Cow<str>` - The string with its escapes replaced.
+pub fn unescape(text: &str) -> Cow<'_, str> {
+  if !text.contains('\\') {
+    return Cow::Borrowed(text);
+  }
+  let mut unescaped = String::new();
+  let mut chars = text.chars();
+  while let Some(char) = chars.next() {
+    match (char, chars.clone().next()) {
+      ('\\', Some(escaped @ ('"' | '\\'))) => {
+        unescaped.push(escaped);
+        chars.next();
+      }
+      (char, _) => unescaped.push(char),
+    }
+  }
+  Cow::Owned(unescaped)
+}
@@ -9,11 +9,19 @@ pub enum Token<'src> {
   Identifier(&'src str),
   Register(u8),
   Number(u16),
+  /// An expression operator or parenthesis.
+  Operator(&'src str),
+  /// A string literal, without the quotes.
+  Str(&'src str),
   Comma,
   LineBreak,
   Eof,
 }
 
+/// Directives whose operand list goes on with the next line when a line ends
+/// with a comma.
+pub const LIST_DIRECTIVES: [&str; 5] = ["SPRITE", "DB", "DW", "TEXT", "TEXTW"];
+
 /// Represents a line of Chip-8 Assembly
 /// Its easier to evaluate a line at a time
 ///
@@ -21,9 +29,12 @@ pub enum Token<'src> {
 ///
 /// * `tokens` - The tokens that make up the line
 /// * `line` - The line number
+/// * `end` - The number of the last line, after the rows of an operand list
+///   going on after a comma
 pub struct Line<'src> {
   pub tokens: Vec<Token<'src>>,
   pub line: usize,
+  pub end: usize,
 }
 
 /// Transform the assembly into tokens
@@ -46,10 +57,14 @@ impl<'src> Tokenizer<'src> {
   /// * `Err(Error)` - If there was an error tokenizing any of the tokens
   pub fn get_line(&mut self) -> Result<Line<'src>, Error> {
     let mut tokens: Vec<Token<'src>> = Vec::new();
-    let line = self.line;
+    let (line, mut end) = (self.line, self.line);
     loop {
       let token = self.next_token()?;
+      // The operand list of a directive ending with a comma goes on with the next line.
       if matches!(token, Token::LineBreak) {
+        if matches!(tokens.last(), Some(Token::Comma)) && is_list(&tokens) {
+          continue;
+        }
         break;
       }
 
@@ -58,13 +73,15 @@ impl<'src> Tokenizer<'src> {
           return Ok(Line {
             tokens: vec![Token::Eof],
             line: self.line,
+            end: self.line,
           });
         }
         break;
       }
+      end = self.line;
       tokens.push(token);
     }
-    Ok(Line { tokens, line })
+    Ok(Line { tokens, line, end })
   }
 
   /// Get the next token
@@ -83,28 +100,23 @@ impl<'src> Tokenizer<'src> {
           self.src = &self.src[1..];
           return Ok(Token::LineBreak);
         }
-        [b'V', b'0'..=b'9' | b'a'..=b'f' | b'A'..=b'F', ..] => {
+        [b'#', b'0'..=b'9' | b'a'..=b'f' | b'A'..=b'F', ..] => {
           self.src = &self.src[1..];
-          let space = self.next_space();
-          let reg_str = self.consume(space);
-          let reg = u8::from_str_radix(reg_str, 16);
-          match reg {
-            Ok(reg) => {
-              if reg > 0xF {
-                return Error::InvalidRegister(reg as _, self.line).warp();
-              }
-              return Ok(Token::Register(reg));
-            }
+          let word = self.next_word();
+          let num_str = self.consume(word);
+          let num = u16::from_str_radix(num_str, 16);
+          match num {
+            Ok(num) => return Ok(Token::Number(num)),
             Err(err) => {
-              return Error::InvalidNumber(err, reg_str.to_string(), self.line).warp();
+              return Error::InvalidNumber(err, num_str.to_string(), self.line).warp();
             }
           }
         }
-        [b'#', b'0'..=b'9' | b'a'..=b'f' | b'A'..=b'F', ..] => {
-          self.src = &self.src[1..];
-          let space = self.next_space();
-          let num_str = self.consume(space);
-          let num = u16::from_str_radix(num_str, 16);
+        [b'0', b'b' | b'B', b'0' | b'1', ..] => {
+          self.src = &self.src[2..];
+          let word = self.next_word();
+          let num_str = self.consume(word);
+          let num = u16::from_str_radix(num_str, 2);
           match num {
             Ok(num) => return Ok(Token::Number(num)),
             Err(err) => {
@@ -113,8 +125,8 @@ impl<'src> Tokenizer<'src> {
           }
         }
         [b'0'..=b'9', ..] => {
-          let space = self.next_space();
-          let num_str = self.consume(space);
+          let word = self.next_word();
+          let num_str = self.consume(word);
           let num = num_str.parse::<u16>();
           match num {
             Ok(num) => return Ok(Token::Number(num)),
@@ -127,11 +139,43 @@ impl<'src> Tokenizer<'src> {
           let _ = self.consume(1);
           return Ok(Token::Comma);
         }
-        [b'a'..=b'z' | b'A'..=b'Z' | b'[' | b']', ..] => {
+        [b'<', b'<', ..] | [b'>', b'>', ..] => return Ok(Token::Operator(self.consume(2))),
+        [b'+' | b'-' | b'*' | b'/' | b'%' | b'&' | b'|' | b'^' | b'~' | b'(' | b')', ..] => {
+          return Ok(Token::Operator(self.consume(1)));
+        }
+        [b'"', ..] => {
+          // A backslash escapes the character after it, like a quote.
+          let bytes = self.src.as_bytes();
+          let mut end = 1;
+          while end < bytes.len() && !matches!(bytes[end], b'"' | b'\n') {
+            let escape = bytes[end] == b'\\' && bytes.get(end + 1) != Some(&b'\n');
+            end += if escape { 2 } else { 1 };
+          }
+          match bytes.get(end) {
+            Some(b'"') => {
+              let text = &self.src[1..end];
+              self.src = &self.src[end + 1..];
+              return Ok(Token::Str(text));
+            }
+            _ => {
+              let end = end.min(self.src.len());
+              return Error::InvalidToken(self.consume(end).to_string(), self.line).warp();
+            }
+          }
+        }
+        [b'[', ..] => {
           let space = self.next_space();
-          let id = self.consume(space);
-          if id.ends_with(':') {
-            return Ok(Token::Label(id.strip_suffix(':').unwrap()));
+          return Ok(Token::Identifier(self.consume(space)));
+        }
+        [b'a'..=b'z' | b'A'..=b'Z' | b'_', ..] => {
+          let word = self.next_word();
+          let id = self.consume(word);
+          if let [b'V', b'0'..=b'9' | b'a'..=b'f' | b'A'..=b'F'] = id.as_bytes() {
+            return Ok(Token::Register(u8::from_str_radix(&id[1..], 16).unwrap()));
+          }
+          if self.src.starts_with(':') {
+            let _ = self.consume(1);
+            return Ok(Token::Label(id));
           }
           return Ok(Token::Identifier(id));
         }
@@ -167,6 +211,20 @@ impl<'src> Tokenizer<'src> {
       .unwrap_or(self.src.len())
   }
 
+  /// Get the end of the word (letters, digits, `_` and `.`) at the start of the input
+  ///
+  /// # Returns
+  ///
+  /// * `usize` - The index of the first character that is not part of the word
+  fn next_word(&mut self) -> usize {
+    self
+      .src
+      .as_bytes()
+      .iter()
+      .position(|&c| !(c.is_ascii_alphanumeric() || c == b'_' || c == b'.'))
+      .unwrap_or(self.src.len())
+  }
+
   /// Consume the next n characters from the input and return them
   ///
   /// # Arguments
@@ -185,17 +243,26 @@ impl<'src> Tokenizer<'src> {
   }
 }
 
+/// Returns `true` if the tokens are the start of a statement of `LIST_DIRECTIVES`.
+fn is_list(tokens: &[Token]) -> bool {
+  let statement = match tokens {
+    [Token::Label(_), statement @ ..] => statement,
+    statement => statement,
+  };
+  matches!(statement.first(), Some(Token::Identifier(name)) if LIST_DIRECTIVES.contains(name))
+}
+
 impl<'src> Iterator for Tokenizer<'src> {
   type Item = Result<Line<'src>, Error>;
 
   /// Get the next line of tokens
   fn next(&mut self) -> Option<Self::Item> {
     let line: Result<Line<'src>, Error> = self.get_line();
-    if let Ok(Line { tokens, line }) = line {
+    if let Ok(Line { tokens, line, end }) = line {
       if let [Token::Eof] = tokens.as_slice() {
         None
       } else {
-        Some(Ok(Line { tokens, line }))
+        Some(Ok(Line { tokens, line, end }))
       }
     } else {
       Some(line)
@@ -211,6 +278,8 @@ pub enum OwnedToken {
   Identifier(String),
   Register(u8),
   Number(u16),
+  Operator(String),
+  Str(String),
   Comma,
   LineBreak,
   Eof,
@@ -223,6 +292,8 @@ impl<'src> From<&Token<'src>> for OwnedToken {
       Token::Identifier(s) => OwnedToken::Identifier(s.to_string()),
       Token::Register(u) => OwnedToken::Register(*u),
       Token::Number(u) => OwnedToken::Number(*u),
+      Token::Operator(s) => OwnedToken::Operator(s.to_string()),
+      Token::Str(s) => OwnedToken::Str(s.to_string()),
       Token::Comma => OwnedToken::Comma,
       Token::Eof => OwnedToken::Eof,
       Token::LineBreak => OwnedToken::LineBreak,
@@ -235,6 +306,7 @@ impl<'src> From<&Token<'src>> for OwnedToken {
 pub struct OwnedLine {
   pub tokens: Vec<OwnedToken>,
   pub line: usize,
+  pub end: usize,
 }
 
 impl<'src> From<&Line<'src>> for OwnedLine {
@@ -242,6 +314,7 @@ impl<'src> From<&Line<'src>> for OwnedLine {
     OwnedLine {
       tokens: line.tokens.iter().map(|t| t.into()).collect(),
       line: line.line,
+      end: line.end,
     }
   }
 }
@@ -253,6 +326,8 @@ impl OwnedToken {
       OwnedToken::Identifier(s) => Token::Identifier(s.as_str()),
       OwnedToken::Register(u) => Token::Register(*u),
       OwnedToken::Number(u) => Token::Number(*u),
+      OwnedToken::Operator(s) => Token::Operator(s.as_str()),
+      OwnedToken::Str(s) => Token::Str(s.as_str()),
       OwnedToken::Comma => Token::Comma,
       OwnedToken::LineBreak => Token::LineBreak,
       OwnedToken::Eof => Token::Eof,
@@ -265,6 +340,7 @@ impl OwnedLine {
     Line {
       tokens: self.tokens.iter().map(|t| t.to_token()).collect(),
       line: self.line,
+      end: self.end,
     }
   }
 }
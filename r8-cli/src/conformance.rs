@@ -0,0 +1,480 @@
+//! Conformance runner for the community CHIP-8 test ROMs.
+//!
+//! The test ROMs report their results on screen with a pass or a fail glyph next
+//! to every opcode or quirk. A `Suite` describes where those glyphs are drawn and
+//! what they look like, the runner reads each cell back from the framebuffer. A
+//! cell that shows neither glyph is reported as unknown, so a layout that does not
+//! match the ROMs never turns into a false pass.
+//!
+//! The bundled suite targets the Timendus test suite, other versions or suites can
+//! be described in the same JSON format.
+
+use std::{fmt, fs, io, path::Path};
+
+use r8_emulator::{constants, Display, Emulator, InputScript, Quirks};
+use serde::Deserialize;
+
+use crate::run::{self, StopReason};
+
+/// The bundled description of the Timendus CHIP-8 test suite.
+pub const TIMENDUS: &str = include_str!("../conformance/timendus.json");
+
+/// How far (in pixels, both ways) a glyph may be from its cell and still match.
+const SLACK: usize = 2;
+
+/// The glyphs and layout of a set of test ROMs.
+///
+/// # Fields
+///
+/// * `name` - The name of the suite, printed in the report header.
+/// * `ticks_per_frame` - How many ticks make one frame of the test ROMs.
+/// * `pass` - The pass glyph, one string per row (`#` lit, `.` unlit).
+/// * `fail` - The fail glyph, in the same format.
+/// * `roms` - The test ROMs.
+#[derive(Deserialize, Debug)]
+pub struct Suite {
+  pub name: String,
+  pub ticks_per_frame: u32,
+  pass: Vec<String>,
+  fail: Vec<String>,
+  pub roms: Vec<SuiteRom>,
+}
+
+/// A test ROM and the cells its results are drawn in.
+///
+/// Checks are laid out in reading order on the grid spanned by `columns` and
+/// `rows`, which give the top-left corner of every cell.
+///
+/// # Fields
+///
+/// * `title` - A short name for the report.
+/// * `file` - The file name of the ROM in the suite directory.
+/// * `frames` - How many frames to run before reading the results.
+/// * `script` - The input script, one entry per line.
+/// * `platforms` - When set, the ROM runs once per platform with its own script
+///   (e.g. the menu choice of the quirks test) and quirks instead of `script`.
+/// * `columns` - The x coordinate of every grid column.
+/// * `rows` - The y coordinate of every grid row.
+/// * `checks` - The name of every checked behaviour, in reading order.
+#[derive(Deserialize, Debug)]
+pub struct SuiteRom {
+  pub title: String,
+  pub file: String,
+  pub frames: u64,
+  #[serde(default)]
+  script: Vec<String>,
+  #[serde(default)]
+  platforms: Vec<Platform>,
+  columns: Vec<usize>,
+  rows: Vec<usize>,
+  pub checks: Vec<String>,
+}
+
+/// A platform profile a test ROM can be run for.
+///
+/// # Fields
+///
+/// * `name` - The name of the profile, printed in the report.
+/// * `quirks` - The platform whose quirks the run uses, by its identifier in the
+///   CHIP-8 database (e.g. `superchip`).
+/// * `script` - The input script, one entry per line (none by default).
+#[derive(Deserialize, Debug)]
+pub struct Platform {
+  pub name: String,
+  quirks: String,
+  #[serde(default)]
+  script: Vec<String>,
+}
+
+impl Platform {
+  /// Returns the quirks the profile runs with.
+  fn quirks(&self) -> Result<Quirks, String> {
+    self
+      .quirks
+      .parse::<r8_emulator::Platform>()
+      .map(|platform| platform.quirks())
+  }
+}
+
+impl Suite {
+  /// Parses and validates a suite description.
+  pub fn parse(json: &str) -> Result<Self, String> {
+    let suite: Suite = serde_json::from_str(json).map_err(|err| err.to_string())?;
+    glyph(&suite.pass).map_err(|err| format!("pass glyph: {err}"))?;
+    glyph(&suite.fail).map_err(|err| format!("fail glyph: {err}"))?;
+    for rom in &suite.roms {
+      if rom.checks.len() > rom.columns.len() * rom.rows.len() {
+        return Err(format!("{}: more checks than grid cells", rom.title));
+      }
+      for (x, y) in rom.cells() {
+        if x >= constants::WIDTH || y >= constants::HEIGHT {
+          return Err(format!("{}: cell ({x}, {y}) is off screen", rom.title));
+        }
+      }
+      for (_, script) in rom.scripts() {
+        script.map_err(|err| format!("{}: {err}", rom.title))?;
+      }
+      for platform in &rom.platforms {
+        platform
+          .quirks()
+          .map_err(|err| format!("{} ({}): {err}", rom.title, platform.name))?;
+      }
+    }
+    Ok(suite)
+  }
+}
+
+impl SuiteRom {
+  /// Returns the top-left corner of every check, in the order of `checks`.
+  fn cells(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+    self
+      .rows
+      .iter()
+      .flat_map(|&y| self.columns.iter().map(move |&x| (x, y)))
+      .take(self.checks.len())
+  }
+
+  /// Returns the input of every run and its platform (`None` without platforms).
+  fn scripts(&self) -> Vec<(Option<&Platform>, Result<InputScript, String>)> {
+    let parse = |lines: &[String]| lines.join("\n").parse().map_err(|err| format!("{err}"));
+    if self.platforms.is_empty() {
+      vec![(None, parse(&self.script))]
+    } else {
+      self
+        .platforms
+        .iter()
+        .map(|platform| (Some(platform), parse(&platform.script)))
+        .collect()
+    }
+  }
+}
+
+/// Parses a glyph, one string per row.
+fn glyph(rows: &[String]) -> Result<Vec<Vec<bool>>, String> {
+  let width = rows.first().map_or(0, String::len);
+  if width == 0 || rows.iter().any(|row| row.len() != width) {
+    return Err("rows must be non-empty and of the same length".to_string());
+  }
+  rows
+    .iter()
+    .map(|row| {
+      row
+        .chars()
+        .map(|c| match c {
+          '#' => Ok(true),
+          '.' => Ok(false),
+          c => Err(format!("unexpected `{c}`, use `#` and `.`")),
+        })
+        .collect()
+    })
+    .collect()
+}
+
+/// The result of one check.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Verdict {
+  /// The pass glyph was found.
+  Pass,
+  /// The fail glyph was found.
+  Fail,
+  /// Neither glyph was found.
+  Unknown,
+  /// The ROM could not be run.
+  Error(String),
+}
+
+impl fmt::Display for Verdict {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(match self {
+      Verdict::Pass => "pass",
+      Verdict::Fail => "FAIL",
+      Verdict::Unknown => "?",
+      Verdict::Error(_) => "error",
+    })
+  }
+}
+
+/// Returns `true` if `glyph` is drawn with its top-left corner at `(x, y)`.
+fn matches_at(display: &Display, glyph: &[Vec<bool>], x: usize, y: usize) -> bool {
+  glyph.iter().enumerate().all(|(dy, row)| {
+    row.iter().enumerate().all(|(dx, &lit)| {
+      let (px, py) = (x + dx, y + dy);
+      px < constants::WIDTH && py < constants::HEIGHT && display.get(px, py) == lit
+    })
+  })
+}
+
+/// Reads the check drawn near `(x, y)`.
+fn read_cell(
+  display: &Display,
+  pass: &[Vec<bool>],
+  fail: &[Vec<bool>],
+  x: usize,
+  y: usize,
+) -> Verdict {
+  let nearby = |glyph: &[Vec<bool>]| {
+    (y.saturating_sub(SLACK)..=y + SLACK)
+      .any(|y| (x.saturating_sub(SLACK)..=x + SLACK).any(|x| matches_at(display, glyph, x, y)))
+  };
+  if nearby(pass) {
+    Verdict::Pass
+  } else if nearby(fail) {
+    Verdict::Fail
+  } else {
+    Verdict::Unknown
+  }
+}
+
+/// The results of one run of a test ROM.
+///
+/// # Fields
+///
+/// * `platform` - The platform profile of the run, if the ROM has any.
+/// * `verdicts` - One verdict per check of the ROM.
+pub struct RomRun {
+  pub platform: Option<String>,
+  pub verdicts: Vec<Verdict>,
+}
+
+/// Runs every ROM of `suite` found in `dir` and reads back the results.
+///
+/// # Returns
+///
+/// `Vec<(&SuiteRom, io::Result<Vec<RomRun>>)>` - The runs of every ROM, or the
+/// error that prevented reading it.
+pub fn run_suite<'a>(suite: &'a Suite, dir: &Path) -> Vec<(&'a SuiteRom, io::Result<Vec<RomRun>>)> {
+  let pass = glyph(&suite.pass).expect("validated by Suite::parse");
+  let fail = glyph(&suite.fail).expect("validated by Suite::parse");
+  suite
+    .roms
+    .iter()
+    .map(|rom| {
+      let runs = fs::read(dir.join(&rom.file)).map(|bytes| {
+        rom
+          .scripts()
+          .into_iter()
+          .map(|(platform, script)| {
+            let script = script.expect("validated by Suite::parse");
+            let quirks =
+              platform.map(|platform| platform.quirks().expect("validated by Suite::parse"));
+            let verdicts = match run_rom(&bytes, &script, quirks, rom.frames, suite.ticks_per_frame)
+            {
+              Ok(emulator) => rom
+                .cells()
+                .map(|(x, y)| read_cell(emulator.display(), &pass, &fail, x, y))
+                .collect(),
+              Err(err) => vec![Verdict::Error(err); rom.checks.len()],
+            };
+            RomRun {
+              platform: platform.map(|platform| platform.name.clone()),
+              verdicts,
+            }
+          })
+          .collect()
+      });
+      (rom, runs)
+    })
+    .collect()
+}
+
+/// Runs a test ROM for `frames` frames and returns the emulator.
+///
+/// # Arguments
+///
+/// * `quirks` - The quirks of the platform profile, `None` for the default ones.
+fn run_rom(
+  rom: &[u8],
+  script: &InputScript,
+  quirks: Option<Quirks>,
+  frames: u64,
+  ticks_per_frame: u32,
+) -> Result<Emulator, String> {
+  let mut emulator = Emulator::new();
+  emulator.load_rom(rom).map_err(|err| err.to_string())?;
+  if let Some(quirks) = quirks {
+    emulator.set_quirks(quirks);
+  }
+  let outcome = run::run(
+    &mut emulator,
+    script,
+    &[],
+    frames,
+    ticks_per_frame,
+    &mut |_| Ok(()),
+  );
+  match outcome.reason {
+    StopReason::Error(err) => Err(err),
+    _ => Ok(emulator),
+  }
+}
+
+/// Writes the conformance table: one section per ROM, one row per check and one
+/// column per platform profile.
+pub fn write_report<W: io::Write>(
+  suite: &Suite,
+  results: &[(&SuiteRom, io::Result<Vec<RomRun>>)],
+  mut out: W,
+) -> io::Result<()> {
+  writeln!(out, "{}", suite.name)?;
+  for (rom, runs) in results {
+    writeln!(out, "\n{} ({})", rom.title, rom.file)?;
+    let runs = match runs {
+      Ok(runs) => runs,
+      Err(err) => {
+        writeln!(out, "  skipped: {err}")?;
+        continue;
+      }
+    };
+    let width = rom.checks.iter().map(String::len).max().unwrap_or(0);
+    let mut line = |first: &str, cells: Vec<String>| {
+      let cells: String = cells.iter().map(|cell| format!("  {cell:8}")).collect();
+      writeln!(out, "{}", format!("  {first:width$}{cells}").trim_end())
+    };
+    if runs.iter().any(|run| run.platform.is_some()) {
+      let platforms = runs
+        .iter()
+        .map(|run| run.platform.clone().unwrap_or_default());
+      line("", platforms.collect())?;
+    }
+    for (index, check) in rom.checks.iter().enumerate() {
+      line(
+        check,
+        runs
+          .iter()
+          .map(|run| run.verdicts[index].to_string())
+          .collect(),
+      )?;
+    }
+    let errors = runs.iter().filter_map(|run| {
+      run.verdicts.iter().find_map(|verdict| match verdict {
+        Verdict::Error(err) => Some((run.platform.as_deref(), err)),
+        _ => None,
+      })
+    });
+    for (platform, err) in errors {
+      match platform {
+        Some(platform) => writeln!(out, "  {platform}: {err}")?,
+        None => writeln!(out, "  {err}")?,
+      }
+    }
+  }
+
+  let verdicts: Vec<_> = results
+    .iter()
+    .filter_map(|(_, runs)| runs.as_ref().ok())
+    .flatten()
+    .flat_map(|run| &run.verdicts)
+    .collect();
+  let count = |wanted: fn(&Verdict) -> bool| verdicts.iter().filter(|v| wanted(v)).count();
+  writeln!(
+    out,
+    "\n{} passed, {} failed, {} unknown, {} errors",
+    count(|v| *v == Verdict::Pass),
+    count(|v| *v == Verdict::Fail),
+    count(|v| *v == Verdict::Unknown),
+    count(|v| matches!(v, Verdict::Error(_))),
+  )
+}
+
+/// Returns `true` if every check of every ROM that ran passed.
+pub fn all_passed(results: &[(&SuiteRom, io::Result<Vec<RomRun>>)]) -> bool {
+  results
+    .iter()
+    .filter_map(|(_, runs)| runs.as_ref().ok())
+    .flatten()
+    .all(|run| run.verdicts.iter().all(|verdict| *verdict == Verdict::Pass))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn bundled_suite_is_valid() {
+    let suite = Suite::parse(TIMENDUS).unwrap();
+    // Every ROM checks behaviours some quirk changes (shift, load/store, vF reset,
+    // key release...), so each one runs for every platform profile.
+    for rom in &suite.roms {
+      let platforms: Vec<_> = rom.platforms.iter().map(|p| p.quirks.as_str()).collect();
+      assert_eq!(
+        platforms,
+        ["originalChip8", "superchip", "xochip"],
+        "{}",
+        rom.title
+      );
+    }
+    assert!(Suite::parse(
+      r##"{"name": "", "ticks_per_frame": 1, "pass": ["#", ".."], "fail": ["#"], "roms": []}"##
+    )
+    .is_err());
+    let unknown_platform = TIMENDUS.replace("\"superchip\"", "\"schip\"");
+    assert!(Suite::parse(&unknown_platform)
+      .unwrap_err()
+      .contains("unknown platform `schip`"));
+  }
+
+  #[test]
+  fn reads_glyphs_from_the_screen() {
+    // Draws the pass glyph of the bundled suite at (5, 3), then halts.
+    let rom = [
+      0x60, 0x05, 0x61, 0x03, 0xA2, 0x0A, 0xD0, 0x14, 0x12, 0x08, 0x08, 0x10, 0xA0, 0x40,
+    ];
+    let emulator = run_rom(&rom, &InputScript::default(), None, 1, 4).unwrap();
+    let suite = Suite::parse(TIMENDUS).unwrap();
+    let (pass, fail) = (glyph(&suite.pass).unwrap(), glyph(&suite.fail).unwrap());
+
+    let read = |x, y| read_cell(emulator.display(), &pass, &fail, x, y);
+    assert_eq!(read(5, 3), Verdict::Pass);
+    assert_eq!(read(7, 1), Verdict::Pass);
+    assert_eq!(read(30, 20), Verdict::Unknown);
+  }
+
+  #[test]
+  fn reads_verdicts_of_a_suite() {
+    // Draws the pass glyph at (10, 2) and the fail glyph at (30, 2), then halts.
+    let rom = [
+      0x60, 0x0A, 0x61, 0x02, 0xA2, 0x12, 0xD0, 0x14, 0x60, 0x1E, 0xA2, 0x16, 0xD0, 0x15, 0x12,
+      0x0E, 0x00, 0x00, 0x08, 0x10, 0xA0, 0x40, 0x88, 0x50, 0x20, 0x50, 0x88,
+    ];
+    let dir = std::env::temp_dir().join(format!("r8-cli-conformance-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("marks.ch8"), rom).unwrap();
+    let bundled = Suite::parse(TIMENDUS).unwrap();
+    let suite = Suite::parse(&format!(
+      r#"{{"name": "marks", "ticks_per_frame": 10, "pass": {:?}, "fail": {:?}, "roms": [
+        {{"title": "marks", "file": "marks.ch8", "frames": 2,
+          "columns": [11, 29, 50], "rows": [1], "checks": ["pass", "fail", "blank"]}}
+      ]}}"#,
+      bundled.pass, bundled.fail
+    ))
+    .unwrap();
+
+    let results = run_suite(&suite, &dir);
+    let runs = results[0].1.as_ref().unwrap();
+    assert_eq!(
+      runs[0].verdicts,
+      [Verdict::Pass, Verdict::Fail, Verdict::Unknown]
+    );
+    assert!(!all_passed(&results));
+    let mut report = vec![];
+    write_report(&suite, &results, &mut report).unwrap();
+    let report = String::from_utf8(report).unwrap();
+    assert!(report.contains("  fail   FAIL\n"));
+    assert!(report.ends_with("1 passed, 1 failed, 1 unknown, 0 errors\n"));
+    fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn missing_roms_are_skipped() {
+    let suite = Suite::parse(TIMENDUS).unwrap();
+    let results = run_suite(&suite, Path::new("/nonexistent"));
+    assert!(results.iter().all(|(_, runs)| runs.is_err()));
+    assert!(all_passed(&results));
+
+    let mut report = vec![];
+    write_report(&suite, &results, &mut report).unwrap();
+    let report = String::from_utf8(report).unwrap();
+    assert!(report.contains("quirks (5-quirks.ch8)\n  skipped"));
+    assert!(report.ends_with("0 passed, 0 failed, 0 unknown, 0 errors\n"));
+  }
+}
@@ -0,0 +1,150 @@
+//! Final state dumps: the framebuffer as ASCII/PBM/PNG and the machine state as JSON.
+
+use std::{io, path::Path};
+
+use r8_emulator::{
+  constants,
+  emulator::State,
+  export::{self, ExportOptions, Frame, ImageFormat},
+  Address, Display, Emulator,
+};
+use serde::Serialize;
+
+use crate::run::{Outcome, StopReason};
+
+/// Output format of a framebuffer dump.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScreenFormat {
+  /// One line per display row, `#` for lit pixels and `.` for unlit ones.
+  Ascii,
+  /// An image written by `r8_emulator::export`.
+  Image(ImageFormat),
+}
+
+impl ScreenFormat {
+  /// Picks the format from the file extension, anything that is not an image is ASCII.
+  pub fn from_path(path: &Path) -> Self {
+    ImageFormat::from_path(path).map_or(ScreenFormat::Ascii, ScreenFormat::Image)
+  }
+}
+
+/// Returns the framebuffer as text, one line per display row.
+pub fn ascii(display: &Display) -> String {
+  let mut text = String::with_capacity((constants::WIDTH + 1) * constants::HEIGHT);
+  for y in 0..constants::HEIGHT {
+    text.extend((0..constants::WIDTH).map(|x| if display.get(x, y) { '#' } else { '.' }));
+    text.push('\n');
+  }
+  text
+}
+
+/// Writes the framebuffer in the given format.
+///
+/// # Arguments
+///
+/// * `display` - The display to dump.
+/// * `format` - The output format.
+/// * `options` - Palette, scale and grid of image formats.
+/// * `out` - Where to write it.
+pub fn write_screen<W: io::Write>(
+  display: &Display,
+  format: ScreenFormat,
+  options: &ExportOptions,
+  mut out: W,
+) -> io::Result<()> {
+  match format {
+    ScreenFormat::Ascii => out.write_all(ascii(display).as_bytes()),
+    ScreenFormat::Image(format) => export::write_image(&Frame::from(display), format, options, out),
+  }
+}
+
+/// The JSON report of a run.
+#[derive(Serialize)]
+pub struct Report {
+  frames: u64,
+  ticks: u64,
+  stop_reason: &'static str,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  error: Option<String>,
+  state: &'static str,
+  pc: u16,
+  i: u16,
+  v: [u8; 16],
+  delay_timer: u8,
+  sound_timer: u8,
+  stack: Vec<u16>,
+  /// The whole 4 KiB address space as a lowercase hex string.
+  memory: String,
+}
+
+impl Report {
+  /// Captures the machine state at the end of a run.
+  pub fn new(emulator: &Emulator, outcome: &Outcome) -> Self {
+    let mut v = [0; 16];
+    for (index, value) in (0..).zip(v.iter_mut()) {
+      *value = *emulator.v_registers().try_index(index).unwrap();
+    }
+    let mut memory = [0; 0x1000];
+    emulator
+      .read_memory(Address::new(0), &mut memory)
+      .expect("the whole memory is readable");
+
+    Self {
+      frames: outcome.frames,
+      ticks: outcome.ticks,
+      stop_reason: outcome.reason.name(),
+      error: match &outcome.reason {
+        StopReason::Error(err) => Some(err.clone()),
+        _ => None,
+      },
+      state: match emulator.state() {
+        State::New => "new",
+        State::Running => "running",
+        State::WaitingKey { .. } => "waiting-key",
+      },
+      pc: emulator.pc().inner(),
+      i: emulator.i().inner(),
+      v,
+      delay_timer: emulator.delay_timer(),
+      sound_timer: emulator.sound_timer(),
+      stack: emulator.stack().iter().map(Address::inner).collect(),
+      memory: memory.iter().map(|byte| format!("{byte:02x}")).collect(),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn screen_formats() {
+    let mut emulator = Emulator::new();
+    // Draw the font glyph "0" (a 4x5 box) at (0, 0).
+    emulator
+      .load_rom(&[0xA0u8, 0x00, 0xD0, 0x05] as &[u8])
+      .unwrap();
+    emulator.tick().unwrap();
+    emulator.tick().unwrap();
+
+    let text = ascii(emulator.display());
+    let lines: Vec<_> = text.lines().collect();
+    assert_eq!(lines.len(), constants::HEIGHT);
+    assert!(lines[0].starts_with("####...."));
+    assert!(lines[1].starts_with("#..#...."));
+
+    let mut pbm = vec![];
+    let format = ScreenFormat::Image(ImageFormat::Pbm);
+    write_screen(emulator.display(), format, &Default::default(), &mut pbm).unwrap();
+    assert!(pbm.starts_with(b"P4\n64 32\n\x0F"));
+
+    assert_eq!(
+      ScreenFormat::from_path(Path::new("out.PNG")),
+      ScreenFormat::Image(ImageFormat::Png)
+    );
+    assert_eq!(
+      ScreenFormat::from_path(Path::new("out.txt")),
+      ScreenFormat::Ascii
+    );
+  }
+}
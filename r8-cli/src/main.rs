@@ -0,0 +1,262 @@
+use std::{
+  fs::File,
+  io::{self, Write},
+  path::{Path, PathBuf},
+  process::ExitCode,
+  time::Duration,
+};
+
+use clap::Parser;
+mod conformance;
+mod dump;
+mod run;
+use crate::conformance::Suite;
+use crate::dump::{Report, ScreenFormat};
+use crate::run::{StopCondition, StopReason};
+use r8_emulator::{
+  export::{self, ExportOptions, Frame, Palette},
+  record::GifRecorder,
+  Emulator, InputScript, RomDb,
+};
+
+/// How long a frame lasts in the recording: frames are steps of the 60 Hz timers.
+const FRAME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+/// CLI for the headless runner
+#[derive(Parser)]
+/// R8 - Headless Chip-8 runner
+///
+/// Exits with 0 when a stop condition holds (or the frame limit is reached and no
+/// condition was given), 2 when the frame limit is reached before any condition
+/// and 1 on errors.
+pub struct R8Cli {
+  /// Path to the ROM to load
+  #[clap(short, long)]
+  rom: Option<PathBuf>,
+  /// Path to the assembly file to load
+  #[clap(short, long)]
+  asm: Option<PathBuf>,
+  /// Maximum number of frames to run
+  #[clap(short, long, default_value_t = 600)]
+  frames: u64,
+  /// Emulator ticks per frame (default: the ROM database tickrate, or 1)
+  #[clap(short, long)]
+  ticks_per_frame: Option<u32>,
+  /// Stop when a condition holds: pc=ADDR, key-wait or halt (repeatable)
+  #[clap(short, long)]
+  until: Vec<StopCondition>,
+  /// Input script with the key presses to feed
+  #[clap(short, long)]
+  script: Option<PathBuf>,
+  /// Seed of the random number generator
+  #[clap(long, default_value_t = 0)]
+  seed: u128,
+  /// Dump the final framebuffer (.pbm, .pgm, .png or ASCII otherwise, `-` for stdout)
+  #[clap(long)]
+  screen: Option<PathBuf>,
+  /// Record every frame to an animated GIF
+  #[clap(long)]
+  record: Option<PathBuf>,
+  /// Image pixels per display pixel of the screen dump and the recording (1 to 64)
+  #[clap(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..=export::MAX_SCALE as i64))]
+  scale: u32,
+  /// Colours of the PNG screen dump and the recording as OFF:ON, e.g. 000000:33FF66
+  #[clap(long, default_value_t = Palette::CLASSIC)]
+  palette: Palette,
+  /// Dump the registers and memory as JSON (`-` for stdout)
+  #[clap(long)]
+  state: Option<PathBuf>,
+  /// ROM database (`programs.json`) used instead of the bundled one
+  #[clap(long, conflicts_with = "no_romdb")]
+  romdb: Option<PathBuf>,
+  /// Run every ROM with the default quirks, without looking it up in the ROM database
+  #[clap(long)]
+  no_romdb: bool,
+  /// Guess the platform of ROMs the ROM database does not know and run them with its quirks
+  #[clap(long)]
+  detect_platform: bool,
+  /// Run the conformance test ROMs found in this directory and print the results
+  #[clap(long, conflicts_with_all = ["rom", "asm"])]
+  conformance: Option<PathBuf>,
+  /// Layout of the conformance test ROMs (default: the bundled Timendus suite)
+  #[clap(long, requires = "conformance")]
+  suite: Option<PathBuf>,
+}
+
+macro_rules! fail {
+  ($($arg:tt)*) => {{
+    eprintln!($($arg)*);
+    return ExitCode::FAILURE;
+  }};
+}
+
+fn main() -> ExitCode {
+  let args = R8Cli::parse();
+  if let Some(dir) = &args.conformance {
+    return run_conformance(&args, dir);
+  }
+
+  let rom = match load_rom(&args) {
+    Ok(rom) => rom,
+    Err(err) => fail!("{}", err),
+  };
+  let script = match &args.script {
+    Some(path) => match std::fs::read_to_string(path).map(|source| source.parse()) {
+      Ok(Ok(script)) => script,
+      Ok(Err(err)) => fail!("{}: {}", path.display(), err),
+      Err(err) => fail!("Failed to read input script: {}", err),
+    },
+    None => InputScript::default(),
+  };
+
+  let mut emu = Emulator::new();
+  match (&args.romdb, args.no_romdb) {
+    (Some(path), _) => match RomDb::load(path) {
+      Ok(db) => emu.set_rom_db(Some(db.into())),
+      Err(err) => fail!("Failed to load ROM database {}: {}", path.display(), err),
+    },
+    (None, false) => emu.set_rom_db(Some(RomDb::bundled())),
+    (None, true) => {}
+  }
+  emu.set_platform_detection(args.detect_platform);
+  if let Err(err) = emu.load_rom(rom.as_slice()) {
+    fail!("Failed to load ROM: {}", err);
+  }
+  emu.set_rand_seed(args.seed);
+  if let Some(detection) = emu.detection() {
+    eprintln!(
+      "Detected {} ({:.0}% confidence)",
+      detection.platform.name(),
+      detection.confidence * 100.0
+    );
+  }
+  let ticks_per_frame = args
+    .ticks_per_frame
+    .or_else(|| emu.rom_info().and_then(|info| info.tickrate))
+    .unwrap_or(1);
+
+  let options = ExportOptions {
+    palette: args.palette,
+    scale: args.scale,
+    grid: None,
+  };
+  let mut recorder = match &args.record {
+    Some(path) => match File::create(path)
+      .and_then(|file| GifRecorder::new(io::BufWriter::new(file), &options))
+    {
+      Ok(recorder) => Some(recorder),
+      Err(err) => fail!("Failed to start the recording: {}", err),
+    },
+    None => None,
+  };
+
+  let outcome = run::run(
+    &mut emu,
+    &script,
+    &args.until,
+    args.frames,
+    ticks_per_frame,
+    &mut |emu| match &mut recorder {
+      Some(recorder) => recorder.push(Frame::from(emu.display()), FRAME),
+      None => Ok(()),
+    },
+  );
+
+  if let Some(recorder) = recorder {
+    if let Err(err) = recorder.finish().and_then(|mut file| file.flush()) {
+      fail!("Failed to write the recording: {}", err);
+    }
+  }
+  if let Some(path) = &args.screen {
+    let format = ScreenFormat::from_path(path);
+    let written = write_output(path, |out| {
+      dump::write_screen(emu.display(), format, &options, out)
+    });
+    if let Err(err) = written {
+      fail!("Failed to write the screen: {}", err);
+    }
+  }
+  if let Some(path) = &args.state {
+    let report = Report::new(&emu, &outcome);
+    let written = write_output(path, |mut out| {
+      serde_json::to_writer_pretty(&mut out, &report)?;
+      writeln!(out)
+    });
+    if let Err(err) = written {
+      fail!("Failed to write the state: {}", err);
+    }
+  }
+
+  match outcome.reason {
+    StopReason::Error(err) => fail!("Emulator error after {} ticks: {}", outcome.ticks, err),
+    StopReason::FrameLimit if !args.until.is_empty() => {
+      eprintln!("Frame limit reached before any stop condition");
+      ExitCode::from(2)
+    }
+    _ => ExitCode::SUCCESS,
+  }
+}
+
+/// Runs the conformance suite on the test ROMs in `dir`.
+///
+/// Exits with 0 when every check passed, 2 when some failed or could not be read
+/// and 1 when no test ROM was found.
+fn run_conformance(args: &R8Cli, dir: &Path) -> ExitCode {
+  let source = match &args.suite {
+    Some(path) => match std::fs::read_to_string(path) {
+      Ok(source) => source,
+      Err(err) => fail!("Failed to read the suite: {}", err),
+    },
+    None => conformance::TIMENDUS.to_string(),
+  };
+  let suite = match Suite::parse(&source) {
+    Ok(suite) => suite,
+    Err(err) => fail!("Invalid suite: {}", err),
+  };
+
+  let results = conformance::run_suite(&suite, dir);
+  if let Err(err) = conformance::write_report(&suite, &results, io::stdout().lock()) {
+    fail!("Failed to write the report: {}", err);
+  }
+  if results.iter().all(|(_, runs)| runs.is_err()) {
+    fail!("No test ROM found in {}", dir.display());
+  }
+  if conformance::all_passed(&results) {
+    ExitCode::SUCCESS
+  } else {
+    ExitCode::from(2)
+  }
+}
+
+/// Reads the ROM, or assembles the source file.
+fn load_rom(args: &R8Cli) -> Result<Vec<u8>, String> {
+  match (&args.rom, &args.asm) {
+    (Some(rom), None) => std::fs::read(rom).map_err(|err| format!("Failed to open ROM: {err}")),
+    (None, Some(asm)) => {
+      let mut rom = vec![];
+      let files = r8_assembly::FileSystem::default();
+      let assembly = r8_assembly::assemble_file(asm, &files, &mut rom)
+        .map_err(|err| format!("Failed to assemble: {err}"))?;
+      assembly
+        .warnings
+        .iter()
+        .for_each(|warning| eprintln!("{warning}"));
+      Ok(rom)
+    }
+    _ => Err("Please specify either a ROM or an assembly file".to_string()),
+  }
+}
+
+/// Writes to the file at `path`, or to stdout when `path` is `-`.
+fn write_output(
+  path: &Path,
+  write: impl FnOnce(&mut dyn Write) -> io::Result<()>,
+) -> io::Result<()> {
+  if path == Path::new("-") {
+    write(&mut io::stdout().lock())
+  } else {
+    let mut file = io::BufWriter::new(File::create(path)?);
+    write(&mut file)?;
+    file.flush()
+  }
+}
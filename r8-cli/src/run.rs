@@ -0,0 +1,209 @@
+//! Headless run loop: ticks the emulator frame by frame, feeds the input script
+//! and stops on the first condition that holds.
+
+use std::{io, str::FromStr};
+
+use r8_emulator::{emulator::State, Emulator, InputScript};
+
+/// A condition that ends the run before the frame limit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopCondition {
+  /// The program counter reaches the given address.
+  Pc(u16),
+  /// The program waits for a key (`LD VX, K`).
+  KeyWait,
+  /// The program jumps to itself (`JP` to its own address), the usual CHIP-8 halt.
+  Halt,
+}
+
+impl FromStr for StopCondition {
+  type Err = String;
+
+  /// Parses `pc=0x2A4` (hex, `0x` optional), `key-wait` or `halt`.
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "key-wait" => Ok(StopCondition::KeyWait),
+      "halt" => Ok(StopCondition::Halt),
+      _ => {
+        let address = s
+          .strip_prefix("pc=")
+          .ok_or_else(|| format!("unknown condition `{s}`, expected pc=ADDR, key-wait or halt"))?;
+        let digits = address.trim_start_matches("0x").trim_start_matches("0X");
+        u16::from_str_radix(digits, 16)
+          .ok()
+          .filter(|&address| address <= 0xFFF)
+          .map(StopCondition::Pc)
+          .ok_or_else(|| format!("invalid address `{address}`"))
+      }
+    }
+  }
+}
+
+/// Why the run ended.
+#[derive(Debug)]
+pub enum StopReason {
+  /// One of the stop conditions held.
+  Condition(StopCondition),
+  /// The frame limit was reached.
+  FrameLimit,
+  /// The emulator returned an error.
+  Error(String),
+}
+
+impl StopReason {
+  /// Returns a short, stable name for reports.
+  pub fn name(&self) -> &'static str {
+    match self {
+      StopReason::Condition(StopCondition::Pc(_)) => "pc",
+      StopReason::Condition(StopCondition::KeyWait) => "key-wait",
+      StopReason::Condition(StopCondition::Halt) => "halt",
+      StopReason::FrameLimit => "frame-limit",
+      StopReason::Error(_) => "error",
+    }
+  }
+}
+
+/// The result of a headless run.
+///
+/// # Fields
+///
+/// * `frames` - The number of frames run (including the one the run stopped in).
+/// * `ticks` - The number of ticks run.
+/// * `reason` - Why the run ended.
+#[derive(Debug)]
+pub struct Outcome {
+  pub frames: u64,
+  pub ticks: u64,
+  pub reason: StopReason,
+}
+
+/// Runs the emulator until a stop condition holds or `max_frames` frames were run.
+///
+/// Every frame applies the script events of that frame, counts the timers down
+/// once, then runs `ticks_per_frame` ticks and finally calls `on_frame`. Conditions are checked after every tick, the
+/// frame a run stops in is still passed to `on_frame`.
+///
+/// # Arguments
+///
+/// * `emulator` - The emulator, with a ROM already loaded.
+/// * `script` - The scripted key presses.
+/// * `conditions` - The conditions that end the run early.
+/// * `max_frames` - The frame limit.
+/// * `ticks_per_frame` - How many ticks make one frame.
+/// * `on_frame` - Called with the emulator at the end of every frame, an error ends the run.
+pub fn run(
+  emulator: &mut Emulator,
+  script: &InputScript,
+  conditions: &[StopCondition],
+  max_frames: u64,
+  ticks_per_frame: u32,
+  on_frame: &mut dyn FnMut(&Emulator) -> io::Result<()>,
+) -> Outcome {
+  let mut ticks = 0;
+  for frame in 0..max_frames {
+    script.apply(frame, emulator);
+    emulator.tick_timers();
+    let mut reason = None;
+    for _ in 0..ticks_per_frame {
+      let pc = emulator.pc();
+      if let Err(err) = emulator.tick() {
+        reason = Some(StopReason::Error(err.to_string()));
+        break;
+      }
+      ticks += 1;
+
+      let waiting = matches!(emulator.state(), State::WaitingKey { .. });
+      let hit = conditions.iter().find(|condition| match condition {
+        StopCondition::Pc(address) => emulator.pc().inner() == *address,
+        StopCondition::KeyWait => waiting,
+        StopCondition::Halt => !waiting && emulator.pc() == pc,
+      });
+      if let Some(&condition) = hit {
+        reason = Some(StopReason::Condition(condition));
+        break;
+      }
+    }
+    if let Err(err) = on_frame(emulator) {
+      reason = Some(StopReason::Error(err.to_string()));
+    }
+    if let Some(reason) = reason {
+      return Outcome {
+        frames: frame + 1,
+        ticks,
+        reason,
+      };
+    }
+  }
+  Outcome {
+    frames: max_frames,
+    ticks,
+    reason: StopReason::FrameLimit,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn emulator(rom: &[u8]) -> Emulator {
+    let mut emulator = Emulator::new();
+    emulator.load_rom(rom).unwrap();
+    emulator
+  }
+
+  #[test]
+  fn parse_conditions() {
+    assert_eq!("pc=0x2A4".parse(), Ok(StopCondition::Pc(0x2A4)));
+    assert_eq!("pc=204".parse(), Ok(StopCondition::Pc(0x204)));
+    assert_eq!("halt".parse(), Ok(StopCondition::Halt));
+    assert_eq!("key-wait".parse(), Ok(StopCondition::KeyWait));
+    assert!("pc=0x1000".parse::<StopCondition>().is_err());
+    assert!("forever".parse::<StopCondition>().is_err());
+  }
+
+  #[test]
+  fn stops_on_conditions() {
+    // 200: LD V0, 1 / 202: LD V1, K / 204: JP 204
+    let rom = [0x60, 0x01, 0xF1, 0x0A, 0x12, 0x04];
+    let script = "5 tap 9".parse().unwrap();
+    let all = [StopCondition::KeyWait, StopCondition::Halt];
+
+    let mut frames = 0;
+    let mut count = |_: &Emulator| {
+      frames += 1;
+      Ok(())
+    };
+    let outcome = run(&mut emulator(&rom), &script, &all, 100, 1, &mut count);
+    assert!(matches!(
+      outcome.reason,
+      StopReason::Condition(StopCondition::KeyWait)
+    ));
+    assert_eq!(outcome.ticks, 2);
+    assert_eq!(frames, 2);
+
+    let mut halted = emulator(&rom);
+    let outcome = run(&mut halted, &script, &all[1..], 100, 1, &mut |_| Ok(()));
+    assert!(matches!(
+      outcome.reason,
+      StopReason::Condition(StopCondition::Halt)
+    ));
+    assert_eq!(halted.v_registers().try_index(1).copied().unwrap(), 9);
+
+    let outcome = run(
+      &mut emulator(&rom),
+      &script,
+      &[StopCondition::Pc(0x300)],
+      10,
+      2,
+      &mut |_| Ok(()),
+    );
+    assert!(matches!(outcome.reason, StopReason::FrameLimit));
+    assert_eq!((outcome.frames, outcome.ticks), (10, 20));
+
+    let outcome = run(&mut emulator(&rom), &script, &[], 10, 1, &mut |_| {
+      Err(io::Error::other("disk full"))
+    });
+    assert!(matches!(outcome.reason, StopReason::Error(ref err) if err == "disk full"));
+    assert_eq!(outcome.frames, 1);
+  }
+}
@@ -0,0 +1,357 @@
+//! The configuration file shared by the front-ends.
+//!
+//! Both the GUI and the TUI read `config.toml` from the configuration directory (or
+//! the file given with `--config`) and let their command-line flags override it.
+//! Every setting is optional, a missing file is the default configuration:
+//!
+//! ```toml
+//! log_level = "info"
+//!
+//! [emulator]
+//! speed = 1                 # instructions per frame
+//! frame_rate = 60.0         # frames per second of the TUI
+//! quirks = "r8"             # "r8", a platform ("chip48", "superchip"...) or a table
+//! romdb = "programs.json"   # ROM database used instead of the bundled one
+//! detect_platform = false
+//!
+//! [display]
+//! palette = "000000:FFFFFF" # OFF:ON colours, each front-end has its own default
+//! scale = 16                # window pixels per CHIP-8 pixel (GUI)
+//! postprocess = "raw"
+//!
+//! [audio]
+//! enabled = true
+//! volume = 1.0
+//!
+//! [keys]
+//! preset = "qwerty"         # "qwerty", "vip" or "hp48"
+//! 5 = ["KeyW", "ArrowUp"]   # CHIP-8 key = host key(s), see `KeyMap`
+//! ```
+
+use std::{
+  fmt::Display,
+  fs, io,
+  path::{Path, PathBuf},
+  str::FromStr,
+};
+
+use log::LevelFilter;
+use r8_emulator::{export::Palette, Platform, PostProcess, Quirks};
+use serde::{de, Deserialize, Deserializer};
+
+use crate::keymap::KeyMap;
+
+/// Name of the configuration file in the configuration directory.
+const FILE_NAME: &str = "config.toml";
+
+/// The configuration of a front-end.
+///
+/// # Fields
+///
+/// * `log_level` - The most verbose log messages that are kept.
+/// * `emulator` - How ROMs run.
+/// * `display` - How the screen looks.
+/// * `audio` - The beeper.
+/// * `keys` - The keypad bindings.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+  #[serde(deserialize_with = "parsed")]
+  pub log_level: LevelFilter,
+  pub emulator: EmulatorConfig,
+  pub display: DisplayConfig,
+  pub audio: AudioConfig,
+  pub keys: KeyMap,
+}
+
+/// The `[emulator]` section.
+///
+/// # Fields
+///
+/// * `speed` - Instructions per frame, ROM database tickrates and per-ROM settings take precedence.
+/// * `frame_rate` - Frames per second of the TUI main loop.
+/// * `quirks` - The quirks of ROMs the ROM database does not know.
+/// * `romdb` - A ROM database (`programs.json`) used instead of the bundled one.
+/// * `detect_platform` - Guess the platform of ROMs the ROM database does not know.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EmulatorConfig {
+  pub speed: u32,
+  pub frame_rate: f64,
+  #[serde(deserialize_with = "quirks")]
+  pub quirks: Quirks,
+  pub romdb: Option<PathBuf>,
+  pub detect_platform: bool,
+}
+
+/// The `[display]` section.
+///
+/// # Fields
+///
+/// * `palette` - The screen colours, `None` keeps the colours of the front-end.
+/// * `scale` - Window pixels per CHIP-8 pixel.
+/// * `postprocess` - The post-processing mode to start with.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DisplayConfig {
+  pub palette: Option<Palette>,
+  pub scale: u32,
+  #[serde(deserialize_with = "parsed")]
+  pub postprocess: PostProcess,
+}
+
+/// The `[audio]` section.
+///
+/// # Fields
+///
+/// * `enabled` - Whether the beeper sounds at all.
+/// * `volume` - The beeper volume, from `0.0` to `1.0`.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AudioConfig {
+  pub enabled: bool,
+  pub volume: f32,
+}
+
+impl Default for Config {
+  fn default() -> Self {
+    Self {
+      log_level: LevelFilter::Info,
+      emulator: EmulatorConfig::default(),
+      display: DisplayConfig::default(),
+      audio: AudioConfig::default(),
+      keys: KeyMap::default(),
+    }
+  }
+}
+
+impl Default for EmulatorConfig {
+  fn default() -> Self {
+    Self {
+      speed: 1,
+      frame_rate: 60.0,
+      quirks: Quirks::R8,
+      romdb: None,
+      detect_platform: false,
+    }
+  }
+}
+
+impl Default for DisplayConfig {
+  fn default() -> Self {
+    Self {
+      palette: None,
+      scale: 16,
+      postprocess: PostProcess::Raw,
+    }
+  }
+}
+
+impl Default for AudioConfig {
+  fn default() -> Self {
+    Self {
+      enabled: true,
+      volume: 1.0,
+    }
+  }
+}
+
+impl Config {
+  /// Returns the path of the configuration file in the user configuration directory.
+  pub fn default_path() -> Option<PathBuf> {
+    crate::config_dir().map(|dir| dir.join(FILE_NAME))
+  }
+
+  /// Loads the configuration.
+  ///
+  /// # Arguments
+  ///
+  /// * `path` - The file given on the command line, it must exist. Without it the
+  ///   file in the configuration directory is read if there is one.
+  pub fn load(path: Option<&Path>) -> io::Result<Self> {
+    let (path, required) = match path {
+      Some(path) => (path.to_path_buf(), true),
+      None => match Self::default_path() {
+        Some(path) => (path, false),
+        None => return Ok(Self::default()),
+      },
+    };
+    match fs::read_to_string(&path) {
+      Ok(source) => Self::parse(&source).map_err(|err| {
+        io::Error::new(
+          io::ErrorKind::InvalidData,
+          format!("{}: {}", path.display(), err),
+        )
+      }),
+      Err(err) if err.kind() == io::ErrorKind::NotFound && !required => Ok(Self::default()),
+      Err(err) => Err(err),
+    }
+  }
+
+  /// Parses a configuration file.
+  pub fn parse(source: &str) -> Result<Self, String> {
+    let config: Self = toml::from_str(source).map_err(|err| err.to_string())?;
+    if config.emulator.speed == 0 {
+      return Err("emulator.speed must be at least 1".into());
+    }
+    if !is_frame_rate(config.emulator.frame_rate) {
+      return Err("emulator.frame_rate must be positive".into());
+    }
+    if config.display.scale == 0 {
+      return Err("display.scale must be at least 1".into());
+    }
+    if !(0.0..=1.0).contains(&config.audio.volume) {
+      return Err("audio.volume must be between 0.0 and 1.0".into());
+    }
+    Ok(config)
+  }
+}
+
+/// Parses a quirks preset: `r8` for the defaults or a platform of the ROM database
+/// (`chip48`, `superchip`...). Command-line flags use it too.
+pub fn parse_quirks(preset: &str) -> Result<Quirks, String> {
+  if preset.eq_ignore_ascii_case("r8") {
+    Ok(Quirks::R8)
+  } else {
+    preset.parse::<Platform>().map(|platform| platform.quirks())
+  }
+}
+
+/// Parses a frame rate for command-line flags, it must be a positive number.
+pub fn parse_frame_rate(rate: &str) -> Result<f64, String> {
+  match rate.parse() {
+    Ok(rate) if is_frame_rate(rate) => Ok(rate),
+    _ => Err(format!(
+      "{} is not a positive number of frames per second",
+      rate
+    )),
+  }
+}
+
+/// Returns `true` if `rate` frames per second can be run: positive and finite.
+fn is_frame_rate(rate: f64) -> bool {
+  rate.is_finite() && rate > 0.0
+}
+
+/// Parses a log level (`off`, `error`, `warn`, `info`, `debug` or `trace`) for
+/// command-line flags.
+pub fn parse_log_level(level: &str) -> Result<LevelFilter, String> {
+  level
+    .parse()
+    .map_err(|_| format!("unknown log level: {}", level))
+}
+
+/// Deserializes a value from its `FromStr` form.
+fn parsed<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+  D: Deserializer<'de>,
+  T: FromStr,
+  T::Err: Display,
+{
+  String::deserialize(deserializer)?
+    .parse()
+    .map_err(de::Error::custom)
+}
+
+/// Deserializes quirks given as a preset name or as a table of quirks.
+fn quirks<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Quirks, D::Error> {
+  #[derive(Deserialize)]
+  #[serde(untagged)]
+  enum Setting {
+    Preset(String),
+    Custom(Quirks),
+  }
+  match Setting::deserialize(deserializer)? {
+    Setting::Preset(preset) => parse_quirks(&preset).map_err(de::Error::custom),
+    Setting::Custom(quirks) => Ok(quirks),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use r8_emulator::{quirks::MemoryQuirk, Key};
+
+  #[test]
+  fn empty_file_is_the_default() {
+    assert_eq!(Config::parse("").unwrap(), Config::default());
+  }
+
+  #[test]
+  fn parse_every_section() {
+    let config = Config::parse(
+      r#"
+        log_level = "debug"
+
+        [emulator]
+        speed = 12
+        quirks = "superchip"
+        detect_platform = true
+
+        [display]
+        palette = "102030:FFCC00"
+        scale = 8
+        postprocess = "phosphor:32"
+
+        [audio]
+        enabled = false
+
+        [keys]
+        5 = "ArrowUp"
+      "#,
+    )
+    .unwrap();
+    assert_eq!(config.log_level, LevelFilter::Debug);
+    assert_eq!(config.emulator.speed, 12);
+    assert_eq!(config.emulator.frame_rate, 60.0);
+    assert_eq!(config.emulator.quirks, Platform::SuperChip.quirks());
+    assert!(config.emulator.detect_platform);
+    assert_eq!(
+      config.display.palette,
+      Some("102030:FFCC00".parse().unwrap())
+    );
+    assert_eq!(config.display.scale, 8);
+    assert_eq!(
+      config.display.postprocess,
+      PostProcess::Phosphor { fade: 32 }
+    );
+    assert!(!config.audio.enabled);
+    assert_eq!(config.audio.volume, 1.0);
+    assert_eq!(config.keys.lookup_code("ArrowUp"), Some(Key::K5));
+  }
+
+  #[test]
+  fn quirks_table() {
+    let config = Config::parse(
+      "[emulator.quirks]\nshift = false\nmemory = \"increment_by_x\"\nwrap = false\njump = true\nlogic = true\n",
+    )
+    .unwrap();
+    let quirks = config.emulator.quirks;
+    assert!(!quirks.shift && !quirks.wrap && quirks.jump && quirks.logic);
+    assert_eq!(quirks.memory, MemoryQuirk::IncrementByX);
+  }
+
+  #[test]
+  fn invalid_values() {
+    assert!(Config::parse("[emulator]\nspeed = 0\n").is_err());
+    assert!(Config::parse("[emulator]\nframe_rate = 0.0\n").is_err());
+    assert!(Config::parse("[emulator]\nframe_rate = nan\n").is_err());
+    assert_eq!(parse_frame_rate("30"), Ok(30.0));
+    assert!(parse_frame_rate("0").is_err());
+    assert!(parse_frame_rate("-60").is_err());
+    assert!(parse_frame_rate("inf").is_err());
+    assert!(Config::parse("[emulator]\nquirks = \"megachip\"\n").is_err());
+    assert!(Config::parse("[display]\nzoom = 2\n").is_err());
+    assert!(Config::parse("[audio]\nvolume = 2.0\n").is_err());
+    assert!(Config::parse("log_level = \"loud\"\n").is_err());
+  }
+
+  #[test]
+  fn missing_file() {
+    let path = std::env::temp_dir().join("r8-config-missing.toml");
+    assert_eq!(
+      Config::load(Some(&path)).unwrap_err().kind(),
+      io::ErrorKind::NotFound
+    );
+  }
+}
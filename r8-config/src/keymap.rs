@@ -0,0 +1,494 @@
+//! Keypad bindings.
+//!
+//! Every CHIP-8 key is bound to one or more host keys. A host key is either a
+//! physical key, named after its position on a US QWERTY keyboard with the W3C
+//! `KeyboardEvent.code` names (`KeyQ`, `Digit1`, `Numpad7`, `ArrowUp`...), or the
+//! key that types a character in the active layout (`q`, `1`...). Physical keys
+//! keep their place on AZERTY or Dvorak keyboards, characters follow the labels.
+//!
+//! The default `qwerty` preset binds the 4x4 keypad to the physical 1234/QWER/ASDF/ZXCV
+//! block:
+//!
+//! ```text
+//! | 1 | 2 | 3 | C |      | 1 | 2 | 3 | 4 |
+//! | 4 | 5 | 6 | D |  ->  | Q | W | E | R |
+//! | 7 | 8 | 9 | E |      | A | S | D | F |
+//! | A | 0 | B | F |      | Z | X | C | V |
+//! ```
+//!
+//! The `vip` preset binds every key to the keys labelled with its hex digit, like
+//! the COSMAC VIP keypad, and the `hp48` preset to the numeric keypad, laid out like
+//! the keys CHIP-48 and SUPER-CHIP used on the HP48 calculators.
+//!
+//! Terminals report characters only, so the TUI matches a physical key with the
+//! character it types on a QWERTY keyboard.
+
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+use r8_emulator::Key;
+use serde::{de, ser::SerializeMap, Deserialize, Deserializer, Serialize, Serializer};
+
+/// The physical keys that can be bound and the character each one types on a US
+/// QWERTY keyboard.
+const CODES: [(&str, Option<char>); 77] = [
+  ("KeyA", Some('a')),
+  ("KeyB", Some('b')),
+  ("KeyC", Some('c')),
+  ("KeyD", Some('d')),
+  ("KeyE", Some('e')),
+  ("KeyF", Some('f')),
+  ("KeyG", Some('g')),
+  ("KeyH", Some('h')),
+  ("KeyI", Some('i')),
+  ("KeyJ", Some('j')),
+  ("KeyK", Some('k')),
+  ("KeyL", Some('l')),
+  ("KeyM", Some('m')),
+  ("KeyN", Some('n')),
+  ("KeyO", Some('o')),
+  ("KeyP", Some('p')),
+  ("KeyQ", Some('q')),
+  ("KeyR", Some('r')),
+  ("KeyS", Some('s')),
+  ("KeyT", Some('t')),
+  ("KeyU", Some('u')),
+  ("KeyV", Some('v')),
+  ("KeyW", Some('w')),
+  ("KeyX", Some('x')),
+  ("KeyY", Some('y')),
+  ("KeyZ", Some('z')),
+  ("Digit0", Some('0')),
+  ("Digit1", Some('1')),
+  ("Digit2", Some('2')),
+  ("Digit3", Some('3')),
+  ("Digit4", Some('4')),
+  ("Digit5", Some('5')),
+  ("Digit6", Some('6')),
+  ("Digit7", Some('7')),
+  ("Digit8", Some('8')),
+  ("Digit9", Some('9')),
+  ("Numpad0", Some('0')),
+  ("Numpad1", Some('1')),
+  ("Numpad2", Some('2')),
+  ("Numpad3", Some('3')),
+  ("Numpad4", Some('4')),
+  ("Numpad5", Some('5')),
+  ("Numpad6", Some('6')),
+  ("Numpad7", Some('7')),
+  ("Numpad8", Some('8')),
+  ("Numpad9", Some('9')),
+  ("NumpadAdd", Some('+')),
+  ("NumpadSubtract", Some('-')),
+  ("NumpadMultiply", Some('*')),
+  ("NumpadDivide", Some('/')),
+  ("NumpadDecimal", Some('.')),
+  ("NumpadEnter", None),
+  ("Minus", Some('-')),
+  ("Equal", Some('=')),
+  ("BracketLeft", Some('[')),
+  ("BracketRight", Some(']')),
+  ("Backslash", Some('\\')),
+  ("Semicolon", Some(';')),
+  ("Quote", Some('\'')),
+  ("Backquote", Some('`')),
+  ("Comma", Some(',')),
+  ("Period", Some('.')),
+  ("Slash", Some('/')),
+  ("Space", Some(' ')),
+  ("Enter", None),
+  ("Tab", None),
+  ("Backspace", None),
+  ("ArrowUp", None),
+  ("ArrowDown", None),
+  ("ArrowLeft", None),
+  ("ArrowRight", None),
+  ("ShiftLeft", None),
+  ("ShiftRight", None),
+  ("ControlLeft", None),
+  ("ControlRight", None),
+  ("AltLeft", None),
+  ("AltRight", None),
+];
+
+/// A host key a CHIP-8 key can be bound to.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum HostKey {
+  /// A physical key, by its W3C code name.
+  Code(&'static str),
+  /// The key that types this character (lowercase) in the active layout.
+  Char(char),
+}
+
+impl HostKey {
+  /// Returns the character the key types on a US QWERTY keyboard, if any.
+  pub fn qwerty_char(&self) -> Option<char> {
+    match self {
+      HostKey::Code(name) => CODES
+        .iter()
+        .find(|(code, _)| code == name)
+        .and_then(|(_, ch)| *ch),
+      HostKey::Char(ch) => Some(*ch),
+    }
+  }
+}
+
+/// Parses a single character or a physical key code, codes are case-insensitive.
+impl FromStr for HostKey {
+  type Err = String;
+
+  fn from_str(name: &str) -> Result<Self, Self::Err> {
+    let mut chars = name.chars();
+    if let (Some(ch), None) = (chars.next(), chars.next()) {
+      return Ok(HostKey::Char(ch.to_ascii_lowercase()));
+    }
+    CODES
+      .iter()
+      .find(|(code, _)| code.eq_ignore_ascii_case(name))
+      .map(|(code, _)| HostKey::Code(code))
+      .ok_or_else(|| {
+        format!(
+          "unknown key `{}`, expected a character or a key code like `KeyQ`",
+          name
+        )
+      })
+  }
+}
+
+impl fmt::Display for HostKey {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      HostKey::Code(code) => f.write_str(code),
+      HostKey::Char(ch) => write!(f, "{}", ch),
+    }
+  }
+}
+
+/// The host keys of each CHIP-8 key.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct KeyMap {
+  bindings: [Vec<HostKey>; 16],
+}
+
+impl KeyMap {
+  /// The names of the built-in layouts, see `KeyMap::preset`.
+  pub const PRESETS: [&'static str; 3] = ["qwerty", "vip", "hp48"];
+
+  /// Returns a built-in layout: `qwerty`, `vip` or `hp48`.
+  pub fn preset(name: &str) -> Option<Self> {
+    let layout: [(Key, &[&'static str]); 16] = match name.to_ascii_lowercase().as_str() {
+      "qwerty" => [
+        (Key::K1, &["Digit1"]),
+        (Key::K2, &["Digit2"]),
+        (Key::K3, &["Digit3"]),
+        (Key::KC, &["Digit4"]),
+        (Key::K4, &["KeyQ"]),
+        (Key::K5, &["KeyW"]),
+        (Key::K6, &["KeyE"]),
+        (Key::KD, &["KeyR"]),
+        (Key::K7, &["KeyA"]),
+        (Key::K8, &["KeyS"]),
+        (Key::K9, &["KeyD"]),
+        (Key::KE, &["KeyF"]),
+        (Key::KA, &["KeyZ"]),
+        (Key::K0, &["KeyX"]),
+        (Key::KB, &["KeyC"]),
+        (Key::KF, &["KeyV"]),
+      ],
+      "vip" => [
+        (Key::K0, &["0", "Numpad0"]),
+        (Key::K1, &["1", "Numpad1"]),
+        (Key::K2, &["2", "Numpad2"]),
+        (Key::K3, &["3", "Numpad3"]),
+        (Key::K4, &["4", "Numpad4"]),
+        (Key::K5, &["5", "Numpad5"]),
+        (Key::K6, &["6", "Numpad6"]),
+        (Key::K7, &["7", "Numpad7"]),
+        (Key::K8, &["8", "Numpad8"]),
+        (Key::K9, &["9", "Numpad9"]),
+        (Key::KA, &["a"]),
+        (Key::KB, &["b"]),
+        (Key::KC, &["c"]),
+        (Key::KD, &["d"]),
+        (Key::KE, &["e"]),
+        (Key::KF, &["f"]),
+      ],
+      "hp48" => [
+        (Key::K1, &["Numpad7"]),
+        (Key::K2, &["Numpad8"]),
+        (Key::K3, &["Numpad9"]),
+        (Key::KC, &["NumpadDivide"]),
+        (Key::K4, &["Numpad4"]),
+        (Key::K5, &["Numpad5"]),
+        (Key::K6, &["Numpad6"]),
+        (Key::KD, &["NumpadMultiply"]),
+        (Key::K7, &["Numpad1"]),
+        (Key::K8, &["Numpad2"]),
+        (Key::K9, &["Numpad3"]),
+        (Key::KE, &["NumpadSubtract"]),
+        (Key::KA, &["Numpad0"]),
+        (Key::K0, &["NumpadDecimal"]),
+        (Key::KB, &["NumpadEnter"]),
+        (Key::KF, &["NumpadAdd"]),
+      ],
+      _ => return None,
+    };
+    let mut map = Self {
+      bindings: Default::default(),
+    };
+    for (key, names) in layout {
+      let host_keys = names
+        .iter()
+        .map(|name| name.parse().expect("a valid preset"));
+      map.bind(key, host_keys.collect());
+    }
+    Some(map)
+  }
+
+  /// Returns the host keys bound to a CHIP-8 key.
+  pub fn host_keys(&self, key: Key) -> &[HostKey] {
+    &self.bindings[key as usize]
+  }
+
+  /// Binds a CHIP-8 key to the given host keys, replacing its previous bindings.
+  pub fn bind(&mut self, key: Key, host_keys: Vec<HostKey>) {
+    self.bindings[key as usize] = host_keys;
+  }
+
+  /// Returns the CHIP-8 key bound to a physical key.
+  ///
+  /// # Arguments
+  ///
+  /// * `code` - The W3C code name of the key, e.g. `KeyQ`.
+  pub fn lookup_code(&self, code: &str) -> Option<Key> {
+    self.find(|host_key| matches!(host_key, HostKey::Code(name) if *name == code))
+  }
+
+  /// Returns the CHIP-8 key bound to the key that typed a character, ignoring case.
+  pub fn lookup_char(&self, ch: char) -> Option<Key> {
+    let ch = ch.to_ascii_lowercase();
+    self.find(|host_key| *host_key == HostKey::Char(ch))
+  }
+
+  /// Returns the CHIP-8 key bound to a typed character when the physical key is
+  /// unknown: character bindings first, then the physical keys typing it on QWERTY.
+  pub fn lookup_typed(&self, ch: char) -> Option<Key> {
+    let ch = ch.to_ascii_lowercase();
+    self
+      .lookup_char(ch)
+      .or_else(|| self.find(|host_key| host_key.qwerty_char() == Some(ch)))
+  }
+
+  /// Iterates over the CHIP-8 keys and their host keys.
+  pub fn iter(&self) -> impl Iterator<Item = (Key, &[HostKey])> {
+    Key::all().map(|&key| (key, self.host_keys(key)))
+  }
+
+  /// Returns the first CHIP-8 key with a host key matching `predicate`.
+  fn find(&self, predicate: impl Fn(&HostKey) -> bool) -> Option<Key> {
+    self
+      .iter()
+      .find(|(_, host_keys)| host_keys.iter().any(&predicate))
+      .map(|(key, _)| key)
+  }
+}
+
+impl Default for KeyMap {
+  fn default() -> Self {
+    Self::preset("qwerty").expect("the default preset exists")
+  }
+}
+
+/// Bindings changing some keys of a key map, as written in a `[keys]` table.
+///
+/// Per-ROM bindings are kept in this form, so they apply over the configured key
+/// map and are saved back with only the keys they change.
+///
+/// # Fields
+///
+/// * `preset` - The preset the bindings start from, `None` for the key map they
+///   apply to.
+/// * `keys` - The host keys of the CHIP-8 keys the table binds, by hex digit.
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct KeyBindings {
+  preset: Option<String>,
+  keys: BTreeMap<u8, Vec<HostKey>>,
+}
+
+impl KeyBindings {
+  /// Binds a CHIP-8 key to the given host keys.
+  pub fn bind(&mut self, key: Key, host_keys: Vec<HostKey>) {
+    self.keys.insert(key as u8, host_keys);
+  }
+
+  /// Returns the key map with these bindings applied.
+  ///
+  /// # Arguments
+  ///
+  /// * `base` - The key map used for the keys the bindings leave out, when they
+  ///   have no preset.
+  pub fn apply(&self, base: &KeyMap) -> KeyMap {
+    let mut map = match &self.preset {
+      Some(name) => KeyMap::preset(name).unwrap_or_else(|| base.clone()),
+      None => base.clone(),
+    };
+    for (&digit, host_keys) in &self.keys {
+      map.bindings[digit as usize] = host_keys.clone();
+    }
+    map
+  }
+}
+
+/// A binding in the configuration file: a single host key or a list of them.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum HostKeys {
+  One(String),
+  Many(Vec<String>),
+}
+
+/// A `[keys]` table: an optional `preset` (`qwerty` by default) and CHIP-8 keys as
+/// hex digits, the keys it leaves out keep the bindings of the preset.
+impl<'de> Deserialize<'de> for KeyMap {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    Ok(KeyBindings::deserialize(deserializer)?.apply(&KeyMap::default()))
+  }
+}
+
+/// A `[keys]` table: an optional `preset` and CHIP-8 keys as hex digits.
+impl<'de> Deserialize<'de> for KeyBindings {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    let mut table = BTreeMap::<String, HostKeys>::deserialize(deserializer)?;
+    let preset = match table.remove("preset") {
+      Some(HostKeys::One(name)) if KeyMap::preset(&name).is_some() => Some(name),
+      Some(HostKeys::One(name)) => {
+        return Err(de::Error::custom(format!(
+          "unknown key preset `{}`, expected one of {}",
+          name,
+          KeyMap::PRESETS.join(", ")
+        )))
+      }
+      Some(HostKeys::Many(_)) => return Err(de::Error::custom("the key preset is a single name")),
+      None => None,
+    };
+    let mut bindings = KeyBindings {
+      preset,
+      keys: BTreeMap::new(),
+    };
+    for (digit, host_keys) in table {
+      let key = u8::from_str_radix(&digit, 16)
+        .ok()
+        .and_then(|index| Key::all().nth(index as usize))
+        .ok_or_else(|| de::Error::custom(format!("`{digit}` is not a CHIP-8 key (0-F)")))?;
+      let names = match host_keys {
+        HostKeys::One(name) => vec![name],
+        HostKeys::Many(names) => names,
+      };
+      let host_keys = names
+        .iter()
+        .map(|name| name.parse())
+        .collect::<Result<_, String>>()
+        .map_err(de::Error::custom)?;
+      bindings.bind(*key, host_keys);
+    }
+    Ok(bindings)
+  }
+}
+
+/// Writes the preset, if any, and only the CHIP-8 keys the bindings change.
+impl Serialize for KeyBindings {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    let mut table = serializer.serialize_map(None)?;
+    if let Some(preset) = &self.preset {
+      table.serialize_entry("preset", preset)?;
+    }
+    for (digit, host_keys) in &self.keys {
+      let names: Vec<String> = host_keys.iter().map(HostKey::to_string).collect();
+      table.serialize_entry(&format!("{:X}", digit), &names)?;
+    }
+    table.end()
+  }
+}
+
+/// Writes every CHIP-8 key as a hex digit and the list of its host keys.
+impl Serialize for KeyMap {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    let mut table = serializer.serialize_map(Some(16))?;
+    for (key, host_keys) in self.iter() {
+      let names: Vec<String> = host_keys.iter().map(HostKey::to_string).collect();
+      table.serialize_entry(&format!("{:X}", key as u8), &names)?;
+    }
+    table.end()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn default_layout() {
+    let map = KeyMap::default();
+    assert_eq!(map.lookup_code("KeyQ"), Some(Key::K4));
+    assert_eq!(map.lookup_code("Digit4"), Some(Key::KC));
+    assert_eq!(map.lookup_code("KeyG"), None);
+    assert_eq!(map.lookup_char('q'), None);
+    assert_eq!(map.lookup_typed('Q'), Some(Key::K4));
+    assert_eq!(map.lookup_typed('x'), Some(Key::K0));
+    assert_eq!(map.host_keys(Key::KF), [HostKey::Code("KeyV")]);
+  }
+
+  #[test]
+  fn presets() {
+    let vip = KeyMap::preset("vip").unwrap();
+    assert_eq!(vip.lookup_char('A'), Some(Key::KA));
+    assert_eq!(vip.lookup_char('7'), Some(Key::K7));
+    assert_eq!(vip.lookup_code("Numpad7"), Some(Key::K7));
+    let hp48 = KeyMap::preset("HP48").unwrap();
+    assert_eq!(hp48.lookup_code("Numpad7"), Some(Key::K1));
+    assert_eq!(hp48.lookup_code("NumpadAdd"), Some(Key::KF));
+    assert_eq!(hp48.lookup_typed('7'), Some(Key::K1));
+    assert!(KeyMap::preset("azerty").is_none());
+  }
+
+  #[test]
+  fn table_overrides_some_keys() {
+    let map: KeyMap = toml::from_str("5 = [\"w\", \"arrowup\"]\nA = \"Space\"\n").unwrap();
+    assert_eq!(map.lookup_code("ArrowUp"), Some(Key::K5));
+    assert_eq!(map.lookup_char('W'), Some(Key::K5));
+    assert_eq!(map.lookup_typed(' '), Some(Key::KA));
+    assert_eq!(map.lookup_code("KeyZ"), None);
+    assert_eq!(map.lookup_code("Digit1"), Some(Key::K1));
+
+    let map: KeyMap = toml::from_str("preset = \"hp48\"\nF = \"KeyF\"\n").unwrap();
+    assert_eq!(map.lookup_code("Numpad7"), Some(Key::K1));
+    assert_eq!(map.lookup_code("KeyF"), Some(Key::KF));
+    assert_eq!(map.lookup_code("NumpadAdd"), None);
+
+    assert!(toml::from_str::<KeyMap>("G = \"g\"\n").is_err());
+    assert!(toml::from_str::<KeyMap>("1 = \"Up\"\n").is_err());
+    assert!(toml::from_str::<KeyMap>("preset = \"dvorak\"\n").is_err());
+  }
+
+  #[test]
+  fn bindings_apply_over_a_key_map() {
+    let bindings: KeyBindings = toml::from_str("F = \"KeyG\"\n").unwrap();
+    let map = bindings.apply(&KeyMap::preset("vip").unwrap());
+    assert_eq!(map.lookup_code("KeyG"), Some(Key::KF));
+    assert_eq!(map.lookup_char('f'), None);
+    assert_eq!(map.lookup_char('a'), Some(Key::KA));
+    assert_eq!(toml::to_string(&bindings).unwrap(), "F = [\"KeyG\"]\n");
+
+    let bindings: KeyBindings = toml::from_str("preset = \"hp48\"\n").unwrap();
+    let map = bindings.apply(&KeyMap::preset("vip").unwrap());
+    assert_eq!(map, KeyMap::preset("hp48").unwrap());
+    assert_eq!(toml::to_string(&bindings).unwrap(), "preset = \"hp48\"\n");
+  }
+
+  #[test]
+  fn serialize_roundtrip() {
+    let mut map = KeyMap::preset("vip").unwrap();
+    map.bind(Key::K5, vec![HostKey::Code("ArrowUp"), HostKey::Char('w')]);
+    let source = toml::to_string(&map).unwrap();
+    assert_eq!(toml::from_str::<KeyMap>(&source).unwrap(), map);
+  }
+}
@@ -0,0 +1,33 @@
+/*!
+r8-config
+
+User settings shared by the R8 front-ends. They live in the `r8` directory of the
+user configuration directory (e.g. `~/.config/r8` on Linux).
+
+- `config`: the `config.toml` shared by the front-ends, their command-line flags
+  override it.
+- `keymap`: the keypad bindings, to physical keys or characters.
+- `rom_settings`: the speed, quirks, palette and key bindings last used with each
+  ROM, keyed by the SHA-1 of the ROM image.
+*/
+
+use std::path::PathBuf;
+
+pub mod config;
+pub mod keymap;
+pub mod rom_settings;
+
+/// Re-export the configuration file and the parser of quirks presets.
+pub use config::{parse_frame_rate, parse_log_level, parse_quirks, Config};
+
+/// Re-export the keypad bindings.
+pub use keymap::{HostKey, KeyBindings, KeyMap};
+
+/// Re-export the per-ROM settings and their store.
+pub use rom_settings::{RomSettings, RomSettingsStore};
+
+/// Returns the directory R8 keeps its settings in, `None` if the platform has no
+/// user configuration directory.
+pub fn config_dir() -> Option<PathBuf> {
+  dirs::config_dir().map(|dir| dir.join("r8"))
+}
@@ -0,0 +1,215 @@
+//! Per-ROM settings persisted across sessions.
+//!
+//! The front-ends save the speed, quirks and palette a ROM was last played with to
+//! `roms.toml` in the configuration directory, one table per ROM keyed by the SHA-1
+//! of its image, and restore them the next time the ROM is loaded. Settings that
+//! were never changed are left out, so they keep following the defaults and the
+//! ROM database.
+//!
+//! A `keys` table, written by hand in the format of the `[keys]` table of the
+//! configuration file, gives a ROM its own key bindings. The keys it leaves out keep
+//! the configured bindings, unless it names a `preset`, and the front-ends keep it
+//! as written when they save the other settings.
+
+use std::{
+  collections::BTreeMap,
+  fs, io,
+  path::{Path, PathBuf},
+};
+
+use r8_emulator::{export::Palette, Quirks};
+
+use crate::KeyBindings;
+use serde::{Deserialize, Serialize};
+
+/// Name of the settings file in the configuration directory.
+const FILE_NAME: &str = "roms.toml";
+
+/// The settings of a single ROM.
+///
+/// # Fields
+///
+/// * `title` - The name of the ROM, only there to make the file readable.
+/// * `speed` - Instructions per frame.
+/// * `quirks` - The quirks the ROM runs with.
+/// * `palette` - The screen colours.
+/// * `keys` - The key bindings, applied over the ones of the configuration file.
+#[derive(Clone, Default, PartialEq, Debug, Serialize, Deserialize)]
+pub struct RomSettings {
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub title: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub speed: Option<u32>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub quirks: Option<Quirks>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub palette: Option<Palette>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub keys: Option<KeyBindings>,
+}
+
+impl RomSettings {
+  /// Returns `true` if no setting is stored (the title does not count).
+  pub fn is_empty(&self) -> bool {
+    self.speed.is_none() && self.quirks.is_none() && self.palette.is_none() && self.keys.is_none()
+  }
+}
+
+/// The contents of `roms.toml`.
+#[derive(Default, Serialize, Deserialize)]
+struct SettingsFile {
+  #[serde(default)]
+  roms: BTreeMap<String, RomSettings>,
+}
+
+/// The settings of every ROM, backed by a TOML file.
+///
+/// # Fields
+///
+/// * `path` - The file the settings are read from and saved to.
+/// * `roms` - The settings, keyed by the lowercase SHA-1 of the ROM image.
+pub struct RomSettingsStore {
+  path: PathBuf,
+  roms: BTreeMap<String, RomSettings>,
+}
+
+impl RomSettingsStore {
+  /// Returns the path of the settings file in the user configuration directory.
+  pub fn default_path() -> Option<PathBuf> {
+    crate::config_dir().map(|dir| dir.join(FILE_NAME))
+  }
+
+  /// Opens the settings file in the user configuration directory.
+  pub fn open_default() -> io::Result<Self> {
+    let path = Self::default_path().ok_or_else(|| {
+      io::Error::new(
+        io::ErrorKind::NotFound,
+        "no user configuration directory on this platform",
+      )
+    })?;
+    Self::open(&path)
+  }
+
+  /// Opens a settings file, a missing file is an empty store.
+  ///
+  /// # Arguments
+  ///
+  /// * `path` - The file to read, it is created by the first `save`.
+  pub fn open(path: &Path) -> io::Result<Self> {
+    let file = match fs::read_to_string(path) {
+      Ok(source) => toml::from_str::<SettingsFile>(&source)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?,
+      Err(err) if err.kind() == io::ErrorKind::NotFound => SettingsFile::default(),
+      Err(err) => return Err(err),
+    };
+    Ok(Self {
+      path: path.to_path_buf(),
+      roms: file.roms,
+    })
+  }
+
+  /// Returns the file the store is saved to.
+  pub fn path(&self) -> &Path {
+    &self.path
+  }
+
+  /// Returns the settings of a ROM.
+  ///
+  /// # Arguments
+  ///
+  /// * `sha1` - The SHA-1 of the ROM image, see `r8_emulator::romdb::sha1`.
+  pub fn get(&self, sha1: &str) -> Option<&RomSettings> {
+    self.roms.get(&sha1.to_ascii_lowercase())
+  }
+
+  /// Stores the settings of a ROM, empty settings remove it.
+  pub fn set(&mut self, sha1: &str, settings: RomSettings) {
+    if settings.is_empty() {
+      self.remove(sha1);
+    } else {
+      self.roms.insert(sha1.to_ascii_lowercase(), settings);
+    }
+  }
+
+  /// Forgets the settings of a ROM, it goes back to the defaults.
+  pub fn remove(&mut self, sha1: &str) -> Option<RomSettings> {
+    self.roms.remove(&sha1.to_ascii_lowercase())
+  }
+
+  /// Writes the store to its file, creating the directory if needed.
+  ///
+  /// The file is written next to the old one and renamed over it, so an
+  /// interrupted save never leaves it half written.
+  pub fn save(&self) -> io::Result<()> {
+    let file = SettingsFile {
+      roms: self.roms.clone(),
+    };
+    let source = toml::to_string_pretty(&file).map_err(io::Error::other)?;
+    if let Some(dir) = self.path.parent() {
+      fs::create_dir_all(dir)?;
+    }
+    let temporary = self.path.with_extension("toml.tmp");
+    fs::write(&temporary, source)?;
+    fs::rename(&temporary, &self.path)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use r8_emulator::Platform;
+
+  fn temporary_path(name: &str) -> PathBuf {
+    std::env::temp_dir()
+      .join(format!("r8-config-{}-{}", std::process::id(), name))
+      .join(FILE_NAME)
+  }
+
+  #[test]
+  fn missing_file_is_empty() {
+    let store = RomSettingsStore::open(&temporary_path("missing")).unwrap();
+    assert!(store.get("00").is_none());
+  }
+
+  #[test]
+  fn save_and_reopen() {
+    let path = temporary_path("roundtrip");
+    let mut store = RomSettingsStore::open(&path).unwrap();
+    let settings = RomSettings {
+      title: Some("Pong".into()),
+      speed: Some(12),
+      quirks: Some(Platform::SuperChip.quirks()),
+      palette: Some("102030:FFCC00".parse().unwrap()),
+      keys: Some(toml::from_str("preset = \"hp48\"\nF = \"KeyF\"").unwrap()),
+    };
+    store.set("ABCDEF", settings.clone());
+    store.set("012345", RomSettings::default());
+    store.save().unwrap();
+    // The key bindings are saved as written, not as a full table.
+    let source = fs::read_to_string(&path).unwrap();
+    assert!(source.contains("preset = \"hp48\"\nF = [\"KeyF\"]\n"));
+    assert!(!source.contains("0 = "));
+
+    let mut store = RomSettingsStore::open(&path).unwrap();
+    assert_eq!(store.get("abcdef"), Some(&settings));
+    assert!(store.get("012345").is_none());
+
+    assert_eq!(store.remove("abcdef"), Some(settings));
+    store.save().unwrap();
+    assert!(RomSettingsStore::open(&path)
+      .unwrap()
+      .get("abcdef")
+      .is_none());
+    fs::remove_dir_all(path.parent().unwrap()).unwrap();
+  }
+
+  #[test]
+  fn invalid_file_is_an_error() {
+    let path = temporary_path("invalid");
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(&path, "[roms.abc]\nquirks = { shift = \"yes\" }\n").unwrap();
+    let err = RomSettingsStore::open(&path).err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    fs::remove_dir_all(path.parent().unwrap()).unwrap();
+  }
+}
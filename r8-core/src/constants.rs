@@ -3,6 +3,10 @@
 /// http://devernay.free.fr/hacks/chip8/C8TECH10.HTM#2.2
 pub const REGISTER_COUNT: usize = 0x10;
 
+/// http://devernay.free.fr/hacks/chip8/C8TECH10.HTM#memmap
+/// Size of the memory for the Chip8 system.
+pub const MEMORY_SIZE: usize = 0x1000;
+
 /// Width of the display.
 pub const WIDTH: usize = 64;
 
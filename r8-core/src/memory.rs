@@ -3,7 +3,7 @@ use std::{
   ops::{Index, IndexMut},
 };
 
-use super::error::EmulatorError;
+use super::{constants::MEMORY_SIZE, error::EmulatorError};
 
 /// Represents an address in memory.
 ///
@@ -92,10 +92,6 @@ impl TryFrom<u16> for Address {
   }
 }
 
-/// http://devernay.free.fr/hacks/chip8/C8TECH10.HTM#memmap
-/// Size of the memory for the Chip8 system.
-const MEMORY_SIZE: usize = 0x1000;
-
 /// https://github.com/mattmikolay/chip-8/wiki/Mastering-CHIP%E2%80%908
 /// HIP-8 contains built-in font utilities to allow for simple output of characters using the DXYN instruction.
 /// All hexadecimal digits (0 - 9, A - F) have corresponding sprite data already stored in the memory of the interpreter.
@@ -181,7 +177,7 @@ impl Memory {
   /// * `Result<(), RuntimeError>` - Returns Ok if successful, otherwise returns an error.
   pub fn read_range(&mut self, start_address: Address, data: &[u8]) -> Result<(), EmulatorError> {
     // Check if the address is valid.
-    if data.len() + start_address.0 as usize > 0xFFF {
+    if data.len() + start_address.0 as usize > MEMORY_SIZE {
       return Err(EmulatorError::OutOfBounds(
         data.len() as u16 + start_address.0,
       ));
@@ -213,7 +209,7 @@ impl Memory {
   /// * `start_address` - The starting address to write to.
   /// * `data` - The slice to write the data from.
   pub fn write_range(&self, start_address: Address, data: &mut [u8]) -> Result<(), EmulatorError> {
-    if start_address.0 as usize + data.len() > 0xFFF {
+    if start_address.0 as usize + data.len() > MEMORY_SIZE {
       return Err(EmulatorError::OutOfBounds(
         data.len() as u16 + start_address.0,
       ));
@@ -239,6 +235,12 @@ impl Memory {
   }
 }
 
+impl Default for Memory {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
 impl Index<Address> for Memory {
   type Output = u8;
 
@@ -72,16 +72,16 @@ pub enum Opcode {
   Sub { x: RegisterIndex, y: RegisterIndex },
   /// 0x8XY6 - SHR VX {, VY}
   ///
-  /// Set VX = VX SHR 1.
-  Shr { x: RegisterIndex },
+  /// Set VX = VX SHR 1 (or VY SHR 1, depending on the shift quirk).
+  Shr { x: RegisterIndex, y: RegisterIndex },
   /// 0x8XY7 - SUBN VX, VY
   ///
   /// Set VX = VY - VX, set VF = NOT borrow.
   Subn { x: RegisterIndex, y: RegisterIndex },
   /// 0x8XYE - SHL VX {, VY}
   ///
-  /// Set VX = VX SHL 1.
-  Shl { x: RegisterIndex },
+  /// Set VX = VX SHL 1 (or VY SHL 1, depending on the shift quirk).
+  Shl { x: RegisterIndex, y: RegisterIndex },
   /// 0x9XY0 - SNE VX, VY
   ///
   /// Skip next instruction if VX != VY.
@@ -262,12 +262,18 @@ impl TryFrom<u16> for Opcode {
           x: register!(1),
           y: register!(2),
         },
-        0x6 => Self::Shr { x: register!(1) },
+        0x6 => Self::Shr {
+          x: register!(1),
+          y: register!(2),
+        },
         0x7 => Self::Subn {
           x: register!(1),
           y: register!(2),
         },
-        0xE => Self::Shl { x: register!(1) },
+        0xE => Self::Shl {
+          x: register!(1),
+          y: register!(2),
+        },
         _ => Self::Invalid(value),
       },
       0x9000..=0x9FFF => match nibble!(3) {
@@ -334,9 +340,9 @@ impl Display for Opcode {
       Self::Xor { x, y } => write!(f, "XOR V{:X}, V{:X}", x, y),
       Self::AddRegister { x, y } => write!(f, "ADD V{:X}, V{:X}", x, y),
       Self::Sub { x, y } => write!(f, "SUB V{:X}, V{:X}", x, y),
-      Self::Shr { x } => write!(f, "SHR V{:X}", x),
+      Self::Shr { x, .. } => write!(f, "SHR V{:X}", x),
       Self::Subn { x, y } => write!(f, "SUBN V{:X}, V{:X}", x, y),
-      Self::Shl { x } => write!(f, "SHL V{:X}", x),
+      Self::Shl { x, .. } => write!(f, "SHL V{:X}", x),
       Self::SneRegister { x, y } => write!(f, "SNE V{:X}, V{:X}", x, y),
       Self::LdI { address } => write!(f, "LD I, #{:X}", address.inner()),
       Self::JpV0 { address } => write!(f, "JP V0, #{:X}", address.inner()),
@@ -32,7 +32,23 @@ impl RandGen {
   ///
   /// * `RandGen` - The new instance of RandGen
   pub fn new() -> Self {
-    let seed = get_epoch_micros(); // Using the current time as seed
+    // Using the current time as seed
+    Self::with_seed(get_epoch_micros())
+  }
+
+  /// Function to initialize a new instance of RandGen with a fixed seed
+  ///
+  /// Two generators created with the same seed produce the same sequence, which
+  /// makes emulation runs reproducible (tests, differential checks, replays).
+  ///
+  /// # Arguments
+  ///
+  /// * `seed` - The initial state of the generator
+  ///
+  /// # Returns
+  ///
+  /// * `RandGen` - The new instance of RandGen
+  pub fn with_seed(seed: u128) -> Self {
     Self {
       multiplier: Wrapping(6364136223846793005),
       increment: Wrapping(1442695040888963407),
@@ -46,8 +62,15 @@ impl RandGen {
   /// # Returns
   ///
   /// * `u8` - The next random number
+  #[allow(clippy::should_implement_trait)]
   pub fn next(&mut self) -> u8 {
     self.state = (self.multiplier * self.state + self.increment) % self.modulus;
     (self.state.0 >> 56) as u8
   }
 }
+
+impl Default for RandGen {
+  fn default() -> Self {
+    Self::new()
+  }
+}
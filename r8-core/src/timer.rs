@@ -36,3 +36,9 @@ impl Timer {
     }
   }
 }
+
+impl Default for Timer {
+  fn default() -> Self {
+    Self::new()
+  }
+}
@@ -0,0 +1,286 @@
+//! Platform detection for ROMs the ROM database does not know.
+//!
+//! The image is scanned for opcode signatures: instructions that only exist on
+//! SUPER-CHIP or XO-CHIP, and usage patterns that only work with the quirks of the
+//! COSMAC VIP interpreter. Only the instructions reachable from the entry point are
+//! scanned, so sprites rarely pass for code, but jumps through `BNNN` cannot be
+//! followed and the result remains a guess with a confidence score.
+
+use r8_core::memory::Address;
+
+use crate::quirks::{MemoryQuirk, Platform, Quirks};
+
+/// The confidence from which `Emulator::load_rom` applies a detected platform.
+pub const MIN_CONFIDENCE: f32 = 0.5;
+
+/// How many instructions around `FX55`/`FX65` and the shifts the patterns look at.
+const PATTERN_DISTANCE: usize = 4;
+
+/// A platform guess and the signatures it is based on.
+///
+/// # Fields
+///
+/// * `platform` - The proposed platform.
+/// * `confidence` - How sure the guess is, from `0.0` to `1.0`.
+/// * `evidence` - The signatures found and how many times, in address order.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Detection {
+  pub platform: Platform,
+  pub confidence: f32,
+  pub evidence: Vec<(Signature, usize)>,
+}
+
+/// An opcode signature that hints at a platform.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Signature {
+  /// `00FE`/`00FF`, switch between low and high resolution.
+  SuperChipResolution,
+  /// `00CN`, `00FB` and `00FC`, scroll the display.
+  SuperChipScroll,
+  /// `00FD`, exit the interpreter.
+  SuperChipExit,
+  /// `DXY0`, draw a 16x16 sprite (also a common data pattern, it weighs half).
+  SuperChipSprite,
+  /// `FX30`, point I to a large font character.
+  SuperChipFont,
+  /// `FX75`/`FX85`, save and restore the RPL user flags.
+  SuperChipFlags,
+  /// `F000 NNNN`, load a 16-bit address into I.
+  XoChipLongLoad,
+  /// `5XY2`/`5XY3`, save and load a range of registers.
+  XoChipRegisterRange,
+  /// `8XY6`/`8XYE` right after VY was written, the shift reads VY.
+  ShiftFromVy,
+  /// `8XY6`/`8XYE` with X != Y right after VX was written, the shift reads VX.
+  ShiftInPlace,
+  /// `FX55`/`FX65` followed by another one without setting I, it relies on I moving.
+  ChainedLoadStore,
+}
+
+impl Signature {
+  /// Returns how much a single occurrence weighs.
+  fn weight(&self) -> f32 {
+    match self {
+      Signature::SuperChipSprite => 0.5,
+      _ => 1.0,
+    }
+  }
+
+  /// Returns the platform the signature points to.
+  fn platform(&self) -> Platform {
+    match self {
+      Signature::SuperChipResolution
+      | Signature::SuperChipScroll
+      | Signature::SuperChipExit
+      | Signature::SuperChipSprite
+      | Signature::SuperChipFont
+      | Signature::SuperChipFlags => Platform::SuperChip,
+      Signature::XoChipLongLoad | Signature::XoChipRegisterRange => Platform::XoChip,
+      Signature::ShiftInPlace => Platform::Chip48,
+      Signature::ShiftFromVy | Signature::ChainedLoadStore => Platform::OriginalChip8,
+    }
+  }
+
+  /// Returns `true` if the pattern does not work with the given quirks.
+  fn contradicts(&self, quirks: Quirks) -> bool {
+    match self {
+      Signature::ShiftFromVy => quirks.shift,
+      Signature::ShiftInPlace => !quirks.shift,
+      Signature::ChainedLoadStore => quirks.memory == MemoryQuirk::Unchanged,
+      _ => false,
+    }
+  }
+
+  /// Returns the short description used in logs.
+  pub fn description(&self) -> &'static str {
+    match self {
+      Signature::SuperChipResolution => "00FE/00FF resolution switch",
+      Signature::SuperChipScroll => "00CN/00FB/00FC scroll",
+      Signature::SuperChipExit => "00FD exit",
+      Signature::SuperChipSprite => "DXY0 16x16 sprite",
+      Signature::SuperChipFont => "FX30 large font",
+      Signature::SuperChipFlags => "FX75/FX85 user flags",
+      Signature::XoChipLongLoad => "F000 NNNN long load",
+      Signature::XoChipRegisterRange => "5XY2/5XY3 register range",
+      Signature::ShiftFromVy => "8XY6/8XYE shifting VY",
+      Signature::ShiftInPlace => "8XY6/8XYE shifting VX",
+      Signature::ChainedLoadStore => "chained FX55/FX65",
+    }
+  }
+}
+
+/// Reads the instruction at `offset` of the image.
+fn word(rom: &[u8], offset: usize) -> Option<u16> {
+  Some(u16::from_be_bytes([
+    *rom.get(offset)?,
+    *rom.get(offset + 1)?,
+  ]))
+}
+
+/// Returns the offsets of the instructions reachable from the entry point, in order.
+///
+/// The walk follows jumps, calls and both outcomes of skips. It stops at returns,
+/// `BNNN` (whose target depends on a register) and words that are no instruction
+/// of any platform, so sprites and other data are left out as long as the program
+/// does not run into them.
+fn reachable(rom: &[u8]) -> Vec<usize> {
+  let mut seen = vec![false; rom.len()];
+  let mut pending = vec![0];
+  while let Some(offset) = pending.pop() {
+    let Some(word) = word(rom, offset) else {
+      continue;
+    };
+    if std::mem::replace(&mut seen[offset], true) {
+      continue;
+    }
+    let target = ((word & 0x0FFF) as usize).checked_sub(Address::ENTRY_POINT.inner() as usize);
+    let low = word & 0xFF;
+    let next = offset + 2;
+    match word >> 12 {
+      0x0 => match word {
+        0x00E0 | 0x00FE | 0x00FF | 0x00FB | 0x00FC | 0x00C1..=0x00CF => pending.push(next),
+        // 00EE returns, 00FD exits and other machine code routines are not followed.
+        _ => {}
+      },
+      0x1 => pending.extend(target),
+      0x2 => pending.extend(target.into_iter().chain([next])),
+      0x3 | 0x4 | 0x9 => pending.extend([next, next + 2]),
+      0x5 if matches!(low & 0xF, 0x0) => pending.extend([next, next + 2]),
+      0x5 if matches!(low & 0xF, 0x2 | 0x3) => pending.push(next),
+      0x6 | 0x7 | 0xA | 0xC | 0xD => pending.push(next),
+      0x8 if matches!(low & 0xF, 0x0..=0x7 | 0xE) => pending.push(next),
+      0xE if matches!(low, 0x9E | 0xA1) => pending.extend([next, next + 2]),
+      // F000 is followed by its 16-bit address.
+      0xF if word == 0xF000 => pending.push(next + 2),
+      0xF
+        if matches!(
+          low,
+          0x07 | 0x0A | 0x15 | 0x18 | 0x1E | 0x29 | 0x30 | 0x33 | 0x55 | 0x65 | 0x75 | 0x85
+        ) =>
+      {
+        pending.push(next)
+      }
+      _ => {}
+    }
+  }
+  (0..rom.len()).filter(|&offset| seen[offset]).collect()
+}
+
+/// Returns the signature matched by the instruction at `offset`.
+fn signature(rom: &[u8], offset: usize) -> Option<Signature> {
+  let word = word(rom, offset)?;
+  let x = (word >> 8) & 0xF;
+  let y = (word >> 4) & 0xF;
+  match word {
+    0x00FE | 0x00FF => Some(Signature::SuperChipResolution),
+    0x00C1..=0x00CF | 0x00FB | 0x00FC => Some(Signature::SuperChipScroll),
+    0x00FD => Some(Signature::SuperChipExit),
+    0xF000 => Some(Signature::XoChipLongLoad),
+    _ => match (word >> 12, word & 0xFF) {
+      (0xD, low) if low & 0xF == 0 => Some(Signature::SuperChipSprite),
+      (0xF, 0x30) => Some(Signature::SuperChipFont),
+      (0xF, 0x75 | 0x85) => Some(Signature::SuperChipFlags),
+      (0x5, low) if matches!(low & 0xF, 0x2 | 0x3) => Some(Signature::XoChipRegisterRange),
+      (0x8, low) if matches!(low & 0xF, 0x6 | 0xE) && x != y => shift_source(rom, offset, x, y),
+      (0xF, 0x55 | 0x65) if chained_load_store(rom, offset + 2) => {
+        Some(Signature::ChainedLoadStore)
+      }
+      _ => None,
+    },
+  }
+}
+
+/// Tells which register a shift at `offset` reads from the instruction that last
+/// wrote VX or VY before it, `None` if neither was written closely before.
+fn shift_source(rom: &[u8], offset: usize, x: u16, y: u16) -> Option<Signature> {
+  let previous = (1..=PATTERN_DISTANCE).map_while(|n| word(rom, offset.checked_sub(2 * n)?));
+  for word in previous {
+    let written = match (word >> 12, word & 0xFF) {
+      (0x6 | 0x7 | 0x8 | 0xC, _) | (0xF, 0x07 | 0x0A) => Some((word >> 8) & 0xF),
+      _ => None,
+    };
+    match written {
+      Some(register) if register == y => return Some(Signature::ShiftFromVy),
+      Some(register) if register == x => return Some(Signature::ShiftInPlace),
+      _ => {}
+    }
+    // Control flow joins here, what comes before may not run.
+    if matches!(word >> 12, 0x1 | 0x2 | 0x3 | 0x4 | 0x5 | 0x9 | 0xB | 0xE) {
+      return None;
+    }
+  }
+  None
+}
+
+/// Returns `true` if another `FX55`/`FX65` follows closely with no `ANNN`, `FX1E`,
+/// jump or call in between, i.e. it continues where the first one left I.
+fn chained_load_store(rom: &[u8], offset: usize) -> bool {
+  for word in (0..PATTERN_DISTANCE).map_while(|n| word(rom, offset + 2 * n)) {
+    match (word >> 12, word & 0xFF) {
+      (0xF, 0x55 | 0x65) => return true,
+      (0xA | 0xB | 0x1 | 0x2, _) | (0xF, 0x1E | 0x29 | 0x33) => return false,
+      (0x0, 0xEE) => return false,
+      _ => {}
+    }
+  }
+  false
+}
+
+/// Guesses the platform a ROM was written for.
+///
+/// # Arguments
+///
+/// * `rom` - The ROM image, as loaded at the entry point.
+///
+/// # Returns
+///
+/// * `Option<Detection>` - The guess, `None` if the image shows no signature at all.
+///
+/// # Notes
+///
+/// * XO-CHIP signatures win over SUPER-CHIP ones, which win over the CHIP-48 and
+///   then the COSMAC VIP patterns, since each platform mostly runs the programs of
+///   the previous one.
+/// * Patterns that do not work with the quirks of the proposed platform lower the
+///   confidence.
+pub fn detect_platform(rom: &[u8]) -> Option<Detection> {
+  let mut evidence: Vec<(Signature, usize)> = vec![];
+  for offset in reachable(rom) {
+    if let Some(signature) = signature(rom, offset) {
+      match evidence.iter_mut().find(|(found, _)| *found == signature) {
+        Some((_, count)) => *count += 1,
+        None => evidence.push((signature, 1)),
+      }
+    }
+  }
+
+  let weight = |platform: Platform| -> f32 {
+    evidence
+      .iter()
+      .filter(|(signature, _)| signature.platform() == platform)
+      .map(|(signature, count)| signature.weight() * *count as f32)
+      .sum()
+  };
+  let platform = [
+    Platform::XoChip,
+    Platform::SuperChip,
+    Platform::Chip48,
+    Platform::OriginalChip8,
+  ]
+  .into_iter()
+  .find(|&platform| weight(platform) > 0.0)?;
+
+  let support = weight(platform);
+  let against: f32 = evidence
+    .iter()
+    .filter(|(signature, _)| signature.contradicts(platform.quirks()))
+    .map(|(signature, count)| signature.weight() * *count as f32)
+    .sum();
+  // One strong signature alone gives 0.5, more of them approach 1.0.
+  let confidence = support / (support + 1.0) * support / (support + against);
+  Some(Detection {
+    platform,
+    confidence,
+    evidence,
+  })
+}
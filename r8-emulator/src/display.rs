@@ -4,37 +4,137 @@
 
 use r8_core::constants;
 
+/// A packed row of the display, bit `WIDTH - 1` (the MSB) is the left-most pixel.
+pub type Row = u64;
+
+// A sprite row XOR relies on rotating a whole row, so a row must be exactly one `Row`.
+const _: () = assert!(constants::WIDTH == Row::BITS as usize);
+// Every display line needs its own bit in `DirtyRows`.
+const _: () = assert!(constants::HEIGHT <= u64::BITS as usize);
+
+/// A set of display lines, one bit per line (bit `y` is line `y`).
+///
+/// Returned by `Display::dirty_rows` to tell front-ends which lines changed since
+/// they last acknowledged the display.
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct DirtyRows(u64);
+
+impl DirtyRows {
+  /// No line is dirty.
+  pub const NONE: Self = Self(0);
+  /// Every line of the display is dirty.
+  pub const ALL: Self = Self(u64::MAX >> (u64::BITS as usize - constants::HEIGHT));
+
+  /// Returns `true` if no line is dirty.
+  pub fn is_empty(&self) -> bool {
+    self.0 == 0
+  }
+
+  /// Returns `true` if line `y` is dirty.
+  pub fn contains(&self, y: usize) -> bool {
+    y < constants::HEIGHT && (self.0 >> y) & 1 == 1
+  }
+
+  /// Returns an iterator over the dirty lines, top to bottom.
+  pub fn iter(&self) -> impl Iterator<Item = usize> {
+    let mut bits = self.0;
+    std::iter::from_fn(move || {
+      if bits == 0 {
+        return None;
+      }
+      let y = bits.trailing_zeros() as usize;
+      bits &= bits - 1;
+      Some(y)
+    })
+  }
+
+  /// Marks line `y` as dirty.
+  pub(crate) fn insert(&mut self, y: usize) {
+    self.0 |= 1 << y;
+  }
+}
+
+impl std::ops::BitOr for DirtyRows {
+  type Output = Self;
+
+  fn bitor(self, rhs: Self) -> Self::Output {
+    Self(self.0 | rhs.0)
+  }
+}
+
 /// Represents the display of the Chip8 system.
 /// The display is a 64x32 monochrome display.
 ///
 /// # Fields
 ///
-/// * `vram` - A 2D array of booleans representing the video RAM of the display.
-/// * `updated` - Indicates whether the display has been updated (to avoid redrawing when there are no changes).
+/// * `vram` - One packed `Row` per display line, representing the video RAM of the display.
+/// * `dirty` - The lines changed since the front-end last acknowledged the display.
+/// * `erased` - The pixels turned off by sprite collisions since the last acknowledge.
 pub struct Display {
-  /// The video RAM of the display.
-  vram: [[bool; constants::HEIGHT]; constants::WIDTH],
-  /// Indicates whether the display has been updated.
-  pub updated: bool,
+  /// The video RAM of the display, row-major, one bit per pixel.
+  vram: [Row; constants::HEIGHT],
+  /// The lines changed since the last `acknowledge`.
+  dirty: DirtyRows,
+  /// The pixels erased by `set` since the last `acknowledge`, same layout as `vram`.
+  erased: [Row; constants::HEIGHT],
 }
 
 impl Display {
-  /// Creates a new display with all pixels set to false and `updated` set to false.
+  /// Creates a new blank display with no dirty lines.
   pub(super) fn new() -> Self {
     Self {
-      vram: [[false; constants::HEIGHT]; constants::WIDTH],
-      updated: false,
+      vram: [0; constants::HEIGHT],
+      dirty: DirtyRows::NONE,
+      erased: [0; constants::HEIGHT],
     }
   }
 
-  /// Clears the display by setting all pixels to false and marking it as updated.
+  /// Clears the display by setting all pixels to false.
+  ///
+  /// Only the lines that had lit pixels are marked as dirty. Cleared pixels are
+  /// not reported as erased, the screen was wiped on purpose.
   pub(super) fn clear(&mut self) {
-    self.updated = true;
-    self.vram = [[false; constants::HEIGHT]; constants::WIDTH];
+    self.erased = [0; constants::HEIGHT];
+    for (y, row) in self.vram.iter_mut().enumerate() {
+      if *row != 0 {
+        *row = 0;
+        self.dirty.insert(y);
+      }
+    }
+  }
+
+  /// Returns the lines changed since the last call to `acknowledge`.
+  ///
+  /// Lines accumulate across ticks, so a front-end that renders less often than
+  /// the emulator ticks does not miss updates.
+  pub fn dirty_rows(&self) -> DirtyRows {
+    self.dirty
+  }
+
+  /// Returns `true` if any line changed since the last call to `acknowledge`.
+  pub fn is_dirty(&self) -> bool {
+    !self.dirty.is_empty()
+  }
+
+  /// Marks every line as clean, call it once the front-end has drawn the dirty lines.
+  pub(super) fn acknowledge(&mut self) {
+    self.dirty = DirtyRows::NONE;
+    self.erased = [0; constants::HEIGHT];
+  }
+
+  /// Returns the pixels turned off by sprite collisions since the last call to
+  /// `acknowledge`, one packed `Row` per line.
+  ///
+  /// XOR drawing erases a sprite before redrawing it, these are the pixels a
+  /// front-end may want to keep on screen to hide the resulting flicker.
+  pub fn erased_rows(&self) -> &[Row; constants::HEIGHT] {
+    &self.erased
   }
 
   /// Sets 8 pixels on the display encoded as a single byte.
   ///
+  /// Pixels that fall past the right edge wrap around to the left edge.
+  ///
   /// # Arguments
   ///
   /// * `x` - The x-coordinate of the pixel (leftmost bit).
@@ -44,35 +144,67 @@ impl Display {
   /// # Returns
   ///
   /// `u8` - 1 if a pixel was erased (collision), otherwise 0.
-  pub fn set(&mut self, x: u8, mut y: u8, value: u8) -> u8 {
-    self.updated = true;
-    let mut result = 0;
-    y %= constants::HEIGHT as u8;
-    let y_usize = y as usize;
-
-    for bit_index in 0..u8::BITS as u8 {
-      let x_usize = ((x + bit_index) as usize) % constants::WIDTH;
-      let pixel = (value & (0x80 >> bit_index)) != 0;
-      if !(self.vram[x_usize][y_usize] ^ pixel) && !pixel {
-        result = 1;
-      }
-      self.vram[x_usize][y_usize] ^= pixel;
+  pub fn set(&mut self, x: u8, y: u8, value: u8) -> u8 {
+    let y = y as usize % constants::HEIGHT;
+    if value != 0 {
+      self.dirty.insert(y);
     }
-
-    result
+    let row = &mut self.vram[y];
+    // Align the sprite byte with the left edge, then rotate it into place so the
+    // bits crossing the right edge wrap around.
+    let sprite = ((value as Row) << (Row::BITS - u8::BITS)).rotate_right(x as u32 % Row::BITS);
+    self.erased[y] |= *row & sprite;
+    let collision = *row & sprite != 0;
+    *row ^= sprite;
+    collision as u8
   }
 
   /// Returns the value of a pixel at the specified coordinates.
   pub fn get(&self, x: usize, y: usize) -> bool {
-    self.vram[x][y]
+    (self.vram[y] >> (constants::WIDTH - 1 - x)) & 1 == 1
   }
 
-  /// Returns a reference to the video RAM of the display.
+  /// Returns the packed row at `y`, the MSB is the left-most pixel.
+  pub fn row(&self, y: usize) -> Row {
+    self.vram[y]
+  }
+
+  /// Returns all the packed rows, top to bottom.
   ///
-  /// Useful for front-ends that want to render the vram directly.
-  pub fn get_vram(&self) -> &[[bool; constants::HEIGHT]; constants::WIDTH] {
+  /// Useful for front-ends that want to render or diff whole rows at once.
+  pub fn rows(&self) -> &[Row; constants::HEIGHT] {
     &self.vram
   }
+
+  /// Returns an iterator over the coordinates `(x, y)` of every lit pixel.
+  ///
+  /// Only set bits are visited, so sparse frames are cheap to walk.
+  pub fn lit_pixels(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+    self.vram.iter().enumerate().flat_map(|(y, &row)| {
+      let mut bits = row;
+      std::iter::from_fn(move || {
+        if bits == 0 {
+          return None;
+        }
+        let x = bits.leading_zeros() as usize;
+        bits &= !(1 << (constants::WIDTH - 1 - x));
+        Some((x, y))
+      })
+    })
+  }
+
+  /// Returns a copy of the video RAM of the display as a column-major boolean array.
+  ///
+  /// Prefer `rows` for rendering, this unpacks every pixel.
+  pub fn get_vram(&self) -> [[bool; constants::HEIGHT]; constants::WIDTH] {
+    let mut vram = [[false; constants::HEIGHT]; constants::WIDTH];
+    for (x, column) in vram.iter_mut().enumerate() {
+      for (y, pixel) in column.iter_mut().enumerate() {
+        *pixel = self.get(x, y);
+      }
+    }
+    vram
+  }
 }
 
 impl std::ops::Index<(usize, usize)> for Display {
@@ -80,6 +212,10 @@ impl std::ops::Index<(usize, usize)> for Display {
 
   /// Index the display to obtain a pixel value by (x, y).
   fn index(&self, (x, y): (usize, usize)) -> &Self::Output {
-    &self.vram[x][y]
+    if self.get(x, y) {
+      &true
+    } else {
+      &false
+    }
   }
 }
@@ -1,4 +1,4 @@
-use std::io::Read;
+use std::{io::Read, sync::Arc};
 
 use log::{debug, error};
 
@@ -8,8 +8,12 @@ use r8_core::{
 };
 
 use crate::{
+  analysis::{self, Detection},
   display::Display,
   keyboard::{self, KeyBoard},
+  quirks::{MemoryQuirk, Quirks},
+  recompiler::BlockCache,
+  romdb::{RomDb, RomInfo},
 };
 
 /// Represents the state of the emulator.
@@ -35,6 +39,13 @@ pub enum State {
 /// * `keyboard` - The keyboard.
 /// * `rand` - The random number generator.
 /// * `state` - The state of the emulator.
+/// * `blocks` - The compiled blocks, only present when the recompiler engine is selected.
+/// * `quirks` - The quirks the loaded ROM runs with.
+/// * `default_quirks` - The quirks of ROMs the database does not know.
+/// * `romdb` - The ROM database `load_rom` looks ROMs up in.
+/// * `rom_info` - What the database knows about the loaded ROM.
+/// * `detect_platform` - Whether `load_rom` guesses the platform of ROMs the database does not know.
+/// * `detection` - The platform guessed for the loaded ROM.
 pub struct Emulator {
   // Registers
   pub(crate) pc: Address,
@@ -51,6 +62,14 @@ pub struct Emulator {
   // Helper Structs
   pub(crate) rand: RandGen,
   pub(crate) state: State,
+  pub(crate) blocks: Option<BlockCache>,
+  // Configuration
+  pub(crate) quirks: Quirks,
+  pub(crate) default_quirks: Quirks,
+  pub(crate) romdb: Option<Arc<RomDb>>,
+  pub(crate) rom_info: Option<RomInfo>,
+  pub(crate) detect_platform: bool,
+  pub(crate) detection: Option<Detection>,
 }
 
 impl Emulator {
@@ -72,6 +91,13 @@ impl Emulator {
       keyboard: KeyBoard::default(),
       rand: RandGen::new(),
       state: State::New,
+      blocks: None,
+      quirks: Quirks::R8,
+      default_quirks: Quirks::R8,
+      romdb: None,
+      rom_info: None,
+      detect_platform: false,
+      detection: None,
     }
   }
 
@@ -88,7 +114,18 @@ impl Emulator {
   /// # Notes
   ///
   /// * The emulator is reset to its initial state.
+  /// * If a ROM database is attached and knows the ROM, the ROM runs with the
+  ///   quirks of its platform, otherwise with the default quirks.
+  /// * With platform detection on, ROMs the database does not know run with the
+  ///   quirks of the detected platform if its confidence reaches `analysis::MIN_CONFIDENCE`.
   pub fn load_rom<R: Read>(&mut self, reader: R) -> Result<(), EmulatorError> {
+    // Buffer the image so it can be looked up in the ROM database, nothing past
+    // the end of the memory can be loaded anyway.
+    let mut rom = Vec::new();
+    reader
+      .take(constants::MEMORY_SIZE as u64)
+      .read_to_end(&mut rom)
+      .map_err(EmulatorError::LoadError)?;
     self.pc = Address::ENTRY_POINT;
     self.i = Address::new(0);
     self.delay_timer = Timer::new();
@@ -96,7 +133,22 @@ impl Emulator {
     self.registers = VRegisters::default();
     self.stack.clear();
     self.display.clear();
-    self.memory.load_rom(reader)?;
+    self.memory.load_rom(rom.as_slice())?;
+    if let Some(cache) = self.blocks.as_mut() {
+      cache.clear();
+    }
+    self.rom_info = self.romdb.as_ref().and_then(|db| db.lookup(&rom)).cloned();
+    let known = self.rom_info.as_ref().and_then(|info| info.quirks);
+    self.detection = match known {
+      None if self.detect_platform => analysis::detect_platform(&rom),
+      _ => None,
+    };
+    let detected = self
+      .detection
+      .as_ref()
+      .filter(|detection| detection.confidence >= analysis::MIN_CONFIDENCE)
+      .map(|detection| detection.platform.quirks());
+    self.quirks = known.or(detected).unwrap_or(self.default_quirks);
     self.state = State::Running;
     Ok(())
   }
@@ -110,13 +162,21 @@ impl Emulator {
   /// # Notes
   ///
   /// * If the emulator is in the `State::New` state, this function does nothing.
-  /// * If the emulator is in the `State::WaitingKey` state and the keyboard is not pressed, this function does nothing.
-  /// * If the emulator is in the `State::WaitingKey` state and the keyboard is pressed, the state is changed to `State::Running`.
+  /// * If the emulator is in the `State::WaitingKey` state and no key was pressed (or pressed
+  ///   and released, see `Quirks::key_release`) since the wait started, this function does
+  ///   nothing.
+  /// * Otherwise the lowest such key is stored in VX and the state is changed to `State::Running`.
+  ///   Keys held down before the wait started never answer it, even once released.
+  /// * The timers are not counted down, see `tick_timers`.
   pub fn tick(&mut self) -> Result<(), EmulatorError> {
     match self.state {
       State::New => return Ok(()),
       State::WaitingKey { x } => {
-        let Some(key) = (0..=0xF).find(|&key| self.keyboard.is_set(key)) else {
+        let answers = |key| match self.quirks.key_release {
+          true => self.keyboard.was_pressed(key) && self.keyboard.was_released(key),
+          false => self.keyboard.was_pressed(key),
+        };
+        let Some(key) = (0..=0xF).find(|&key| answers(key)) else {
           return Ok(());
         };
         self.registers[x] = key;
@@ -125,12 +185,6 @@ impl Emulator {
       _ => {}
     }
 
-    // reset the updated flag
-    self.display.updated = false;
-
-    self.sound_timer.decrement();
-    self.delay_timer.decrement();
-
     // Fetch the opcode
     let opcode = self.fetch_opcode()?;
 
@@ -141,6 +195,16 @@ impl Emulator {
     Ok(())
   }
 
+  /// Counts the delay and sound timers down by one.
+  ///
+  /// The timers run at 60 Hz whatever the number of instructions per frame, so a
+  /// front-end calls this once per 60 Hz frame and `tick` as many times as the
+  /// speed of the ROM asks for.
+  pub fn tick_timers(&mut self) {
+    self.sound_timer.decrement();
+    self.delay_timer.decrement();
+  }
+
   /// Fetches the next opcode from memory.
   ///
   /// # Returns
@@ -203,9 +267,18 @@ impl Emulator {
       Opcode::LdByte { x, byte } => V![x] = byte,
       Opcode::AddByte { x, byte } => V![x] = V![x].wrapping_add(byte),
       Opcode::LdRegister { x, y } => V![x] = V![y],
-      Opcode::Or { x, y } => V![x] |= V![y],
-      Opcode::And { x, y } => V![x] &= V![y],
-      Opcode::Xor { x, y } => V![x] ^= V![y],
+      Opcode::Or { x, y } => {
+        V![x] |= V![y];
+        self.reset_flag_after_logic();
+      }
+      Opcode::And { x, y } => {
+        V![x] &= V![y];
+        self.reset_flag_after_logic();
+      }
+      Opcode::Xor { x, y } => {
+        V![x] ^= V![y];
+        self.reset_flag_after_logic();
+      }
       Opcode::AddRegister { x, y } => {
         let result = V![x] as u16 + V![y] as u16;
         V![x] = (result & 0xFF) as u8;
@@ -215,37 +288,21 @@ impl Emulator {
         V![FLAGS] = if V![x] > V![y] { 1 } else { 0 };
         V![x] = V![x].wrapping_sub(V![y]);
       }
-      Opcode::Shr { x } => {
-        V![FLAGS] = V![x] & 1;
-        V![x] >>= 1;
-      }
+      Opcode::Shr { x, y } => self.shift(x, y, false),
       Opcode::Subn { x, y } => {
         V![FLAGS] = if V![y] > V![x] { 1 } else { 0 };
         V![x] = V![y].wrapping_sub(V![x]);
       }
-      Opcode::Shl { x } => {
-        V![FLAGS] = (V![x] >> 7) & 1;
-        V![x] <<= 1;
-      }
+      Opcode::Shl { x, y } => self.shift(x, y, true),
       Opcode::SneRegister { x, y } => {
         if V![x] != V![y] {
           self.pc.add_assign(2)?
         }
       }
       Opcode::LdI { address } => self.i = address,
-      Opcode::JpV0 { address } => self.pc.add_assign(address.inner() + V![0] as u16)?,
+      Opcode::JpV0 { address } => self.jump_with_offset(address.inner())?,
       Opcode::Rnd { x, byte } => V![x] = self.rand.next() & byte,
-      Opcode::Drw { x, y, n } => {
-        V![FLAGS] = 0;
-        let (x, y) = (V![x], V![y]);
-        for row in 0..n {
-          V![FLAGS] |= self.display.set(
-            x,
-            (y % (constants::HEIGHT as u8)) + row,
-            self.memory[(self.i.inner() + row as u16).try_into()?],
-          )
-        }
-      }
+      Opcode::Drw { x, y, n } => self.draw(x, y, n)?,
       Opcode::Skp { x } => {
         if self.keyboard.is_set(V![x] & 0xF) {
           self.pc.add_assign(2)?;
@@ -257,14 +314,17 @@ impl Emulator {
         }
       }
       Opcode::LdVxDT { x } => V![x] = self.delay_timer.get(),
-      Opcode::LdVxK { x } => self.state = State::WaitingKey { x },
+      Opcode::LdVxK { x } => self.wait_key(x),
       Opcode::LdDTVx { x } => self.delay_timer.set(V![x]),
       Opcode::LdSTVx { x } => self.sound_timer.set(V![x]),
       Opcode::AddIVx { x } => self.i.add_assign(V![x] as u16)?,
       Opcode::LdFVx { x } => self.i = Address::new((V![x] & 0xF) as u16 * 5),
-      Opcode::LdBVx { x } => self.memory.read_range(self.i, &bcd(V![x]))?,
-      Opcode::LdIVx { x } => self.memory.read_range(self.i, &V![0 => x])?,
-      Opcode::LdVxI { x } => self.memory.write_range(self.i, &mut V![0 => x])?,
+      Opcode::LdBVx { x } => {
+        self.memory.read_range(self.i, &bcd(V![x]))?;
+        self.invalidate_code(self.i, 3);
+      }
+      Opcode::LdIVx { x } => self.store_registers(x)?,
+      Opcode::LdVxI { x } => self.load_registers(x)?,
       Opcode::Invalid(data) => {
         error!(
           "Unrecognized OpCode: | 0x{PC:X} | {:X?}",
@@ -277,6 +337,139 @@ impl Emulator {
     Ok(())
   }
 
+  /// `FX0A`: waits for a key, only presses and releases from now on answer it.
+  pub(crate) fn wait_key(&mut self, x: RegisterIndex) {
+    self.keyboard.clear_edges();
+    self.state = State::WaitingKey { x };
+  }
+
+  /// `8XY1`, `8XY2`, `8XY3`: resets VF when the logic quirk is enabled.
+  pub(crate) fn reset_flag_after_logic(&mut self) {
+    if self.quirks.logic {
+      self.registers[RegisterIndex::FLAG] = 0;
+    }
+  }
+
+  /// `8XY6`, `8XYE`: shifts VX (or VY, see `Quirks::shift`) by one bit into VX,
+  /// VF gets the bit shifted out.
+  pub(crate) fn shift(&mut self, x: RegisterIndex, y: RegisterIndex, left: bool) {
+    let source = if self.quirks.shift { x } else { y };
+    if left {
+      self.registers[RegisterIndex::FLAG] = (self.registers[source] >> 7) & 1;
+      self.registers[x] = self.registers[source] << 1;
+    } else {
+      self.registers[RegisterIndex::FLAG] = self.registers[source] & 1;
+      self.registers[x] = self.registers[source] >> 1;
+    }
+  }
+
+  /// `BNNN`: jumps to NNN + V0, or to XNN + VX with the jump quirk.
+  pub(crate) fn jump_with_offset(&mut self, address: u16) -> Result<(), EmulatorError> {
+    let offset = if self.quirks.jump {
+      self.registers[RegisterIndex::try_from((address >> 8) as u8 & 0xF)?]
+    } else {
+      self.registers[RegisterIndex::ZERO]
+    };
+    self.pc = Address::try_new(address + offset as u16)?;
+    Ok(())
+  }
+
+  /// `DXYN`: draws the N bytes sprite at I on (VX, VY), VF is set on collision.
+  ///
+  /// The sprite starts at VX and VY modulo the screen size, the part crossing the
+  /// edges wraps around or is clipped depending on `Quirks::wrap`.
+  pub(crate) fn draw(
+    &mut self,
+    x: RegisterIndex,
+    y: RegisterIndex,
+    n: u8,
+  ) -> Result<(), EmulatorError> {
+    self.registers[RegisterIndex::FLAG] = 0;
+    let (x, y) = (
+      self.registers[x],
+      self.registers[y] % constants::HEIGHT as u8,
+    );
+    let mut collision = 0;
+    for row in 0..n {
+      let mut sprite = self.memory[(self.i.inner() + row as u16).try_into()?];
+      if !self.quirks.wrap {
+        if (y + row) as usize >= constants::HEIGHT {
+          break;
+        }
+        // Drop the pixels past the right edge.
+        let overflow = (x as usize % constants::WIDTH + 8).saturating_sub(constants::WIDTH);
+        sprite &= (0xFF_u16 << overflow) as u8;
+      }
+      collision |= self.display.set(x, y + row, sprite);
+    }
+    self.registers[RegisterIndex::FLAG] |= collision;
+    Ok(())
+  }
+
+  /// `FX55`: stores V0 to VX at I.
+  pub(crate) fn store_registers(&mut self, x: RegisterIndex) -> Result<(), EmulatorError> {
+    let registers = &self.registers[RegisterIndex::ZERO..=x];
+    self.memory.read_range(self.i, registers)?;
+    let count = registers.len();
+    self.invalidate_code(self.i, count);
+    self.advance_i(count)
+  }
+
+  /// `FX65`: loads V0 to VX from I.
+  pub(crate) fn load_registers(&mut self, x: RegisterIndex) -> Result<(), EmulatorError> {
+    let registers = &mut self.registers[RegisterIndex::ZERO..=x];
+    self.memory.write_range(self.i, registers)?;
+    let count = registers.len();
+    self.advance_i(count)
+  }
+
+  /// Moves I past `count` registers loaded or stored, as the memory quirk says.
+  fn advance_i(&mut self, count: usize) -> Result<(), EmulatorError> {
+    match self.quirks.memory {
+      MemoryQuirk::Unchanged => Ok(()),
+      MemoryQuirk::IncrementByX => self.i.add_assign(count as u16 - 1),
+      MemoryQuirk::IncrementByXPlusOne => self.i.add_assign(count as u16),
+    }
+  }
+
+  /// Returns the quirks the loaded ROM runs with.
+  pub fn quirks(&self) -> Quirks {
+    self.quirks
+  }
+
+  /// Sets the quirks of ROMs the ROM database does not know, they apply right away.
+  pub fn set_quirks(&mut self, quirks: Quirks) {
+    self.default_quirks = quirks;
+    self.quirks = quirks;
+  }
+
+  /// Sets the quirks of the loaded ROM only, the next `load_rom` picks them again.
+  pub fn set_rom_quirks(&mut self, quirks: Quirks) {
+    self.quirks = quirks;
+  }
+
+  /// Attaches the ROM database `load_rom` looks ROMs up in, `None` detaches it.
+  pub fn set_rom_db(&mut self, romdb: Option<Arc<RomDb>>) {
+    self.romdb = romdb;
+  }
+
+  /// Returns what the ROM database knows about the loaded ROM.
+  pub fn rom_info(&self) -> Option<&RomInfo> {
+    self.rom_info.as_ref()
+  }
+
+  /// Makes `load_rom` guess the platform of ROMs the ROM database does not know,
+  /// and run them with its quirks when the guess is confident enough.
+  pub fn set_platform_detection(&mut self, enabled: bool) {
+    self.detect_platform = enabled;
+  }
+
+  /// Returns the platform guessed for the loaded ROM, even if it was not confident
+  /// enough to be applied.
+  pub fn detection(&self) -> Option<&Detection> {
+    self.detection.as_ref()
+  }
+
   /// Returns a reference to the emulator's display.
   ///
   /// # Returns
@@ -286,6 +479,21 @@ impl Emulator {
     &self.display
   }
 
+  /// Reseeds the random number generator used by `RND`.
+  ///
+  /// Headless and test runs use a fixed seed so their results are reproducible.
+  pub fn set_rand_seed(&mut self, seed: u128) {
+    self.rand = RandGen::with_seed(seed);
+  }
+
+  /// Marks the display as drawn, clearing its dirty lines.
+  ///
+  /// Front-ends call this after rendering `display().dirty_rows()`, the emulator
+  /// never clears them on its own.
+  pub fn acknowledge_display(&mut self) {
+    self.display.acknowledge();
+  }
+
   /// Change the state of the virtual `key` key to pressed.
   pub fn press_key(&mut self, key: keyboard::Key) {
     self.keyboard.set(key as u8);
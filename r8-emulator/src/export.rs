@@ -0,0 +1,382 @@
+//! Framebuffer export to image files.
+//!
+//! PBM and PGM are written by hand (binary `P4`/`P5`), PNG goes through the `png`
+//! crate and supports a colour palette, integer scaling and an optional pixel grid.
+//! Any `Frame` can be exported, built either from the raw `Display` or from the
+//! post-processed output front-ends actually show.
+
+use std::{
+  fmt, io,
+  path::{Path, PathBuf},
+  str::FromStr,
+  time::{SystemTime, UNIX_EPOCH},
+};
+
+use r8_core::constants;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{postprocess::FULL, Display, FrameProcessor};
+
+/// An RGB colour.
+pub type Rgb = [u8; 3];
+
+/// The two colours of the screen, pixels in between (e.g. fading phosphor) are
+/// blended linearly.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Palette {
+  /// Colour of an unlit pixel.
+  pub off: Rgb,
+  /// Colour of a fully lit pixel.
+  pub on: Rgb,
+}
+
+impl Palette {
+  /// White pixels on a black background, the colours of both front-ends.
+  pub const CLASSIC: Self = Self {
+    off: [0x00, 0x00, 0x00],
+    on: [0xFF, 0xFF, 0xFF],
+  };
+
+  /// Returns the colour of a pixel with the given intensity (`0` is off, `FULL` is on).
+  pub fn color(&self, intensity: u8) -> Rgb {
+    let mix = |off: u8, on: u8| {
+      let (off, on, t) = (off as u32, on as u32, intensity as u32);
+      ((off * (FULL as u32 - t) + on * t) / FULL as u32) as u8
+    };
+    [
+      mix(self.off[0], self.on[0]),
+      mix(self.off[1], self.on[1]),
+      mix(self.off[2], self.on[2]),
+    ]
+  }
+}
+
+impl Default for Palette {
+  fn default() -> Self {
+    Self::CLASSIC
+  }
+}
+
+impl fmt::Display for Palette {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let [r, g, b] = self.off;
+    let [r2, g2, b2] = self.on;
+    write!(f, "{r:02X}{g:02X}{b:02X}:{r2:02X}{g2:02X}{b2:02X}")
+  }
+}
+
+/// Palettes are stored in settings files in their `OFF:ON` form.
+impl Serialize for Palette {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(self)
+  }
+}
+
+impl<'de> Deserialize<'de> for Palette {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    String::deserialize(deserializer)?
+      .parse()
+      .map_err(de::Error::custom)
+  }
+}
+
+impl FromStr for Palette {
+  type Err = String;
+
+  /// Parses `OFF:ON` with both colours as `RRGGBB` hex, e.g. `000000:33FF66`.
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let (off, on) = s
+      .split_once(':')
+      .ok_or_else(|| format!("invalid palette `{s}`, expected OFF:ON (e.g. 000000:FFFFFF)"))?;
+    Ok(Self {
+      off: parse_rgb(off)?,
+      on: parse_rgb(on)?,
+    })
+  }
+}
+
+/// Parses an `RRGGBB` hex colour, a leading `#` is accepted.
+pub fn parse_rgb(s: &str) -> Result<Rgb, String> {
+  let hex = s.strip_prefix('#').unwrap_or(s);
+  let value = (hex.len() == 6)
+    .then(|| u32::from_str_radix(hex, 16).ok())
+    .flatten()
+    .ok_or_else(|| format!("invalid colour `{s}`, expected RRGGBB"))?;
+  let [_, r, g, b] = value.to_be_bytes();
+  Ok([r, g, b])
+}
+
+/// A snapshot of the screen, one intensity per pixel (`0` is off, `FULL` is on).
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Frame {
+  pixels: [[u8; constants::WIDTH]; constants::HEIGHT],
+}
+
+impl Frame {
+  /// Returns the intensity of the pixel at `(x, y)`.
+  pub fn intensity(&self, x: usize, y: usize) -> u8 {
+    self.pixels[y][x]
+  }
+
+  /// Returns `true` if the pixel at `(x, y)` is at least half lit.
+  pub fn is_lit(&self, x: usize, y: usize) -> bool {
+    self.pixels[y][x] > FULL / 2
+  }
+}
+
+impl From<&Display> for Frame {
+  fn from(display: &Display) -> Self {
+    let mut pixels = [[0; constants::WIDTH]; constants::HEIGHT];
+    for (x, y) in display.lit_pixels() {
+      pixels[y][x] = FULL;
+    }
+    Self { pixels }
+  }
+}
+
+impl From<&FrameProcessor> for Frame {
+  fn from(output: &FrameProcessor) -> Self {
+    let mut pixels = [[0; constants::WIDTH]; constants::HEIGHT];
+    for (y, row) in pixels.iter_mut().enumerate() {
+      for (x, pixel) in row.iter_mut().enumerate() {
+        *pixel = output.intensity(x, y);
+      }
+    }
+    Self { pixels }
+  }
+}
+
+/// Supported image formats.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ImageFormat {
+  /// Binary portable bitmap (`P4`), lit pixels are white.
+  Pbm,
+  /// Binary portable graymap (`P5`), the grey level is the pixel intensity.
+  Pgm,
+  /// RGB PNG using the palette and the grid of the options.
+  Png,
+}
+
+impl ImageFormat {
+  /// Returns the file extension of the format.
+  pub fn extension(&self) -> &'static str {
+    match self {
+      ImageFormat::Pbm => "pbm",
+      ImageFormat::Pgm => "pgm",
+      ImageFormat::Png => "png",
+    }
+  }
+
+  /// Picks the format from the extension of `path`, if it is a known one.
+  pub fn from_path(path: &Path) -> Option<Self> {
+    let extension = path.extension()?.to_str()?;
+    [ImageFormat::Pbm, ImageFormat::Pgm, ImageFormat::Png]
+      .into_iter()
+      .find(|format| format.extension().eq_ignore_ascii_case(extension))
+  }
+}
+
+/// The largest `scale` of `ExportOptions`, a 4096x2048 image.
+pub const MAX_SCALE: u32 = 64;
+
+/// How a frame is turned into an image.
+///
+/// # Fields
+///
+/// * `palette` - The colours of the PNG output.
+/// * `scale` - The size in image pixels of one display pixel (1 to `MAX_SCALE`).
+/// * `grid` - When set, the top and left edges of every scaled pixel are drawn in
+///   this colour (PNG only, needs a `scale` of 2 or more).
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ExportOptions {
+  pub palette: Palette,
+  pub scale: u32,
+  pub grid: Option<Rgb>,
+}
+
+impl Default for ExportOptions {
+  fn default() -> Self {
+    Self {
+      palette: Palette::CLASSIC,
+      scale: 1,
+      grid: None,
+    }
+  }
+}
+
+impl ExportOptions {
+  /// Returns the size of the exported image in pixels.
+  pub fn size(&self) -> (u32, u32) {
+    let scale = self.scale.max(1);
+    (
+      (constants::WIDTH as u32).saturating_mul(scale),
+      (constants::HEIGHT as u32).saturating_mul(scale),
+    )
+  }
+
+  /// Returns the size of the exported image in pixels.
+  ///
+  /// # Returns
+  ///
+  /// * `io::Result<(u32, u32)>` - The size, an `InvalidInput` error if the scale is
+  ///   over `MAX_SCALE`.
+  pub(crate) fn checked_size(&self) -> io::Result<(u32, u32)> {
+    if self.scale > MAX_SCALE {
+      return Err(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!(
+          "the scale {} is over the maximum of {MAX_SCALE}",
+          self.scale
+        ),
+      ));
+    }
+    Ok(self.size())
+  }
+
+  /// Returns the display pixel shown at image pixel `(x, y)`, or `None` for a grid line.
+  pub(crate) fn source(&self, x: u32, y: u32) -> Option<(usize, usize)> {
+    let scale = self.scale.max(1);
+    let on_grid =
+      self.grid.is_some() && scale > 1 && (x.is_multiple_of(scale) || y.is_multiple_of(scale));
+    (!on_grid).then_some(((x / scale) as usize, (y / scale) as usize))
+  }
+}
+
+/// Writes `frame` as an image.
+///
+/// # Arguments
+///
+/// * `frame` - The frame to export.
+/// * `format` - The image format.
+/// * `options` - Palette, scale and grid (PBM and PGM only use the scale).
+/// * `out` - Where to write the image.
+///
+/// # Returns
+///
+/// `io::Result<()>` - An `InvalidInput` error if the scale is over `MAX_SCALE`.
+pub fn write_image<W: io::Write>(
+  frame: &Frame,
+  format: ImageFormat,
+  options: &ExportOptions,
+  mut out: W,
+) -> io::Result<()> {
+  let (width, height) = options.checked_size()?;
+  let scale = options.scale.max(1);
+  let pixel = |x: u32, y: u32| frame.intensity((x / scale) as usize, (y / scale) as usize);
+
+  match format {
+    ImageFormat::Pbm => {
+      write!(out, "P4\n{width} {height}\n")?;
+      // 1 is black in PBM, rows are padded to a whole byte.
+      let mut row = vec![0u8; width.div_ceil(8) as usize];
+      for y in 0..height {
+        row.fill(0);
+        for x in 0..width {
+          if pixel(x, y) <= FULL / 2 {
+            row[x as usize / 8] |= 0x80 >> (x % 8);
+          }
+        }
+        out.write_all(&row)?;
+      }
+      Ok(())
+    }
+    ImageFormat::Pgm => {
+      write!(out, "P5\n{width} {height}\n{FULL}\n")?;
+      let data: Vec<u8> = (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .map(|(x, y)| pixel(x, y))
+        .collect();
+      out.write_all(&data)
+    }
+    ImageFormat::Png => {
+      let mut encoder = png::Encoder::new(out, width, height);
+      encoder.set_color(png::ColorType::Rgb);
+      encoder.set_depth(png::BitDepth::Eight);
+      let mut writer = encoder.write_header().map_err(io::Error::other)?;
+      let mut data = Vec::with_capacity(width as usize * height as usize * 3);
+      for y in 0..height {
+        for x in 0..width {
+          let color = match options.source(x, y) {
+            Some((x, y)) => options.palette.color(frame.intensity(x, y)),
+            None => options.grid.unwrap_or(options.palette.off),
+          };
+          data.extend_from_slice(&color);
+        }
+      }
+      writer.write_image_data(&data).map_err(io::Error::other)
+    }
+  }
+}
+
+/// Writes `frame` to the file at `path`, the format is picked from its extension.
+///
+/// # Returns
+///
+/// `io::Result<()>` - An `InvalidInput` error if the extension is not a known format.
+pub fn save_image(frame: &Frame, path: &Path, options: &ExportOptions) -> io::Result<()> {
+  let format = ImageFormat::from_path(path).ok_or_else(|| {
+    io::Error::new(
+      io::ErrorKind::InvalidInput,
+      format!("unknown image format: {}", path.display()),
+    )
+  })?;
+  let mut file = io::BufWriter::new(std::fs::File::create(path)?);
+  write_image(frame, format, options, &mut file)?;
+  io::Write::flush(&mut file)
+}
+
+/// Returns a path for a capture of `rom` that does not exist yet.
+///
+/// The file is placed next to the ROM and named after it with a UTC timestamp,
+/// e.g. `roms/PONG-20240131-235959.png`. Without a ROM the current directory and
+/// the name `r8` are used.
+///
+/// # Arguments
+///
+/// * `rom` - The path of the loaded ROM, if known.
+/// * `extension` - The extension of the capture, without the dot.
+pub fn timestamped_path(rom: Option<&Path>, extension: &str) -> PathBuf {
+  let dir = rom
+    .and_then(Path::parent)
+    .map(Path::to_path_buf)
+    .unwrap_or_default();
+  let stem = rom
+    .and_then(Path::file_stem)
+    .map(|stem| stem.to_string_lossy().into_owned())
+    .unwrap_or_else(|| "r8".to_string());
+  let seconds = SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|elapsed| elapsed.as_secs())
+    .unwrap_or_default();
+  let base = format!("{stem}-{}", utc_timestamp(seconds));
+
+  // Several captures in the same second get a counter.
+  (1..)
+    .map(|n| match n {
+      1 => dir.join(format!("{base}.{extension}")),
+      n => dir.join(format!("{base}-{n}.{extension}")),
+    })
+    .find(|path| !path.exists())
+    .expect("some counter is free")
+}
+
+/// Formats seconds since the Unix epoch as `YYYYMMDD-HHMMSS` (UTC).
+pub(crate) fn utc_timestamp(seconds: u64) -> String {
+  let (days, time) = (seconds / 86_400, seconds % 86_400);
+  // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm).
+  let z = days as i64 + 719_468;
+  let era = z.div_euclid(146_097);
+  let doe = z.rem_euclid(146_097);
+  let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+  let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+  let mp = (5 * doy + 2) / 153;
+  let day = doy - (153 * mp + 2) / 5 + 1;
+  let month = if mp < 10 { mp + 3 } else { mp - 9 };
+  let year = yoe + era * 400 + (month <= 2) as i64;
+  format!(
+    "{year:04}{month:02}{day:02}-{:02}{:02}{:02}",
+    time / 3_600,
+    time / 60 % 60,
+    time % 60
+  )
+}
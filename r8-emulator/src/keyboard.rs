@@ -1,8 +1,21 @@
-#[repr(transparent)]
 #[derive(Default)]
 /// http://devernay.free.fr/hacks/chip8/C8TECH10.HTM#keyboard
-/// Represents the keyboard of the Chip8 system as a bitmask.
-pub struct KeyBoard(u16);
+/// Represents the keyboard of the Chip8 system as bitmasks.
+///
+/// Besides the keys held down, the keyboard remembers which keys were pressed and
+/// released since its edges were last cleared, so `FX0A` can wait for a new press
+/// or a release instead of taking a key held from before.
+///
+/// # Fields
+///
+/// * `held` - The keys currently down.
+/// * `pressed` - The keys that went down since the edges were cleared.
+/// * `released` - The keys that went up since the edges were cleared.
+pub struct KeyBoard {
+  held: u16,
+  pressed: u16,
+  released: u16,
+}
 
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -51,22 +64,28 @@ impl Key {
 }
 
 impl KeyBoard {
-  /// Set the key at the given index
+  /// Set the key at the given index, a key that was up records a press
   ///
   /// # Arguments
   ///
   /// * `key` - The index of the key to set
   pub fn set(&mut self, key: u8) {
-    self.0 |= 1 << key
+    if !self.is_set(key) {
+      self.pressed |= 1 << key;
+    }
+    self.held |= 1 << key
   }
 
-  /// Unset the key at the given index
+  /// Unset the key at the given index, a key that was down records a release
   ///
   /// # Arguments
   ///
   /// * `key` - The index of the key to unset
   pub fn unset(&mut self, key: u8) {
-    self.0 &= !(1 << key)
+    if self.is_set(key) {
+      self.released |= 1 << key;
+    }
+    self.held &= !(1 << key)
   }
 
   /// Check if the key at the given index is set
@@ -79,6 +98,30 @@ impl KeyBoard {
   ///
   /// * `bool` - Returns true if the key is set, otherwise returns false
   pub fn is_set(&self, key: u8) -> bool {
-    (self.0 >> key) & 1 == 1
+    (self.held >> key) & 1 == 1
+  }
+
+  /// Check if the key at the given index went down since the edges were cleared
+  ///
+  /// # Arguments
+  ///
+  /// * `key` - The index of the key to check
+  pub fn was_pressed(&self, key: u8) -> bool {
+    (self.pressed >> key) & 1 == 1
+  }
+
+  /// Check if the key at the given index went up since the edges were cleared
+  ///
+  /// # Arguments
+  ///
+  /// * `key` - The index of the key to check
+  pub fn was_released(&self, key: u8) -> bool {
+    (self.released >> key) & 1 == 1
+  }
+
+  /// Forget the presses and releases seen so far, held keys stay down
+  pub fn clear_edges(&mut self) {
+    self.pressed = 0;
+    self.released = 0;
   }
 }
@@ -11,21 +11,47 @@ via `r8_core`.
 */
 
 // Public modules that belong to this crate. Keep these modules focused on the runtime and devices.
+pub mod analysis;
 pub mod debug;
 pub mod display;
 pub mod emulator;
+pub mod export;
 pub mod keyboard;
+pub mod postprocess;
+pub mod quirks;
+pub mod recompiler;
+pub mod record;
+pub mod romdb;
+pub mod script;
 
 /// Re-export the main emulator type so downstream crates can import it directly:
 ///
 /// use r8_emulator::Emulator;
 pub use emulator::Emulator;
 
+/// Re-export the execution engine selector used by `Emulator::run`.
+pub use recompiler::Engine;
+
 /// Re-export the common keyboard types so frontends can map or forward inputs easily.
 pub use keyboard::{Key, KeyBoard};
 
+/// Re-export the platform presets and quirks the emulator can run with.
+pub use quirks::{Platform, Quirks};
+
+/// Re-export the ROM database `load_rom` looks ROMs up in.
+pub use romdb::{RomDb, RomInfo};
+
+/// Re-export the input script used to drive headless runs.
+pub use script::InputScript;
+
+/// Re-export the output stage front-ends render from.
+pub use postprocess::{FrameProcessor, PostProcess};
+
+/// Re-export the core constants and addresses so frontends do not need `r8-core` for them.
+pub use r8_core::{constants, Address};
+
 /// Optionally re-export the public display type to be used by frontends that need direct access.
-pub use display::Display;
+pub use display::{DirtyRows, Display};
 
 #[cfg(test)]
 mod tests;
@@ -0,0 +1,226 @@
+//! Output stage that turns the raw framebuffer into a presentable frame.
+//!
+//! CHIP-8 programs move sprites by erasing them with an XOR draw and drawing them
+//! again, so sampling the raw VRAM once per frame makes them flicker. A
+//! `FrameProcessor` keeps a short history of sampled frames and computes a
+//! per-pixel intensity from it, front-ends render that instead of the VRAM.
+
+use std::{fmt, str::FromStr};
+
+use r8_core::constants;
+
+use crate::display::{DirtyRows, Display, Row};
+
+/// The maximum number of frames `PostProcess::Blend` can OR together.
+pub const MAX_BLEND_FRAMES: u8 = 8;
+
+/// Intensity of a fully lit pixel.
+pub const FULL: u8 = u8::MAX;
+
+/// How a `FrameProcessor` builds the output frame.
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum PostProcess {
+  /// The VRAM as is, pixels are either off or fully lit.
+  #[default]
+  Raw,
+  /// A pixel is lit if it was lit in any of the last `frames` frames (1..=`MAX_BLEND_FRAMES`).
+  Blend { frames: u8 },
+  /// Lit pixels are full intensity and fade by `fade` every frame once turned off,
+  /// like the phosphor of a CRT.
+  Phosphor { fade: u8 },
+  /// Pixels erased by a sprite collision stay lit for one more frame, pixels
+  /// removed by clearing the screen go off immediately.
+  Smooth,
+}
+
+impl PostProcess {
+  /// One preset of every mode, in the order `next` cycles through them.
+  pub const PRESETS: [PostProcess; 4] = [
+    PostProcess::Raw,
+    PostProcess::Blend { frames: 3 },
+    PostProcess::Phosphor { fade: 64 },
+    PostProcess::Smooth,
+  ];
+
+  /// Returns the short name of the mode, as accepted by `from_str`.
+  pub fn name(&self) -> &'static str {
+    match self {
+      PostProcess::Raw => "raw",
+      PostProcess::Blend { .. } => "blend",
+      PostProcess::Phosphor { .. } => "phosphor",
+      PostProcess::Smooth => "smooth",
+    }
+  }
+
+  /// Returns the preset of the mode that follows this one, wrapping around.
+  ///
+  /// Handy for front-ends that switch modes with a single hotkey.
+  pub fn next(self) -> Self {
+    let index = Self::PRESETS
+      .iter()
+      .position(|preset| preset.name() == self.name())
+      .unwrap_or(0);
+    Self::PRESETS[(index + 1) % Self::PRESETS.len()]
+  }
+}
+
+impl fmt::Display for PostProcess {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      PostProcess::Blend { frames } => write!(f, "{}:{frames}", self.name()),
+      PostProcess::Phosphor { fade } => write!(f, "{}:{fade}", self.name()),
+      _ => f.write_str(self.name()),
+    }
+  }
+}
+
+impl FromStr for PostProcess {
+  type Err = String;
+
+  /// Parses `raw`, `smooth`, `blend[:frames]` or `phosphor[:fade]`.
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let (name, arg) = match s.split_once(':') {
+      Some((name, arg)) => (name, Some(arg)),
+      None => (s, None),
+    };
+    let preset = Self::PRESETS
+      .into_iter()
+      .find(|preset| preset.name().eq_ignore_ascii_case(name))
+      .ok_or_else(|| format!("unknown post-processing mode `{name}`"))?;
+    let Some(arg) = arg else {
+      return Ok(preset);
+    };
+    let value: u8 = arg
+      .parse()
+      .map_err(|_| format!("invalid value `{arg}` for `{name}`"))?;
+    match preset {
+      PostProcess::Blend { .. } if (1..=MAX_BLEND_FRAMES).contains(&value) => {
+        Ok(PostProcess::Blend { frames: value })
+      }
+      PostProcess::Blend { .. } => Err(format!(
+        "blend needs between 1 and {MAX_BLEND_FRAMES} frames"
+      )),
+      PostProcess::Phosphor { .. } if value > 0 => Ok(PostProcess::Phosphor { fade: value }),
+      PostProcess::Phosphor { .. } => Err("phosphor fade must be greater than 0".to_string()),
+      _ => Err(format!("`{name}` takes no value")),
+    }
+  }
+}
+
+/// Builds the presented frame from the history of the display.
+///
+/// Call `push` once per rendered frame, before acknowledging the display, then
+/// draw the lines reported by `changed_rows` using `intensity`.
+///
+/// # Fields
+///
+/// * `mode` - The active post-processing mode.
+/// * `history` - Ring buffer of the last sampled frames, `head` is the newest.
+/// * `intensity` - The output frame, row-major, `0` is off and `FULL` is fully lit.
+/// * `changed` - The output lines that changed in the last `push`.
+pub struct FrameProcessor {
+  mode: PostProcess,
+  history: [[Row; constants::HEIGHT]; MAX_BLEND_FRAMES as usize],
+  head: usize,
+  intensity: [[u8; constants::WIDTH]; constants::HEIGHT],
+  changed: DirtyRows,
+  /// Report every line on the next `push` (after a mode switch or `repaint`).
+  force: bool,
+}
+
+impl FrameProcessor {
+  /// Creates a processor with an empty history and a blank output.
+  ///
+  /// # Arguments
+  ///
+  /// * `mode` - The post-processing mode to start with.
+  pub fn new(mode: PostProcess) -> Self {
+    Self {
+      mode,
+      history: [[0; constants::HEIGHT]; MAX_BLEND_FRAMES as usize],
+      head: 0,
+      intensity: [[0; constants::WIDTH]; constants::HEIGHT],
+      changed: DirtyRows::NONE,
+      force: false,
+    }
+  }
+
+  /// Returns the active post-processing mode.
+  pub fn mode(&self) -> PostProcess {
+    self.mode
+  }
+
+  /// Switches the post-processing mode, the next `push` reports every line as changed.
+  pub fn set_mode(&mut self, mode: PostProcess) {
+    if mode != self.mode {
+      self.mode = mode;
+      self.force = true;
+    }
+  }
+
+  /// Makes the next `push` report every line as changed, e.g. after a palette change.
+  pub fn repaint(&mut self) {
+    self.force = true;
+  }
+
+  /// Samples the display and recomputes the output frame.
+  ///
+  /// # Arguments
+  ///
+  /// * `display` - The emulator display, sampled once per rendered frame.
+  pub fn push(&mut self, display: &Display) {
+    let previous = self.history[self.head];
+    self.head = (self.head + 1) % self.history.len();
+    self.history[self.head] = *display.rows();
+
+    self.changed = if std::mem::take(&mut self.force) {
+      DirtyRows::ALL
+    } else {
+      DirtyRows::NONE
+    };
+
+    let erased = display.erased_rows();
+    for (y, (&previous, &erased)) in previous.iter().zip(erased).enumerate() {
+      let current = self.history[self.head][y];
+      let lit = match self.mode {
+        PostProcess::Raw | PostProcess::Phosphor { .. } => current,
+        PostProcess::Blend { frames } => (0..frames.clamp(1, MAX_BLEND_FRAMES) as usize)
+          .map(|age| self.history[(self.head + self.history.len() - age) % self.history.len()][y])
+          .fold(0, |acc, row| acc | row),
+        PostProcess::Smooth => current | (erased & previous),
+      };
+
+      let mut changed = false;
+      for (x, pixel) in self.intensity[y].iter_mut().enumerate() {
+        let value = if (lit >> (constants::WIDTH - 1 - x)) & 1 == 1 {
+          FULL
+        } else if let PostProcess::Phosphor { fade } = self.mode {
+          pixel.saturating_sub(fade)
+        } else {
+          0
+        };
+        changed |= *pixel != value;
+        *pixel = value;
+      }
+      if changed {
+        self.changed.insert(y);
+      }
+    }
+  }
+
+  /// Returns the intensity of the output pixel at `(x, y)`, from `0` (off) to `FULL`.
+  pub fn intensity(&self, x: usize, y: usize) -> u8 {
+    self.intensity[y][x]
+  }
+
+  /// Returns the output lines that changed in the last `push`.
+  pub fn changed_rows(&self) -> DirtyRows {
+    self.changed
+  }
+}
+
+impl Default for FrameProcessor {
+  fn default() -> Self {
+    Self::new(PostProcess::default())
+  }
+}
@@ -0,0 +1,214 @@
+//! Behaviour differences between CHIP-8 platforms ("quirks").
+//!
+//! The CHIP-8 interpreters of the COSMAC VIP, the HP48 (CHIP-48, SUPER-CHIP) and
+//! modern XO-CHIP disagree on a handful of instructions. Programs written for one
+//! of them often misbehave on the others, so the emulator lets every quirk be
+//! switched on its own and offers the usual `Platform` presets. The names follow
+//! the community CHIP-8 database.
+
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+/// How `FX55` and `FX65` move the index register.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MemoryQuirk {
+  /// `I` is left unchanged (SUPER-CHIP 1.1).
+  Unchanged,
+  /// `I` is incremented by X (CHIP-48, SUPER-CHIP 1.0).
+  IncrementByX,
+  /// `I` is incremented by X + 1, it ends right after the last register (COSMAC VIP).
+  IncrementByXPlusOne,
+}
+
+/// The set of quirks the emulator runs with.
+///
+/// # Fields
+///
+/// * `shift` - `8XY6`/`8XYE` shift VX in place instead of storing the shifted VY in VX.
+/// * `memory` - How `FX55`/`FX65` move the index register.
+/// * `wrap` - Sprites wrap around the screen edges instead of being clipped.
+/// * `jump` - `BXNN` jumps to XNN + VX instead of NNN + V0.
+/// * `logic` - `8XY1`, `8XY2` and `8XY3` reset VF to 0.
+/// * `key_release` - `FX0A` answers when a key is released, like the COSMAC VIP, instead
+///   of when a key goes down. Either way only keys pressed after `FX0A` started answer
+///   it, a key held from before does not even once released. Not part of the CHIP-8 database, it defaults to off.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Quirks {
+  pub shift: bool,
+  pub memory: MemoryQuirk,
+  pub wrap: bool,
+  pub jump: bool,
+  pub logic: bool,
+  #[serde(default)]
+  pub key_release: bool,
+}
+
+impl Quirks {
+  /// The behaviour R8 always had: in-place shifts, `I` unchanged by loads and
+  /// stores, wrapping sprites and the original `BNNN`. `FX0A` answers a new press.
+  pub const R8: Self = Self {
+    shift: true,
+    memory: MemoryQuirk::Unchanged,
+    wrap: true,
+    jump: false,
+    logic: false,
+    key_release: false,
+  };
+
+  /// Returns the name of the preset these quirks match: `R8` for the defaults,
+  /// otherwise the first platform with the same quirks, `None` for a custom set.
+  pub fn preset_name(&self) -> Option<&'static str> {
+    if *self == Self::R8 {
+      return Some("R8");
+    }
+    Platform::ALL
+      .iter()
+      .find(|platform| platform.quirks() == *self)
+      .map(Platform::name)
+  }
+
+  /// Returns the next distinct preset after these quirks: the defaults, then the
+  /// platforms oldest first. Custom quirks go back to the defaults.
+  pub fn next_preset(&self) -> Quirks {
+    let mut presets =
+      std::iter::once(Self::R8).chain(Platform::ALL.map(|platform| platform.quirks()));
+    presets.position(|quirks| quirks == *self);
+    presets.find(|quirks| quirks != self).unwrap_or(Self::R8)
+  }
+}
+
+impl Default for Quirks {
+  fn default() -> Self {
+    Self::R8
+  }
+}
+
+/// A CHIP-8 platform, named as in the community CHIP-8 database.
+///
+/// Only the quirks of a platform are emulated, the extra instructions of
+/// SUPER-CHIP and XO-CHIP are not.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Platform {
+  /// The original interpreter of the COSMAC VIP.
+  OriginalChip8,
+  /// CHIP-8 on the COSMAC VIP with extra machine code routines.
+  HybridVip,
+  /// CHIP-8 as most modern interpreters run it.
+  ModernChip8,
+  /// CHIP-48 on the HP48 calculators.
+  Chip48,
+  /// SUPER-CHIP 1.0.
+  SuperChip1,
+  /// SUPER-CHIP 1.1, as run by modern interpreters.
+  SuperChip,
+  /// XO-CHIP.
+  XoChip,
+}
+
+impl Platform {
+  /// Every platform, oldest first.
+  pub const ALL: [Platform; 7] = [
+    Platform::OriginalChip8,
+    Platform::HybridVip,
+    Platform::ModernChip8,
+    Platform::Chip48,
+    Platform::SuperChip1,
+    Platform::SuperChip,
+    Platform::XoChip,
+  ];
+
+  /// Returns the identifier of the platform in the CHIP-8 database.
+  pub fn id(&self) -> &'static str {
+    match self {
+      Platform::OriginalChip8 => "originalChip8",
+      Platform::HybridVip => "hybridVIP",
+      Platform::ModernChip8 => "modernChip8",
+      Platform::Chip48 => "chip48",
+      Platform::SuperChip1 => "superchip1",
+      Platform::SuperChip => "superchip",
+      Platform::XoChip => "xochip",
+    }
+  }
+
+  /// Returns the human readable name of the platform.
+  pub fn name(&self) -> &'static str {
+    match self {
+      Platform::OriginalChip8 => "CHIP-8 (COSMAC VIP)",
+      Platform::HybridVip => "CHIP-8 hybrid (COSMAC VIP)",
+      Platform::ModernChip8 => "CHIP-8 (modern)",
+      Platform::Chip48 => "CHIP-48",
+      Platform::SuperChip1 => "SUPER-CHIP 1.0",
+      Platform::SuperChip => "SUPER-CHIP 1.1",
+      Platform::XoChip => "XO-CHIP",
+    }
+  }
+
+  /// Returns the quirks of the platform.
+  pub fn quirks(&self) -> Quirks {
+    match self {
+      Platform::OriginalChip8 | Platform::HybridVip => Quirks {
+        shift: false,
+        memory: MemoryQuirk::IncrementByXPlusOne,
+        wrap: false,
+        jump: false,
+        logic: true,
+        key_release: true,
+      },
+      Platform::ModernChip8 => Quirks {
+        shift: false,
+        memory: MemoryQuirk::IncrementByXPlusOne,
+        wrap: false,
+        jump: false,
+        logic: false,
+        key_release: true,
+      },
+      Platform::Chip48 | Platform::SuperChip1 => Quirks {
+        shift: true,
+        memory: MemoryQuirk::IncrementByX,
+        wrap: false,
+        jump: true,
+        logic: false,
+        key_release: false,
+      },
+      Platform::SuperChip => Quirks {
+        shift: true,
+        memory: MemoryQuirk::Unchanged,
+        wrap: false,
+        jump: true,
+        logic: false,
+        key_release: false,
+      },
+      Platform::XoChip => Quirks {
+        shift: false,
+        memory: MemoryQuirk::IncrementByXPlusOne,
+        wrap: true,
+        jump: false,
+        logic: false,
+        key_release: true,
+      },
+    }
+  }
+}
+
+impl fmt::Display for Platform {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(self.id())
+  }
+}
+
+impl FromStr for Platform {
+  type Err = String;
+
+  /// Parses the database identifier (e.g. `superchip`), case-insensitively.
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    Platform::ALL
+      .into_iter()
+      .find(|platform| platform.id().eq_ignore_ascii_case(s))
+      .ok_or_else(|| {
+        let ids: Vec<_> = Platform::ALL.iter().map(Platform::id).collect();
+        format!("unknown platform `{s}`, expected one of {}", ids.join(", "))
+      })
+  }
+}
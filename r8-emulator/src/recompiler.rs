@@ -0,0 +1,436 @@
+//! Basic-block recompiler for the R8 emulator crate.
+//!
+//! The interpreter (`Emulator::tick`) fetches and decodes one opcode per tick. For
+//! headless workloads (fuzzing, training agents, regression runs) that decoding work
+//! dominates, so this module translates straight-line runs of opcodes ("basic blocks")
+//! into a compact micro-op IR once and caches them by start address.
+//!
+//! A block ends at the first instruction that may change the control flow (jumps,
+//! calls, returns, skips, key waits) or that writes memory (`FX33`, `FX55`). Writes
+//! invalidate every cached block overlapping the written range, so self-modifying
+//! programs are recompiled the next time the modified code is reached.
+//!
+//! The recompiler must behave exactly like `Emulator::execute_opcode`; this is checked
+//! by the differential tests in `tests.rs`.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use log::error;
+
+use r8_core::{constants::MEMORY_SIZE, Address, EmulatorError, Memory, Opcode, RegisterIndex};
+
+use crate::emulator::{Emulator, State};
+
+/// Maximum amount of micro-ops compiled into a single block.
+const MAX_BLOCK_LEN: usize = 64;
+
+/// Selects how the emulator executes instructions in [`Emulator::run`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Engine {
+  /// Fetch, decode and execute one opcode per tick.
+  #[default]
+  Interpreter,
+  /// Translate basic blocks into micro-ops and cache them between runs.
+  Recompiler,
+}
+
+/// A pre-decoded instruction.
+///
+/// Register operands are already validated `RegisterIndex`es and sprite font
+/// addresses or call targets are resolved, so executing a micro-op never touches
+/// the opcode decoder.
+#[derive(Debug, Clone, Copy)]
+enum MicroOp {
+  Cls,
+  Ret,
+  Jump(Address),
+  Call(Address),
+  SkipEqByte(RegisterIndex, u8),
+  SkipNeByte(RegisterIndex, u8),
+  SkipEqReg(RegisterIndex, RegisterIndex),
+  SkipNeReg(RegisterIndex, RegisterIndex),
+  LoadByte(RegisterIndex, u8),
+  AddByte(RegisterIndex, u8),
+  LoadReg(RegisterIndex, RegisterIndex),
+  Or(RegisterIndex, RegisterIndex),
+  And(RegisterIndex, RegisterIndex),
+  Xor(RegisterIndex, RegisterIndex),
+  AddReg(RegisterIndex, RegisterIndex),
+  Sub(RegisterIndex, RegisterIndex),
+  Shr(RegisterIndex, RegisterIndex),
+  Subn(RegisterIndex, RegisterIndex),
+  Shl(RegisterIndex, RegisterIndex),
+  LoadI(Address),
+  JumpV0(u16),
+  Rand(RegisterIndex, u8),
+  Draw(RegisterIndex, RegisterIndex, u8),
+  SkipKey(RegisterIndex),
+  SkipNotKey(RegisterIndex),
+  LoadDelay(RegisterIndex),
+  WaitKey(RegisterIndex),
+  SetDelay(RegisterIndex),
+  SetSound(RegisterIndex),
+  AddI(RegisterIndex),
+  LoadFont(RegisterIndex),
+  StoreBcd(RegisterIndex),
+  StoreRegs(RegisterIndex),
+  LoadRegs(RegisterIndex),
+  Invalid(u16),
+}
+
+impl MicroOp {
+  /// Lowers a decoded opcode into its micro-op.
+  fn lower(opcode: Opcode) -> Self {
+    match opcode {
+      Opcode::Cls => Self::Cls,
+      Opcode::Ret => Self::Ret,
+      Opcode::Jp { address } => Self::Jump(address),
+      Opcode::Sys { address } | Opcode::Call { address } => Self::Call(address),
+      Opcode::SeByte { x, byte } => Self::SkipEqByte(x, byte),
+      Opcode::SneByte { x, byte } => Self::SkipNeByte(x, byte),
+      Opcode::SeRegister { x, y } => Self::SkipEqReg(x, y),
+      Opcode::LdByte { x, byte } => Self::LoadByte(x, byte),
+      Opcode::AddByte { x, byte } => Self::AddByte(x, byte),
+      Opcode::LdRegister { x, y } => Self::LoadReg(x, y),
+      Opcode::Or { x, y } => Self::Or(x, y),
+      Opcode::And { x, y } => Self::And(x, y),
+      Opcode::Xor { x, y } => Self::Xor(x, y),
+      Opcode::AddRegister { x, y } => Self::AddReg(x, y),
+      Opcode::Sub { x, y } => Self::Sub(x, y),
+      Opcode::Shr { x, y } => Self::Shr(x, y),
+      Opcode::Subn { x, y } => Self::Subn(x, y),
+      Opcode::Shl { x, y } => Self::Shl(x, y),
+      Opcode::SneRegister { x, y } => Self::SkipNeReg(x, y),
+      Opcode::LdI { address } => Self::LoadI(address),
+      Opcode::JpV0 { address } => Self::JumpV0(address.inner()),
+      Opcode::Rnd { x, byte } => Self::Rand(x, byte),
+      Opcode::Drw { x, y, n } => Self::Draw(x, y, n),
+      Opcode::Skp { x } => Self::SkipKey(x),
+      Opcode::Sknp { x } => Self::SkipNotKey(x),
+      Opcode::LdVxDT { x } => Self::LoadDelay(x),
+      Opcode::LdVxK { x } => Self::WaitKey(x),
+      Opcode::LdDTVx { x } => Self::SetDelay(x),
+      Opcode::LdSTVx { x } => Self::SetSound(x),
+      Opcode::AddIVx { x } => Self::AddI(x),
+      Opcode::LdFVx { x } => Self::LoadFont(x),
+      Opcode::LdBVx { x } => Self::StoreBcd(x),
+      Opcode::LdIVx { x } => Self::StoreRegs(x),
+      Opcode::LdVxI { x } => Self::LoadRegs(x),
+      Opcode::Invalid(data) => Self::Invalid(data),
+    }
+  }
+
+  /// Returns true if the micro-op must be the last one of its block.
+  ///
+  /// These are the instructions that may change the program counter in a
+  /// non-sequential way, stop the CPU, or write memory (and therefore may
+  /// invalidate the block being executed).
+  fn ends_block(&self) -> bool {
+    matches!(
+      self,
+      Self::Ret
+        | Self::Jump(_)
+        | Self::Call(_)
+        | Self::SkipEqByte(..)
+        | Self::SkipNeByte(..)
+        | Self::SkipEqReg(..)
+        | Self::SkipNeReg(..)
+        | Self::JumpV0(_)
+        | Self::SkipKey(_)
+        | Self::SkipNotKey(_)
+        | Self::WaitKey(_)
+        | Self::StoreBcd(_)
+        | Self::StoreRegs(_)
+    )
+  }
+}
+
+/// A compiled basic block.
+///
+/// # Fields
+///
+/// * `start` - Address of the first instruction of the block.
+/// * `end` - Address right after the last instruction of the block.
+/// * `ops` - The micro-ops, one per instruction, in program order.
+#[derive(Debug)]
+struct Block {
+  start: u16,
+  end: u16,
+  ops: Vec<MicroOp>,
+}
+
+impl Block {
+  /// Compiles the block starting at `start`.
+  ///
+  /// Returns `None` if not even the first instruction can be fetched, in that case
+  /// the caller falls back to the interpreter so the error is reported the same way.
+  fn compile(memory: &Memory, start: u16) -> Option<Self> {
+    let mut ops = Vec::new();
+    let mut address = start;
+    while ops.len() < MAX_BLOCK_LEN {
+      // The block ends with the memory, `Address::new` would wrap to 0x000.
+      let mut raw = [0, 0];
+      let Ok(at) = Address::try_new(address) else {
+        break;
+      };
+      if memory.write_range(at, &mut raw).is_err() {
+        break;
+      }
+      let Ok(opcode) = Opcode::try_from(raw) else {
+        break;
+      };
+      let op = MicroOp::lower(opcode);
+      ops.push(op);
+      address += 2;
+      if op.ends_block() {
+        break;
+      }
+    }
+
+    if ops.is_empty() {
+      None
+    } else {
+      Some(Self {
+        start,
+        end: address,
+        ops,
+      })
+    }
+  }
+}
+
+/// Cache of compiled blocks indexed by their start address.
+///
+/// # Fields
+///
+/// * `blocks` - The compiled blocks.
+/// * `code_map` - One flag per memory byte, set if any cached block covers it.
+#[derive(Debug)]
+pub(crate) struct BlockCache {
+  blocks: HashMap<u16, Arc<Block>>,
+  code_map: Box<[bool; MEMORY_SIZE]>,
+}
+
+impl BlockCache {
+  /// Creates an empty cache.
+  pub(crate) fn new() -> Self {
+    Self {
+      blocks: HashMap::new(),
+      code_map: Box::new([false; MEMORY_SIZE]),
+    }
+  }
+
+  /// Drops every compiled block.
+  pub(crate) fn clear(&mut self) {
+    self.blocks.clear();
+    self.code_map.fill(false);
+  }
+
+  /// Returns the block starting at `start`, compiling it if needed.
+  fn get_or_compile(&mut self, memory: &Memory, start: u16) -> Option<Arc<Block>> {
+    if let Some(block) = self.blocks.get(&start) {
+      return Some(block.clone());
+    }
+    let block = Arc::new(Block::compile(memory, start)?);
+    self.code_map[block.start as usize..block.end as usize].fill(true);
+    self.blocks.insert(start, block.clone());
+    Some(block)
+  }
+
+  /// Drops every block overlapping the memory range `[start, start + len)`.
+  pub(crate) fn invalidate(&mut self, start: u16, len: usize) {
+    let start = start as usize;
+    let end = (start + len).min(MEMORY_SIZE);
+    if !self.code_map[start..end].iter().any(|&covered| covered) {
+      return;
+    }
+
+    self
+      .blocks
+      .retain(|_, block| block.end as usize <= start || block.start as usize >= end);
+
+    // Rebuild the code map from the surviving blocks.
+    self.code_map.fill(false);
+    for block in self.blocks.values() {
+      self.code_map[block.start as usize..block.end as usize].fill(true);
+    }
+  }
+
+  /// Returns the amount of cached blocks.
+  #[cfg(test)]
+  pub(crate) fn len(&self) -> usize {
+    self.blocks.len()
+  }
+}
+
+impl Emulator {
+  /// Selects the execution engine used by [`Emulator::run`].
+  ///
+  /// Switching engines drops every compiled block.
+  pub fn set_engine(&mut self, engine: Engine) {
+    self.blocks = match engine {
+      Engine::Interpreter => None,
+      Engine::Recompiler => Some(BlockCache::new()),
+    };
+  }
+
+  /// Returns the execution engine used by [`Emulator::run`].
+  pub fn engine(&self) -> Engine {
+    if self.blocks.is_some() {
+      Engine::Recompiler
+    } else {
+      Engine::Interpreter
+    }
+  }
+
+  /// Executes `ticks` ticks with the selected engine.
+  ///
+  /// # Arguments
+  ///
+  /// * `ticks` - The number of ticks (instructions) to execute.
+  ///
+  /// # Returns
+  ///
+  /// * `Result<(), RuntimeError>` - The result of the operation.
+  ///
+  /// # Notes
+  ///
+  /// * The resulting state is the same as calling `tick` `ticks` times.
+  /// * The recompiler does not log each executed opcode.
+  pub fn run(&mut self, ticks: usize) -> Result<(), EmulatorError> {
+    let mut remaining = ticks;
+    while remaining > 0 {
+      let block = match (&self.state, self.blocks.as_mut()) {
+        (State::Running, Some(cache)) => cache.get_or_compile(&self.memory, self.pc.inner()),
+        _ => None,
+      };
+      match block {
+        Some(block) => remaining -= self.execute_block(&block, remaining)?,
+        None => {
+          self.tick()?;
+          remaining -= 1;
+        }
+      }
+    }
+    Ok(())
+  }
+
+  /// Invalidates compiled code after a write to `[start, start + len)`.
+  pub(crate) fn invalidate_code(&mut self, start: Address, len: usize) {
+    if let Some(cache) = self.blocks.as_mut() {
+      cache.invalidate(start.inner(), len);
+    }
+  }
+
+  /// Executes at most `limit` micro-ops of `block`, returns how many were executed.
+  fn execute_block(&mut self, block: &Block, limit: usize) -> Result<usize, EmulatorError> {
+    // Macro to jump if a condition is met
+    macro_rules! jump_if {
+      ($cond:expr) => {
+        if $cond {
+          self.pc.add_assign(2)?;
+        }
+      };
+    }
+    // Macro to facilitate access to the V registers
+    macro_rules! V {
+      (0) => {
+        self.registers[RegisterIndex::ZERO]
+      };
+      (FLAGS) => {
+        self.registers[RegisterIndex::FLAG]
+      };
+      ($reg: expr) => {
+        self.registers[$reg]
+      };
+      (0 => $end: expr) => {
+        self.registers[RegisterIndex::ZERO..=$end]
+      };
+    }
+
+    let count = block.ops.len().min(limit);
+    let mut next = block.start;
+    for op in &block.ops[..count] {
+      // Same bookkeeping as `tick`: the sequential program counter is already
+      // known at compile time.
+      next += 2;
+      // Running off the end of memory fails like `pc.add_assign(2)` in `tick`.
+      self.pc = Address::try_new(next)?;
+
+      match *op {
+        MicroOp::Cls => self.display.clear(),
+        MicroOp::Ret => self.pc = self.stack.pop()?,
+        MicroOp::Jump(address) => self.pc = address,
+        MicroOp::Call(address) => {
+          self.stack.push(self.pc)?;
+          self.pc = address;
+        }
+        MicroOp::SkipEqByte(x, byte) => jump_if!(V![x] == byte),
+        MicroOp::SkipNeByte(x, byte) => jump_if!(V![x] != byte),
+        MicroOp::SkipEqReg(x, y) => jump_if!(V![x] == V![y]),
+        MicroOp::SkipNeReg(x, y) => jump_if!(V![x] != V![y]),
+        MicroOp::LoadByte(x, byte) => V![x] = byte,
+        MicroOp::AddByte(x, byte) => V![x] = V![x].wrapping_add(byte),
+        MicroOp::LoadReg(x, y) => V![x] = V![y],
+        MicroOp::Or(x, y) => {
+          V![x] |= V![y];
+          self.reset_flag_after_logic();
+        }
+        MicroOp::And(x, y) => {
+          V![x] &= V![y];
+          self.reset_flag_after_logic();
+        }
+        MicroOp::Xor(x, y) => {
+          V![x] ^= V![y];
+          self.reset_flag_after_logic();
+        }
+        MicroOp::AddReg(x, y) => {
+          let (result, carry) = V![x].overflowing_add(V![y]);
+          V![x] = result;
+          V![FLAGS] = carry as u8;
+        }
+        MicroOp::Sub(x, y) => {
+          V![FLAGS] = (V![x] > V![y]) as u8;
+          V![x] = V![x].wrapping_sub(V![y]);
+        }
+        MicroOp::Shr(x, y) => self.shift(x, y, false),
+        MicroOp::Subn(x, y) => {
+          V![FLAGS] = (V![y] > V![x]) as u8;
+          V![x] = V![y].wrapping_sub(V![x]);
+        }
+        MicroOp::Shl(x, y) => self.shift(x, y, true),
+        MicroOp::LoadI(address) => self.i = address,
+        MicroOp::JumpV0(address) => self.jump_with_offset(address)?,
+        MicroOp::Rand(x, byte) => V![x] = self.rand.next() & byte,
+        MicroOp::Draw(x, y, n) => self.draw(x, y, n)?,
+        MicroOp::SkipKey(x) => jump_if!(self.keyboard.is_set(V![x] & 0xF)),
+        MicroOp::SkipNotKey(x) => jump_if!(!self.keyboard.is_set(V![x] & 0xF)),
+        MicroOp::LoadDelay(x) => V![x] = self.delay_timer.get(),
+        MicroOp::WaitKey(x) => self.wait_key(x),
+        MicroOp::SetDelay(x) => self.delay_timer.set(V![x]),
+        MicroOp::SetSound(x) => self.sound_timer.set(V![x]),
+        MicroOp::AddI(x) => self.i.add_assign(V![x] as u16)?,
+        MicroOp::LoadFont(x) => self.i = Address::new((V![x] & 0xF) as u16 * 5),
+        MicroOp::StoreBcd(x) => {
+          let value = V![x];
+          self
+            .memory
+            .read_range(self.i, &[value / 100, (value % 100) / 10, value % 10])?;
+          self.invalidate_code(self.i, 3);
+        }
+        MicroOp::StoreRegs(x) => self.store_registers(x)?,
+        MicroOp::LoadRegs(x) => self.load_registers(x)?,
+        MicroOp::Invalid(data) => {
+          error!(
+            "Unrecognized OpCode: | 0x{PC:X} | {:X?}",
+            data,
+            PC = self.pc.inner()
+          )
+        }
+      }
+    }
+
+    Ok(count)
+  }
+}
@@ -0,0 +1,146 @@
+//! Animated GIF recording of the emulator output.
+//!
+//! Every frame is pushed with the time it was shown for, the frame interval of the
+//! front-end. Consecutive identical frames are merged into one GIF frame with a
+//! longer delay, so static screens cost nothing. GIF delays are counted in
+//! hundredths of a second and most viewers slow down frames shorter than 2/100 s,
+//! so a frame that would be shown for less than that is dropped and its time is
+//! given to the next one. The total duration always matches the time of the
+//! pushed frames.
+
+use std::{borrow::Cow, io, time::Duration};
+
+use crate::export::{ExportOptions, Frame};
+
+/// The shortest delay written, in hundredths of a second.
+const MIN_DELAY: u64 = 2;
+
+/// Records frames into an animated GIF.
+///
+/// # Fields
+///
+/// * `encoder` - The GIF encoder writing to the output.
+/// * `scale` - The size in image pixels of one display pixel.
+/// * `size` - The size of the image in pixels.
+/// * `pending` - The last distinct frame, written once it is replaced or on `finish`.
+/// * `frames` - The number of frames pushed so far.
+/// * `elapsed` - The time the pushed frames were shown for.
+/// * `written` - The duration already written, in hundredths of a second.
+pub struct GifRecorder<W: io::Write> {
+  encoder: gif::Encoder<W>,
+  scale: u32,
+  size: (u16, u16),
+  pending: Option<Frame>,
+  frames: u64,
+  elapsed: Duration,
+  written: u64,
+}
+
+impl<W: io::Write> GifRecorder<W> {
+  /// Starts a looping GIF on `out`.
+  ///
+  /// # Arguments
+  ///
+  /// * `out` - Where to write the GIF.
+  /// * `options` - The palette and the scale of the frames (the grid is ignored).
+  ///
+  /// # Returns
+  ///
+  /// * `io::Result<Self>` - The recorder, an error if the scale is over `MAX_SCALE`
+  ///   or makes images larger than the 65535 pixels a GIF side can have.
+  pub fn new(out: W, options: &ExportOptions) -> io::Result<Self> {
+    let (width, height) = options.checked_size()?;
+    let (Ok(width), Ok(height)) = (u16::try_from(width), u16::try_from(height)) else {
+      return Err(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("a {}x{} image is too large for a GIF", width, height),
+      ));
+    };
+    // One palette entry per intensity, so pixel values can be written as is.
+    let palette: Vec<u8> = (0..=u8::MAX)
+      .flat_map(|intensity| options.palette.color(intensity))
+      .collect();
+    let mut encoder = gif::Encoder::new(out, width, height, &palette).map_err(io::Error::other)?;
+    encoder
+      .set_repeat(gif::Repeat::Infinite)
+      .map_err(io::Error::other)?;
+    Ok(Self {
+      encoder,
+      scale: options.scale.max(1),
+      size: (width, height),
+      pending: None,
+      frames: 0,
+      elapsed: Duration::ZERO,
+      written: 0,
+    })
+  }
+
+  /// Adds the next frame of the recording.
+  ///
+  /// # Arguments
+  ///
+  /// * `frame` - The frame.
+  /// * `shown` - How long the frame is shown for, the frame interval of the caller.
+  pub fn push(&mut self, frame: Frame, shown: Duration) -> io::Result<()> {
+    let elapsed = self.elapsed();
+    self.frames += 1;
+    self.elapsed += shown;
+    if self.pending.as_ref() == Some(&frame) {
+      return Ok(());
+    }
+    if let Some(previous) = self.pending.replace(frame) {
+      let delay = elapsed - self.written;
+      if delay >= MIN_DELAY {
+        self.write(&previous, delay)?;
+      }
+    }
+    Ok(())
+  }
+
+  /// Returns the number of frames pushed so far.
+  pub fn frames(&self) -> u64 {
+    self.frames
+  }
+
+  /// Writes the last frame and the GIF trailer, returns the output.
+  pub fn finish(mut self) -> io::Result<W> {
+    if let Some(last) = self.pending.take() {
+      let delay = (self.elapsed() - self.written).max(MIN_DELAY);
+      self.write(&last, delay)?;
+    }
+    self.encoder.into_inner()
+  }
+
+  /// Returns the duration of the pushed frames, rounded to hundredths of a second.
+  fn elapsed(&self) -> u64 {
+    ((self.elapsed + Duration::from_millis(5)).as_millis() / 10) as u64
+  }
+
+  /// Writes `frame` shown for `delay` hundredths of a second.
+  fn write(&mut self, frame: &Frame, delay: u64) -> io::Result<()> {
+    let (scale, (width, height)) = (self.scale, self.size);
+    let pixels: Vec<u8> = (0..height as u32)
+      .flat_map(|y| (0..width as u32).map(move |x| (x, y)))
+      .map(|(x, y)| frame.intensity((x / scale) as usize, (y / scale) as usize))
+      .collect();
+    // Very long still frames are split, a GIF delay is only 16 bits.
+    let mut remaining = delay;
+    while remaining > 0 {
+      let chunk = remaining.min(u16::MAX as u64);
+      let gif_frame = gif::Frame {
+        width,
+        height,
+        delay: chunk as u16,
+        buffer: Cow::Borrowed(&pixels),
+        ..Default::default()
+      };
+      self
+        .encoder
+        .write_frame(&gif_frame)
+        .map_err(io::Error::other)?;
+      remaining -= chunk;
+    }
+    self.written += delay;
+    Ok(())
+  }
+}
@@ -0,0 +1,237 @@
+//! ROM metadata database.
+//!
+//! ROMs are identified by the SHA-1 of their image and described in the format of
+//! the community CHIP-8 database (`programs.json`): title, authors, the platforms
+//! the ROM was written for, per-platform quirk overrides, the recommended speed,
+//! colours and key hints. A small database covering the ROMs shipped in `roms/`
+//! is bundled, the full community database can be loaded from disk instead.
+//!
+//! When a database is attached to the emulator, `Emulator::load_rom` looks the ROM
+//! up and runs it with the quirks of its platform.
+
+use std::{
+  collections::HashMap,
+  io,
+  path::Path,
+  sync::{Arc, OnceLock},
+};
+
+use serde::Deserialize;
+
+use crate::{
+  export::{parse_rgb, Palette},
+  quirks::{MemoryQuirk, Platform, Quirks},
+  Key,
+};
+
+/// The bundled database, in the format of the community `programs.json`.
+const BUNDLED: &str = include_str!("../romdb/programs.json");
+
+/// A program of `programs.json`, one or more ROM images share it.
+#[derive(Deserialize)]
+struct Program {
+  title: String,
+  description: Option<String>,
+  release: Option<String>,
+  #[serde(default)]
+  authors: Vec<String>,
+  roms: HashMap<String, RomEntry>,
+}
+
+/// A ROM image of a program.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RomEntry {
+  #[serde(default)]
+  platforms: Vec<String>,
+  #[serde(default)]
+  quirky_platforms: HashMap<String, QuirkOverrides>,
+  tickrate: Option<u32>,
+  colors: Option<Colors>,
+  #[serde(default)]
+  keys: HashMap<String, u8>,
+}
+
+/// Quirks that differ from the defaults of a platform (`vblank` is not emulated).
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct QuirkOverrides {
+  shift: Option<bool>,
+  memory_increment_by_x: Option<bool>,
+  memory_leave_i_unchanged: Option<bool>,
+  wrap: Option<bool>,
+  jump: Option<bool>,
+  logic: Option<bool>,
+}
+
+#[derive(Deserialize)]
+struct Colors {
+  #[serde(default)]
+  pixels: Vec<String>,
+}
+
+/// What the database knows about a ROM.
+///
+/// # Fields
+///
+/// * `sha1` - The SHA-1 of the ROM image, lowercase hex.
+/// * `title` - The title of the program.
+/// * `description` - A short description.
+/// * `release` - The release date or year.
+/// * `authors` - The authors of the program.
+/// * `platform` - The first platform of the ROM that R8 knows about.
+/// * `quirks` - The quirks of that platform with the overrides of the ROM applied.
+/// * `tickrate` - The recommended number of instructions per frame.
+/// * `palette` - The recommended colours (the first two database colours).
+/// * `keys` - Key hints, e.g. `("up", Key::K5)`, sorted by name.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RomInfo {
+  pub sha1: String,
+  pub title: String,
+  pub description: Option<String>,
+  pub release: Option<String>,
+  pub authors: Vec<String>,
+  pub platform: Option<Platform>,
+  pub quirks: Option<Quirks>,
+  pub tickrate: Option<u32>,
+  pub palette: Option<Palette>,
+  pub keys: Vec<(String, Key)>,
+}
+
+/// A ROM metadata database indexed by SHA-1.
+#[derive(Debug, Default)]
+pub struct RomDb {
+  pub(crate) roms: HashMap<String, RomInfo>,
+}
+
+impl RomDb {
+  /// Parses a database in the format of the community `programs.json`.
+  pub fn from_json(json: &str) -> Result<Self, String> {
+    let programs: Vec<Program> = serde_json::from_str(json).map_err(|err| err.to_string())?;
+    let mut roms = HashMap::new();
+    for program in programs {
+      for (sha1, entry) in &program.roms {
+        let sha1 = sha1.to_ascii_lowercase();
+        let info = rom_info(&program, &sha1, entry).map_err(|err| format!("{sha1}: {err}"))?;
+        roms.insert(sha1, info);
+      }
+    }
+    Ok(Self { roms })
+  }
+
+  /// Loads a `programs.json` file.
+  pub fn load(path: &Path) -> io::Result<Self> {
+    let json = std::fs::read_to_string(path)?;
+    Self::from_json(&json).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+  }
+
+  /// Returns the database bundled with R8.
+  pub fn bundled() -> Arc<Self> {
+    static BUNDLED_DB: OnceLock<Arc<RomDb>> = OnceLock::new();
+    BUNDLED_DB
+      .get_or_init(|| Arc::new(Self::from_json(BUNDLED).expect("the bundled database is valid")))
+      .clone()
+  }
+
+  /// Returns the number of ROMs in the database.
+  pub fn len(&self) -> usize {
+    self.roms.len()
+  }
+
+  /// Returns `true` if the database has no ROM.
+  pub fn is_empty(&self) -> bool {
+    self.roms.is_empty()
+  }
+
+  /// Looks up a ROM image.
+  pub fn lookup(&self, rom: &[u8]) -> Option<&RomInfo> {
+    self.roms.get(&sha1(rom))
+  }
+}
+
+/// Returns the SHA-1 of `data` as lowercase hex.
+pub fn sha1(data: &[u8]) -> String {
+  sha1_smol::Sha1::from(data).digest().to_string()
+}
+
+/// Builds the `RomInfo` of a database entry.
+fn rom_info(program: &Program, sha1: &str, entry: &RomEntry) -> Result<RomInfo, String> {
+  // Platforms R8 does not know (e.g. MEGA-CHIP) are skipped.
+  let platform = entry
+    .platforms
+    .iter()
+    .find_map(|id| id.parse::<Platform>().ok());
+  let quirks = platform.map(|platform| {
+    let overrides = entry
+      .quirky_platforms
+      .iter()
+      .find(|(id, _)| id.parse() == Ok(platform))
+      .map(|(_, overrides)| overrides);
+    let mut quirks = platform.quirks();
+    if let Some(overrides) = overrides {
+      overrides.apply(&mut quirks);
+    }
+    quirks
+  });
+
+  let palette = match entry.colors.as_ref().map(|colors| colors.pixels.as_slice()) {
+    Some([off, on, ..]) => Some(Palette {
+      off: parse_rgb(off)?,
+      on: parse_rgb(on)?,
+    }),
+    _ => None,
+  };
+
+  let mut keys = entry
+    .keys
+    .iter()
+    .map(|(hint, &key)| {
+      let key = Key::all()
+        .nth(key as usize)
+        .ok_or_else(|| format!("invalid key {key} for `{hint}`"))?;
+      Ok((hint.clone(), *key))
+    })
+    .collect::<Result<Vec<_>, String>>()?;
+  keys.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+  Ok(RomInfo {
+    sha1: sha1.to_string(),
+    title: program.title.clone(),
+    description: program.description.clone(),
+    release: program.release.clone(),
+    authors: program.authors.clone(),
+    platform,
+    quirks,
+    tickrate: entry.tickrate,
+    palette,
+    keys,
+  })
+}
+
+impl QuirkOverrides {
+  /// Applies the overrides on top of the quirks of the platform.
+  fn apply(&self, quirks: &mut Quirks) {
+    let set = |target: &mut bool, value: Option<bool>| {
+      if let Some(value) = value {
+        *target = value;
+      }
+    };
+    set(&mut quirks.shift, self.shift);
+    set(&mut quirks.wrap, self.wrap);
+    set(&mut quirks.jump, self.jump);
+    set(&mut quirks.logic, self.logic);
+    if self.memory_leave_i_unchanged == Some(true) {
+      quirks.memory = MemoryQuirk::Unchanged;
+    } else if let Some(by_x) = self.memory_increment_by_x {
+      quirks.memory = if by_x {
+        MemoryQuirk::IncrementByX
+      } else {
+        MemoryQuirk::IncrementByXPlusOne
+      };
+    } else if self.memory_leave_i_unchanged == Some(false)
+      && quirks.memory == MemoryQuirk::Unchanged
+    {
+      quirks.memory = MemoryQuirk::IncrementByXPlusOne;
+    }
+  }
+}
@@ -0,0 +1,155 @@
+//! Scripted keypad input for headless runs.
+//!
+//! A script is plain text, one entry per line, `#` starts a comment:
+//!
+//! ```text
+//! # frame  action   keys
+//! 0        press    5
+//! +10      release  5      # relative to the previous entry
+//! 30       tap      A B    # press on frame 30, release on frame 31
+//! ```
+//!
+//! Frames are counted by the caller (e.g. one per batch of ticks), keys are
+//! hexadecimal keypad digits.
+
+use std::str::FromStr;
+
+use crate::{Emulator, Key};
+
+/// Whether a scripted key goes down or up.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum KeyAction {
+  Press,
+  Release,
+}
+
+/// A single key change at a given frame.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ScriptEvent {
+  pub frame: u64,
+  pub action: KeyAction,
+  pub key: Key,
+}
+
+/// An error found while parsing an input script.
+///
+/// # Fields
+///
+/// * `line` - The line of the script (1-based).
+/// * `message` - What is wrong with the line.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ScriptError {
+  pub line: usize,
+  pub message: String,
+}
+
+impl std::fmt::Display for ScriptError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(
+      f,
+      "Invalid input script at line {}: {}",
+      self.line, self.message
+    )
+  }
+}
+
+impl std::error::Error for ScriptError {}
+
+/// A parsed input script, its events are sorted by frame.
+#[derive(Clone, Debug, Default)]
+pub struct InputScript {
+  events: Vec<ScriptEvent>,
+}
+
+impl InputScript {
+  /// Returns every event, sorted by frame.
+  pub fn events(&self) -> &[ScriptEvent] {
+    &self.events
+  }
+
+  /// Returns the frame of the last event, or `None` for an empty script.
+  pub fn last_frame(&self) -> Option<u64> {
+    self.events.last().map(|event| event.frame)
+  }
+
+  /// Presses and releases the keys scheduled for `frame`.
+  ///
+  /// Call it once per frame, before running the ticks of that frame.
+  ///
+  /// # Arguments
+  ///
+  /// * `frame` - The current frame number.
+  /// * `emulator` - The emulator receiving the key changes.
+  pub fn apply(&self, frame: u64, emulator: &mut Emulator) {
+    let start = self.events.partition_point(|event| event.frame < frame);
+    for event in self.events[start..]
+      .iter()
+      .take_while(|event| event.frame == frame)
+    {
+      match event.action {
+        KeyAction::Press => emulator.press_key(event.key),
+        KeyAction::Release => emulator.release_key(event.key),
+      }
+    }
+  }
+}
+
+impl FromStr for InputScript {
+  type Err = ScriptError;
+
+  fn from_str(source: &str) -> Result<Self, Self::Err> {
+    let mut events = vec![];
+    let mut previous = 0u64;
+    for (index, line) in source.lines().enumerate() {
+      let error = |message: String| ScriptError {
+        line: index + 1,
+        message,
+      };
+      let line = line.split('#').next().unwrap_or_default();
+      let mut fields = line.split_whitespace();
+      let Some(frame) = fields.next() else {
+        continue;
+      };
+
+      let frame = match frame.strip_prefix('+') {
+        Some(delta) => delta
+          .parse()
+          .ok()
+          .and_then(|delta: u64| previous.checked_add(delta)),
+        None => frame.parse().ok(),
+      }
+      .ok_or_else(|| error(format!("invalid frame `{frame}`")))?;
+      previous = frame;
+
+      let action = fields
+        .next()
+        .ok_or_else(|| error("missing action".to_string()))?;
+      let actions: &[(u64, KeyAction)] = match action.to_ascii_lowercase().as_str() {
+        "press" => &[(0, KeyAction::Press)],
+        "release" => &[(0, KeyAction::Release)],
+        "tap" => &[(0, KeyAction::Press), (1, KeyAction::Release)],
+        _ => return Err(error(format!("unknown action `{action}`"))),
+      };
+
+      let mut keys = fields.peekable();
+      if keys.peek().is_none() {
+        return Err(error("missing key".to_string()));
+      }
+      for key in keys {
+        let key = u8::from_str_radix(key, 16)
+          .ok()
+          .and_then(|digit| Key::all().nth(digit as usize).copied())
+          .ok_or_else(|| error(format!("invalid key `{key}`, expected 0-F")))?;
+        for &(offset, action) in actions {
+          let frame = frame
+            .checked_add(offset)
+            .ok_or_else(|| error(format!("no frame after {frame} to release the tap")))?;
+          events.push(ScriptEvent { frame, action, key });
+        }
+      }
+    }
+    // Stable, so events of the same frame keep the order of the script.
+    events.sort_by_key(|event| event.frame);
+    Ok(Self { events })
+  }
+}
@@ -1,4 +1,14 @@
-use super::emulator::Emulator;
+use super::emulator::{Emulator, State};
+use crate::{
+  analysis::{detect_platform, Signature, MIN_CONFIDENCE},
+  export::{self, ExportOptions, Frame, ImageFormat, Palette},
+  postprocess::FULL,
+  quirks::MemoryQuirk,
+  record::GifRecorder,
+  script::{KeyAction, ScriptEvent},
+  DirtyRows, Display, Engine, FrameProcessor, InputScript, Key, Platform, PostProcess, Quirks,
+  RomDb,
+};
 use r8_core::{Address, RegisterIndex};
 
 fn initialize_empty_emulator() -> Emulator {
@@ -33,6 +43,38 @@ fn memory_load_and_access() {
   assert_eq!(buffer, data);
 }
 
+#[test]
+/// Ranges may end at the last byte of memory, 0xFFF, but not go past it
+fn memory_ranges_end_at_the_last_byte() {
+  let mut emulator = initialize_empty_emulator();
+
+  let mut ram = [0; 0x1000];
+  emulator
+    .memory
+    .write_range(Address::new(0), &mut ram)
+    .unwrap();
+
+  let mut last = [0; 2];
+  emulator
+    .memory
+    .read_range(Address::new(0xFFE), &[0xAB, 0xCD])
+    .unwrap();
+  emulator
+    .memory
+    .write_range(Address::new(0xFFE), &mut last)
+    .unwrap();
+  assert_eq!(last, [0xAB, 0xCD]);
+
+  assert!(emulator
+    .memory
+    .read_range(Address::new(0xFFF), &[0, 0])
+    .is_err());
+  assert!(emulator
+    .memory
+    .write_range(Address::new(0xFFF), &mut last)
+    .is_err());
+}
+
 #[test]
 /// Test 1NNN, 2NNN and 00EE chip-8 instructions
 fn test_jump_instructions() {
@@ -66,6 +108,46 @@ fn test_jump_instructions() {
   assert_eq!(address.inner() + 2, emulator.pc.inner());
 }
 
+#[test]
+/// The timers count down once per frame, however many instructions the frame runs
+fn test_timers_count_down_per_frame() {
+  let program = [
+    0x60, 0x0A, // LD V0, 10
+    0xF0, 0x15, // LD DT, V0
+    0xF0, 0x18, // LD ST, V0
+    0x12, 0x06, // JP 0x206
+  ];
+
+  let (mut interpreter, mut recompiler) = differential_pair(&program);
+  for _ in 0..15 {
+    interpreter.tick().unwrap();
+  }
+  recompiler.run(15).unwrap();
+  for emulator in [&mut interpreter, &mut recompiler] {
+    assert_eq!(emulator.delay_timer.get(), 10);
+    emulator.tick_timers();
+    assert_eq!(emulator.delay_timer.get(), 9);
+    assert_eq!(emulator.sound_timer.get(), 9);
+  }
+}
+
+#[test]
+/// Test BNNN on both engines, it jumps to NNN + V0 instead of moving the PC by it
+fn test_jump_with_offset() {
+  let program = [
+    0x60, 0x04, // LD V0, 4
+    0xB2, 0x10, // JP V0, 0x210 -> 0x214
+  ];
+
+  let (mut interpreter, mut recompiler) = differential_pair(&program);
+  interpreter.tick().unwrap();
+  interpreter.tick().unwrap();
+  recompiler.run(2).unwrap();
+
+  assert_eq!(interpreter.pc.inner(), 0x214);
+  assert_same_state(&interpreter, &recompiler);
+}
+
 #[test]
 /// Test 3XKK, 4XKK and 5XY0 chip-8 instructions (skip conditions)
 fn test_skip() {
@@ -122,3 +204,760 @@ fn test_skip() {
   assert!(matches!(emulator.tick(), Ok(())));
   assert_eq!(emulator.pc.inner(), Address::ENTRY_POINT.inner() + 18);
 }
+
+/// Asserts that two emulators have exactly the same observable state.
+fn assert_same_state(expected: &Emulator, actual: &Emulator) {
+  assert_eq!(expected.pc, actual.pc, "PC differs");
+  assert_eq!(expected.i, actual.i, "I differs");
+  for index in 0..=0xF {
+    assert_eq!(
+      expected.registers.try_index(index).unwrap(),
+      actual.registers.try_index(index).unwrap(),
+      "V{index:X} differs"
+    );
+  }
+  assert_eq!(expected.delay_timer.get(), actual.delay_timer.get());
+  assert_eq!(expected.sound_timer.get(), actual.sound_timer.get());
+  assert!(
+    expected.stack.iter().eq(actual.stack.iter()),
+    "Stack differs"
+  );
+  assert_eq!(
+    format!("{:?}", expected.state),
+    format!("{:?}", actual.state)
+  );
+  assert_eq!(expected.display.dirty_rows(), actual.display.dirty_rows());
+  assert_eq!(expected.display.get_vram(), actual.display.get_vram());
+
+  let (mut expected_ram, mut actual_ram) = ([0; 0x1000], [0; 0x1000]);
+  expected
+    .memory
+    .write_range(Address::new(0), &mut expected_ram)
+    .unwrap();
+  actual
+    .memory
+    .write_range(Address::new(0), &mut actual_ram)
+    .unwrap();
+  assert_eq!(expected_ram, actual_ram, "Memory differs");
+}
+
+/// Creates an interpreter and a recompiler emulator loaded with the same ROM and seed.
+fn differential_pair(rom: &[u8]) -> (Emulator, Emulator) {
+  let mut interpreter = Emulator::new();
+  let mut recompiler = Emulator::new();
+  recompiler.set_engine(Engine::Recompiler);
+  for emulator in [&mut interpreter, &mut recompiler] {
+    emulator.load_rom(rom).unwrap();
+    emulator.set_rand_seed(0x5EED);
+  }
+  (interpreter, recompiler)
+}
+
+#[test]
+/// Run every bundled ROM on both engines with the same input and compare the state,
+/// once with the default quirks and once per platform.
+fn test_recompiler_matches_interpreter() {
+  let roms = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("../roms");
+  let quirks = std::iter::once(Quirks::R8).chain(Platform::ALL.map(|platform| platform.quirks()));
+  for (entry, quirks) in std::fs::read_dir(roms).unwrap().zip(quirks.cycle()) {
+    let path = entry.unwrap().path();
+    let rom = std::fs::read(&path).unwrap();
+    let (mut interpreter, mut recompiler) = differential_pair(&rom);
+    interpreter.set_quirks(quirks);
+    recompiler.set_quirks(quirks);
+
+    // Chunks of odd sizes so blocks get split at arbitrary points.
+    for chunk in 0..200 {
+      let key = Key::all().nth(chunk % 16).copied().unwrap();
+      if chunk % 3 == 0 {
+        interpreter.press_key(key);
+        recompiler.press_key(key);
+      } else {
+        interpreter.release_key(key);
+        recompiler.release_key(key);
+      }
+
+      let ticks = 37 + chunk % 64;
+      let expected = (0..ticks).try_for_each(|_| interpreter.tick());
+      let actual = recompiler.run(ticks);
+      assert_eq!(
+        format!("{expected:?}"),
+        format!("{actual:?}"),
+        "{}",
+        path.display()
+      );
+      assert_same_state(&interpreter, &recompiler);
+      if expected.is_err() {
+        break;
+      }
+    }
+  }
+}
+
+#[test]
+/// Self-modifying code must invalidate the compiled blocks it overwrites.
+fn test_recompiler_self_modifying_code() {
+  let mut program = [0u8; 0x16];
+  program[..0x0E].copy_from_slice(&[
+    0x22, 0x10, // 200: CALL 0x210  -> V2 = 5
+    0x60, 0x62, // 202: LD V0, #62
+    0x61, 0x09, // 204: LD V1, #09
+    0xA2, 0x12, // 206: LD I, #212
+    0xF1, 0x55, // 208: LD [I], V1  -> 0x212 becomes LD V2, #09
+    0x22, 0x10, // 20A: CALL 0x210  -> V2 = 9
+    0x12, 0x0C, // 20C: JP 0x20C
+  ]);
+  program[0x10..].copy_from_slice(&[
+    0x63, 0x01, // 210: LD V3, 1
+    0x62, 0x05, // 212: LD V2, 5
+    0x00, 0xEE, // 214: RET
+  ]);
+
+  let (mut interpreter, mut recompiler) = differential_pair(&program);
+  for _ in 0..32 {
+    interpreter.tick().unwrap();
+  }
+  recompiler.run(32).unwrap();
+
+  assert_same_state(&interpreter, &recompiler);
+  assert_eq!(recompiler.registers[RegisterIndex::new(2)], 9);
+  assert_eq!(recompiler.pc.inner(), 0x20C);
+}
+
+#[test]
+/// Running off the end of memory is the same error on both engines.
+fn test_recompiler_end_of_memory() {
+  let mut program = vec![0u8; 0xE00];
+  program[..2].copy_from_slice(&[0x1F, 0xFE]); // 200: JP 0xFFE
+  program[0xDFE..].copy_from_slice(&[0x60, 0x01]); // FFE: LD V0, 1
+
+  let (mut interpreter, mut recompiler) = differential_pair(&program);
+  interpreter.tick().unwrap();
+  let expected = interpreter.tick().unwrap_err();
+  let actual = recompiler.run(2).unwrap_err();
+
+  assert_eq!(expected.to_string(), actual.to_string());
+  assert_same_state(&interpreter, &recompiler);
+  assert_eq!(recompiler.pc.inner(), 0xFFE);
+}
+
+#[test]
+/// Loading a new ROM must drop the blocks compiled for the previous one.
+fn test_recompiler_cache_cleared_on_load() {
+  let mut emulator = Emulator::new();
+  emulator.set_engine(Engine::Recompiler);
+  emulator
+    .load_rom(&[0x60u8, 0x01, 0x12, 0x00] as &[u8])
+    .unwrap();
+  emulator.run(8).unwrap();
+  assert_eq!(emulator.blocks.as_ref().unwrap().len(), 1);
+
+  emulator
+    .load_rom(&[0x60u8, 0x02, 0x12, 0x00] as &[u8])
+    .unwrap();
+  assert_eq!(emulator.blocks.as_ref().unwrap().len(), 0);
+  emulator.run(8).unwrap();
+  assert_eq!(emulator.registers[RegisterIndex::ZERO], 2);
+}
+
+#[test]
+/// Sprite rows are XORed into the packed rows and report collisions.
+fn test_display_set_and_collision() {
+  let mut display = Display::new();
+
+  assert_eq!(display.set(0, 0, 0b1111_0000), 0);
+  assert_eq!(display.row(0), 0xF000_0000_0000_0000);
+  assert!(display.get(0, 0) && display.get(3, 0) && !display.get(4, 0));
+
+  // Drawing over unlit pixels is not a collision.
+  assert_eq!(display.set(4, 0, 0b1111_0000), 0);
+  assert_eq!(display.row(0), 0xFF00_0000_0000_0000);
+
+  // Erasing a lit pixel is.
+  assert_eq!(display.set(2, 0, 0b1000_0000), 1);
+  assert!(!display.get(2, 0));
+  assert!(!display[(2, 0)] && display[(1, 0)]);
+}
+
+#[test]
+/// Sprites crossing the right or bottom edge wrap around.
+fn test_display_wraps() {
+  let mut display = Display::new();
+
+  display.set(60, 33, 0xFF);
+  assert_eq!(display.row(1), 0xF000_0000_0000_000F);
+  assert_eq!(
+    display.lit_pixels().collect::<Vec<_>>(),
+    [
+      (0, 1),
+      (1, 1),
+      (2, 1),
+      (3, 1),
+      (60, 1),
+      (61, 1),
+      (62, 1),
+      (63, 1)
+    ]
+  );
+
+  // The per-pixel view is built on top of the packed rows.
+  let vram = display.get_vram();
+  for (x, column) in vram.iter().enumerate() {
+    for (y, &pixel) in column.iter().enumerate() {
+      assert_eq!(pixel, display.get(x, y));
+    }
+  }
+}
+
+#[test]
+/// Changed lines stay dirty across ticks until the front-end acknowledges them.
+fn test_display_dirty_rows() {
+  let program = [
+    0xA0u8, 0x00, // LD I, 0 (font glyph "0", 5 lines)
+    0xD0, 0x05, // DRW V0, V0, 5
+    0x00, 0xE0, // CLS
+    0x00, 0xE0, // CLS
+  ];
+  let mut emulator = Emulator::new();
+  emulator.load_rom(&program as &[u8]).unwrap();
+  assert!(!emulator.display().is_dirty());
+
+  emulator.tick().unwrap();
+  emulator.tick().unwrap();
+  emulator.tick().unwrap();
+  // Drawn then cleared: still reported, ticks never acknowledge.
+  assert_eq!(
+    emulator.display().dirty_rows().iter().collect::<Vec<_>>(),
+    [0, 1, 2, 3, 4]
+  );
+
+  emulator.acknowledge_display();
+  assert_eq!(emulator.display().dirty_rows(), DirtyRows::NONE);
+
+  // Clearing a blank screen changes nothing.
+  emulator.tick().unwrap();
+  assert!(!emulator.display().is_dirty());
+}
+
+/// Draws the flicker pattern of a moving sprite: a pixel erased in one frame and
+/// redrawn in the next. Returns the intensity of the pixel after each frame.
+fn flicker_frames(mode: PostProcess) -> Vec<u8> {
+  let mut display = Display::new();
+  let mut output = FrameProcessor::new(mode);
+  let mut frames = vec![];
+  for frame in 0..6 {
+    // Lit on frame 0, erased by a collision on frame 1, off until frame 5.
+    if frame == 0 || frame == 1 || frame == 5 {
+      display.set(0, 0, 0x80);
+    }
+    output.push(&display);
+    display.acknowledge();
+    frames.push(output.intensity(0, 0));
+  }
+  frames
+}
+
+#[test]
+/// Every post-processing mode hides the erased pixel its own way.
+fn test_postprocess_modes() {
+  assert_eq!(flicker_frames(PostProcess::Raw), [FULL, 0, 0, 0, 0, FULL]);
+  assert_eq!(
+    flicker_frames(PostProcess::Blend { frames: 3 }),
+    [FULL, FULL, FULL, 0, 0, FULL]
+  );
+  assert_eq!(
+    flicker_frames(PostProcess::Phosphor { fade: 100 }),
+    [FULL, 155, 55, 0, 0, FULL]
+  );
+  assert_eq!(
+    flicker_frames(PostProcess::Smooth),
+    [FULL, FULL, 0, 0, 0, FULL]
+  );
+}
+
+#[test]
+/// Only the lines whose output changed are reported, a mode switch reports all of them.
+fn test_postprocess_changed_rows() {
+  let mut display = Display::new();
+  let mut output = FrameProcessor::default();
+
+  display.set(0, 3, 0xFF);
+  output.push(&display);
+  assert_eq!(output.changed_rows().iter().collect::<Vec<_>>(), [3]);
+  output.push(&display);
+  assert!(output.changed_rows().is_empty());
+
+  output.set_mode(PostProcess::Smooth);
+  output.push(&display);
+  assert_eq!(output.changed_rows(), DirtyRows::ALL);
+}
+
+#[test]
+fn test_postprocess_parse() {
+  assert_eq!("raw".parse(), Ok(PostProcess::Raw));
+  assert_eq!("blend".parse(), Ok(PostProcess::Blend { frames: 3 }));
+  assert_eq!(
+    "Phosphor:32".parse(),
+    Ok(PostProcess::Phosphor { fade: 32 })
+  );
+  assert!("blend:9".parse::<PostProcess>().is_err());
+  assert!("smooth:1".parse::<PostProcess>().is_err());
+  assert!("crt".parse::<PostProcess>().is_err());
+  for preset in PostProcess::PRESETS {
+    assert_eq!(preset.to_string().parse(), Ok(preset));
+  }
+  assert_eq!(PostProcess::Smooth.next(), PostProcess::Raw);
+}
+
+#[test]
+/// Scripts accept absolute and relative frames, taps and comments.
+fn test_input_script_parse() {
+  let script: InputScript = "
+    # warm up
+    0 press 5
+    +10 release 5   # relative
+    30 TAP a b
+  "
+  .parse()
+  .unwrap();
+  let event = |frame, action, key| ScriptEvent { frame, action, key };
+  assert_eq!(
+    script.events(),
+    [
+      event(0, KeyAction::Press, Key::K5),
+      event(10, KeyAction::Release, Key::K5),
+      event(30, KeyAction::Press, Key::KA),
+      event(30, KeyAction::Press, Key::KB),
+      event(31, KeyAction::Release, Key::KA),
+      event(31, KeyAction::Release, Key::KB),
+    ]
+  );
+  assert_eq!(script.last_frame(), Some(31));
+
+  let error = "0 press 5\n1 hold 5".parse::<InputScript>().unwrap_err();
+  assert_eq!(error.line, 2);
+  assert!("0 press G".parse::<InputScript>().is_err());
+  assert!("x press 1".parse::<InputScript>().is_err());
+  assert!("0 press".parse::<InputScript>().is_err());
+  // Frames past the last `u64` are refused instead of overflowing.
+  let error = "18446744073709551615 press 1\n+1 release 1"
+    .parse::<InputScript>()
+    .unwrap_err();
+  assert_eq!(error.line, 2);
+  assert!("18446744073709551615 tap 1".parse::<InputScript>().is_err());
+}
+
+#[test]
+/// A scripted key press is seen by `LD VX, K`.
+fn test_input_script_apply() {
+  let mut emulator = Emulator::new();
+  emulator
+    .load_rom(&[0xF3u8, 0x0A, 0x12, 0x02] as &[u8])
+    .unwrap();
+  let script: InputScript = "2 tap 7".parse().unwrap();
+
+  for frame in 0..4 {
+    script.apply(frame, &mut emulator);
+    emulator.tick().unwrap();
+  }
+  assert_eq!(emulator.registers[RegisterIndex::new(3)], 7);
+  assert!(!emulator.keyboard.is_set(7));
+}
+
+/// A frame with the font glyph "0" (a 4x5 box) drawn at the top-left corner.
+fn glyph_frame() -> Frame {
+  let mut display = Display::new();
+  for (y, row) in [0xF0, 0x90, 0x90, 0x90, 0xF0].into_iter().enumerate() {
+    display.set(0, y as u8, row);
+  }
+  Frame::from(&display)
+}
+
+#[test]
+/// PBM and PGM are written by hand, check the exact bytes.
+fn test_export_netpbm() {
+  let frame = glyph_frame();
+  let options = ExportOptions {
+    scale: 2,
+    ..Default::default()
+  };
+
+  let mut pbm = vec![];
+  export::write_image(&frame, ImageFormat::Pbm, &options, &mut pbm).unwrap();
+  let header = b"P4\n128 64\n";
+  assert!(pbm.starts_with(header));
+  // 16 bytes per row, every display row is written twice; 1 is an unlit pixel.
+  assert_eq!(pbm.len(), header.len() + 16 * 64);
+  assert_eq!(pbm[header.len()..header.len() + 2], [0x00, 0xFF]);
+  assert_eq!(pbm[header.len() + 32..header.len() + 34], [0x3C, 0xFF]);
+
+  let mut pgm = vec![];
+  export::write_image(&frame, ImageFormat::Pgm, &options, &mut pgm).unwrap();
+  let header = b"P5\n128 64\n255\n";
+  assert!(pgm.starts_with(header));
+  assert_eq!(pgm.len(), header.len() + 128 * 64);
+  assert_eq!(pgm[header.len() + 128 * 2 + 2], 0);
+  assert_eq!(pgm[header.len() + 128 * 2 + 1], FULL);
+
+  // Scales past the maximum are refused instead of overflowing the image size.
+  for scale in [export::MAX_SCALE + 1, u32::MAX] {
+    let options = ExportOptions {
+      scale,
+      ..Default::default()
+    };
+    for format in [ImageFormat::Pbm, ImageFormat::Pgm, ImageFormat::Png] {
+      let error = export::write_image(&frame, format, &options, vec![]).unwrap_err();
+      assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    }
+  }
+}
+
+#[test]
+fn test_export_png_grid_and_palette() {
+  let options = ExportOptions {
+    palette: "102030:F0E0D0".parse().unwrap(),
+    scale: 4,
+    grid: Some([1, 2, 3]),
+  };
+  assert_eq!(options.source(0, 5), None);
+  assert_eq!(options.source(5, 5), Some((1, 1)));
+  assert_eq!(options.size(), (256, 128));
+
+  let mut png = vec![];
+  export::write_image(&glyph_frame(), ImageFormat::Png, &options, &mut png).unwrap();
+  assert!(png.starts_with(b"\x89PNG\r\n\x1a\n"));
+
+  let palette = Palette::CLASSIC;
+  assert_eq!(palette.color(0), [0, 0, 0]);
+  assert_eq!(palette.color(FULL), [255, 255, 255]);
+  assert_eq!(palette.color(128), [128, 128, 128]);
+  assert_eq!(options.palette.to_string(), "102030:F0E0D0");
+  assert!("102030".parse::<Palette>().is_err());
+  assert!("10203G:000000".parse::<Palette>().is_err());
+}
+
+#[test]
+fn test_export_paths() {
+  assert_eq!(export::utc_timestamp(0), "19700101-000000");
+  assert_eq!(export::utc_timestamp(951_868_799), "20000229-235959");
+  assert_eq!(
+    ImageFormat::from_path(std::path::Path::new("a/b.PGM")),
+    Some(ImageFormat::Pgm)
+  );
+  assert_eq!(ImageFormat::from_path(std::path::Path::new("a/b")), None);
+
+  let path = export::timestamped_path(Some(std::path::Path::new("roms/PONG.ch8")), "png");
+  assert_eq!(path.parent(), Some(std::path::Path::new("roms")));
+  let name = path.file_name().unwrap().to_str().unwrap();
+  assert!(
+    name.starts_with("PONG-") && name.ends_with(".png"),
+    "{name}"
+  );
+}
+
+#[test]
+/// Identical frames are merged and the delays add up to the recorded time.
+fn test_gif_recorder() {
+  let blank = Frame::from(&Display::new());
+  let glyph = glyph_frame();
+  let mut recorder = GifRecorder::new(vec![], &ExportOptions::default()).unwrap();
+
+  // One second of the glyph at 60 fps, one frame blank (too short, dropped), then
+  // one second blank at 30 fps.
+  let fps = |rate: u32| std::time::Duration::from_secs(1) / rate;
+  let frames = std::iter::repeat_n((&glyph, fps(60)), 60)
+    .chain([(&blank, fps(100)), (&glyph, fps(100))])
+    .chain(std::iter::repeat_n((&blank, fps(30)), 30));
+  for (frame, shown) in frames {
+    recorder.push(frame.clone(), shown).unwrap();
+  }
+  assert_eq!(recorder.frames(), 92);
+  let gif = recorder.finish().unwrap();
+  assert!(gif.starts_with(b"GIF89a"));
+  assert_eq!(gif.last(), Some(&0x3B));
+
+  // Graphic control extensions hold the delays (little endian, hundredths of a second).
+  let delays: Vec<u16> = gif
+    .windows(4)
+    .filter(|window| window[..3] == [0x21, 0xF9, 0x04])
+    .map(|window| window.as_ptr() as usize - gif.as_ptr() as usize)
+    .map(|at| u16::from_le_bytes([gif[at + 4], gif[at + 5]]))
+    .collect();
+  assert_eq!(delays, [100, 2, 100]);
+
+  // The scale is bounded, which also keeps a GIF side under 65535 pixels.
+  let options = ExportOptions {
+    scale: 2048,
+    ..ExportOptions::default()
+  };
+  assert!(GifRecorder::new(vec![], &options).is_err());
+}
+
+/// Runs `program` for `ticks` ticks with the given quirks on both engines.
+fn run_with_quirks(quirks: Quirks, program: &[u8], ticks: usize) -> Emulator {
+  let (mut interpreter, mut recompiler) = differential_pair(program);
+  interpreter.set_quirks(quirks);
+  recompiler.set_quirks(quirks);
+  (0..ticks).try_for_each(|_| interpreter.tick()).unwrap();
+  recompiler.run(ticks).unwrap();
+  assert_same_state(&interpreter, &recompiler);
+  interpreter
+}
+
+#[test]
+/// Every quirk changes the behaviour of its instructions, on both engines.
+fn test_quirks() {
+  let v = |emulator: &Emulator, index: u8| *emulator.registers.try_index(index).unwrap();
+  let with = |change: fn(&mut Quirks)| {
+    let mut quirks = Quirks::R8;
+    change(&mut quirks);
+    quirks
+  };
+
+  // LD V0, 1 / LD V1, 6 / SHR V0, V1
+  let shift = [0x60, 0x01, 0x61, 0x06, 0x80, 0x16];
+  let emulator = run_with_quirks(Quirks::R8, &shift, 3);
+  assert_eq!((v(&emulator, 0), v(&emulator, 0xF)), (0, 1));
+  let emulator = run_with_quirks(with(|q| q.shift = false), &shift, 3);
+  assert_eq!((v(&emulator, 0), v(&emulator, 0xF)), (3, 0));
+
+  // LD VF, 5 / OR V0, V1
+  let logic = [0x6F, 0x05, 0x80, 0x11];
+  assert_eq!(v(&run_with_quirks(Quirks::R8, &logic, 2), 0xF), 5);
+  assert_eq!(
+    v(&run_with_quirks(with(|q| q.logic = true), &logic, 2), 0xF),
+    0
+  );
+
+  // LD I, 0x300 / LD [I], V2
+  let memory = [0xA3, 0x00, 0xF2, 0x55];
+  for (quirk, i) in [
+    (MemoryQuirk::Unchanged, 0x300),
+    (MemoryQuirk::IncrementByX, 0x302),
+    (MemoryQuirk::IncrementByXPlusOne, 0x303),
+  ] {
+    let mut quirks = Quirks::R8;
+    quirks.memory = quirk;
+    assert_eq!(run_with_quirks(quirks, &memory, 2).i.inner(), i);
+  }
+
+  // LD V0, 4 / LD V1, 8 / JP V0, 0x100
+  let jump = [0x60, 0x04, 0x61, 0x08, 0xB1, 0x00];
+  assert_eq!(run_with_quirks(Quirks::R8, &jump, 3).pc.inner(), 0x104);
+  assert_eq!(
+    run_with_quirks(with(|q| q.jump = true), &jump, 3)
+      .pc
+      .inner(),
+    0x108
+  );
+
+  // LD V0, 62 / LD V1, 30 / LD I, 0 / DRW V0, V1, 5 (font "0" across both edges)
+  let wrap = [0x60, 0x3E, 0x61, 0x1E, 0xA0, 0x00, 0xD0, 0x15];
+  let emulator = run_with_quirks(Quirks::R8, &wrap, 4);
+  assert!(emulator.display.get(62, 30) && emulator.display.get(1, 30));
+  assert!(emulator.display.get(62, 0));
+  let emulator = run_with_quirks(with(|q| q.wrap = false), &wrap, 4);
+  assert!(emulator.display.get(62, 30) && emulator.display.get(62, 31));
+  assert!(!emulator.display.get(1, 30) && !emulator.display.get(62, 0));
+
+  assert_eq!(Quirks::R8.preset_name(), Some("R8"));
+  assert_eq!(
+    Platform::HybridVip.quirks().preset_name(),
+    Some(Platform::OriginalChip8.name())
+  );
+  assert_eq!(with(|q| q.logic = true).preset_name(), None);
+  // Platforms with the same quirks as the previous one are skipped.
+  assert_eq!(Quirks::R8.next_preset(), Platform::OriginalChip8.quirks());
+  assert_eq!(
+    Platform::OriginalChip8.quirks().next_preset(),
+    Platform::ModernChip8.quirks()
+  );
+  assert_eq!(
+    Platform::Chip48.quirks().next_preset(),
+    Platform::SuperChip.quirks()
+  );
+  assert_eq!(Platform::XoChip.quirks().next_preset(), Quirks::R8);
+  assert_eq!(with(|q| q.logic = true).next_preset(), Quirks::R8);
+}
+
+#[test]
+/// `FX0A` waits for a new press, or its release with the `key_release` quirk, and
+/// ignores keys held from before.
+fn test_wait_key() {
+  // LD V1, K / JP 0x200
+  let program = [0xF1, 0x0A, 0x12, 0x00];
+  let waiting = |emulator: &Emulator| matches!(emulator.state, State::WaitingKey { .. });
+  let v1 = |emulator: &Emulator| *emulator.registers.try_index(1).unwrap();
+  let start = |key_release: bool, held: &[Key]| {
+    let mut emulator = Emulator::new();
+    emulator.load_rom(program.as_slice()).unwrap();
+    emulator.set_rom_quirks(Quirks {
+      key_release,
+      ..Quirks::R8
+    });
+    held.iter().for_each(|&key| emulator.press_key(key));
+    emulator.tick().unwrap();
+    emulator.tick().unwrap();
+    assert!(waiting(&emulator));
+    emulator
+  };
+
+  for key_release in [false, true] {
+    // Held: a key down before the wait does not answer it.
+    let mut emulator = start(key_release, &[Key::K3]);
+    emulator.tick().unwrap();
+    assert!(waiting(&emulator));
+    // Neither is releasing it, the key was not pressed during the wait.
+    emulator.release_key(Key::K3);
+    emulator.tick().unwrap();
+    assert!(waiting(&emulator));
+
+    // Tapped between two ticks.
+    let mut emulator = start(key_release, &[]);
+    emulator.press_key(Key::K7);
+    emulator.release_key(Key::K7);
+    emulator.tick().unwrap();
+    assert!(!waiting(&emulator));
+    assert_eq!(v1(&emulator), 7);
+  }
+
+  // Pressed: answers as the key goes down, unless the VIP semantics wait for it to go up.
+  let mut emulator = start(false, &[Key::K3]);
+  emulator.press_key(Key::K9);
+  emulator.tick().unwrap();
+  assert_eq!((waiting(&emulator), v1(&emulator)), (false, 9));
+  let mut emulator = start(true, &[]);
+  emulator.press_key(Key::K9);
+  emulator.tick().unwrap();
+  assert!(waiting(&emulator));
+
+  // Several keys: the released one answers, the other is still down.
+  let mut emulator = start(true, &[]);
+  emulator.press_key(Key::K2);
+  emulator.press_key(Key::K5);
+  emulator.tick().unwrap();
+  emulator.release_key(Key::K5);
+  emulator.tick().unwrap();
+  assert_eq!((waiting(&emulator), v1(&emulator)), (false, 5));
+
+  // The next wait starts afresh, K2 is still held and its release does not answer it.
+  emulator.tick().unwrap();
+  assert!(waiting(&emulator));
+  emulator.release_key(Key::K2);
+  emulator.tick().unwrap();
+  assert!(waiting(&emulator));
+  emulator.press_key(Key::K2);
+  emulator.release_key(Key::K2);
+  emulator.tick().unwrap();
+  assert_eq!((waiting(&emulator), v1(&emulator)), (false, 2));
+
+  assert!(Platform::OriginalChip8.quirks().key_release);
+  assert!(!Platform::SuperChip.quirks().key_release);
+}
+
+#[test]
+/// Known ROMs run with the quirks of their platform, unknown ones with the defaults.
+fn test_romdb() {
+  let pong = std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/../roms/PONG.ch8")).unwrap();
+  let db = RomDb::bundled();
+  let info = db.lookup(&pong).unwrap().clone();
+  assert_eq!(info.title, "Pong");
+  assert_eq!(info.platform, Some(Platform::Chip48));
+
+  let mut emulator = Emulator::new();
+  emulator.set_quirks(Platform::XoChip.quirks());
+  emulator.load_rom(pong.as_slice()).unwrap();
+  assert!(emulator.rom_info().is_none());
+
+  emulator.set_rom_db(Some(db));
+  emulator.load_rom(pong.as_slice()).unwrap();
+  assert_eq!(
+    emulator.rom_info().map(|info| info.sha1.as_str()),
+    Some(info.sha1.as_str())
+  );
+  assert_eq!(emulator.quirks(), Platform::Chip48.quirks());
+  emulator.load_rom(&[0x12u8, 0x00] as &[u8]).unwrap();
+  assert!(emulator.rom_info().is_none());
+  assert_eq!(emulator.quirks(), Platform::XoChip.quirks());
+
+  let db = RomDb::from_json(
+    r##"[{
+      "title": "Test",
+      "roms": {
+        "6D6A0A94D2D1E3DB4F8A3D1A5F8A2E3D7C7D2D9B": {
+          "platforms": ["megachip8", "superchip"],
+          "quirkyPlatforms": { "superchip": { "wrap": true, "memoryIncrementByX": true } },
+          "tickrate": 30,
+          "colors": { "pixels": ["#102030", "#ffcc00"] },
+          "keys": { "up": 5, "a": 10 }
+        }
+      }
+    }]"##,
+  )
+  .unwrap();
+  let info = &db.roms["6d6a0a94d2d1e3db4f8a3d1a5f8a2e3d7c7d2d9b"];
+  assert_eq!(info.platform, Some(Platform::SuperChip));
+  let quirks = info.quirks.unwrap();
+  assert!(quirks.wrap && quirks.jump);
+  assert_eq!(quirks.memory, MemoryQuirk::IncrementByX);
+  assert_eq!(info.tickrate, Some(30));
+  assert_eq!(
+    info.palette,
+    Some(Palette {
+      off: [0x10, 0x20, 0x30],
+      on: [0xFF, 0xCC, 0x00]
+    })
+  );
+  assert_eq!(info.keys, [("a".into(), Key::KA), ("up".into(), Key::K5)]);
+  assert!(RomDb::from_json(r#"[{"title": "Bad", "roms": {"00": {"keys": {"up": 16}}}}]"#).is_err());
+}
+
+#[test]
+/// Only reachable instructions count as evidence, and `load_rom` applies confident guesses on request.
+fn test_detect_platform() {
+  let detect = |rom: &[u8]| detect_platform(rom).map(|detection| detection.platform);
+  // LD I, long 0x300 / SAVE V0 - V1 / JP self
+  assert_eq!(
+    detect(&[0xF0, 0x00, 0x03, 0x00, 0x50, 0x12, 0x12, 0x06]),
+    Some(Platform::XoChip)
+  );
+  // HIGH / DRW V0, V1, 0 / JP self
+  let schip = [0x00, 0xFF, 0xD0, 0x10, 0x12, 0x04];
+  assert_eq!(detect(&schip), Some(Platform::SuperChip));
+  // LD V1, 8 / SHR V0, V1 / JP self
+  assert_eq!(
+    detect(&[0x61, 0x08, 0x80, 0x16, 0x12, 0x04]),
+    Some(Platform::OriginalChip8)
+  );
+  // LD V0, 8 / SHR V0, V1 / JP self
+  assert_eq!(
+    detect(&[0x60, 0x08, 0x80, 0x16, 0x12, 0x04]),
+    Some(Platform::Chip48)
+  );
+  // LD I, 0x300 / LD V1, [I] / LD V1, [I] / JP self
+  assert_eq!(
+    detect(&[0xA3, 0x00, 0xF1, 0x65, 0xF1, 0x65, 0x12, 0x06]),
+    Some(Platform::OriginalChip8)
+  );
+  // JP 0x204 / (data: 00FF) / JP self
+  assert_eq!(detect(&[0x12, 0x04, 0x00, 0xFF, 0x12, 0x04]), None);
+
+  let detection = detect_platform(&schip).unwrap();
+  assert!(detection.confidence >= MIN_CONFIDENCE && detection.confidence < 1.0);
+  assert_eq!(
+    detection.evidence,
+    [
+      (Signature::SuperChipResolution, 1),
+      (Signature::SuperChipSprite, 1)
+    ]
+  );
+
+  let mut emulator = Emulator::new();
+  emulator.load_rom(schip.as_slice()).unwrap();
+  assert!(emulator.detection().is_none());
+  assert_eq!(emulator.quirks(), Quirks::R8);
+  emulator.set_platform_detection(true);
+  emulator.load_rom(schip.as_slice()).unwrap();
+  assert_eq!(emulator.detection(), Some(&detection));
+  assert_eq!(emulator.quirks(), Platform::SuperChip.quirks());
+}
@@ -0,0 +1,147 @@
+//! Golden-image regression tests over the bundled `roms/` directory.
+//!
+//! Every ROM runs with a fixed seed and scripted input for `FRAMES` frames. The
+//! final framebuffer is hashed and rendered as ASCII, and both are compared to
+//! `tests/golden/<rom>.txt`. A ROM may come with its own input in
+//! `tests/golden/<rom>.script` (see `InputScript`), otherwise every keypad key is
+//! tapped in turn.
+//!
+//! After an intended behaviour change, update the golden files with
+//!
+//! ```text
+//! R8_BLESS=1 cargo test -p r8-emulator --test golden
+//! ```
+
+use std::{
+  fmt::Write,
+  fs,
+  path::{Path, PathBuf},
+};
+
+use r8_core::constants;
+use r8_emulator::{Display, Emulator, InputScript};
+
+/// Frames run per ROM.
+const FRAMES: u64 = 300;
+
+/// Ticks per frame, about 500 instructions per second at 60 frames per second.
+const TICKS_PER_FRAME: u32 = 8;
+
+/// Seed of the random number generator.
+const SEED: u128 = 0x5EED;
+
+/// The input of ROMs without a script: from frame 60, tap one key every 15 frames.
+fn default_script() -> InputScript {
+  let mut script = String::new();
+  for (n, key) in (0..16)
+    .cycle()
+    .take(((FRAMES - 60) / 15) as usize)
+    .enumerate()
+  {
+    writeln!(script, "{} tap {:X}", 60 + n * 15, key).unwrap();
+  }
+  script.parse().expect("the default script is valid")
+}
+
+fn roms_dir() -> PathBuf {
+  Path::new(env!("CARGO_MANIFEST_DIR")).join("../roms")
+}
+
+fn golden_dir() -> PathBuf {
+  Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden")
+}
+
+/// FNV-1a over the VRAM rows, top to bottom.
+fn hash(display: &Display) -> u64 {
+  display
+    .rows()
+    .iter()
+    .flat_map(|row| row.to_le_bytes())
+    .fold(0xCBF2_9CE4_8422_2325, |hash, byte| {
+      (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01B3)
+    })
+}
+
+/// Runs `rom` and returns the snapshot stored in its golden file.
+fn snapshot(name: &str, rom: &[u8], script: &InputScript) -> String {
+  let mut emulator = Emulator::new();
+  emulator.load_rom(rom).expect("the ROM fits in memory");
+  emulator.set_rand_seed(SEED);
+
+  let mut error = None;
+  let mut ticks = 0;
+  'frames: for frame in 0..FRAMES {
+    script.apply(frame, &mut emulator);
+    emulator.tick_timers();
+    for _ in 0..TICKS_PER_FRAME {
+      if let Err(err) = emulator.tick() {
+        error = Some(format!("frame {frame}: {err}"));
+        break 'frames;
+      }
+      ticks += 1;
+    }
+  }
+
+  let display = emulator.display();
+  let mut text = format!("# {name}\nticks: {ticks}\nhash: {:016x}\n", hash(display));
+  if let Some(error) = error {
+    writeln!(text, "error: {error}").unwrap();
+  }
+  text.push('\n');
+  for y in 0..constants::HEIGHT {
+    text.extend((0..constants::WIDTH).map(|x| if display.get(x, y) { '#' } else { '.' }));
+    text.push('\n');
+  }
+  text
+}
+
+#[test]
+fn golden_roms() {
+  let bless = std::env::var_os("R8_BLESS").is_some_and(|value| value != "0");
+  let mut roms: Vec<_> = fs::read_dir(roms_dir())
+    .expect("the roms directory exists")
+    .map(|entry| entry.unwrap().path())
+    .filter(|path| path.extension().is_some_and(|ext| ext == "ch8"))
+    .collect();
+  roms.sort();
+  assert!(
+    !roms.is_empty(),
+    "no ROMs found in {}",
+    roms_dir().display()
+  );
+
+  let mut failures = vec![];
+  for path in &roms {
+    let stem = path.file_stem().unwrap().to_string_lossy();
+    let name = path.file_name().unwrap().to_string_lossy();
+    let script = match fs::read_to_string(golden_dir().join(format!("{stem}.script"))) {
+      Ok(source) => source
+        .parse()
+        .unwrap_or_else(|err| panic!("{stem}.script: {err}")),
+      Err(_) => default_script(),
+    };
+    let actual = snapshot(&name, &fs::read(path).unwrap(), &script);
+
+    let golden = golden_dir().join(format!("{stem}.txt"));
+    if bless {
+      fs::create_dir_all(golden_dir()).unwrap();
+      fs::write(&golden, &actual).unwrap();
+      continue;
+    }
+    match fs::read_to_string(&golden) {
+      Ok(expected) if expected == actual => {}
+      Ok(expected) => failures.push(format!(
+        "{name}: snapshot differs\n--- expected\n{expected}--- actual\n{actual}"
+      )),
+      Err(_) => failures.push(format!("{name}: missing {}", golden.display())),
+    }
+  }
+
+  assert!(
+    failures.is_empty(),
+    "{} of {} ROMs differ from their golden files (rerun with R8_BLESS=1 if the change is intended):\n\n{}",
+    failures.len(),
+    roms.len(),
+    failures.join("\n")
+  );
+}
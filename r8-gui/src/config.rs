@@ -0,0 +1,119 @@
+use std::path::PathBuf;
+
+use bevy::log::{Level, LogPlugin};
+use bevy::prelude::*;
+use clap::Parser;
+use log::LevelFilter;
+use r8_config::{parse_log_level, parse_quirks, Config};
+use r8_core::constants;
+use r8_emulator::{export::Palette, PostProcess, Quirks};
+
+/// CLI wrapper for the GUI binary, flags override the configuration file
+#[derive(Parser)]
+/// R8 - Chip-8 Emulator
+struct Args {
+  /// Configuration file (default: config.toml in the user configuration directory)
+  #[clap(long)]
+  config: Option<PathBuf>,
+  /// Most verbose log messages: off, error, warn, info, debug or trace
+  #[clap(long, value_parser = parse_log_level)]
+  log_level: Option<LevelFilter>,
+  /// Instructions per frame of ROMs without a recommended or saved speed
+  #[clap(long)]
+  speed: Option<u32>,
+  /// Quirks preset of ROMs without recommended or saved quirks: r8 or a platform (chip48, superchip...)
+  #[clap(long, value_parser = parse_quirks)]
+  quirks: Option<Quirks>,
+  /// Screen colours as OFF:ON, e.g. 000000:33FF66
+  #[clap(long)]
+  palette: Option<Palette>,
+  /// Window pixels per CHIP-8 pixel
+  #[clap(long)]
+  scale: Option<u32>,
+  /// Post-processing mode: raw, blend[:frames], phosphor[:fade] or smooth
+  #[clap(short, long)]
+  postprocess: Option<PostProcess>,
+  /// Buzzer volume, from 0.0 to 1.0
+  #[clap(long)]
+  volume: Option<f32>,
+  /// Disable the buzzer
+  #[clap(long)]
+  mute: bool,
+  /// ROM database to use instead of the bundled one
+  #[clap(long)]
+  romdb: Option<PathBuf>,
+  /// Guess the platform of ROMs missing from the database from their opcodes
+  #[clap(long)]
+  detect_platform: bool,
+}
+
+/// The configuration file with the command-line flags applied, read once at startup.
+#[derive(Resource)]
+pub struct AppConfig(pub Config);
+
+impl AppConfig {
+  /// Parses the command line and loads the configuration file it points to,
+  /// exits with a message when either is invalid.
+  pub fn load() -> Self {
+    let args = Args::parse();
+    let mut config = match Config::load(args.config.as_deref()) {
+      Ok(config) => config,
+      Err(err) => {
+        eprintln!("Failed to load the configuration: {}", err);
+        std::process::exit(1);
+      }
+    };
+    if let Some(level) = args.log_level {
+      config.log_level = level;
+    }
+    if let Some(speed) = args.speed {
+      config.emulator.speed = speed.max(1);
+    }
+    if let Some(quirks) = args.quirks {
+      config.emulator.quirks = quirks;
+    }
+    if let Some(palette) = args.palette {
+      config.display.palette = Some(palette);
+    }
+    if let Some(scale) = args.scale {
+      config.display.scale = scale.max(1);
+    }
+    if let Some(mode) = args.postprocess {
+      config.display.postprocess = mode;
+    }
+    if let Some(volume) = args.volume {
+      config.audio.volume = volume.clamp(0.0, 1.0);
+    }
+    if args.mute {
+      config.audio.enabled = false;
+    }
+    if let Some(path) = args.romdb {
+      config.emulator.romdb = Some(path);
+    }
+    if args.detect_platform {
+      config.emulator.detect_platform = true;
+    }
+    Self(config)
+  }
+
+  /// Returns the size of the emulator screen in window pixels.
+  pub fn resolution(&self) -> (u32, u32) {
+    let scale = self.0.display.scale;
+    (
+      constants::WIDTH as u32 * scale,
+      constants::HEIGHT as u32 * scale,
+    )
+  }
+
+  /// Returns the logging setup, `None` when logging is off.
+  pub fn log_plugin(&self) -> Option<LogPlugin> {
+    let level = match self.0.log_level.to_level()? {
+      log::Level::Error => Level::ERROR,
+      log::Level::Warn => Level::WARN,
+      log::Level::Info => Level::INFO,
+      log::Level::Debug => Level::DEBUG,
+      log::Level::Trace => Level::TRACE,
+    };
+    Some(LogPlugin { level, ..default() })
+  }
+}
@@ -1,17 +1,87 @@
+use std::{
+  fs::File,
+  io::{BufWriter, Write},
+  path::PathBuf,
+};
+
 use bevy::prelude::*;
 use r8_core::constants;
+use r8_emulator::{
+  export::{self, ExportOptions, Frame, Palette},
+  record::GifRecorder,
+  FrameProcessor,
+};
+
+use crate::{config::AppConfig, emulator::Emulator, ui::TopPanelState};
+
+/// Image pixels per CHIP-8 pixel of the screenshots and recordings.
+const CAPTURE_SCALE: u32 = 8;
+
+/// Colours of the screen, screenshots and recordings. The configuration file sets
+/// the default ones and a ROM database entry may recommend its own, set when the
+/// ROM is loaded.
+#[derive(Resource)]
+pub struct ScreenPalette(pub Palette);
+
+impl Default for ScreenPalette {
+  fn default() -> Self {
+    Self(Palette::CLASSIC)
+  }
+}
 
-use crate::{emulator::Emulator, RESOLUTION};
+impl ScreenPalette {
+  /// Screenshots and recordings use the screen colours, scaled up.
+  fn capture_options(&self) -> ExportOptions {
+    ExportOptions {
+      palette: self.0,
+      scale: CAPTURE_SCALE,
+      grid: None,
+    }
+  }
+}
 
 #[derive(Component)]
 struct Pixel(usize, usize);
 
+/// The post-processing stage the screen is rendered from, its mode is picked in the bottom panel.
+#[derive(Resource, Default)]
+pub struct DisplayOutput(pub FrameProcessor);
+
+/// GIF recording of the presented frames, toggled from the bottom panel.
+///
+/// # Fields
+///
+/// * `wanted` - Set by the UI, the recording starts or stops on the next frame.
+/// * `active` - The file being recorded and its recorder.
+#[derive(Resource, Default)]
+pub struct Recording {
+  pub wanted: bool,
+  active: Option<(PathBuf, GifRecorder<BufWriter<File>>)>,
+}
+
+impl Recording {
+  /// Returns `true` while frames are being recorded.
+  pub fn is_active(&self) -> bool {
+    self.active.is_some()
+  }
+}
+
 pub struct DisplayPlugin;
 
 impl Plugin for DisplayPlugin {
   fn build(&self, app: &mut App) {
+    app.init_resource::<DisplayOutput>();
+    app.init_resource::<ScreenPalette>();
+    app.init_resource::<Recording>();
     app.add_systems(Startup, init_display);
-    app.add_systems(Update, update_screen_system);
+    app.add_systems(
+      Update,
+      (
+        update_screen_system,
+        screenshot_system,
+        recording_system.after(update_screen_system),
+      ),
+    );
   }
 }
 
@@ -19,12 +89,21 @@ fn init_display(
   mut commands: Commands,
   mut meshes: ResMut<Assets<Mesh>>,
   mut materials: ResMut<Assets<ColorMaterial>>,
+  mut palette: ResMut<ScreenPalette>,
+  mut output: ResMut<DisplayOutput>,
+  config: Res<AppConfig>,
 ) {
+  if let Some(colors) = config.0.display.palette {
+    palette.0 = colors;
+  }
+  output.0.set_mode(config.0.display.postprocess);
+
   // Spawn a camera
   commands.spawn(Camera2d);
 
-  let pixel_width = RESOLUTION.0 as f32 / constants::WIDTH as f32;
-  let pixel_height = RESOLUTION.1 as f32 / constants::HEIGHT as f32;
+  let resolution = config.resolution();
+  let pixel_width = resolution.0 as f32 / constants::WIDTH as f32;
+  let pixel_height = resolution.1 as f32 / constants::HEIGHT as f32;
 
   let rectangle = meshes.add(Rectangle::new(pixel_width, pixel_height));
 
@@ -33,9 +112,9 @@ fn init_display(
     for x in 0..constants::WIDTH {
       // Calculate position: center each pixel in its grid cell
       // Origin is at center of window, so we offset by half resolution
-      let pos_x = (x as f32 + 0.5) * pixel_width - (RESOLUTION.0 as f32 / 2.0);
+      let pos_x = (x as f32 + 0.5) * pixel_width - (resolution.0 as f32 / 2.0);
       let pos_y =
-        ((constants::HEIGHT - y - 1) as f32 + 0.5) * pixel_height - (RESOLUTION.1 as f32 / 2.0);
+        ((constants::HEIGHT - y - 1) as f32 + 0.5) * pixel_height - (resolution.1 as f32 / 2.0);
 
       commands.spawn((
         Pixel(x, y),
@@ -48,21 +127,94 @@ fn init_display(
 }
 
 fn update_screen_system(
-  r8: Res<Emulator>,
+  mut r8: ResMut<Emulator>,
+  mut output: ResMut<DisplayOutput>,
+  palette: Res<ScreenPalette>,
   mut materials: ResMut<Assets<ColorMaterial>>,
   query: Query<(&MeshMaterial2d<ColorMaterial>, &Pixel)>,
 ) {
-  // Only update when the emulator's display has been flagged as updated to reduce work.
-  if r8.0.display().updated {
-    for (mesh_material, pixel) in &query {
-      let color = if r8.0.display().get(pixel.0, pixel.1) {
-        Color::WHITE
-      } else {
-        Color::BLACK
-      };
-      if let Some(material) = materials.get_mut(&mesh_material.0) {
-        material.color = color;
+  // Sample the display every frame (fading modes change even when the VRAM does not),
+  // then only touch the pixels on the lines whose output changed.
+  output.0.push(r8.0.display());
+  r8.0.acknowledge_display();
+
+  let changed = output.0.changed_rows();
+  if changed.is_empty() {
+    return;
+  }
+  for (mesh_material, pixel) in &query {
+    if !changed.contains(pixel.1) {
+      continue;
+    }
+    // Blend from the off to the on colour of the palette.
+    let [r, g, b] = palette.0.color(output.0.intensity(pixel.0, pixel.1));
+    let color = Color::srgb_u8(r, g, b);
+    if let Some(material) = materials.get_mut(&mesh_material.0) {
+      material.color = color;
+    }
+  }
+}
+
+/// Saves the presented frame as a timestamped PNG next to the loaded ROM (F12).
+fn screenshot_system(
+  keyboard_input: Res<ButtonInput<KeyCode>>,
+  output: Res<DisplayOutput>,
+  palette: Res<ScreenPalette>,
+  top_state: Res<TopPanelState>,
+) {
+  if !keyboard_input.just_pressed(KeyCode::F12) {
+    return;
+  }
+  let path = export::timestamped_path(top_state.latest_path.as_deref(), "png");
+  match export::save_image(&Frame::from(&output.0), &path, &palette.capture_options()) {
+    Ok(()) => log::info!("Saved screenshot to {}", path.display()),
+    Err(err) => log::error!("Failed to save screenshot {}: {}", path.display(), err),
+  }
+}
+
+/// Starts and stops the GIF recording next to the loaded ROM, and records every
+/// presented frame while it runs, shown until the next update.
+fn recording_system(
+  time: Res<Time>,
+  mut recording: ResMut<Recording>,
+  output: Res<DisplayOutput>,
+  palette: Res<ScreenPalette>,
+  top_state: Res<TopPanelState>,
+) {
+  let recording = &mut *recording;
+  if recording.wanted && recording.active.is_none() {
+    let path = export::timestamped_path(top_state.latest_path.as_deref(), "gif");
+    let options = palette.capture_options();
+    let started =
+      File::create(&path).and_then(|file| GifRecorder::new(BufWriter::new(file), &options));
+    match started {
+      Ok(recorder) => {
+        log::info!("Recording to {}", path.display());
+        recording.active = Some((path, recorder));
+      }
+      Err(err) => {
+        log::error!("Failed to start recording {}: {}", path.display(), err);
+        recording.wanted = false;
       }
     }
   }
+
+  if !recording.wanted {
+    if let Some((path, recorder)) = recording.active.take() {
+      let frames = recorder.frames();
+      match recorder.finish().and_then(|mut file| file.flush()) {
+        Ok(()) => log::info!("Saved {} frames to {}", frames, path.display()),
+        Err(err) => log::error!("Failed to save recording {}: {}", path.display(), err),
+      }
+    }
+    return;
+  }
+
+  if let Some((path, recorder)) = &mut recording.active {
+    if let Err(err) = recorder.push(Frame::from(&output.0), time.delta()) {
+      log::error!("Failed to record {}: {}", path.display(), err);
+      recording.active = None;
+      recording.wanted = false;
+    }
+  }
 }
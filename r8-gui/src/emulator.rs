@@ -1,11 +1,18 @@
+use std::collections::BTreeSet;
+
 use bevy::prelude::*;
-use r8_emulator::Emulator as CoreEmulator;
+use r8_emulator::{Emulator as CoreEmulator, RomDb};
+
+use crate::config::AppConfig;
+
+/// Highest clock multiplier, the speed slider and ROM database tickrates are capped to it.
+pub const MAX_CLOCK_MULTIPLIER: u32 = 50;
 
 #[derive(Resource)]
 pub struct Emulator(pub CoreEmulator);
 
-/// Controls emulation execution: pause/resume, single-step requests, and a
-/// clock multiplier that allows running multiple CPU ticks per update.
+/// Controls emulation execution: pause/resume, single-step requests, a clock
+/// multiplier that allows running multiple CPU ticks per update, and breakpoints.
 #[derive(Resource, Debug)]
 pub struct ExecutionState {
   /// When true, the main tick loop won't be executed automatically.
@@ -14,6 +21,12 @@ pub struct ExecutionState {
   pub clock_multiplier: u32,
   /// When true, run a single CPU tick on the next update and then clear this flag.
   pub step_request: bool,
+  /// Addresses that pause the emulation when the PC reaches them while running.
+  pub breakpoints: BTreeSet<u16>,
+  /// The address the emulation was paused at by a breakpoint or a step, its
+  /// breakpoint is run over once on resume instead of pausing again. Cleared when
+  /// a ROM is loaded, so a breakpoint on its first instruction stops it.
+  pub resume_from: Option<u16>,
 }
 
 impl Default for ExecutionState {
@@ -22,6 +35,8 @@ impl Default for ExecutionState {
       paused: false,
       clock_multiplier: 1,
       step_request: false,
+      breakpoints: BTreeSet::new(),
+      resume_from: None,
     }
   }
 }
@@ -35,9 +50,27 @@ impl Plugin for EmulatorPlugin {
   }
 }
 
-fn setup_system(mut commands: Commands) {
-  commands.insert_resource(Emulator(CoreEmulator::new()));
-  commands.insert_resource(ExecutionState::default());
+fn setup_system(mut commands: Commands, config: Res<AppConfig>) {
+  let settings = &config.0.emulator;
+  let mut emulator = CoreEmulator::new();
+  emulator.set_quirks(settings.quirks);
+  let db = match &settings.romdb {
+    Some(path) => match RomDb::load(path) {
+      Ok(db) => db.into(),
+      Err(err) => {
+        log::error!("Failed to load ROM database {}: {}", path.display(), err);
+        std::process::exit(1);
+      }
+    },
+    None => RomDb::bundled(),
+  };
+  emulator.set_rom_db(Some(db));
+  emulator.set_platform_detection(settings.detect_platform);
+  commands.insert_resource(Emulator(emulator));
+  commands.insert_resource(ExecutionState {
+    clock_multiplier: settings.speed.clamp(1, MAX_CLOCK_MULTIPLIER),
+    ..default()
+  });
 }
 
 fn tick_system(mut r8: ResMut<Emulator>, mut exec: ResMut<ExecutionState>) {
@@ -49,12 +82,24 @@ fn tick_system(mut r8: ResMut<Emulator>, mut exec: ResMut<ExecutionState>) {
         std::process::exit(1);
       }
       exec.step_request = false;
+      exec.resume_from = Some(r8.0.pc().inner());
     }
     return;
   }
 
-  // When running, execute `clock_multiplier` ticks per update.
+  // When running, an update is a frame: the timers count down once and
+  // `clock_multiplier` instructions run.
+  r8.0.tick_timers();
   for _ in 0..exec.clock_multiplier {
+    // Checked before the tick, so a breakpoint on the first instruction stops it.
+    let pc = r8.0.pc().inner();
+    let resuming = exec.resume_from.take() == Some(pc);
+    if !resuming && exec.breakpoints.contains(&pc) {
+      log::info!("Breakpoint at 0x{:03X}", pc);
+      exec.paused = true;
+      exec.resume_from = Some(pc);
+      break;
+    }
     if let Err(err) = r8.0.tick() {
       log::error!("Fatal emulator error: {}", err);
       std::process::exit(1);
@@ -1,8 +1,10 @@
+use crate::config::AppConfig;
 use crate::emulator::Emulator;
+use crate::settings::RomSettingsState;
 use crate::ui::{FileChooserMode, FileChooserState, UiPanelState};
 use crate::ui::{BOTTOM_PANEL_HEIGHT, RIGHT_PANEL_WIDTH, TOP_PANEL_HEIGHT};
-use crate::RESOLUTION;
 
+use bevy::input::keyboard::Key;
 use bevy::prelude::*;
 use bevy::window::PrimaryWindow;
 
@@ -44,11 +46,17 @@ fn input_toggle_system(
   mut file_chooser: ResMut<FileChooserState>,
   mut window_query: Query<&mut Window, With<PrimaryWindow>>,
   mut camera_query: Query<&mut Transform, With<Camera2d>>,
+  config: Res<AppConfig>,
 ) {
   // Toggle debug panel (Escape)
   if keyboard_input.just_pressed(KeyCode::Escape) {
     panel_state.show_debug = !panel_state.show_debug;
-    update_window_and_camera(panel_state.show_debug, &mut window_query, &mut camera_query);
+    update_window_and_camera(
+      panel_state.show_debug,
+      config.resolution(),
+      &mut window_query,
+      &mut camera_query,
+    );
   }
 
   // Toggle file chooser (F1)
@@ -67,20 +75,27 @@ fn camera_update_system(
   panel_state: Res<UiPanelState>,
   mut window_query: Query<&mut Window, With<PrimaryWindow>>,
   mut camera_query: Query<&mut Transform, With<Camera2d>>,
+  config: Res<AppConfig>,
 ) {
   if panel_state.is_changed() {
-    update_window_and_camera(panel_state.show_debug, &mut window_query, &mut camera_query);
+    update_window_and_camera(
+      panel_state.show_debug,
+      config.resolution(),
+      &mut window_query,
+      &mut camera_query,
+    );
   }
 }
 
 /// Helper to update window size and camera position based on debug panel visibility
 fn update_window_and_camera(
   show_debug: bool,
+  resolution: (u32, u32),
   window_query: &mut Query<&mut Window, With<PrimaryWindow>>,
   camera_query: &mut Query<&mut Transform, With<Camera2d>>,
 ) {
-  let base_width = RESOLUTION.0 as f32;
-  let base_height = RESOLUTION.1 as f32 + TOP_PANEL_HEIGHT + BOTTOM_PANEL_HEIGHT;
+  let base_width = resolution.0 as f32;
+  let base_height = resolution.1 as f32 + TOP_PANEL_HEIGHT + BOTTOM_PANEL_HEIGHT;
 
   // Vertical offset to center display between top and bottom panels
   let vertical_offset = (BOTTOM_PANEL_HEIGHT - TOP_PANEL_HEIGHT) / 2.0;
@@ -113,48 +128,38 @@ fn update_window_and_camera(
   }
 }
 
-/// System that maps KeyCode presses/releases to the emulator's virtual keypad.
+/// System that maps key presses/releases to the emulator's virtual keypad through
+/// the key bindings of the loaded ROM, or the ones of the configuration file.
 ///
-/// Mapping:
-/// | 1 | 2 | 3 | C |  ->  1 2 3 4
-/// | Q | W | E | R |  ->  Q W E R
-/// | A | S | D | F |  ->  A S D F
-/// | Z | X | C | V |  ->  Z X C V
-fn emulator_keys_system(mut r8: ResMut<Emulator>, keyboard_input: Res<ButtonInput<KeyCode>>) {
-  use r8_emulator::Key;
-
-  /// Map Real KeyCodes to the corresponding Chip8 Virtual Keys
-  fn map_key(key: &KeyCode) -> Option<Key> {
-    Some(match key {
-      KeyCode::Digit1 => Key::K1,
-      KeyCode::Digit2 => Key::K2,
-      KeyCode::Digit3 => Key::K3,
-      KeyCode::Digit4 => Key::KC,
-      KeyCode::KeyQ => Key::K4,
-      KeyCode::KeyW => Key::K5,
-      KeyCode::KeyE => Key::K6,
-      KeyCode::KeyR => Key::KD,
-      KeyCode::KeyA => Key::K7,
-      KeyCode::KeyS => Key::K8,
-      KeyCode::KeyD => Key::K9,
-      KeyCode::KeyF => Key::KE,
-      KeyCode::KeyZ => Key::KA,
-      KeyCode::KeyX => Key::K0,
-      KeyCode::KeyC => Key::KB,
-      KeyCode::KeyV => Key::KF,
-      _ => return None,
-    })
-  }
+/// Physical key bindings (`KeyQ`, `Numpad7`...) match the `KeyCode` of the key,
+/// character bindings match the character the key types in the active layout.
+fn emulator_keys_system(
+  mut r8: ResMut<Emulator>,
+  codes: Res<ButtonInput<KeyCode>>,
+  chars: Res<ButtonInput<Key>>,
+  settings: Res<RomSettingsState>,
+  config: Res<AppConfig>,
+) {
+  let keys = settings.keys.as_ref().unwrap_or(&config.0.keys);
+  // `KeyCode` variants are named after the W3C codes the key map uses.
+  let map_code = |code: &KeyCode| keys.lookup_code(&format!("{:?}", code));
+  let map_char = |key: &Key| match key {
+    Key::Character(text) => keys.lookup_char(text.chars().next()?),
+    Key::Space => keys.lookup_char(' '),
+    _ => None,
+  };
 
   // When a mapped key is pressed, notify the emulator
-  keyboard_input
+  codes
     .get_just_pressed()
-    .filter_map(map_key)
+    .filter_map(map_code)
+    .chain(chars.get_just_pressed().filter_map(map_char))
     .for_each(|key| r8.0.press_key(key));
 
   // When a mapped key is released, notify the emulator
-  keyboard_input
+  codes
     .get_just_released()
-    .filter_map(map_key)
+    .filter_map(map_code)
+    .chain(chars.get_just_released().filter_map(map_char))
     .for_each(|key| r8.0.release_key(key));
 }
@@ -0,0 +1,143 @@
+use bevy::prelude::*;
+use r8_config::{KeyBindings, KeyMap, RomSettings, RomSettingsStore};
+use r8_emulator::{export::Palette, Quirks};
+
+use crate::display::{DisplayOutput, ScreenPalette};
+use crate::emulator::{Emulator, ExecutionState, MAX_CLOCK_MULTIPLIER};
+
+/// The settings of the loaded ROM: its speed, quirks and palette are restored when
+/// it is loaded and saved whenever they change in the bottom panel. Its key bindings
+/// are only read, they are edited in `roms.toml`.
+///
+/// # Fields
+///
+/// * `store` - Where settings are saved, `None` if the settings file could not be opened.
+/// * `rom` - The SHA-1 and the title of the loaded ROM.
+/// * `defaults` - The speed, quirks and palette the ROM runs with without saved settings.
+/// * `dirty` - Set by the UI when a setting changed, they are saved on the next update.
+/// * `reset` - Set by the UI to forget the saved settings of the ROM.
+/// * `bindings` - The saved key bindings of the ROM, saved back as they are.
+/// * `keys` - The key map of the ROM, its bindings over the configured ones, the
+///   configured ones are used without.
+#[derive(Resource, Default)]
+pub struct RomSettingsState {
+  store: Option<RomSettingsStore>,
+  rom: Option<(String, String)>,
+  defaults: Option<(u32, Quirks, Palette)>,
+  pub dirty: bool,
+  pub reset: bool,
+  bindings: Option<KeyBindings>,
+  pub keys: Option<KeyMap>,
+}
+
+impl RomSettingsState {
+  /// Records the defaults of a freshly loaded ROM and applies its saved settings.
+  ///
+  /// # Arguments
+  ///
+  /// * `sha1` - The SHA-1 of the ROM image.
+  /// * `title` - The title of the ROM, saved along with its settings.
+  /// * `emulator` - The emulator the ROM was loaded in.
+  /// * `exec` - Its speed.
+  /// * `palette` - The screen colours.
+  /// * `keys` - The configured key map.
+  pub fn rom_loaded(
+    &mut self,
+    sha1: String,
+    title: String,
+    emulator: &mut Emulator,
+    exec: &mut ExecutionState,
+    palette: &mut ScreenPalette,
+    keys: &KeyMap,
+  ) {
+    self.defaults = Some((exec.clock_multiplier, emulator.0.quirks(), palette.0));
+    self.dirty = false;
+    self.reset = false;
+    self.bindings = None;
+    self.keys = None;
+    let saved = self
+      .store
+      .as_ref()
+      .and_then(|store| store.get(&sha1))
+      .cloned();
+    self.rom = Some((sha1, title));
+    let Some(saved) = saved else {
+      return;
+    };
+    log::info!("Restoring the saved settings of this ROM");
+    if let Some(speed) = saved.speed {
+      exec.clock_multiplier = speed.clamp(1, MAX_CLOCK_MULTIPLIER);
+    }
+    if let Some(quirks) = saved.quirks {
+      emulator.0.set_rom_quirks(quirks);
+    }
+    if let Some(colors) = saved.palette {
+      palette.0 = colors;
+    }
+    self.keys = saved.keys.as_ref().map(|bindings| bindings.apply(keys));
+    self.bindings = saved.keys;
+  }
+
+  /// Saves the settings that differ from the defaults of the loaded ROM.
+  fn save(&mut self, emulator: &Emulator, exec: &ExecutionState, palette: &ScreenPalette) {
+    let (Some(store), Some((sha1, title)), Some((speed, quirks, colors))) =
+      (&mut self.store, &self.rom, self.defaults)
+    else {
+      return;
+    };
+    let settings = RomSettings {
+      title: Some(title.clone()),
+      speed: (exec.clock_multiplier != speed).then_some(exec.clock_multiplier),
+      quirks: (emulator.0.quirks() != quirks).then_some(emulator.0.quirks()),
+      palette: (palette.0 != colors).then_some(palette.0),
+      keys: self.bindings.clone(),
+    };
+    store.set(sha1, settings);
+    if let Err(err) = store.save() {
+      log::error!("Failed to save {}: {}", store.path().display(), err);
+    }
+  }
+}
+
+pub struct SettingsPlugin;
+
+impl Plugin for SettingsPlugin {
+  fn build(&self, app: &mut App) {
+    app.init_resource::<RomSettingsState>();
+    app.add_systems(Startup, setup_settings_system);
+    app.add_systems(Update, settings_system);
+  }
+}
+
+fn setup_settings_system(mut settings: ResMut<RomSettingsState>) {
+  match RomSettingsStore::open_default() {
+    Ok(store) => settings.store = Some(store),
+    Err(err) => log::error!(
+      "Failed to open the ROM settings, they will not be saved: {}",
+      err
+    ),
+  }
+}
+
+/// Goes back to the defaults of the ROM when asked to, and saves changed settings.
+fn settings_system(
+  mut settings: ResMut<RomSettingsState>,
+  mut emulator: ResMut<Emulator>,
+  mut exec: ResMut<ExecutionState>,
+  mut palette: ResMut<ScreenPalette>,
+  mut output: ResMut<DisplayOutput>,
+) {
+  if std::mem::take(&mut settings.reset) {
+    if let Some((speed, quirks, colors)) = settings.defaults {
+      exec.clock_multiplier = speed;
+      emulator.0.set_rom_quirks(quirks);
+      palette.0 = colors;
+      output.0.repaint();
+      settings.dirty = true;
+      log::info!("Settings reset to the defaults of this ROM");
+    }
+  }
+  if std::mem::take(&mut settings.dirty) {
+    settings.save(&emulator, &exec, &palette);
+  }
+}
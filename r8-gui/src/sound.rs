@@ -1,4 +1,6 @@
+use crate::config::AppConfig;
 use crate::emulator::Emulator;
+use bevy::audio::Volume;
 use bevy::prelude::*;
 
 #[derive(Component)]
@@ -13,8 +15,17 @@ impl Plugin for SoundPlugin {
   }
 }
 
-fn setup_plugin(mut commands: Commands, asset_server: Res<AssetServer>) {
-  commands.spawn((AudioPlayer::new(asset_server.load("out.ogg")), Sound));
+fn setup_plugin(mut commands: Commands, asset_server: Res<AssetServer>, config: Res<AppConfig>) {
+  let audio = &config.0.audio;
+  // Without a sink the buzzer stays silent.
+  if !audio.enabled {
+    return;
+  }
+  commands.spawn((
+    AudioPlayer::new(asset_server.load("out.ogg")),
+    PlaybackSettings::ONCE.with_volume(Volume::Linear(audio.volume)),
+    Sound,
+  ));
 }
 
 fn update_sound(r8: Res<Emulator>, sound: Query<&AudioSink, With<Sound>>) {
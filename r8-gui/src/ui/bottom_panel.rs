@@ -1,15 +1,26 @@
 use bevy::prelude::*;
 use bevy_egui::egui;
 
+use r8_emulator::{Platform, PostProcess, Quirks};
+
 use super::state::UiPanelState;
-use crate::emulator::ExecutionState;
+use crate::display::{DisplayOutput, Recording, ScreenPalette};
+use crate::emulator::{Emulator, ExecutionState, MAX_CLOCK_MULTIPLIER};
+use crate::settings::RomSettingsState;
 
 pub const BOTTOM_PANEL_HEIGHT: f32 = 32.0;
 
-/// Renders the bottom panel with always-visible playback controls
+/// Renders the bottom panel with always-visible playback controls and the
+/// settings of the loaded ROM
+#[allow(clippy::too_many_arguments)]
 pub fn bottom_panel_system(
   ctx: &egui::Context,
   exec: &mut ResMut<ExecutionState>,
+  output: &mut ResMut<DisplayOutput>,
+  recording: &mut ResMut<Recording>,
+  emulator: &mut ResMut<Emulator>,
+  palette: &mut ResMut<ScreenPalette>,
+  settings: &mut ResMut<RomSettingsState>,
   panel_state: &mut ResMut<UiPanelState>,
 ) {
   egui::TopBottomPanel::bottom("r8_bottom_panel")
@@ -38,11 +49,77 @@ pub fn bottom_panel_system(
 
         ui.separator();
 
-        // Clock Speed multiplier (1..=10)
+        // Clock Speed multiplier (1..=MAX_CLOCK_MULTIPLIER)
         let mut multiplier = exec.clock_multiplier as i32;
         ui.label("Speed:");
-        ui.add(egui::Slider::new(&mut multiplier, 1..=10).show_value(true));
-        exec.clock_multiplier = multiplier.max(1) as u32;
+        ui.add(
+          egui::Slider::new(&mut multiplier, 1..=MAX_CLOCK_MULTIPLIER as i32).show_value(true),
+        );
+        if multiplier.max(1) as u32 != exec.clock_multiplier {
+          exec.clock_multiplier = multiplier.max(1) as u32;
+          settings.dirty = true;
+        }
+
+        ui.separator();
+
+        // Quirks preset of the loaded ROM
+        let mut quirks = emulator.0.quirks();
+        egui::ComboBox::from_id_salt("r8_quirks")
+          .selected_text(quirks.preset_name().unwrap_or("Custom"))
+          .show_ui(ui, |ui| {
+            ui.selectable_value(&mut quirks, Quirks::R8, "R8");
+            for platform in Platform::ALL {
+              ui.selectable_value(&mut quirks, platform.quirks(), platform.name());
+            }
+          });
+        if quirks != emulator.0.quirks() {
+          emulator.0.set_rom_quirks(quirks);
+          settings.dirty = true;
+        }
+
+        // Screen colours, off then on
+        let mut colors = palette.0;
+        ui.color_edit_button_srgb(&mut colors.off);
+        ui.color_edit_button_srgb(&mut colors.on);
+        if colors != palette.0 {
+          palette.0 = colors;
+          output.0.repaint();
+          settings.dirty = true;
+        }
+
+        // Forget the saved speed, quirks and colours of the ROM
+        if ui
+          .button("↺ Defaults")
+          .on_hover_text("Reset the settings saved for this ROM")
+          .clicked()
+        {
+          settings.reset = true;
+        }
+
+        ui.separator();
+
+        // Post-processing mode used to build the presented frame
+        let mut mode = output.0.mode();
+        egui::ComboBox::from_id_salt("r8_postprocess")
+          .selected_text(mode.name())
+          .show_ui(ui, |ui| {
+            for preset in PostProcess::PRESETS {
+              ui.selectable_value(&mut mode, preset, preset.name());
+            }
+          });
+        output.0.set_mode(mode);
+
+        ui.separator();
+
+        // GIF recording of the presented frames
+        let record_label = if recording.is_active() {
+          "⏹ Stop"
+        } else {
+          "⏺ Record"
+        };
+        if ui.button(record_label).clicked() {
+          recording.wanted = !recording.is_active();
+        }
 
         ui.separator();
 
@@ -3,6 +3,8 @@ use bevy_egui::egui;
 use std::fs;
 use std::path::PathBuf;
 
+use super::UiLoadRomMessage;
+
 /// Mode for the file chooser: load raw ROM or assemble a source file first.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum FileChooserMode {
@@ -69,11 +71,11 @@ fn allowed_by_mode(path: &PathBuf, mode: FileChooserMode) -> bool {
   }
 }
 
-/// Renders the file chooser UI and returns Some((contents, name)) if a file was loaded
+/// Renders the file chooser UI and returns the message to load the file, if one was loaded
 pub fn file_chooser_ui(
   ui: &mut egui::Ui,
   state: &mut FileChooserState,
-) -> Option<(Vec<u8>, String)> {
+) -> Option<UiLoadRomMessage> {
   let mut result = None;
 
   // Show which mode we're using
@@ -215,7 +217,12 @@ pub fn file_chooser_ui(
                 .map(|n| n.to_string_lossy().to_string())
                 .unwrap_or_else(|| "Unknown".to_string());
 
-              result = Some((contents, name));
+              result = Some(UiLoadRomMessage {
+                contents,
+                name,
+                path: Some(path.clone()),
+                assembly: None,
+              });
               state.show = false;
               state.error_message = None;
             }
@@ -224,29 +231,34 @@ pub fn file_chooser_ui(
             }
           },
 
-          FileChooserMode::Asm => match fs::File::open(path) {
-            Ok(mut file) => {
-              let mut rom = vec![];
-              match r8_assembly::assemble(&mut file, &mut rom) {
-                Ok(_) => {
-                  let name = path
-                    .file_name()
-                    .map(|n| n.to_string_lossy().to_string())
-                    .unwrap_or_else(|| "Unknown".to_string());
-
-                  result = Some((rom, name));
-                  state.show = false;
-                  state.error_message = None;
-                }
-                Err(e) => {
-                  state.error_message = Some(format!("Failed to assemble: {}", e));
-                }
+          FileChooserMode::Asm => {
+            let mut rom = vec![];
+            let files = r8_assembly::FileSystem::default();
+            match r8_assembly::assemble_file(path, &files, &mut rom) {
+              Ok(assembly) => {
+                assembly
+                  .warnings
+                  .iter()
+                  .for_each(|warning| log::warn!("{}", warning));
+                let name = path
+                  .file_name()
+                  .map(|n| n.to_string_lossy().to_string())
+                  .unwrap_or_else(|| "Unknown".to_string());
+
+                result = Some(UiLoadRomMessage {
+                  contents: rom,
+                  name,
+                  path: Some(path.clone()),
+                  assembly: Some(assembly),
+                });
+                state.show = false;
+                state.error_message = None;
+              }
+              Err(e) => {
+                state.error_message = Some(format!("Failed to assemble: {}", e));
               }
             }
-            Err(e) => {
-              state.error_message = Some(format!("Failed to open source: {}", e));
-            }
-          },
+          }
         }
       }
     }
@@ -1,6 +1,7 @@
 mod bottom_panel;
 mod file_chooser;
 mod right_panel;
+mod source_view;
 mod state;
 mod top_panel;
 
@@ -12,15 +13,25 @@ pub use top_panel::{TopPanelState, TOP_PANEL_HEIGHT};
 
 use bevy::prelude::*;
 use bevy_egui::{egui, EguiContexts, EguiPrimaryContextPass};
+use r8_emulator::{export::Palette, romdb};
 use std::io::Cursor;
+use std::path::PathBuf;
 
-use crate::emulator::{Emulator, ExecutionState};
+use crate::config::AppConfig;
+use crate::display::{DisplayOutput, Recording, ScreenPalette};
+use crate::emulator::{Emulator, ExecutionState, MAX_CLOCK_MULTIPLIER};
+use crate::settings::RomSettingsState;
+use source_view::SourceView;
 
 /// Message event for loading a ROM into the emulator from the UI
 #[derive(Message)]
 pub struct UiLoadRomMessage {
   pub contents: Vec<u8>,
   pub name: String,
+  /// Where the ROM (or its source) was loaded from, screenshots are saved next to it.
+  pub path: Option<PathBuf>,
+  /// The listing and symbols when the ROM was assembled, for source level debugging.
+  pub assembly: Option<r8_assembly::Assembly>,
 }
 
 /// Top panel and file chooser plugin
@@ -38,13 +49,18 @@ impl Plugin for UiPlugin {
 }
 
 /// Main UI system that renders all panels
+#[allow(clippy::too_many_arguments)]
 fn ui_system(
   mut contexts: EguiContexts,
   mut file_state: ResMut<FileChooserState>,
   top_state: Res<TopPanelState>,
   mut panel_state: ResMut<UiPanelState>,
   mut exec: ResMut<ExecutionState>,
-  emulator: Res<Emulator>,
+  mut output: ResMut<DisplayOutput>,
+  mut recording: ResMut<Recording>,
+  mut palette: ResMut<ScreenPalette>,
+  mut settings: ResMut<RomSettingsState>,
+  mut emulator: ResMut<Emulator>,
   mut rom_writer: MessageWriter<UiLoadRomMessage>,
 ) {
   let Ok(ctx) = contexts.ctx_mut() else {
@@ -55,11 +71,27 @@ fn ui_system(
   top_panel::top_panel_system(ctx, &mut file_state, &top_state);
 
   // Always render bottom panel with playback controls
-  bottom_panel::bottom_panel_system(ctx, &mut exec, &mut panel_state);
+  bottom_panel::bottom_panel_system(
+    ctx,
+    &mut exec,
+    &mut output,
+    &mut recording,
+    &mut emulator,
+    &mut palette,
+    &mut settings,
+    &mut panel_state,
+  );
 
   // Render right debug panel if enabled
   if panel_state.show_debug {
-    right_panel::right_panel_system(ctx, &emulator, &mut panel_state.memory_inspector);
+    let panel_state = &mut *panel_state;
+    right_panel::right_panel_system(
+      ctx,
+      &emulator,
+      &mut panel_state.memory_inspector,
+      panel_state.source.as_mut(),
+      &mut exec.breakpoints,
+    );
   }
 
   // Render file chooser as a floating window if open
@@ -79,8 +111,8 @@ fn render_file_chooser_window(
     .resizable(false)
     .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
     .show(ctx, |ui| {
-      if let Some((contents, name)) = file_chooser::file_chooser_ui(ui, state) {
-        rom_writer.write(UiLoadRomMessage { contents, name });
+      if let Some(msg) = file_chooser::file_chooser_ui(ui, state) {
+        rom_writer.write(msg);
       }
     });
 }
@@ -89,16 +121,61 @@ fn rom_loaded_system(
   mut rom_reader: MessageReader<UiLoadRomMessage>,
   mut emulator: ResMut<Emulator>,
   mut top_state: ResMut<TopPanelState>,
+  mut exec: ResMut<ExecutionState>,
+  mut palette: ResMut<ScreenPalette>,
+  mut output: ResMut<DisplayOutput>,
+  mut settings: ResMut<RomSettingsState>,
+  mut panel_state: ResMut<UiPanelState>,
+  config: Res<AppConfig>,
 ) {
   for msg in rom_reader.read() {
     match emulator.0.load_rom(Cursor::new(&msg.contents)) {
       Ok(_) => {
         log::info!("Loaded ROM (UI): {}", msg.name);
+        let info = emulator.0.rom_info().cloned();
+        // Apply the recommended settings of known ROMs, unknown ones get the configured ones.
+        exec.clock_multiplier = config.0.emulator.speed.clamp(1, MAX_CLOCK_MULTIPLIER);
+        if let Some(info) = &info {
+          log::info!(
+            "Recognized {} ({})",
+            info.title,
+            info
+              .platform
+              .map_or("unknown platform", |platform| platform.name())
+          );
+          if let Some(tickrate) = info.tickrate {
+            exec.clock_multiplier = tickrate.clamp(1, MAX_CLOCK_MULTIPLIER);
+          }
+        }
+        palette.0 = info
+          .as_ref()
+          .and_then(|info| info.palette)
+          .or(config.0.display.palette)
+          .unwrap_or(Palette::CLASSIC);
+        let title = info
+          .as_ref()
+          .map_or_else(|| msg.name.clone(), |info| info.title.clone());
+        settings.rom_loaded(
+          romdb::sha1(&msg.contents),
+          title,
+          &mut emulator,
+          &mut exec,
+          &mut palette,
+          &config.0.keys,
+        );
+        output.0.repaint();
+        exec.breakpoints.clear();
+        exec.resume_from = None;
+        panel_state.source = msg.assembly.clone().map(SourceView::new);
         top_state.latest_loaded = Some(msg.name.clone());
+        top_state.latest_path = msg.path.clone();
+        top_state.rom_info = info;
       }
       Err(e) => {
         log::error!("Failed to load ROM {}: {}", msg.name, e);
         top_state.latest_loaded = None;
+        top_state.latest_path = None;
+        top_state.rom_info = None;
       }
     }
   }
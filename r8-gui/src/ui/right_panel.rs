@@ -1,6 +1,9 @@
+use std::collections::BTreeSet;
+
 use bevy_egui::egui;
 use r8_core::constants;
 
+use super::source_view::{self, SourceView};
 use crate::emulator::Emulator;
 
 pub const RIGHT_PANEL_WIDTH: f32 = 300.0;
@@ -32,11 +35,14 @@ impl MemoryInspectorState {
   }
 }
 
-/// Renders the right debug panel with CPU state, registers, and memory inspector
+/// Renders the right debug panel with CPU state, registers, memory inspector and,
+/// for assembled programs, their source
 pub fn right_panel_system(
   ctx: &egui::Context,
   emulator: &Emulator,
   memory_state: &mut MemoryInspectorState,
+  mut source: Option<&mut SourceView>,
+  breakpoints: &mut BTreeSet<u16>,
 ) {
   egui::SidePanel::right("r8_debug_panel")
     .exact_width(RIGHT_PANEL_WIDTH)
@@ -91,6 +97,15 @@ pub fn right_panel_system(
         ui.add_space(8.0);
         ui.separator();
 
+        // Source Section
+        if let Some(source) = source.as_deref_mut() {
+          ui.heading("Source");
+          ui.separator();
+          source_view::source_ui(ui, source, emulator.0.pc().inner(), breakpoints);
+          ui.add_space(8.0);
+          ui.separator();
+        }
+
         // Registers Section
         ui.heading("V Registers");
         ui.separator();
@@ -122,12 +137,17 @@ pub fn right_panel_system(
           ui.label("(empty)");
         } else {
           egui::Grid::new("stack_grid")
-            .num_columns(2)
+            .num_columns(3)
             .spacing([8.0, 2.0])
             .show(ui, |ui| {
               for (i, addr) in stack.iter().enumerate() {
                 ui.monospace(format!("[{}]", i));
                 ui.monospace(format!("0x{:03X}", addr.inner()));
+                // The return address, named after the label of the caller.
+                let label = source
+                  .as_deref()
+                  .and_then(|source| source.label_for(addr.inner()));
+                ui.monospace(label.unwrap_or_default());
                 ui.end_row();
               }
             });
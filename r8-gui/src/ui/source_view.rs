@@ -0,0 +1,173 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use bevy_egui::egui;
+use r8_assembly::Assembly;
+
+/// Source level view of a program assembled by the GUI.
+///
+/// # Fields
+///
+/// * `assembly` - The listing and symbols of the program.
+/// * `map` - The file and line of every assembled address.
+/// * `addresses` - The addresses every line starts at, a line of a macro has one
+///   per call.
+/// * `files` - The lines of every file of the program, in the order they are first
+///   assembled.
+/// * `shown` - The index in `files` of the file shown.
+/// * `follow_pc` - Whether to show the file and line of the PC.
+/// * `last_pc` - The PC the view last scrolled to.
+pub struct SourceView {
+  assembly: Assembly,
+  map: BTreeMap<u16, (String, usize)>,
+  addresses: HashMap<(String, usize), Vec<u16>>,
+  files: Vec<(String, Vec<String>)>,
+  shown: usize,
+  pub follow_pc: bool,
+  last_pc: Option<u16>,
+}
+
+impl SourceView {
+  /// Builds the view of an assembled program.
+  ///
+  /// The files are read again from disk to show their comments and blank lines,
+  /// a file that cannot be read is shown from the listing.
+  pub fn new(assembly: Assembly) -> Self {
+    let mut addresses = HashMap::<_, Vec<u16>>::new();
+    let mut files = Vec::<(String, Vec<String>)>::new();
+    for line in assembly.lines.iter().filter(|line| !line.bytes.is_empty()) {
+      addresses
+        .entry((line.file.clone(), line.line))
+        .or_default()
+        .push(line.address);
+      if !files.iter().any(|(file, _)| *file == line.file) {
+        let lines = match std::fs::read_to_string(&line.file) {
+          Ok(src) => src.lines().map(str::to_string).collect(),
+          Err(_) => listing_lines(&assembly, &line.file),
+        };
+        files.push((line.file.clone(), lines));
+      }
+    }
+    Self {
+      map: assembly.source_map(),
+      assembly,
+      addresses,
+      files,
+      shown: 0,
+      follow_pc: true,
+      last_pc: None,
+    }
+  }
+
+  /// Returns the label at or right before `address`, like `loop+4`.
+  pub fn label_for(&self, address: u16) -> Option<String> {
+    self.assembly.label_for(address)
+  }
+}
+
+/// Rebuilds the lines of `file` from the listing, the lines that assembled to
+/// nothing are left blank.
+fn listing_lines(assembly: &Assembly, file: &str) -> Vec<String> {
+  let mut lines = Vec::new();
+  for line in assembly.lines.iter().filter(|line| line.file == file) {
+    for (row, source) in line.source.split('\n').enumerate() {
+      if lines.len() < line.line + row {
+        lines.resize(line.line + row, String::new());
+      }
+      lines[line.line + row - 1] = source.to_string();
+    }
+  }
+  lines
+}
+
+/// Renders the source of the program, with the line of the PC highlighted.
+///
+/// Clicking a line that assembled to something toggles a breakpoint on every
+/// address it starts at.
+pub fn source_ui(
+  ui: &mut egui::Ui,
+  view: &mut SourceView,
+  pc: u16,
+  breakpoints: &mut BTreeSet<u16>,
+) {
+  if view.files.is_empty() {
+    ui.label("(no source)");
+    return;
+  }
+  let pc_line = view.map.get(&pc).cloned();
+  if view.follow_pc {
+    if let Some((file, _)) = &pc_line {
+      if let Some(index) = view.files.iter().position(|(name, _)| name == file) {
+        view.shown = index;
+      }
+    }
+  }
+
+  ui.horizontal(|ui| {
+    if view.files.len() > 1 {
+      let shown = file_name(&view.files[view.shown].0);
+      egui::ComboBox::from_id_salt("source_file")
+        .selected_text(shown)
+        .show_ui(ui, |ui| {
+          for (index, (file, _)) in view.files.iter().enumerate() {
+            if ui
+              .selectable_label(view.shown == index, file_name(file))
+              .clicked()
+            {
+              view.shown = index;
+              view.follow_pc = false;
+            }
+          }
+        });
+    }
+    ui.checkbox(&mut view.follow_pc, "Follow PC");
+  });
+
+  let scroll = view.follow_pc && view.last_pc != Some(pc);
+  view.last_pc = Some(pc);
+  let (file, lines) = &view.files[view.shown];
+  egui::ScrollArea::vertical()
+    .id_salt("source_scroll")
+    .max_height(250.0)
+    .show(ui, |ui| {
+      ui.style_mut().override_text_style = Some(egui::TextStyle::Monospace);
+      for (index, text) in lines.iter().enumerate() {
+        let line = index + 1;
+        let key = (file.clone(), line);
+        let starts = view.addresses.get(&key);
+        let is_pc = pc_line.as_ref() == Some(&key);
+        let has_breakpoint =
+          starts.is_some_and(|starts| starts.iter().any(|addr| breakpoints.contains(addr)));
+        let marker = if has_breakpoint { "●" } else { " " };
+        let mut label = egui::RichText::new(format!("{} {:>4} {}", marker, line, text));
+        if has_breakpoint {
+          label = label.color(egui::Color32::LIGHT_RED);
+        }
+        if is_pc {
+          label = label.background_color(egui::Color32::from_rgb(70, 70, 20));
+        }
+        let response = ui.add(egui::Label::new(label).sense(egui::Sense::click()));
+        if let Some(starts) = starts {
+          if response.clicked() {
+            for addr in starts {
+              match has_breakpoint {
+                true => breakpoints.remove(addr),
+                false => breakpoints.insert(*addr),
+              };
+            }
+          }
+        }
+        if is_pc && scroll {
+          response.scroll_to_me(Some(egui::Align::Center));
+        }
+      }
+    });
+  ui.label("Click a line to toggle a breakpoint");
+}
+
+/// Returns the file name of a path, for the file selector.
+fn file_name(path: &str) -> &str {
+  std::path::Path::new(path)
+    .file_name()
+    .and_then(|name| name.to_str())
+    .unwrap_or(path)
+}
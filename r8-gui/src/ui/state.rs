@@ -1,6 +1,7 @@
 use bevy::prelude::*;
 
 use super::right_panel::MemoryInspectorState;
+use super::source_view::SourceView;
 
 /// Shared state for UI panels visibility and configuration
 #[derive(Resource)]
@@ -9,6 +10,8 @@ pub struct UiPanelState {
   pub show_debug: bool,
   /// Memory inspector state (persisted even when panel is hidden)
   pub memory_inspector: MemoryInspectorState,
+  /// Source of the program when it was assembled from a file, `None` for ROMs
+  pub source: Option<SourceView>,
 }
 
 impl Default for UiPanelState {
@@ -16,6 +19,7 @@ impl Default for UiPanelState {
     Self {
       show_debug: false,
       memory_inspector: MemoryInspectorState::new(),
+      source: None,
     }
   }
 }
@@ -1,5 +1,7 @@
 use bevy::prelude::*;
 use bevy_egui::egui;
+use r8_emulator::RomInfo;
+use std::path::PathBuf;
 
 use super::file_chooser::{FileChooserMode, FileChooserState};
 
@@ -9,6 +11,10 @@ pub const TOP_PANEL_HEIGHT: f32 = 28.0;
 #[derive(Resource, Default)]
 pub struct TopPanelState {
   pub latest_loaded: Option<String>,
+  /// Path of the last loaded file, if it came from disk.
+  pub latest_path: Option<PathBuf>,
+  /// What the ROM database knows about the loaded ROM.
+  pub rom_info: Option<RomInfo>,
 }
 
 pub fn top_panel_system(
@@ -40,7 +46,9 @@ pub fn top_panel_system(
 
         ui.separator();
 
-        if let Some(name) = &top_state.latest_loaded {
+        if let Some(info) = &top_state.rom_info {
+          rom_info_label(ui, info);
+        } else if let Some(name) = &top_state.latest_loaded {
           ui.label(format!("Loaded: {}", name));
         } else {
           ui.label("No ROM loaded");
@@ -48,3 +56,35 @@ pub fn top_panel_system(
       });
     });
 }
+
+/// Shows the title and authors of a known ROM, the details are in the hover text.
+fn rom_info_label(ui: &mut egui::Ui, info: &RomInfo) {
+  let mut label = format!("Loaded: {}", info.title);
+  if !info.authors.is_empty() {
+    label.push_str(&format!(" by {}", info.authors.join(", ")));
+  }
+
+  let mut details = vec![];
+  if let Some(description) = &info.description {
+    details.push(description.clone());
+  }
+  if let Some(release) = &info.release {
+    details.push(format!("Released: {}", release));
+  }
+  if let Some(platform) = info.platform {
+    details.push(format!("Platform: {}", platform.name()));
+  }
+  if !info.keys.is_empty() {
+    let keys: Vec<_> = info
+      .keys
+      .iter()
+      .map(|(hint, key)| format!("{} = {:X}", hint, *key as u8))
+      .collect();
+    details.push(format!("Keys: {}", keys.join(", ")));
+  }
+
+  let response = ui.label(label);
+  if !details.is_empty() {
+    response.on_hover_text(details.join("\n\n"));
+  }
+}
@@ -1,9 +1,10 @@
 //! Terminal display helpers for the TUI frontend.
 //!
 //! This module implements an efficient renderer for the chip-8 framebuffer using a
-//! virtual buffer. It avoids clearing and redrawing the entire screen by
-//! calculating the difference between the previous and the current framebuffer
-//! and only updating already changed pixels. It also checks the terminal size
+//! virtual buffer. It renders the output of a `FrameProcessor` and avoids
+//! clearing and redrawing the entire screen by only visiting the lines the
+//! processor reports as changed and, within them, updating only the cells whose
+//! shade differs from the previous frame. It also checks the terminal size
 //! and provides a friendly message when the terminal is too small to render
 //! the chip-8 framebuffer.
 
@@ -11,11 +12,12 @@ use std::io::{self, Stdout, Write};
 
 use crossterm::{
   cursor::{self, MoveTo},
-  style::{self, Stylize},
+  style::{self, Color, Stylize},
   terminal::{self, Clear, ClearType},
   ExecutableCommand,
 };
 use r8_core::constants;
+use r8_emulator::{export::Palette, postprocess::FULL, DirtyRows, FrameProcessor};
 
 /// TUI renderer for the CHIP-8 framebuffer.
 ///
@@ -24,17 +26,21 @@ use r8_core::constants;
 ///   maps to a 2-column cell).
 pub struct TerminalDisplay {
   stdout: Stdout,
-  prev_vram: Vec<bool>, // flattened [x + y * WIDTH]
-  pub min_cols: u16,    // minimum required terminal columns (WIDTH * 2)
-  pub min_rows: u16,    // minimum required terminal rows (HEIGHT)
+  prev_shades: [[usize; constants::WIDTH]; constants::HEIGHT], // last rendered shade per cell
+  pub min_cols: u16, // minimum required terminal columns (WIDTH * 2)
+  pub min_rows: u16, // minimum required terminal rows (HEIGHT)
   small_warning_shown: bool,
+  pending: DirtyRows, // lines that could not be drawn yet (e.g. terminal too small)
+  colors: Option<Palette>, // configured colours, the terminal's blue on black otherwise
 }
 
 impl TerminalDisplay {
   /// Create a new `TerminalDisplay` instance.
   ///
-  /// This clears the terminal and hides the cursor (if possible).
-  pub fn new() -> io::Result<Self> {
+  /// This clears the terminal and hides the cursor (if possible). Without
+  /// `colors` the display draws blue on black with the terminal's own shades,
+  /// a palette is drawn with true colours.
+  pub fn new(colors: Option<Palette>) -> io::Result<Self> {
     let mut stdout = std::io::stdout();
 
     // Hide cursor to avoid annoying flicker
@@ -42,14 +48,14 @@ impl TerminalDisplay {
     // Start with a clean screen
     stdout.execute(Clear(ClearType::All))?;
 
-    let prev_vram = vec![false; constants::WIDTH * constants::HEIGHT];
-
     Ok(Self {
       stdout,
-      prev_vram,
+      prev_shades: [[0; constants::WIDTH]; constants::HEIGHT],
       min_cols: (constants::WIDTH * 2) as u16,
       min_rows: constants::HEIGHT as u16,
       small_warning_shown: false,
+      pending: DirtyRows::NONE,
+      colors,
     })
   }
 
@@ -85,74 +91,67 @@ impl TerminalDisplay {
       if self.small_warning_shown {
         self.stdout.execute(Clear(ClearType::All))?;
         self.small_warning_shown = false;
+        // The screen is blank now, so every line has to be drawn again.
+        self.prev_shades = [[0; constants::WIDTH]; constants::HEIGHT];
+        self.pending = DirtyRows::ALL;
         let _ = self.stdout.flush();
       }
       Ok(true)
     }
   }
 
-  /// Public wrapper to check terminal size and show a message if needed.
-  ///
-  /// This is provided so upstream code (e.g. main) can check the terminal size
-  /// independently of rendering behavior.
-  pub fn check_size(&mut self) -> io::Result<bool> {
-    self.ensure_size_ok()
-  }
-
-  /// Render the provided emulator framebuffer.
-  ///
-  /// The framebuffer is the emulator's 64x32 boolean array where `true` means
-  /// a lit pixel.
+  /// Render the output frame of the post-processing stage.
   ///
-  /// This method computes a list of changed pixels compared to the previous
-  /// frame and updates only those cells in the terminal to minimize flicker.
-  pub fn render(&mut self, vram: &[[bool; constants::HEIGHT]; constants::WIDTH]) -> io::Result<()> {
+  /// Only the lines `output` reports as changed are visited, and within them only
+  /// the cells whose shade changed are updated to minimize flicker. Partially lit
+  /// pixels (e.g. fading phosphor) are drawn with lighter block characters.
+  /// Lines that cannot be drawn because the terminal is too small are kept and
+  /// drawn on a later call, so the caller can always acknowledge the display.
+  pub fn render(&mut self, output: &FrameProcessor) -> io::Result<()> {
     // If the terminal is too small, show a message and skip rendering.
     let size_ok = self.ensure_size_ok()?;
+    self.pending = self.pending | output.changed_rows();
     if !size_ok {
       return Ok(());
     }
 
-    // We'll collect changed pixel coordinates first to avoid interleaved cursor positions
-    // while generating the change list.
-    let mut changes: Vec<(u16, u16, bool)> = Vec::with_capacity(128);
-
-    for y in 0..constants::HEIGHT {
-      for x in 0..constants::WIDTH {
-        let idx = x + y * constants::WIDTH;
-        let new_pixel = vram[x][y];
-        let old_pixel = self.prev_vram[idx];
-        if new_pixel != old_pixel {
-          changes.push(((x as u16) * 2, y as u16, new_pixel));
+    let mut changed = false;
+    for y in std::mem::take(&mut self.pending).iter() {
+      for (x, old_shade) in self.prev_shades[y].iter_mut().enumerate() {
+        let shade = shade(output.intensity(x, y));
+        if shade == *old_shade {
+          continue;
         }
+        *old_shade = shade;
+
+        // Move cursor to that pixel (2 columns per chip pixel)
+        self.stdout.execute(MoveTo((x as u16) * 2, y as u16))?;
+        if let Some(colors) = self.colors {
+          let [r, g, b] = colors.off;
+          let off = Color::Rgb { r, g, b };
+          let [r, g, b] = colors.on;
+          let on = Color::Rgb { r, g, b };
+          self
+            .stdout
+            .execute(style::Print(SHADES[shade].with(on).on(off)))?;
+        } else if shade == 0 {
+          // Empty pixel: print two spaces which effectively clears the two-character cell.
+          // Style the off pixel as black to keep visual consistency with the on pixel's
+          // styled `blue()` content and avoid artifacting on some terminals.
+          self.stdout.execute(style::Print("  ".black()))?;
+        } else {
+          // Lit pixel: print with blue foreground such that it looks like a block.
+          self.stdout.execute(style::Print(SHADES[shade].blue()))?;
+        }
+        changed = true;
       }
     }
 
     // If nothing changed, just return.
-    if changes.is_empty() {
+    if !changed {
       return Ok(());
     }
 
-    // Now perform the minimum number of terminal writes to update the changed pixels.
-    for (tx, ty, new_state) in changes.iter() {
-      // Move cursor to that pixel
-      self.stdout.execute(MoveTo(*tx, *ty))?;
-      // Print the content for new or off content for false.
-      if *new_state {
-        // Filled pixel: print with blue foreground such that it looks like a block.
-        // We prefer printing full block characters; the original main used "██".blue()
-        self.stdout.execute(style::Print("██".blue()))?;
-      } else {
-        // Empty pixel: print two spaces which effectively clears the two-character cell.
-        // Style the off pixel as black to keep visual consistency with the on pixel's
-        // styled `blue()` content and avoid artifacting on some terminals.
-        self.stdout.execute(style::Print("  ".black()))?;
-      }
-      // Update the internal state
-      let idx = ((*tx as usize) / 2) + (*ty as usize) * constants::WIDTH;
-      self.prev_vram[idx] = *new_state;
-    }
-
     // Move cursor to the bottom-right corner to avoid disrupting user's input flow.
     let (cols, rows) = terminal::size()?;
     self
@@ -163,6 +162,24 @@ impl TerminalDisplay {
   }
 }
 
+/// Cell contents from off to fully lit, indexed by `shade`.
+const SHADES: [&str; 5] = ["  ", "░░", "▒▒", "▓▓", "██"];
+
+/// The colours the terminal draws with (blue on black), used for captures.
+pub const PALETTE: Palette = Palette {
+  off: [0x00, 0x00, 0x00],
+  on: [0x00, 0x00, 0xEE],
+};
+
+/// Maps a pixel intensity to an index into `SHADES`, only `FULL` maps to a solid block.
+fn shade(intensity: u8) -> usize {
+  match intensity {
+    0 => 0,
+    FULL => SHADES.len() - 1,
+    partial => 1 + partial as usize * (SHADES.len() - 2) / FULL as usize,
+  }
+}
+
 impl Drop for TerminalDisplay {
   fn drop(&mut self) {
     // Try to restore cursor visibility. This is best-effort because destructors should
@@ -183,15 +200,28 @@ mod tests {
   /// a functional test.
   #[test]
   fn display_render_smoke() {
-    let mut td = TerminalDisplay::new().expect("Failed to create TerminalDisplay");
-
-    // Prepare a small test pattern (one pixel at (0,0) and one at (1,1))
-    let mut test_vram = [[false; constants::HEIGHT]; constants::WIDTH];
-    test_vram[0][0] = true;
-    test_vram[1][1] = true;
+    let mut td = TerminalDisplay::new(None).expect("Failed to create TerminalDisplay");
+
+    // Draw the font glyph "0" at (0,0) with a tiny program
+    let mut emu = r8_emulator::Emulator::new();
+    emu
+      .load_rom(&[0xA0u8, 0x00, 0xD0, 0x05] as &[u8])
+      .expect("Failed to load ROM");
+    emu.tick().unwrap();
+    emu.tick().unwrap();
+    let mut output = FrameProcessor::default();
+    output.push(emu.display());
 
     // Even if terminal is small (e.g. in CI), render should return without panicking.
-    let result = td.render(&test_vram);
+    let result = td.render(&output);
     assert!(result.is_ok());
   }
+
+  #[test]
+  fn shade_levels() {
+    assert_eq!(shade(0), 0);
+    assert_eq!(shade(1), 1);
+    assert_eq!(shade(FULL - 1), SHADES.len() - 2);
+    assert_eq!(shade(FULL), SHADES.len() - 1);
+  }
 }
@@ -1,72 +1,91 @@
 //! Input handling for the TUI frontend.
 //!
 //! This module contains helpers to map terminal keys to emulator keys and to process
-//! crossterm key events coming from the terminal. The `map_key` function mirrors
-//! the mapping used by the original `main.rs`. The `process_key_event` is a
+//! crossterm key events coming from the terminal. The `map_key` function looks keys
+//! up in the key map of the configuration file. The `process_event` is a
 //! helper to decouple the event handling from the rest of the application.
 
 use crossterm::event::{Event, KeyCode, KeyEvent};
+use r8_config::KeyMap;
 use r8_emulator::{Emulator, Key as EmuKey};
 
-/// Map a char to an emulator Key.
+/// Map a char to an emulator Key through the configured key map.
 ///
-/// Chip-8 original layout:
+/// The default map lays the Chip-8 keypad on the 1234/QWER/ASDF/ZXCV block, see
+/// `r8_config::KeyMap`. Terminals only report the typed character, so physical
+/// key bindings match the character the key types on a QWERTY keyboard.
 ///
-/// | 1 | 2 | 3 | C |
-/// |---|---|---|---|
-/// | 4 | 5 | 6 | D |
-/// | 7 | 8 | 9 | E |
-/// | A | 0 | B | F |
-///
-/// TUI mapping:
-///
-/// | 1 | 2 | 3 | 4 |  ->  1 2 3 4
-/// | Q | W | E | R |  ->  Q W E R
-/// | A | S | D | F |
-/// | Z | X | C | V |
-///
-/// Returns `Some(Key)` if the char corresponds to a keypad button, otherwise `None`.
-pub fn map_key(key: char) -> Option<EmuKey> {
-  match key {
-    '1' => Some(EmuKey::K1),
-    '2' => Some(EmuKey::K2),
-    '3' => Some(EmuKey::K3),
-    '4' => Some(EmuKey::KC),
-    'Q' | 'q' => Some(EmuKey::K4),
-    'W' | 'w' => Some(EmuKey::K5),
-    'E' | 'e' => Some(EmuKey::K6),
-    'R' | 'r' => Some(EmuKey::KD),
-    'A' | 'a' => Some(EmuKey::K7),
-    'S' | 's' => Some(EmuKey::K8),
-    'D' | 'd' => Some(EmuKey::K9),
-    'F' | 'f' => Some(EmuKey::KE),
-    'Z' | 'z' => Some(EmuKey::KA),
-    'X' | 'x' => Some(EmuKey::K0),
-    'C' | 'c' => Some(EmuKey::KB),
-    'V' | 'v' => Some(EmuKey::KF),
-    _ => None,
-  }
+/// Returns `Some(Key)` if the char is bound to a keypad button, otherwise `None`.
+pub fn map_key(key: char, keys: &KeyMap) -> Option<EmuKey> {
+  keys.lookup_typed(key)
+}
+
+/// Map a terminal key to an emulator Key: characters go through `map_key`, the
+/// arrows, `Enter` and `Backspace` match their physical key bindings.
+fn keypad_key(code: KeyCode, keys: &KeyMap) -> Option<EmuKey> {
+  let name = match code {
+    KeyCode::Char(ch) => return map_key(ch, keys),
+    KeyCode::Up => "ArrowUp",
+    KeyCode::Down => "ArrowDown",
+    KeyCode::Left => "ArrowLeft",
+    KeyCode::Right => "ArrowRight",
+    KeyCode::Enter => "Enter",
+    KeyCode::Backspace => "Backspace",
+    _ => return None,
+  };
+  keys.lookup_code(name)
+}
+
+/// What the main loop should do after an event was processed.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Action {
+  /// Nothing besides forwarding keypad keys to the emulator.
+  None,
+  /// Exit the TUI (e.g. `Esc` key).
+  Exit,
+  /// Switch to the next post-processing mode (`Tab` key).
+  CyclePostProcess,
+  /// Save a screenshot next to the ROM (`P` key).
+  Screenshot,
+  /// Start or stop recording a GIF next to the ROM (`G` key).
+  ToggleRecording,
+  /// Run more instructions per frame (`+` key).
+  Faster,
+  /// Run fewer instructions per frame (`-` key).
+  Slower,
+  /// Switch to the next quirks preset (`F2` key).
+  CycleQuirks,
+  /// Forget the saved settings of the ROM (`F5` key).
+  ResetSettings,
 }
 
 /// Process a `crossterm::event::Event`.
 ///
-/// Returns `true` if the event should cause the TUI to exit (e.g. `Esc` key),
-/// otherwise `false`.
+/// Keypad keys are forwarded to the emulator, the returned `Action` tells the
+/// caller about frontend hotkeys.
 ///
-/// Handles only `Event::Key` events and ignores other event kinds.
-pub fn process_event(event: Event, emu: &mut Emulator) -> bool {
+/// Handles only `Event::Key` events and ignores other event kinds. Keypad bindings
+/// win over hotkeys of the same key, so presets binding `+` or `-` keep every key.
+pub fn process_event(event: Event, emu: &mut Emulator, keys: &KeyMap) -> Action {
   match event {
-    Event::Key(KeyEvent { code, .. }) => match code {
-      KeyCode::Esc => true,
-      KeyCode::Char(ch) => {
-        if let Some(k) = map_key(ch) {
-          emu.press_key(k);
-        }
-        false
+    Event::Key(KeyEvent { code, .. }) => {
+      if let Some(key) = keypad_key(code, keys) {
+        emu.press_key(key);
+        return Action::None;
+      }
+      match code {
+        KeyCode::Esc => Action::Exit,
+        KeyCode::Tab => Action::CyclePostProcess,
+        KeyCode::Char('p' | 'P') => Action::Screenshot,
+        KeyCode::Char('g' | 'G') => Action::ToggleRecording,
+        KeyCode::Char('+' | '=') => Action::Faster,
+        KeyCode::Char('-' | '_') => Action::Slower,
+        KeyCode::F(2) => Action::CycleQuirks,
+        KeyCode::F(5) => Action::ResetSettings,
+        _ => Action::None,
       }
-      _ => false,
-    },
-    _ => false,
+    }
+    _ => Action::None,
   }
 }
 
@@ -81,24 +100,111 @@ pub fn release_all_keys(emu: &mut Emulator) {
 #[cfg(test)]
 mod tests {
   use super::*;
+  use r8_config::HostKey;
 
   #[test]
   fn map_key_lowercase() {
-    assert_eq!(map_key('q'), Some(EmuKey::K4));
-    assert_eq!(map_key('x'), Some(EmuKey::K0));
-    assert_eq!(map_key('z'), Some(EmuKey::KA));
+    let keys = KeyMap::default();
+    assert_eq!(map_key('q', &keys), Some(EmuKey::K4));
+    assert_eq!(map_key('x', &keys), Some(EmuKey::K0));
+    assert_eq!(map_key('z', &keys), Some(EmuKey::KA));
   }
 
   #[test]
   fn map_key_uppercase() {
-    assert_eq!(map_key('Q'), Some(EmuKey::K4));
-    assert_eq!(map_key('X'), Some(EmuKey::K0));
-    assert_eq!(map_key('Z'), Some(EmuKey::KA));
+    let keys = KeyMap::default();
+    assert_eq!(map_key('Q', &keys), Some(EmuKey::K4));
+    assert_eq!(map_key('X', &keys), Some(EmuKey::K0));
+    assert_eq!(map_key('Z', &keys), Some(EmuKey::KA));
+  }
+
+  #[test]
+  fn process_event_hotkeys() {
+    let mut emu = Emulator::new();
+    let keys = KeyMap::default();
+    let key = |code| Event::Key(KeyEvent::from(code));
+    assert_eq!(
+      process_event(key(KeyCode::Esc), &mut emu, &keys),
+      Action::Exit
+    );
+    assert_eq!(
+      process_event(key(KeyCode::Tab), &mut emu, &keys),
+      Action::CyclePostProcess
+    );
+    assert_eq!(
+      process_event(key(KeyCode::Char('P')), &mut emu, &keys),
+      Action::Screenshot
+    );
+    assert_eq!(
+      process_event(key(KeyCode::Char('g')), &mut emu, &keys),
+      Action::ToggleRecording
+    );
+    assert_eq!(
+      process_event(key(KeyCode::Char('+')), &mut emu, &keys),
+      Action::Faster
+    );
+    assert_eq!(
+      process_event(key(KeyCode::Char('-')), &mut emu, &keys),
+      Action::Slower
+    );
+    assert_eq!(
+      process_event(key(KeyCode::F(2)), &mut emu, &keys),
+      Action::CycleQuirks
+    );
+    assert_eq!(
+      process_event(key(KeyCode::F(5)), &mut emu, &keys),
+      Action::ResetSettings
+    );
+    assert_eq!(
+      process_event(key(KeyCode::Char('q')), &mut emu, &keys),
+      Action::None
+    );
+  }
+
+  #[test]
+  fn keypad_bindings_win_over_hotkeys() {
+    let mut emu = Emulator::new();
+    let keys = KeyMap::preset("hp48").unwrap();
+    let key = |code| Event::Key(KeyEvent::from(code));
+    assert_eq!(
+      process_event(key(KeyCode::Char('-')), &mut emu, &keys),
+      Action::None
+    );
+    assert_eq!(
+      process_event(key(KeyCode::Char('+')), &mut emu, &keys),
+      Action::None
+    );
+    assert_eq!(keypad_key(KeyCode::Char('-'), &keys), Some(EmuKey::KE));
+    assert_eq!(keypad_key(KeyCode::Char('+'), &keys), Some(EmuKey::KF));
+    // `=` and `_` are not bound, they still change the speed.
+    assert_eq!(
+      process_event(key(KeyCode::Char('=')), &mut emu, &keys),
+      Action::Faster
+    );
   }
 
   #[test]
   fn map_key_invalid() {
-    assert_eq!(map_key('g'), None);
-    assert_eq!(map_key('\n'), None);
+    let keys = KeyMap::default();
+    assert_eq!(map_key('g', &keys), None);
+    assert_eq!(map_key('\n', &keys), None);
+  }
+
+  #[test]
+  fn map_key_custom() {
+    let mut keys = KeyMap::default();
+    keys.bind(
+      EmuKey::K5,
+      vec![
+        HostKey::Char('k'),
+        HostKey::Code("KeyI"),
+        HostKey::Code("ArrowUp"),
+      ],
+    );
+    assert_eq!(map_key('K', &keys), Some(EmuKey::K5));
+    assert_eq!(map_key('i', &keys), Some(EmuKey::K5));
+    assert_eq!(map_key('w', &keys), None);
+    assert_eq!(keypad_key(KeyCode::Up, &keys), Some(EmuKey::K5));
+    assert_eq!(keypad_key(KeyCode::Down, &keys), None);
   }
 }
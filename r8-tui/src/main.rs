@@ -1,30 +1,75 @@
-use std::path::PathBuf;
+use std::{
+  fs::File,
+  io::{BufWriter, Write},
+  path::PathBuf,
+};
 
 use clap::Parser;
 mod display;
 mod input;
-use crate::display::TerminalDisplay;
-use crate::input::{process_event, release_all_keys};
-use r8_emulator::Emulator;
+mod settings;
+use crate::display::{TerminalDisplay, PALETTE};
+use crate::input::{process_event, release_all_keys, Action};
+use crate::settings::Session;
+use log::LevelFilter;
+use r8_config::{parse_frame_rate, parse_log_level, parse_quirks, Config, RomSettingsStore};
+use r8_emulator::{
+  export::{self, ExportOptions, Frame, Palette},
+  record::GifRecorder,
+  romdb, Emulator, FrameProcessor, PostProcess, Quirks, RomDb,
+};
 
-/// CLI wrapper for the TUI binary
+/// CLI wrapper for the TUI binary, flags override the configuration file
 #[derive(Parser)]
 /// R8 - Chip-8 Emulator
 pub struct R8 {
   #[clap(short, long)]
-  /// Enable debug mode
+  /// Enable debug mode (same as `--log-level debug`)
   debug: bool,
+  /// Most verbose log messages written to r8.log: off, error, warn, info, debug or trace
+  #[clap(long, value_parser = parse_log_level)]
+  log_level: Option<LevelFilter>,
+  /// Configuration file (default: config.toml in the user configuration directory)
+  #[clap(long)]
+  config: Option<PathBuf>,
   /// Path to the ROM to load
   #[clap(short, long)]
   rom: Option<PathBuf>,
   /// Path to the assembly file to load
   #[clap(short, long)]
   asm: Option<PathBuf>,
-  // Clock speed in hz (default: 60)
-  #[clap(short = 'c', long, default_value_t = 60.0)]
-  clock: f64,
+  /// Frames per second, a positive number (default: 60)
+  #[clap(short = 'c', long, value_parser = parse_frame_rate)]
+  clock: Option<f64>,
+  /// Instructions per frame of ROMs without a recommended or saved speed (default: 1)
+  #[clap(long)]
+  speed: Option<u32>,
+  /// Quirks preset of ROMs without recommended or saved quirks: r8 or a platform (chip48, superchip...)
+  #[clap(long, value_parser = parse_quirks)]
+  quirks: Option<Quirks>,
+  /// Screen colours of ROMs without recommended ones, as OFF:ON, e.g. 000000:33FF66 (default: blue on black)
+  #[clap(long)]
+  palette: Option<Palette>,
+  /// Post-processing mode: raw, blend[:frames], phosphor[:fade] or smooth (Tab cycles at runtime)
+  #[clap(short, long)]
+  postprocess: Option<PostProcess>,
+  /// ROM database (`programs.json`) used instead of the bundled one
+  #[clap(long)]
+  romdb: Option<PathBuf>,
+  /// Guess the platform of ROMs the ROM database does not know and run them with its quirks
+  #[clap(long)]
+  detect_platform: bool,
 }
 
+/// Image pixels per CHIP-8 pixel of the screenshots and recordings.
+const CAPTURE_SCALE: u32 = 8;
+
+/// Countdowns per second of the delay and sound timers.
+const TIMER_RATE: f64 = 60.0;
+
+/// A GIF recording in progress.
+type Recording = (PathBuf, GifRecorder<BufWriter<File>>);
+
 macro_rules! log_and_exit {
     ($($arg:tt)*) => {
         log::error!($($arg)*);
@@ -36,14 +81,15 @@ macro_rules! log_and_exit {
 
 fn main() {
   let args = R8::parse();
-
-  let log_level = if args.debug {
-    log::LevelFilter::Debug
-  } else {
-    log::LevelFilter::Info
+  let config = match load_config(&args) {
+    Ok(config) => config,
+    Err(err) => {
+      eprintln!("Failed to load the configuration: {}", err);
+      std::process::exit(1);
+    }
   };
 
-  if let Err(err) = simple_logging::log_to_file("r8.log", log_level) {
+  if let Err(err) = simple_logging::log_to_file("r8.log", config.log_level) {
     println!("Failed to initialize logging: {}", err);
     return;
   }
@@ -52,17 +98,89 @@ fn main() {
   crossterm::terminal::enable_raw_mode().unwrap();
 
   let mut emu = Emulator::new();
+  emu.set_quirks(config.emulator.quirks);
+  let db = match &config.emulator.romdb {
+    Some(path) => match RomDb::load(path) {
+      Ok(db) => db.into(),
+      Err(err) => {
+        log_and_exit!("Failed to load ROM database {}: {}", path.display(), err);
+      }
+    },
+    None => RomDb::bundled(),
+  };
+  emu.set_rom_db(Some(db));
+  emu.set_platform_detection(config.emulator.detect_platform);
 
-  load_rom(&args, &mut emu);
+  let rom = load_rom(&args, &mut emu);
+
+  // Known ROMs run at their recommended speed, at the configured one otherwise.
+  let mut ticks_per_frame = config.emulator.speed;
+  if let Some(info) = emu.rom_info() {
+    log::info!("Recognized {} by {}", info.title, info.authors.join(", "));
+    if let Some(platform) = info.platform.filter(|_| info.quirks.is_some()) {
+      log::info!("Running with the quirks of {}", platform.name());
+    }
+    for (hint, key) in &info.keys {
+      log::info!("Key {}: {:X}", hint, *key as u8);
+    }
+    ticks_per_frame = info.tickrate.unwrap_or(ticks_per_frame).max(1);
+  }
+  if let Some(detection) = emu.detection() {
+    let evidence: Vec<_> = detection
+      .evidence
+      .iter()
+      .map(|(signature, count)| format!("{} x{}", signature.description(), count))
+      .collect();
+    log::info!(
+      "Detected {} ({:.0}% confidence): {}",
+      detection.platform.name(),
+      detection.confidence * 100.0,
+      evidence.join(", ")
+    );
+  }
 
-  let mut td = match TerminalDisplay::new() {
+  let store = match RomSettingsStore::open_default() {
+    Ok(store) => Some(store),
+    Err(err) => {
+      log::error!(
+        "Failed to open the ROM settings, they will not be saved: {}",
+        err
+      );
+      None
+    }
+  };
+  let title = emu.rom_info().map(|info| info.title.clone()).or_else(|| {
+    let source = args.rom.as_deref().or(args.asm.as_deref())?;
+    Some(source.file_name()?.to_string_lossy().into_owned())
+  });
+  let mut session = Session::new(
+    store,
+    romdb::sha1(&rom),
+    title,
+    ticks_per_frame,
+    // The colours of the ROM database win over the configured ones.
+    emu
+      .rom_info()
+      .and_then(|info| info.palette)
+      .or(config.display.palette),
+    &config.keys,
+    &mut emu,
+  );
+  let mut td = match TerminalDisplay::new(session.palette) {
     Ok(display) => display,
     Err(err) => {
       log_and_exit!("Failed to initialize terminal display: {}", err);
     }
   };
 
-  let frame_duration = std::time::Duration::from_secs_f64(1.0 / args.clock);
+  let mut output = FrameProcessor::new(config.display.postprocess);
+  let mut recording: Option<Recording> = None;
+  let palette = session.palette.unwrap_or(PALETTE);
+
+  let frame_duration = std::time::Duration::from_secs_f64(1.0 / config.emulator.frame_rate);
+  // The timers count down at 60 Hz whatever the frame rate, this is how many
+  // countdowns are due.
+  let mut timers_due = 0.0;
 
   loop {
     let frame_start = std::time::Instant::now();
@@ -71,9 +189,23 @@ fn main() {
       match crossterm::event::read() {
         Ok(event) => {
           log::debug!("Event: {:?}", event);
-          if process_event(event, &mut emu) {
+          match process_event(event, &mut emu, &session.keys) {
             // input instructs to exit (e.g. Esc)
-            break;
+            Action::Exit => break,
+            Action::CyclePostProcess => {
+              output.set_mode(output.mode().next());
+              log::info!("Post-processing: {}", output.mode());
+            }
+            Action::Screenshot => save_screenshot(&args, &output, palette),
+            Action::ToggleRecording => match recording.take() {
+              Some(recording) => stop_recording(recording),
+              None => recording = start_recording(&args, palette),
+            },
+            Action::Faster => session.change_speed(1, &emu),
+            Action::Slower => session.change_speed(-1, &emu),
+            Action::CycleQuirks => session.cycle_quirks(&mut emu),
+            Action::ResetSettings => session.reset(&mut emu),
+            Action::None => {}
           }
         }
         Err(err) => {
@@ -82,19 +214,29 @@ fn main() {
       }
     }
 
-    if let Err(err) = emu.tick() {
-      log_and_exit!("Fatal emulator error: {}", err);
+    for _ in 0..session.speed {
+      if let Err(err) = emu.tick() {
+        log_and_exit!("Fatal emulator error: {}", err);
+      }
+    }
+    timers_due += TIMER_RATE / config.emulator.frame_rate;
+    while timers_due >= 1.0 {
+      emu.tick_timers();
+      timers_due -= 1.0;
     }
 
-    if emu.display().updated {
-      let vram = emu.display().get_vram();
-      if let Err(err) = td.render(vram) {
-        log_and_exit!("Failed to render display: {}", err);
-      }
-    } else {
-      // When there's no update, still check terminal size so we can show the resize message
-      if let Err(err) = td.check_size() {
-        log_and_exit!("Failed to check terminal size: {}", err);
+    // Build the presented frame and render the lines that changed (this also checks
+    // the terminal size so we can show the resize message), then acknowledge them.
+    output.push(emu.display());
+    if let Err(err) = td.render(&output) {
+      log_and_exit!("Failed to render display: {}", err);
+    }
+    emu.acknowledge_display();
+
+    if let Some((path, recorder)) = &mut recording {
+      if let Err(err) = recorder.push(Frame::from(&output), frame_duration) {
+        log::error!("Failed to record {}: {}", path.display(), err);
+        recording = None;
       }
     }
 
@@ -107,40 +249,126 @@ fn main() {
       std::thread::sleep(frame_duration - elapsed);
     }
   }
+  if let Some(recording) = recording {
+    stop_recording(recording);
+  }
   crossterm::terminal::disable_raw_mode().unwrap();
 }
 
-/// Key mapping and event processing are handled inside the `input` module.
-/// See `r8-tui/src/input.rs` for details.
+// Key mapping and event processing are handled inside the `input` module.
+// See `r8-tui/src/input.rs` for details.
 
-/// Loads the ROM or the assembly file.
-fn load_rom(args: &R8, emu: &mut Emulator) {
+/// Loads the configuration file and applies the command-line flags on top of it.
+fn load_config(args: &R8) -> std::io::Result<Config> {
+  let mut config = Config::load(args.config.as_deref())?;
+  if let Some(level) = args.log_level {
+    config.log_level = level;
+  }
+  if args.debug {
+    config.log_level = LevelFilter::Debug;
+  }
+  if let Some(clock) = args.clock {
+    config.emulator.frame_rate = clock;
+  }
+  if let Some(speed) = args.speed {
+    config.emulator.speed = speed.max(1);
+  }
+  if let Some(quirks) = args.quirks {
+    config.emulator.quirks = quirks;
+  }
+  if let Some(palette) = args.palette {
+    config.display.palette = Some(palette);
+  }
+  if let Some(mode) = args.postprocess {
+    config.display.postprocess = mode;
+  }
+  if let Some(path) = &args.romdb {
+    config.emulator.romdb = Some(path.clone());
+  }
+  if args.detect_platform {
+    config.emulator.detect_platform = true;
+  }
+  Ok(config)
+}
+
+/// Saves the presented frame as a PNG next to the ROM (or the assembly file).
+fn save_screenshot(args: &R8, output: &FrameProcessor, palette: Palette) {
+  let source = args.rom.as_deref().or(args.asm.as_deref());
+  let path = export::timestamped_path(source, "png");
+  match export::save_image(&Frame::from(output), &path, &capture_options(palette)) {
+    Ok(()) => log::info!("Saved screenshot to {}", path.display()),
+    Err(err) => log::error!("Failed to save screenshot {}: {}", path.display(), err),
+  }
+}
+
+/// Starts recording a GIF next to the ROM (or the assembly file).
+fn start_recording(args: &R8, palette: Palette) -> Option<Recording> {
+  let source = args.rom.as_deref().or(args.asm.as_deref());
+  let path = export::timestamped_path(source, "gif");
+  let options = capture_options(palette);
+  let started =
+    File::create(&path).and_then(|file| GifRecorder::new(BufWriter::new(file), &options));
+  match started {
+    Ok(recorder) => {
+      log::info!("Recording to {}", path.display());
+      Some((path, recorder))
+    }
+    Err(err) => {
+      log::error!("Failed to start recording {}: {}", path.display(), err);
+      None
+    }
+  }
+}
+
+/// Writes the end of the GIF and closes the file.
+fn stop_recording((path, recorder): Recording) {
+  let frames = recorder.frames();
+  match recorder.finish().and_then(|mut file| file.flush()) {
+    Ok(()) => log::info!("Saved {} frames to {}", frames, path.display()),
+    Err(err) => log::error!("Failed to save recording {}: {}", path.display(), err),
+  }
+}
+
+/// Screenshots and recordings use the terminal colours, scaled up.
+fn capture_options(palette: Palette) -> ExportOptions {
+  ExportOptions {
+    palette,
+    scale: CAPTURE_SCALE,
+    grid: None,
+  }
+}
+
+/// Loads the ROM or the assembly file, returns the ROM image.
+fn load_rom(args: &R8, emu: &mut Emulator) -> Vec<u8> {
   match (args.rom.clone(), args.asm.clone()) {
     (Some(rom), None) => {
-      let rom = match std::fs::File::open(rom) {
-        Ok(file) => file,
+      let rom = match std::fs::read(rom) {
+        Ok(rom) => rom,
         Err(err) => {
           log_and_exit!("Failed to open ROM: {}", err);
         }
       };
-      if let Err(err) = emu.load_rom(rom) {
+      if let Err(err) = emu.load_rom(rom.as_slice()) {
         log_and_exit!("Failed to load ROM: {}", err);
       }
+      rom
     }
     (None, Some(asm)) => {
-      let mut asm = match std::fs::File::open(asm) {
-        Ok(file) => file,
+      let mut rom = vec![];
+      let files = r8_assembly::FileSystem::default();
+      match r8_assembly::assemble_file(&asm, &files, &mut rom) {
+        Ok(assembly) => assembly
+          .warnings
+          .iter()
+          .for_each(|warning| log::warn!("{}", warning)),
         Err(err) => {
-          log_and_exit!("Failed to open assembly file: {}", err);
+          log_and_exit!("Failed to assemble: {}", err);
         }
-      };
-      let mut rom = vec![];
-      if let Err(err) = r8_assembly::assemble(&mut asm, &mut rom) {
-        log_and_exit!("Failed to assemble: {}", err);
       }
-      if let Err(err) = emu.load_rom(std::io::Cursor::new(rom)) {
+      if let Err(err) = emu.load_rom(rom.as_slice()) {
         log_and_exit!("Failed to load ROM: {}", err);
       }
+      rom
     }
     _ => {
       log_and_exit!("Please specify either a ROM or an assembly file");
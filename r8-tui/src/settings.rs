@@ -0,0 +1,178 @@
+//! Per-ROM settings of the TUI.
+//!
+//! The speed and quirks a ROM was last played with are restored when it is loaded
+//! and saved whenever they change, see `r8_config::RomSettingsStore`. The palette
+//! and the key bindings of the ROM are restored too, the bindings over the
+//! configured ones. The TUI cannot change them, they are kept as the GUI saved them.
+
+use r8_config::{KeyBindings, KeyMap, RomSettings, RomSettingsStore};
+use r8_emulator::{export::Palette, Emulator, Quirks};
+
+/// Highest speed the hotkeys go to, in instructions per frame.
+pub const MAX_SPEED: u32 = 50;
+
+/// The settings of the loaded ROM.
+///
+/// # Fields
+///
+/// * `store` - Where settings are saved, `None` if the settings file could not be opened.
+/// * `sha1` - The SHA-1 of the loaded ROM.
+/// * `title` - The title of the ROM, saved along with its settings.
+/// * `defaults` - The speed and quirks the ROM runs with without saved settings.
+/// * `speed` - Instructions per frame.
+/// * `palette` - The screen colours, `None` for the TUI default.
+/// * `bindings` - The saved key bindings of the ROM, if any, saved back as they are.
+/// * `keys` - The key map of the ROM: its bindings over the configured ones.
+pub struct Session {
+  store: Option<RomSettingsStore>,
+  sha1: String,
+  title: Option<String>,
+  defaults: (u32, Quirks),
+  pub speed: u32,
+  pub palette: Option<Palette>,
+  bindings: Option<KeyBindings>,
+  pub keys: KeyMap,
+}
+
+impl Session {
+  /// Starts a session for the loaded ROM and applies its saved settings.
+  ///
+  /// # Arguments
+  ///
+  /// * `store` - The settings store.
+  /// * `sha1` - The SHA-1 of the loaded ROM.
+  /// * `title` - The title of the ROM.
+  /// * `speed` - The default speed of the ROM (its tickrate or the TUI default).
+  /// * `palette` - The default colours of the ROM (from the database or the configuration).
+  /// * `keys` - The configured key map.
+  /// * `emu` - The emulator the ROM is loaded in, it runs with the saved quirks.
+  pub fn new(
+    store: Option<RomSettingsStore>,
+    sha1: String,
+    title: Option<String>,
+    speed: u32,
+    palette: Option<Palette>,
+    keys: &KeyMap,
+    emu: &mut Emulator,
+  ) -> Self {
+    let mut session = Self {
+      store,
+      sha1,
+      title,
+      defaults: (speed, emu.quirks()),
+      speed,
+      palette,
+      bindings: None,
+      keys: keys.clone(),
+    };
+    let saved = session
+      .store
+      .as_ref()
+      .and_then(|store| store.get(&session.sha1))
+      .cloned();
+    if let Some(saved) = saved {
+      log::info!("Restoring the saved settings of this ROM");
+      if let Some(speed) = saved.speed {
+        session.speed = speed.clamp(1, MAX_SPEED);
+      }
+      if let Some(quirks) = saved.quirks {
+        emu.set_rom_quirks(quirks);
+      }
+      if let Some(palette) = saved.palette {
+        session.palette = Some(palette);
+      }
+      if let Some(bindings) = &saved.keys {
+        session.keys = bindings.apply(keys);
+      }
+      session.bindings = saved.keys;
+    }
+    session
+  }
+
+  /// Changes the speed by `delta` instructions per frame.
+  pub fn change_speed(&mut self, delta: i32, emu: &Emulator) {
+    self.speed = self.speed.saturating_add_signed(delta).clamp(1, MAX_SPEED);
+    log::info!("Speed: {} instructions per frame", self.speed);
+    self.save(emu);
+  }
+
+  /// Switches to the next quirks preset.
+  pub fn cycle_quirks(&mut self, emu: &mut Emulator) {
+    let quirks = emu.quirks().next_preset();
+    emu.set_rom_quirks(quirks);
+    log::info!("Quirks: {}", quirks.preset_name().unwrap_or("custom"));
+    self.save(emu);
+  }
+
+  /// Forgets the saved settings of the ROM and goes back to its defaults.
+  pub fn reset(&mut self, emu: &mut Emulator) {
+    let (speed, quirks) = self.defaults;
+    self.speed = speed;
+    emu.set_rom_quirks(quirks);
+    log::info!("Settings reset to the defaults of this ROM");
+    self.save(emu);
+  }
+
+  /// Saves the settings that differ from the defaults, keeping the saved palette
+  /// and key bindings.
+  fn save(&mut self, emu: &Emulator) {
+    let Some(store) = &mut self.store else {
+      return;
+    };
+    let (speed, quirks) = self.defaults;
+    let palette = store.get(&self.sha1).and_then(|settings| settings.palette);
+    let keys = self.bindings.clone();
+    let settings = RomSettings {
+      title: self.title.clone(),
+      speed: (self.speed != speed).then_some(self.speed),
+      quirks: (emu.quirks() != quirks).then_some(emu.quirks()),
+      palette,
+      keys,
+    };
+    store.set(&self.sha1, settings);
+    if let Err(err) = store.save() {
+      log::error!("Failed to save {}: {}", store.path().display(), err);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn restores_the_saved_palette() {
+    let path = std::env::temp_dir()
+      .join(format!("r8-tui-{}-palette", std::process::id()))
+      .join("roms.toml");
+    let saved: Palette = "102030:FFCC00".parse().unwrap();
+    let mut store = RomSettingsStore::open(&path).unwrap();
+    let settings = RomSettings {
+      palette: Some(saved),
+      ..Default::default()
+    };
+    store.set("ABCDEF", settings);
+
+    let default = Some("000000:FFFFFF".parse().unwrap());
+    let mut emu = Emulator::new();
+    let keys = KeyMap::default();
+    let mut session = Session::new(
+      Some(store),
+      "ABCDEF".into(),
+      None,
+      1,
+      default,
+      &keys,
+      &mut emu,
+    );
+    assert_eq!(session.palette, Some(saved));
+
+    // Saving another setting keeps the palette.
+    session.change_speed(1, &emu);
+    let store = RomSettingsStore::open(&path).unwrap();
+    assert_eq!(store.get("ABCDEF").unwrap().palette, Some(saved));
+
+    let session = Session::new(None, "ABCDEF".into(), None, 1, default, &keys, &mut emu);
+    assert_eq!(session.palette, default);
+  }
+}